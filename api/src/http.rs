@@ -18,11 +18,14 @@ use url::Url;
 use micro_http::{HttpServer, MediaType, Request, Response, StatusCode};
 use vmm_sys_util::eventfd::EventFd;
 
+#[cfg(feature = "backend-faulty")]
+use crate::http_endpoint::BackendFaultsHandler;
 use crate::http_endpoint::{
-    error_response, ApiError, ApiRequest, ApiResponse, EventsHandler, ExitHandler, FsBackendInfo,
-    HttpError, HttpResult, InfoHandler, MetricsBackendHandler, MetricsBlobcacheHandler,
-    MetricsFilesHandler, MetricsHandler, MetricsInflightHandler, MetricsPatternHandler,
-    MountHandler, SendFuseFdHandler, TakeoverHandler,
+    error_response, ApiError, ApiRequest, ApiResponse, BlobcacheGcHandler, BlobcacheScrubHandler,
+    EventsHandler, ExitHandler, FsBackendInfo, HttpError, HttpResult, InfoHandler,
+    MetricsBackendHandler, MetricsBlobcacheHandler, MetricsFilesHandler, MetricsHandler,
+    MetricsInflightHandler, MetricsPatternHandler, MountHandler, PrefetchEventsHandler,
+    PrefetchHandler, SendFuseFdHandler, TakeoverHandler,
 };
 
 const HTTP_ROOT: &str = "/api/v1";
@@ -73,6 +76,12 @@ lazy_static! {
         r.routes.insert(endpoint!("/metrics/backend"), Box::new(MetricsBackendHandler{}));
         r.routes.insert(endpoint!("/metrics/blobcache"), Box::new(MetricsBlobcacheHandler{}));
         r.routes.insert(endpoint!("/metrics/inflight"), Box::new(MetricsInflightHandler{}));
+        #[cfg(feature = "backend-faulty")]
+        r.routes.insert(endpoint!("/backend/faults"), Box::new(BackendFaultsHandler{}));
+        r.routes.insert(endpoint!("/blobcache/gc"), Box::new(BlobcacheGcHandler{}));
+        r.routes.insert(endpoint!("/blobcache/scrub"), Box::new(BlobcacheScrubHandler{}));
+        r.routes.insert(endpoint!("/prefetch"), Box::new(PrefetchHandler{}));
+        r.routes.insert(endpoint!("/prefetch/events"), Box::new(PrefetchEventsHandler{}));
         r
     };
 }
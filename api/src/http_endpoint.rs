@@ -60,6 +60,9 @@ pub enum ApiError {
     DaemonAbnormal(DaemonErrorKind),
     Events(String),
     Metrics(MetricsErrorKind),
+    #[cfg(feature = "backend-faulty")]
+    /// Failed to access faults of the faulty backend
+    BackendFaults(io::Error),
 }
 pub type ApiResult<T> = std::result::Result<T, ApiError>;
 
@@ -79,6 +82,11 @@ pub enum ApiResponsePayload {
     BackendMetrics(String),
     BlobcacheMetrics(String),
     InflightMetrics(String),
+    #[cfg(feature = "backend-faulty")]
+    BackendFaults(String),
+    BlobcacheGc(String),
+    BlobcacheScrubProgress(String),
+    PrefetchProgress(String),
 }
 
 /// This is the response sent by the API server through the mpsc channel.
@@ -96,10 +104,22 @@ pub enum ApiRequest {
     ExportGlobalMetrics(Option<String>),
     ExportFilesMetrics(Option<String>, bool),
     ExportAccessPatterns(Option<String>),
+    ExportAccessTrace(Option<String>),
     ExportBackendMetrics(Option<String>),
     ExportBlobcacheMetrics(Option<String>),
     ExportInflightMetrics,
     ExportFsBackendInfo(String),
+    #[cfg(feature = "backend-faulty")]
+    ExportBackendFaults(Option<String>),
+    #[cfg(feature = "backend-faulty")]
+    SetBackendFaults(Option<String>, String),
+    BlobcacheGc(BlobcacheGcCmd),
+    BlobcacheScrub(BlobcacheScrubCmd),
+    BlobcacheScrubProgress,
+    Prefetch(String, PrefetchCmd),
+    CancelPrefetch(String),
+    PrefetchProgress,
+    PrefetchEvents,
     SendFuseFd,
     Takeover,
     Exit,
@@ -120,6 +140,55 @@ pub struct ApiUmountCmd {
     pub mountpoint: String,
 }
 
+#[derive(Clone, Deserialize, Debug)]
+pub struct BlobcacheGcCmd {
+    /// Only report what would be removed.
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Blobs touched within the period in unit of seconds are not removed.
+    #[serde(default = "default_gc_grace_period")]
+    pub grace_period: u64,
+}
+
+impl Default for BlobcacheGcCmd {
+    fn default() -> Self {
+        BlobcacheGcCmd {
+            dry_run: false,
+            grace_period: default_gc_grace_period(),
+        }
+    }
+}
+
+pub fn default_gc_grace_period() -> u64 {
+    3600
+}
+
+#[derive(Clone, Deserialize, Debug)]
+pub struct BlobcacheScrubCmd {
+    /// Maximum bytes read from blobcache per second, zero means unlimited which must be
+    /// specified explicitly.
+    #[serde(default = "default_scrub_rate")]
+    pub rate: u32,
+}
+
+impl Default for BlobcacheScrubCmd {
+    fn default() -> Self {
+        BlobcacheScrubCmd {
+            rate: default_scrub_rate(),
+        }
+    }
+}
+
+pub fn default_scrub_rate() -> u32 {
+    10 << 20
+}
+
+#[derive(Clone, Deserialize, Debug)]
+pub struct PrefetchCmd {
+    /// Files or directories to prefetch, as absolute paths within the mount.
+    pub files: Vec<String>,
+}
+
 fn parse_body<'a, F: Deserialize<'a>>(b: &'a Body) -> Result<F, HttpError> {
     serde_json::from_slice::<F>(b.raw()).map_err(HttpError::ParseBody)
 }
@@ -153,6 +222,11 @@ pub enum HttpError {
     BackendMetrics(ApiError),
     FsBackendInfo(ApiError),
     InflightMetrics(ApiError),
+    #[cfg(feature = "backend-faulty")]
+    BackendFaults(ApiError),
+    BlobcacheGc(ApiError),
+    BlobcacheScrub(ApiError),
+    Prefetch(ApiError),
 }
 
 fn success_response(body: Option<String>) -> Response {
@@ -201,6 +275,12 @@ fn translate_status_code(e: &ApiError) -> StatusCode {
             _ => StatusCode::InternalServerError,
         },
         ApiError::Metrics(MetricsErrorKind::Stats(IoStatsError::NoCounter)) => StatusCode::NotFound,
+        #[cfg(feature = "backend-faulty")]
+        ApiError::BackendFaults(e) => match e.kind() {
+            io::ErrorKind::NotFound => StatusCode::NotFound,
+            io::ErrorKind::InvalidInput => StatusCode::BadRequest,
+            _ => StatusCode::InternalServerError,
+        },
         _ => StatusCode::InternalServerError,
     }
 }
@@ -224,6 +304,11 @@ fn convert_to_response<O: FnOnce(ApiError) -> HttpError>(api_resp: ApiResponse,
                 BlobcacheMetrics(d) => success_response(Some(d)),
                 FsBackendInfo(d) => success_response(Some(d)),
                 InflightMetrics(d) => success_response(Some(d)),
+                #[cfg(feature = "backend-faulty")]
+                BackendFaults(d) => success_response(Some(d)),
+                BlobcacheGc(d) => success_response(Some(d)),
+                BlobcacheScrubProgress(d) => success_response(Some(d)),
+                PrefetchProgress(d) => success_response(Some(d)),
             }
         }
         Err(e) => {
@@ -350,7 +435,12 @@ impl EndpointHandler for MetricsPatternHandler {
         match (req.method(), req.body.as_ref()) {
             (Method::Get, None) => {
                 let id = extract_query_part(req, "id");
-                let r = kicker(ApiRequest::ExportAccessPatterns(id));
+                // Files in order of first access, in the format consumed by nydus-image.
+                let r = if extract_query_part(req, "format").as_deref() == Some("trace") {
+                    kicker(ApiRequest::ExportAccessTrace(id))
+                } else {
+                    kicker(ApiRequest::ExportAccessPatterns(id))
+                };
                 Ok(convert_to_response(r, HttpError::Pattern))
             }
             _ => Err(HttpError::BadRequest),
@@ -411,6 +501,129 @@ impl EndpointHandler for MetricsInflightHandler {
     }
 }
 
+#[cfg(feature = "backend-faulty")]
+pub struct BackendFaultsHandler {}
+#[cfg(feature = "backend-faulty")]
+impl EndpointHandler for BackendFaultsHandler {
+    fn handle_request(
+        &self,
+        req: &Request,
+        kicker: &dyn Fn(ApiRequest) -> ApiResponse,
+    ) -> HttpResult {
+        let id = extract_query_part(req, "id");
+        match (req.method(), req.body.as_ref()) {
+            (Method::Get, None) => {
+                let r = kicker(ApiRequest::ExportBackendFaults(id));
+                Ok(convert_to_response(r, HttpError::BackendFaults))
+            }
+            (Method::Put, Some(body)) => {
+                let faults = String::from_utf8_lossy(body.raw()).to_string();
+                let r = kicker(ApiRequest::SetBackendFaults(id, faults));
+                Ok(convert_to_response(r, HttpError::BackendFaults))
+            }
+            _ => Err(HttpError::BadRequest),
+        }
+    }
+}
+
+pub struct BlobcacheGcHandler {}
+impl EndpointHandler for BlobcacheGcHandler {
+    fn handle_request(
+        &self,
+        req: &Request,
+        kicker: &dyn Fn(ApiRequest) -> ApiResponse,
+    ) -> HttpResult {
+        match (req.method(), req.body.as_ref()) {
+            (Method::Put, None) => {
+                let r = kicker(ApiRequest::BlobcacheGc(BlobcacheGcCmd::default()));
+                Ok(convert_to_response(r, HttpError::BlobcacheGc))
+            }
+            (Method::Put, Some(body)) => {
+                let cmd = parse_body(body)?;
+                let r = kicker(ApiRequest::BlobcacheGc(cmd));
+                Ok(convert_to_response(r, HttpError::BlobcacheGc))
+            }
+            _ => Err(HttpError::BadRequest),
+        }
+    }
+}
+
+pub struct BlobcacheScrubHandler {}
+impl EndpointHandler for BlobcacheScrubHandler {
+    fn handle_request(
+        &self,
+        req: &Request,
+        kicker: &dyn Fn(ApiRequest) -> ApiResponse,
+    ) -> HttpResult {
+        match (req.method(), req.body.as_ref()) {
+            (Method::Get, None) => {
+                let r = kicker(ApiRequest::BlobcacheScrubProgress);
+                Ok(convert_to_response(r, HttpError::BlobcacheScrub))
+            }
+            (Method::Put, None) => {
+                let r = kicker(ApiRequest::BlobcacheScrub(BlobcacheScrubCmd::default()));
+                Ok(convert_to_response(r, HttpError::BlobcacheScrub))
+            }
+            (Method::Put, Some(body)) => {
+                let cmd = parse_body(body)?;
+                let r = kicker(ApiRequest::BlobcacheScrub(cmd));
+                Ok(convert_to_response(r, HttpError::BlobcacheScrub))
+            }
+            _ => Err(HttpError::BadRequest),
+        }
+    }
+}
+
+pub struct PrefetchHandler {}
+impl EndpointHandler for PrefetchHandler {
+    fn handle_request(
+        &self,
+        req: &Request,
+        kicker: &dyn Fn(ApiRequest) -> ApiResponse,
+    ) -> HttpResult {
+        let mountpoint = || {
+            extract_query_part(req, "mountpoint").ok_or_else(|| {
+                HttpError::QueryString(
+                    "'mountpoint' should be specified in query string".to_string(),
+                )
+            })
+        };
+        match (req.method(), req.body.as_ref()) {
+            (Method::Get, None) => {
+                let r = kicker(ApiRequest::PrefetchProgress);
+                Ok(convert_to_response(r, HttpError::Prefetch))
+            }
+            (Method::Post, Some(body)) => {
+                let cmd = parse_body(body)?;
+                let r = kicker(ApiRequest::Prefetch(mountpoint()?, cmd));
+                Ok(convert_to_response(r, HttpError::Prefetch))
+            }
+            (Method::Delete, None) => {
+                let r = kicker(ApiRequest::CancelPrefetch(mountpoint()?));
+                Ok(convert_to_response(r, HttpError::Prefetch))
+            }
+            _ => Err(HttpError::BadRequest),
+        }
+    }
+}
+
+pub struct PrefetchEventsHandler {}
+impl EndpointHandler for PrefetchEventsHandler {
+    fn handle_request(
+        &self,
+        req: &Request,
+        kicker: &dyn Fn(ApiRequest) -> ApiResponse,
+    ) -> HttpResult {
+        match (req.method(), req.body.as_ref()) {
+            (Method::Get, None) => {
+                let r = kicker(ApiRequest::PrefetchEvents);
+                Ok(convert_to_response(r, HttpError::Prefetch))
+            }
+            _ => Err(HttpError::BadRequest),
+        }
+    }
+}
+
 pub struct SendFuseFdHandler {}
 impl EndpointHandler for SendFuseFdHandler {
     fn handle_request(
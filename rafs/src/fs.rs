@@ -31,7 +31,13 @@ use crate::*;
 use nydus_utils::metrics::{self, FopRecorder, StatsFop::*};
 use storage::device::BlobPrefetchControl;
 use storage::*;
-use storage::{cache::PrefetchWorker, device};
+use storage::{
+    cache::{
+        prefetch::{PrefetchPriority, PrefetchProgress},
+        PrefetchWorker, ScrubProgress,
+    },
+    device,
+};
 
 /// Type of RAFS fuse handle.
 pub type Handle = u64;
@@ -294,6 +300,7 @@ impl Rafs {
 
             let prefetch_all = self.prefetch_all;
 
+            device.begin_prefetch();
             let _ = std::thread::spawn(move || {
                 let mut reader = r;
                 let inodes = match prefetch_files {
@@ -313,10 +320,12 @@ impl Rafs {
 
                 // Prefetch procedure does not affect rafs mounting
                 sb.prefetch_hint_files(&mut reader, inodes, &|mut desc| {
-                    device.prefetch(&mut desc).unwrap_or_else(|e| {
-                        warn!("Prefetch error, {:?}", e);
-                        0
-                    });
+                    device
+                        .prefetch(&mut desc, PrefetchPriority::Hinted)
+                        .unwrap_or_else(|e| {
+                            warn!("Prefetch error, {:?}", e);
+                            0
+                        });
                 })
                 .unwrap_or_else(|e| {
                     info!("No file to be prefetched {:?}", e);
@@ -325,22 +334,21 @@ impl Rafs {
                 if prefetch_all {
                     let root = vec![RAFS_ROOT_INODE];
                     sb.prefetch_hint_files(&mut reader, Some(root), &|mut desc| {
-                        device.prefetch(&mut desc).unwrap_or_else(|e| {
-                            warn!("Prefetch error, {:?}", e);
-                            0
-                        });
+                        device
+                            .prefetch(&mut desc, PrefetchPriority::Blob)
+                            .unwrap_or_else(|e| {
+                                warn!("Prefetch error, {:?}", e);
+                                0
+                            });
                     })
                     .unwrap_or_else(|e| {
                         info!("No file to be prefetched {:?}", e);
                     })
                 }
 
-                // For now, we only have hinted prefetch. So stopping prefetch workers once
-                // it's done is Okay. But if we involve more policies someday, we have to be
-                // careful when to stop prefetch progresses.
-                device
-                    .stop_prefetch()
-                    .unwrap_or_else(|_| error!("Failed in stopping prefetch workers"));
+                // Prefetch workers are not stopped here but when rafs is dropped on umount,
+                // since more files may be prefetched by `prefetch_files()` later.
+                device.end_prefetch();
             });
         }
 
@@ -348,6 +356,63 @@ impl Rafs {
         Ok(())
     }
 
+    /// Start verifying cached data of all chunks in the filesystem in background, reading
+    /// at most `rate` bytes per second from cache, zero means unlimited.
+    pub fn scrub(&self, rate: u32) -> Result<()> {
+        let bios = self.sb.collect_chunks()?;
+        self.device.scrub(bios, rate)
+    }
+
+    pub fn scrub_progress(&self) -> Option<ScrubProgress> {
+        self.device.scrub_progress()
+    }
+
+    /// Prefetch the files or directories in background after rafs is mounted, files are
+    /// queued in front of those prefetched for the whole filesystem.
+    pub fn prefetch_files(&self, files: &[PathBuf]) -> Result<()> {
+        if !self.fs_prefetch {
+            return Err(enosys!("fs_prefetch is not enabled"));
+        }
+
+        let mut inodes = Vec::with_capacity(files.len());
+        for f in files {
+            let ino = self
+                .sb
+                .ino_from_path(f)
+                .map_err(|e| enoent!(format!("failed to find {:?}, {}", f, e)))?;
+            inodes.push(ino);
+        }
+
+        let sb = self.sb.clone();
+        let device = self.device.clone();
+        device.begin_prefetch();
+        let _ = std::thread::spawn(move || {
+            sb.prefetch_files(&inodes, &|mut desc| {
+                device
+                    .prefetch(&mut desc, PrefetchPriority::Hinted)
+                    .unwrap_or_else(|e| {
+                        warn!("Prefetch error, {:?}", e);
+                        0
+                    });
+            })
+            .unwrap_or_else(|e| warn!("Failed to prefetch files {:?}", e));
+            device.end_prefetch();
+        });
+
+        Ok(())
+    }
+
+    /// Drop prefetch requests not issued to storage backend yet.
+    pub fn cancel_prefetch(&self) -> Result<()> {
+        self.device
+            .cancel_prefetch()
+            .map_err(|e| eother!(format!("{:?}", e)))
+    }
+
+    pub fn prefetch_progress(&self) -> Option<PrefetchProgress> {
+        self.device.prefetch_progress()
+    }
+
     /// umount a previously mounted rafs virtual path
     pub fn destroy(&mut self) -> Result<()> {
         info! {"Destroy rafs"}
@@ -495,6 +560,16 @@ impl Rafs {
     }
 }
 
+impl Drop for Rafs {
+    fn drop(&mut self) {
+        // Prefetch workers hold the cache and wait for prefetch requests issued at runtime
+        // forever, stop them once the filesystem is umounted.
+        self.device
+            .stop_prefetch()
+            .unwrap_or_else(|e| error!("Failed to stop prefetch, {:?}", e));
+    }
+}
+
 impl BackendFileSystem for Rafs {
     fn mount(&self) -> Result<(Entry, u64)> {
         let root_inode = self.sb.get_inode(ROOT_ID, self.digest_validate)?;
@@ -915,6 +990,59 @@ mod tests {
         }
     }
 
+    #[test]
+    fn it_should_stop_prefetch_workers_on_umount() {
+        let tmp_dir = vmm_sys_util::tempdir::TempDir::new().unwrap();
+        let config = format!(
+            r#"
+        {{
+            "device": {{
+              "backend": {{
+                "type": "localfs",
+                "config": {{
+                  "dir": {:?}
+                }}
+              }},
+              "cache": {{
+                "type": "blobcache",
+                "config": {{
+                  "work_dir": {:?}
+                }}
+              }}
+            }},
+            "mode": "direct",
+            "fs_prefetch": {{
+              "enable": true,
+              "threads_count": 4
+            }}
+          }}"#,
+            tmp_dir.as_path(),
+            tmp_dir.as_path().join("cache"),
+        );
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        let mut source_path = PathBuf::from(root_dir);
+        source_path.push("../tests/texture/bootstrap/image_v2.boot");
+        let rafs_config = RafsConfig::from_str(&config).unwrap();
+        let mut bootstrap = <dyn RafsIoRead>::from_file(source_path.to_str().unwrap()).unwrap();
+        let mut rafs = Rafs::new(rafs_config, "prefetch-umount", &mut bootstrap).unwrap();
+        rafs.import(bootstrap, None).unwrap();
+
+        // Prefetch workers and the mount-time prefetch thread hold the cache.
+        let cache = Arc::downgrade(&**rafs.device.rw_layer.load());
+        drop(rafs);
+
+        // Prefetch workers are joined on drop, only the mount-time prefetch thread may still
+        // be walking the prefetch table.
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        while cache.upgrade().is_some() {
+            assert!(
+                std::time::Instant::now() < deadline,
+                "prefetch workers still hold the cache after umount"
+            );
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+    }
+
     #[test]
     fn it_should_get_statfs() {
         let rafs = new_rafs_backend();
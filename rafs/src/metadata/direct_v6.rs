@@ -0,0 +1,926 @@
+// Copyright (C) 2021 Alibaba Cloud. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+/// A bootstrap driver to directly use on disk RAFS v6 bootstrap as runtime in-memory bootstrap.
+///
+/// The V6 on disk bootstrap is laid out in the EROFS format, and it's memory mapped like V5, so
+/// the EROFS inodes, directory blocks and RAFS inode extensions are parsed on demand.
+///
+/// # Security
+/// The bootstrap file may be provided by untrusted parties, so we must ensure strong validations
+/// before making use of any bootstrap, especially we are using them in memory-mapped mode. The
+/// rule is to call validate() after creating any data structure from the on-disk bootstrap.
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::io::Result;
+use std::io::SeekFrom;
+use std::mem::size_of;
+use std::ops::Deref;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{FromRawFd, IntoRawFd, RawFd};
+use std::slice;
+use std::sync::Arc;
+
+use arc_swap::{ArcSwap, Guard};
+
+use nydus_utils::digest::{Algorithm, RafsDigest};
+use storage::device::RafsBioDesc;
+use storage::utils::readahead;
+
+use crate::metadata::layout::v5::{
+    rafsv5_alloc_bio_desc, rafsv5_validate_digest, RafsBlobEntry, RafsChunkFlags, RafsChunkInfo,
+    RafsV5BlobTable, RafsV5ChunkInfo, RafsV5Inode, RafsV5InodeFlags, RafsV5InodeOps,
+};
+use crate::metadata::layout::v6::{
+    parse_rafsv6_xattrs, rafsv6_align, RafsV6DirBlock, RafsV6Inode, RafsV6InodeExt,
+    RafsV6InodeTable, EROFS_BLOCK_SIZE, EROFS_INODE_CHUNK_BASED, EROFS_INODE_FLAT_INLINE,
+    EROFS_INODE_FLAT_PLAIN, EROFS_INODE_SLOT_SIZE, RAFSV6_DEVICE_TABLE_OFFSET,
+};
+use crate::metadata::layout::{bytes_to_os_str, XattrName, XattrValue};
+use crate::metadata::{
+    Attr, Entry, Inode, RafsInode, RafsSuperBlobs, RafsSuperBlock, RafsSuperInodes, RafsSuperMeta,
+    DOT, DOTDOT, RAFS_INODE_BLOCKSIZE, RAFS_MAX_METADATA_SIZE, RAFS_MAX_NAME,
+};
+use crate::{RafsError, RafsIoReader, RafsResult};
+
+/// Impl get accessor for chunkinfo object.
+macro_rules! impl_chunkinfo_getter {
+    ($G: ident, $U: ty) => {
+        #[inline]
+        fn $G(&self) -> $U {
+            let state = self.state();
+
+            self.chunk(state.deref()).$G
+        }
+    };
+}
+
+/// The underlying struct to maintain memory mapped bootstrap for a file system.
+///
+/// Only the DirectMappingState may store raw pointers, as in `direct_v5`.
+#[derive(Clone)]
+struct DirectMappingState {
+    meta: RafsSuperMeta,
+    inode_table: Arc<RafsV6InodeTable>,
+    blob_table: Arc<RafsV5BlobTable>,
+    base: *const u8,
+    end: *const u8,
+    size: usize,
+    fd: RawFd,
+    validate_digest: bool,
+}
+
+impl DirectMappingState {
+    fn new(meta: &RafsSuperMeta, validate_digest: bool) -> Self {
+        DirectMappingState {
+            meta: *meta,
+            inode_table: Arc::new(RafsV6InodeTable::default()),
+            blob_table: Arc::new(RafsV5BlobTable::default()),
+            fd: -1,
+            base: std::ptr::null(),
+            end: std::ptr::null(),
+            size: 0,
+            validate_digest,
+        }
+    }
+
+    /// Mmap to bootstrap ondisk data directly.
+    fn cast_to_ref<T>(&self, offset: usize) -> Result<&T> {
+        let start = self.base.wrapping_add(offset);
+        let end = start.wrapping_add(size_of::<T>());
+
+        if start > end
+            || start < self.base
+            || end < self.base
+            || end > self.end
+            || start as usize & (std::mem::align_of::<T>() - 1) != 0
+        {
+            return Err(einval!("invalid mmap offset"));
+        }
+
+        Ok(unsafe { &*(start as *const T) })
+    }
+
+    #[inline]
+    fn validate_range(&self, offset: usize, size: usize) -> Result<()> {
+        let start = self.base.wrapping_add(offset);
+        let end = start.wrapping_add(size);
+
+        if start > end || start < self.base || end < self.base || end > self.end {
+            return Err(einval!("invalid range"));
+        }
+
+        Ok(())
+    }
+
+    /// Get a slice of the bootstrap, the range must have been validated.
+    #[inline]
+    fn slice(&self, offset: usize, size: usize) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.base.add(offset), size) }
+    }
+}
+
+impl Drop for DirectMappingState {
+    fn drop(&mut self) {
+        if !self.base.is_null() {
+            unsafe { libc::munmap(self.base as *mut u8 as *mut libc::c_void, self.size) };
+            self.base = std::ptr::null();
+            self.end = std::ptr::null();
+            self.size = 0;
+        }
+        if self.fd >= 0 {
+            let _ = nix::unistd::close(self.fd);
+            self.fd = -1;
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct DirectSuperBlockV6 {
+    state: ArcSwap<DirectMappingState>,
+}
+
+// Safe to Send/Sync because the underlying data structures are readonly
+unsafe impl Send for DirectSuperBlockV6 {}
+unsafe impl Sync for DirectSuperBlockV6 {}
+
+impl DirectSuperBlockV6 {
+    pub fn new(meta: &RafsSuperMeta, validate_digest: bool) -> Self {
+        let state = DirectMappingState::new(meta, validate_digest);
+
+        Self {
+            state: ArcSwap::new(Arc::new(state)),
+        }
+    }
+
+    #[inline]
+    fn get_inode_wrapper(
+        &self,
+        nid: u64,
+        alias: Option<(&OsStr, Inode)>,
+        state: &DirectMappingState,
+    ) -> Result<OndiskInodeWrapper> {
+        let offset = nid
+            .checked_mul(EROFS_INODE_SLOT_SIZE)
+            .filter(|offset| *offset >= RAFSV6_DEVICE_TABLE_OFFSET)
+            .ok_or_else(|| einval!("invalid inode nid"))? as usize;
+        let _inode = state.cast_to_ref::<RafsV6Inode>(offset)?;
+        let mut wrapper = OndiskInodeWrapper {
+            mapping: self.clone(),
+            offset,
+            name: None,
+            parent: None,
+        };
+
+        // Hardlinks share the same inode, so record the name and parent of the directory entry.
+        if let Some((name, parent)) = alias {
+            let inode = wrapper.inode(state);
+            if !inode.is_dir() && inode.i_nlink > 1 {
+                wrapper.name = Some(name.to_os_string());
+                wrapper.parent = Some(parent);
+            }
+        }
+
+        // TODO: use bitmap to record validation result.
+        wrapper.validate()?;
+
+        Ok(wrapper)
+    }
+
+    fn update_state(&self, r: &mut RafsIoReader) -> Result<()> {
+        let old_state = self.state.load();
+
+        // Validate file size
+        let fd = unsafe { libc::dup(r.as_raw_fd()) };
+        if fd < 0 {
+            return Err(last_error!("failed to dup bootstrap file fd"));
+        }
+        let file = unsafe { File::from_raw_fd(fd) };
+        let md = file.metadata()?;
+        let len = md.len();
+        let size = len as usize;
+        if len < EROFS_BLOCK_SIZE
+            || len > RAFS_MAX_METADATA_SIZE as u64
+            || len & (EROFS_BLOCK_SIZE - 1) != 0
+        {
+            return Err(ebadf!("invalid bootstrap file"));
+        }
+
+        // Table layouts have been validated with the extended superblock.
+        let meta = &old_state.meta;
+        let mut blob_table = RafsV5BlobTable::new();
+        if meta.extended_blob_table_offset > 0 {
+            r.seek(SeekFrom::Start(meta.extended_blob_table_offset))?;
+            blob_table
+                .extended
+                .load(r, meta.extended_blob_table_entries as usize)?;
+        }
+        r.seek(SeekFrom::Start(meta.blob_table_offset))?;
+        blob_table.load(r, meta.blob_table_size)?;
+
+        let mut inode_table = RafsV6InodeTable::new(meta.inode_table_entries as usize);
+        inode_table.data.truncate(meta.inode_table_entries as usize);
+        r.seek(SeekFrom::Start(meta.inode_table_offset))?;
+        let (_, data, _) = unsafe { inode_table.data.align_to_mut::<u8>() };
+        r.read_exact(data)?;
+
+        // Prefetch the bootstrap file
+        readahead(fd, 0, len);
+
+        // Mmap the bootstrap file into current process for direct access
+        let base = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                size,
+                libc::PROT_READ,
+                libc::MAP_NORESERVE | libc::MAP_PRIVATE,
+                fd,
+                0,
+            )
+        } as *const u8;
+        if base as *mut core::ffi::c_void == libc::MAP_FAILED {
+            return Err(last_error!("failed to mmap bootstrap"));
+        }
+        if base.is_null() {
+            return Err(ebadf!("failed to mmap bootstrap"));
+        }
+        // Safe because the mmap area should covered the range [start, end)
+        let end = unsafe { base.add(size) };
+
+        let state = DirectMappingState {
+            meta: old_state.meta,
+            inode_table: Arc::new(inode_table),
+            blob_table: Arc::new(blob_table),
+            fd: file.into_raw_fd(),
+            base,
+            end,
+            size,
+            validate_digest: old_state.validate_digest,
+        };
+
+        // Swap new and old DirectMappingState object, the old object will be destroyed when the
+        // reference count reaches zero.
+        self.state.store(Arc::new(state));
+
+        Ok(())
+    }
+}
+
+impl RafsSuperInodes for DirectSuperBlockV6 {
+    fn get_max_ino(&self) -> Inode {
+        let state = self.state.load();
+
+        state.inode_table.len() as u64
+    }
+
+    /// Find inode nid by ino from inode table and mmap to OndiskInode.
+    fn get_inode(&self, ino: Inode, validate_digest: bool) -> Result<Arc<dyn RafsInode>> {
+        let state = self.state.load();
+        let nid = state.inode_table.get(ino)?;
+        let wrapper = self.get_inode_wrapper(nid, None, state.deref())?;
+        let inode = Arc::new(wrapper) as Arc<dyn RafsInode>;
+
+        if validate_digest {
+            let digester = state.meta.get_digester();
+            if !self.validate_digest(inode.clone(), false, digester)? {
+                return Err(einval!("invalid inode digest"));
+            }
+        }
+
+        Ok(inode)
+    }
+
+    fn validate_digest(
+        &self,
+        inode: Arc<dyn RafsInode>,
+        recursive: bool,
+        digester: Algorithm,
+    ) -> Result<bool> {
+        rafsv5_validate_digest(inode, recursive, digester)
+    }
+}
+
+impl RafsSuperBlobs for DirectSuperBlockV6 {
+    fn get_blob_table(&self) -> Arc<RafsV5BlobTable> {
+        let state = self.state.load();
+        state.blob_table.clone()
+    }
+}
+
+impl RafsSuperBlock for DirectSuperBlockV6 {
+    fn load(&mut self, r: &mut RafsIoReader) -> Result<()> {
+        self.update_state(r)
+    }
+
+    fn update(&self, r: &mut RafsIoReader) -> RafsResult<()> {
+        self.update_state(r).map_err(RafsError::SwapBackend)
+    }
+
+    fn destroy(&mut self) {
+        let state = DirectMappingState::new(&RafsSuperMeta::default(), false);
+        self.state.store(Arc::new(state));
+    }
+}
+
+pub struct OndiskInodeWrapper {
+    pub mapping: DirectSuperBlockV6,
+    pub offset: usize,
+    /// Name and parent of the directory entry for hardlinks, which share the same inode.
+    name: Option<OsString>,
+    parent: Option<Inode>,
+}
+
+impl OndiskInodeWrapper {
+    #[inline]
+    fn state(&self) -> Guard<Arc<DirectMappingState>> {
+        self.mapping.state.load()
+    }
+
+    #[allow(clippy::cast_ptr_alignment)]
+    #[inline]
+    fn inode<'a>(&self, state: &'a DirectMappingState) -> &'a RafsV6Inode {
+        unsafe {
+            let ptr = state.base.add(self.offset);
+            &*(ptr as *const RafsV6Inode)
+        }
+    }
+
+    #[inline]
+    fn ext_offset(&self, state: &DirectMappingState) -> usize {
+        self.offset + self.inode(state).ext_offset()
+    }
+
+    /// Get the RAFS inode extension.
+    ///
+    /// # Safety
+    /// It depends on Self::validate() to ensure valid memory layout.
+    #[allow(clippy::cast_ptr_alignment)]
+    #[inline]
+    fn ext<'a>(&self, state: &'a DirectMappingState) -> &'a RafsV6InodeExt {
+        unsafe {
+            let ptr = state.base.add(self.ext_offset(state));
+            &*(ptr as *const RafsV6InodeExt)
+        }
+    }
+
+    fn name_ref<'a>(&'a self, state: &'a DirectMappingState) -> &'a OsStr {
+        if let Some(name) = self.name.as_ref() {
+            return name;
+        }
+
+        let offset = self.ext_offset(state) + size_of::<RafsV6InodeExt>();
+        let size = self.ext(state).e_name_size as usize;
+
+        bytes_to_os_str(state.slice(offset, size))
+    }
+
+    fn xattr_data<'a>(&self, state: &'a DirectMappingState) -> &'a [u8] {
+        let inode = self.inode(state);
+        state.slice(self.offset + size_of::<RafsV6Inode>(), inode.xattr_size())
+    }
+
+    /// Get the range of data blocks of flat inodes.
+    fn data_range(&self, state: &DirectMappingState) -> Result<(usize, usize)> {
+        let inode = self.inode(state);
+        let offset = (inode.i_u as u64)
+            .checked_mul(EROFS_BLOCK_SIZE)
+            .ok_or_else(|| einval!("invalid inode data block"))?;
+        state.validate_range(offset as usize, inode.i_size as usize)?;
+
+        Ok((offset as usize, inode.i_size as usize))
+    }
+
+    /// Walk through directory blocks, the iteration breaks if the callback returns false.
+    fn walk_dir_blocks<'a, F>(&self, state: &'a DirectMappingState, mut cb: F) -> Result<()>
+    where
+        F: FnMut(&RafsV6DirBlock<'a>) -> Result<bool>,
+    {
+        let (offset, size) = self.data_range(state)?;
+        let data = state.slice(offset, size);
+
+        for block in data.chunks(EROFS_BLOCK_SIZE as usize) {
+            if !cb(&RafsV6DirBlock::new(block)?)? {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn get_child(
+        &self,
+        state: &DirectMappingState,
+        name: &OsStr,
+        nid: u64,
+    ) -> Result<Arc<dyn RafsInode>> {
+        let alias = Some((name, self.ino()));
+        let wrapper = self.mapping.get_inode_wrapper(nid, alias, state)?;
+
+        Ok(Arc::new(wrapper))
+    }
+}
+
+impl RafsInode for OndiskInodeWrapper {
+    fn validate(&self) -> Result<()> {
+        let state = self.state();
+        let inode = self.inode(state.deref());
+
+        if inode.i_nlink == 0 || inode.i_ino == 0 {
+            return Err(ebadf!(format!(
+                "inode validation failure, inode {:?}",
+                inode
+            )));
+        }
+
+        let ext_offset = self.ext_offset(state.deref());
+        let ext = state.cast_to_ref::<RafsV6InodeExt>(ext_offset)?;
+        if ext.e_name_size as usize > RAFS_MAX_NAME + 1 {
+            return Err(ebadf!(format!("inode validation failure, ext {:?}", ext)));
+        }
+        state.validate_range(ext_offset, ext.size(inode))?;
+
+        match inode.data_layout() {
+            EROFS_INODE_CHUNK_BASED => {
+                if !inode.is_reg()
+                    || inode.chunk_size() != state.meta.block_size as u64
+                    || ext.e_child_count as usize > inode.chunk_index_count()
+                {
+                    return Err(ebadf!("invalid chunk based inode"));
+                }
+            }
+            EROFS_INODE_FLAT_PLAIN => {
+                if inode.is_dir() || inode.is_symlink() {
+                    self.data_range(state.deref())?;
+                } else if inode.is_reg() && inode.i_size != 0 {
+                    return Err(ebadf!("invalid flat inode"));
+                }
+            }
+            EROFS_INODE_FLAT_INLINE => {
+                if !inode.is_symlink() || inode.i_size >= EROFS_BLOCK_SIZE {
+                    return Err(ebadf!("invalid inline inode"));
+                }
+            }
+            _ => return Err(ebadf!("invalid inode data layout")),
+        }
+
+        if inode.is_dir() {
+            // * - parent inode number must be less than child inode number.
+            // * - child inode number has mapping in the inode table
+            let max_ino = state.inode_table.len();
+            if ext.e_parent >= inode.i_ino as u64
+                && inode.i_ino as u64 != crate::metadata::layout::RAFS_ROOT_INODE
+                || ext.e_child_index as usize > max_ino + 1
+                || ext.e_child_count as usize > max_ino
+            {
+                return Err(ebadf!("invalid inode"));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn get_entry(&self) -> Entry {
+        let state = self.state();
+        let inode = self.inode(state.deref());
+
+        Entry {
+            attr: self.get_attr().into(),
+            inode: inode.i_ino as u64,
+            generation: 0,
+            attr_timeout: state.meta.attr_timeout,
+            entry_timeout: state.meta.entry_timeout,
+        }
+    }
+
+    fn get_attr(&self) -> Attr {
+        let state = self.state();
+        let inode = self.inode(state.deref());
+        let ext = self.ext(state.deref());
+
+        Attr {
+            ino: inode.i_ino as u64,
+            size: inode.i_size,
+            blocks: ext.e_blocks,
+            mode: inode.i_mode as u32,
+            nlink: inode.i_nlink,
+            uid: inode.i_uid,
+            gid: inode.i_gid,
+            mtime: inode.i_mtime,
+            mtimensec: inode.i_mtime_nsec,
+            blksize: RAFS_INODE_BLOCKSIZE,
+            rdev: self.rdev(),
+            ..Default::default()
+        }
+    }
+
+    /// Get symlink target of the inode, inlined or in a data block.
+    fn get_symlink(&self) -> Result<OsString> {
+        let state = self.state();
+        let inode = self.inode(state.deref());
+
+        if !inode.is_symlink() {
+            return Err(einval!("inode is not a symlink"));
+        }
+        let symlink = if inode.data_layout() == EROFS_INODE_FLAT_INLINE {
+            let offset = self.offset + size_of::<RafsV6Inode>() + inode.xattr_size();
+            state.slice(offset, inode.inline_size())
+        } else {
+            let (offset, size) = self.data_range(state.deref())?;
+            state.slice(offset, size)
+        };
+
+        Ok(bytes_to_os_str(symlink).to_os_string())
+    }
+
+    /// Get the child with the specified name.
+    ///
+    /// Directory blocks are sorted by name, so binary search the block by the first entry name,
+    /// then binary search the entry in the block.
+    fn get_child_by_name(&self, name: &OsStr) -> Result<Arc<dyn RafsInode>> {
+        let state = self.state();
+        let inode = self.inode(state.deref());
+
+        if !inode.is_dir() {
+            return Err(einval!("inode is not a directory"));
+        }
+        if name == DOT || name == DOTDOT {
+            return Err(enoent!());
+        }
+
+        let (offset, size) = self.data_range(state.deref())?;
+        let data = state.slice(offset, size);
+        let blocks: Vec<&[u8]> = data.chunks(EROFS_BLOCK_SIZE as usize).collect();
+        let mut first = 0;
+        let mut last = blocks.len();
+
+        while first < last {
+            let pivot = first + ((last - first) >> 1);
+            let block = RafsV6DirBlock::new(blocks[pivot])?;
+            if block.entry(0)?.0 > name {
+                last = pivot;
+            } else {
+                first = pivot + 1;
+            }
+        }
+        if first == 0 {
+            return Err(enoent!());
+        }
+
+        let block = RafsV6DirBlock::new(blocks[first - 1])?;
+        if let Ok(idx) = block.lookup(name)? {
+            let (name, nid, _) = block.entry(idx)?;
+            return self.get_child(state.deref(), name, nid);
+        }
+
+        Err(enoent!())
+    }
+
+    /// Get the child with the specified index, "." and ".." are not counted.
+    fn get_child_by_index(&self, idx: Inode) -> Result<Arc<dyn RafsInode>> {
+        let state = self.state();
+        let inode = self.inode(state.deref());
+
+        if !inode.is_dir() {
+            return Err(einval!("inode is not a directory"));
+        }
+        if idx >= self.get_child_count() as u64 {
+            return Err(enoent!("invalid child index"));
+        }
+
+        let mut idx = idx as usize;
+        let mut child = None;
+        self.walk_dir_blocks(state.deref(), |block| {
+            let mut dots = Vec::new();
+            for dot in [DOT, DOTDOT].iter() {
+                if let Ok(pos) = block.lookup(OsStr::new(dot))? {
+                    dots.push(pos);
+                }
+            }
+
+            let count = block.len() - dots.len();
+            if idx >= count {
+                idx -= count;
+                return Ok(true);
+            }
+            for pos in dots {
+                if pos <= idx {
+                    idx += 1;
+                }
+            }
+            child = Some(block.entry(idx)?);
+
+            Ok(false)
+        })?;
+
+        match child {
+            Some((name, nid, _)) => self.get_child(state.deref(), name, nid),
+            None => Err(enoent!("invalid child index")),
+        }
+    }
+
+    fn get_child_index(&self) -> Result<u32> {
+        let state = self.state();
+        Ok(self.ext(state.deref()).e_child_index)
+    }
+
+    #[inline]
+    fn get_child_count(&self) -> u32 {
+        let state = self.state();
+        self.ext(state.deref()).e_child_count
+    }
+
+    /// Get chunk information with index `idx`
+    ///
+    /// # Safety
+    /// It depends on Self::validate() to ensure valid memory layout.
+    fn get_chunk_info(&self, idx: u32) -> Result<Arc<dyn RafsChunkInfo>> {
+        let state = self.state();
+        let inode = self.inode(state.deref());
+        let ext = self.ext(state.deref());
+
+        if !inode.is_reg() || ext.e_child_count == 0 || idx > ext.e_child_count - 1 {
+            return Err(enoent!("invalid chunk info"));
+        }
+
+        let offset = self.ext_offset(state.deref())
+            + size_of::<RafsV6InodeExt>()
+            + rafsv6_align(ext.e_name_size as usize)
+            + size_of::<RafsV5ChunkInfo>() * idx as usize;
+        let chunk = state.cast_to_ref::<RafsV5ChunkInfo>(offset)?;
+        let wrapper = DirectChunkInfoV6::new(chunk, self.mapping.clone(), offset);
+
+        Ok(Arc::new(wrapper))
+    }
+
+    fn has_xattr(&self) -> bool {
+        let state = self.state();
+        self.inode(state.deref()).i_xattr_icount > 0
+    }
+
+    fn get_xattr(&self, name: &OsStr) -> Result<Option<XattrValue>> {
+        let state = self.state();
+        let mut value = None;
+
+        parse_rafsv6_xattrs(self.xattr_data(state.deref()), |n, v| {
+            if n.as_slice() == name.as_bytes() {
+                value = Some(v.to_vec());
+                return false;
+            }
+            true
+        })?;
+
+        Ok(value)
+    }
+
+    fn get_xattrs(&self) -> Result<Vec<XattrName>> {
+        let state = self.state();
+        let mut names = Vec::new();
+
+        parse_rafsv6_xattrs(self.xattr_data(state.deref()), |n, _| {
+            names.push(n);
+            true
+        })?;
+
+        Ok(names)
+    }
+
+    fn is_dir(&self) -> bool {
+        let state = self.state();
+        self.inode(state.deref()).is_dir()
+    }
+
+    fn is_symlink(&self) -> bool {
+        let state = self.state();
+        self.inode(state.deref()).is_symlink()
+    }
+
+    fn is_reg(&self) -> bool {
+        let state = self.state();
+        self.inode(state.deref()).is_reg()
+    }
+
+    fn is_hardlink(&self) -> bool {
+        let state = self.state();
+        self.inode(state.deref()).i_nlink > 1
+    }
+
+    fn ino(&self) -> u64 {
+        let state = self.state();
+        self.inode(state.deref()).i_ino as u64
+    }
+
+    /// Get name of the inode.
+    ///
+    /// # Safety
+    /// It depends on Self::validate() to ensure valid memory layout.
+    fn name(&self) -> OsString {
+        let state = self.state();
+        self.name_ref(state.deref()).to_owned()
+    }
+
+    fn parent(&self) -> u64 {
+        if let Some(parent) = self.parent {
+            return parent;
+        }
+        let state = self.state();
+        self.ext(state.deref()).e_parent
+    }
+
+    fn rdev(&self) -> u32 {
+        let state = self.state();
+        let inode = self.inode(state.deref());
+
+        if inode.is_special() {
+            inode.i_u
+        } else {
+            0
+        }
+    }
+
+    fn flags(&self) -> u64 {
+        let state = self.state();
+        self.ext(state.deref()).e_flags.bits()
+    }
+
+    fn projid(&self) -> u32 {
+        let state = self.state();
+        self.ext(state.deref()).e_projid
+    }
+
+    fn size(&self) -> u64 {
+        let state = self.state();
+        self.inode(state.deref()).i_size
+    }
+
+    fn get_name_size(&self) -> u16 {
+        let state = self.state();
+        self.name_ref(state.deref()).len() as u16
+    }
+
+    fn get_symlink_size(&self) -> u16 {
+        let state = self.state();
+        let inode = self.inode(state.deref());
+
+        if inode.is_symlink() {
+            inode.i_size as u16
+        } else {
+            0
+        }
+    }
+
+    fn get_digest(&self) -> RafsDigest {
+        let state = self.state();
+        self.ext(state.deref()).e_digest
+    }
+
+    fn collect_descendants_inodes(
+        &self,
+        descendants: &mut Vec<Arc<dyn RafsInode>>,
+    ) -> Result<usize> {
+        if !self.is_dir() {
+            return Err(enotdir!());
+        }
+
+        let state = self.state();
+        let mut child_dirs: Vec<Arc<dyn RafsInode>> = Vec::new();
+
+        self.walk_dir_blocks(state.deref(), |block| {
+            for idx in 0..block.len() {
+                let (name, nid, _) = block.entry(idx)?;
+                if name == DOT || name == DOTDOT {
+                    continue;
+                }
+                let child_inode = self.get_child(state.deref(), name, nid)?;
+                if child_inode.is_dir() {
+                    trace!("Got dir {:?}", child_inode.name());
+                    child_dirs.push(child_inode);
+                } else if !child_inode.is_empty_size() {
+                    descendants.push(child_inode);
+                }
+            }
+            Ok(true)
+        })?;
+
+        for d in child_dirs {
+            d.collect_descendants_inodes(descendants)?;
+        }
+
+        Ok(0)
+    }
+
+    fn alloc_bio_desc(&self, offset: u64, size: usize, user_io: bool) -> Result<RafsBioDesc> {
+        rafsv5_alloc_bio_desc(self, offset, size, user_io)
+    }
+}
+
+impl RafsV5InodeOps for OndiskInodeWrapper {
+    fn get_blob_by_index(&self, idx: u32) -> Result<Arc<RafsBlobEntry>> {
+        self.state().blob_table.get(idx)
+    }
+
+    fn get_blocksize(&self) -> u32 {
+        self.mapping.state.load().meta.block_size
+    }
+
+    fn has_hole(&self) -> bool {
+        let state = self.state();
+        self.ext(state.deref())
+            .e_flags
+            .contains(RafsV5InodeFlags::HAS_HOLE)
+    }
+
+    fn cast_ondisk(&self) -> Result<RafsV5Inode> {
+        let state = self.state();
+        let ext = self.ext(state.deref());
+        let attr = self.get_attr();
+
+        Ok(RafsV5Inode {
+            i_digest: ext.e_digest,
+            i_parent: self.parent(),
+            i_ino: attr.ino,
+            i_uid: attr.uid,
+            i_gid: attr.gid,
+            i_projid: ext.e_projid,
+            i_mode: attr.mode,
+            i_size: attr.size,
+            i_blocks: attr.blocks,
+            i_flags: ext.e_flags,
+            i_nlink: attr.nlink,
+            i_child_index: ext.e_child_index,
+            i_child_count: ext.e_child_count,
+            i_name_size: self.get_name_size(),
+            i_symlink_size: self.get_symlink_size(),
+            i_rdev: attr.rdev,
+            i_mtime_nsec: attr.mtimensec,
+            i_mtime: attr.mtime,
+            i_reserved: [0u8; 8],
+        })
+    }
+}
+
+pub struct DirectChunkInfoV6 {
+    mapping: DirectSuperBlockV6,
+    offset: usize,
+    digest: RafsDigest,
+}
+
+unsafe impl Send for DirectChunkInfoV6 {}
+unsafe impl Sync for DirectChunkInfoV6 {}
+
+// This is *direct* metadata mode in-memory chunk info object.
+impl DirectChunkInfoV6 {
+    #[inline]
+    fn new(chunk: &RafsV5ChunkInfo, mapping: DirectSuperBlockV6, offset: usize) -> Self {
+        Self {
+            mapping,
+            offset,
+            digest: chunk.block_id,
+        }
+    }
+
+    #[inline]
+    fn state(&self) -> Guard<Arc<DirectMappingState>> {
+        self.mapping.state.load()
+    }
+
+    /// Dereference the underlying RafsV5ChunkInfo object.
+    ///
+    /// # Safety
+    /// The DirectChunkInfoV6 could only be constructed from a valid RafsV5ChunkInfo pointer,
+    /// so it's safe to dereference the underlying RafsV5ChunkInfo object.
+    #[allow(clippy::cast_ptr_alignment)]
+    fn chunk<'a>(&self, state: &'a DirectMappingState) -> &'a RafsV5ChunkInfo {
+        unsafe {
+            let ptr = state.base.add(self.offset);
+            &*(ptr as *const RafsV5ChunkInfo)
+        }
+    }
+}
+
+impl RafsChunkInfo for DirectChunkInfoV6 {
+    fn block_id(&self) -> &RafsDigest {
+        &self.digest
+    }
+
+    fn is_compressed(&self) -> bool {
+        self.chunk(self.state().deref())
+            .flags
+            .contains(RafsChunkFlags::COMPRESSED)
+    }
+
+    fn is_hole(&self) -> bool {
+        self.chunk(self.state().deref())
+            .flags
+            .contains(RafsChunkFlags::HOLECHUNK)
+    }
+
+    impl_chunkinfo_getter!(blob_index, u32);
+    impl_chunkinfo_getter!(index, u32);
+    impl_chunkinfo_getter!(compress_offset, u64);
+    impl_chunkinfo_getter!(compress_size, u32);
+    impl_chunkinfo_getter!(decompress_offset, u64);
+    impl_chunkinfo_getter!(decompress_size, u32);
+    impl_chunkinfo_getter!(file_offset, u64);
+    impl_chunkinfo_getter!(flags, RafsChunkFlags);
+}
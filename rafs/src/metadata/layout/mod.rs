@@ -13,6 +13,7 @@ use fuse_backend_rs::abi::linux_abi::ROOT_ID;
 
 pub const RAFS_SUPER_VERSION_V4: u32 = 0x400;
 pub const RAFS_SUPER_VERSION_V5: u32 = 0x500;
+pub const RAFS_SUPER_VERSION_V6: u32 = 0x600;
 pub const RAFS_SUPER_MIN_VERSION: u32 = RAFS_SUPER_VERSION_V4;
 pub const RAFS_ROOT_INODE: u64 = ROOT_ID;
 
@@ -20,6 +21,7 @@ pub type XattrName = Vec<u8>;
 pub type XattrValue = Vec<u8>;
 
 pub mod v5;
+pub mod v6;
 
 #[macro_export]
 macro_rules! impl_bootstrap_converter {
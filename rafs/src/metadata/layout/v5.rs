@@ -381,11 +381,9 @@ impl RafsV5PrefetchTable {
         self.inodes.push(ino);
     }
 
+    /// Store the prefetch table, entries are prefetched in the order they're added when mounting
+    /// rafs, so builder should add them in the same order as file data dumped into blob.
     pub fn store(&mut self, w: &mut RafsIoWriter) -> Result<usize> {
-        // Sort prefetch table by inode index, hopefully, it can save time when mounting rafs
-        // Because file data is dumped in the order of inode index.
-        self.inodes.sort_unstable();
-
         let (_, data, _) = unsafe { self.inodes.align_to::<u8>() };
 
         w.write_all(data.as_ref())?;
@@ -731,7 +729,7 @@ impl RafsStore for RafsV5ExtBlobTable {
                 w.write_all(&entry.reserved2)?;
                 size += size_of::<u32>()
                     + entry.reserved1.len()
-                    + size_of::<u64>()
+                    + size_of::<u64>() * 2
                     + entry.reserved2.len();
                 Ok(())
             })?;
@@ -992,7 +990,7 @@ impl_bootstrap_converter!(RafsV5XAttrsTable);
 
 #[derive(Clone, Default)]
 pub struct RafsV5XAttrs {
-    pairs: HashMap<OsString, XattrValue>,
+    pub(crate) pairs: HashMap<OsString, XattrValue>,
 }
 
 impl RafsV5XAttrs {
@@ -1360,7 +1358,12 @@ pub mod tests {
             .open(tmp_file.as_path())
             .unwrap();
         let mut writer = Box::new(BufWriter::new(file)) as Box<dyn RafsIoWrite>;
-        table.store(&mut writer).unwrap();
+        let size = table.store(&mut writer).unwrap();
+        writer.flush().unwrap();
+
+        // The returned size should match bytes written
+        assert_eq!(size, table.size());
+        assert_eq!(size as u64, tmp_file.as_file().metadata().unwrap().len());
 
         // Load extended blob table
         let file = OpenOptions::new()
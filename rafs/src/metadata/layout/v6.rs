@@ -0,0 +1,1268 @@
+// Copyright (C) 2021 Alibaba Cloud. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! RAFS on disk layout structures.
+//!
+//! # RAFS File System Meta Data Format Version 6
+//! The V5 meta data format is private to nydusd, so a RAFS image can't be mounted without
+//! the userspace daemon. The RAFS File System Meta Data Format Version 6 (aka V6) lays out the
+//! meta data in the format of the in-kernel EROFS file system, so the same bootstrap could be
+//! mounted by nydusd and by the EROFS driver:
+//! 1) The first 1024 bytes are reserved, the EROFS superblock follows at offset 1024, and the RAFS
+//!    extended superblock follows the EROFS superblock for information EROFS doesn't care.
+//! 2) Each data blob is described by an EROFS device slot following the extended superblock.
+//! 3) Inodes are EROFS extended inodes, addressed by `nid`, the offset from the bootstrap
+//!    base in unit of 32 bytes. Inline xattrs follow the inode in EROFS format.
+//! 4) Regular files are chunk based, an EROFS chunk index maps each chunk of file data to the
+//!    device of its blob and its block address in the uncompressed blob.
+//! 5) A RAFS inode extension follows the inline xattrs and chunk indexes (or inline data), which
+//!    carries the digest, parent, file name and RAFS v5 chunk information of the inode. The EROFS
+//!    driver just ignores it.
+//! 6) Directory entries are stored in EROFS directory blocks, and hardlinks share the same inode.
+//! 7) A RAFS inode table follows the directory blocks, which maps inode numbers to `nid`s.
+//!    The prefetch table and blob tables follow it as in V5.
+//!
+//! Data blobs are accessed by chunk, so EROFS could read file data directly from uncompressed
+//! blobs, or from blob cache files, only if chunks are aligned to EROFS blocks.
+
+use std::cmp::Ordering;
+use std::convert::{TryFrom, TryInto};
+use std::ffi::OsStr;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::{Result, SeekFrom};
+use std::mem::size_of;
+use std::os::unix::ffi::OsStrExt;
+
+use nydus_utils::digest::{self, RafsDigest};
+use storage::compress;
+
+use crate::metadata::layout::v5::{RafsV5ChunkInfo, RafsV5InodeFlags, RafsV5XAttrs};
+use crate::metadata::layout::{bytes_to_os_str, XattrName, XattrValue, RAFS_SUPER_VERSION_V6};
+use crate::metadata::{Inode, RafsStore, RafsSuperFlags, RAFS_DEFAULT_BLOCK_SIZE};
+use crate::{impl_bootstrap_converter, impl_pub_getter_setter, RafsIoReader, RafsIoWriter};
+
+pub(crate) const RAFSV6_ALIGNMENT: usize = 8;
+
+/// Offset of the EROFS superblock, the first 1024 bytes are reserved for boot loaders.
+pub const EROFS_SUPER_OFFSET: u64 = 1024;
+/// Block size of RAFS v6 images, which is also the EROFS block size.
+pub const EROFS_BLOCK_SIZE: u64 = 1u64 << EROFS_BLOCK_BITS;
+/// Unit of `nid`, the address of EROFS inodes.
+pub const EROFS_INODE_SLOT_SIZE: u64 = 32;
+/// Block address of holes in chunk indexes.
+pub const EROFS_NULL_ADDR: u32 = u32::MAX;
+
+/// Inode data is stored in contiguous blocks from `raw_blkaddr`.
+pub const EROFS_INODE_FLAT_PLAIN: u16 = 0;
+/// Same as `EROFS_INODE_FLAT_PLAIN`, but the tail data is inlined after the inode.
+pub const EROFS_INODE_FLAT_INLINE: u16 = 2;
+/// Inode data is mapped by chunk indexes following the inode.
+pub const EROFS_INODE_CHUNK_BASED: u16 = 4;
+
+const EROFS_SUPER_MAGIC_V1: u32 = 0xE0F5_E1E2;
+const EROFS_BLOCK_BITS: u8 = 12;
+const EROFS_DEVICE_SLOT_SIZE: u64 = 128;
+const EROFS_FEATURE_INCOMPAT_CHUNKED_FILE: u32 = 0x0000_0004;
+const EROFS_FEATURE_INCOMPAT_DEVICE_TABLE: u32 = 0x0000_0008;
+const EROFS_INODE_LAYOUT_EXTENDED: u16 = 1;
+const EROFS_I_DATALAYOUT_BIT: u16 = 1;
+const EROFS_I_DATALAYOUT_MASK: u16 = 0x7;
+const EROFS_CHUNK_FORMAT_BLKBITS_MASK: u16 = 0x001f;
+const EROFS_CHUNK_FORMAT_INDEXES: u16 = 0x0020;
+
+// File types in EROFS directory entries, same as `fs_ftype` of Linux.
+const EROFS_FT_UNKNOWN: u8 = 0;
+const EROFS_FT_REG_FILE: u8 = 1;
+const EROFS_FT_DIR: u8 = 2;
+const EROFS_FT_CHRDEV: u8 = 3;
+const EROFS_FT_BLKDEV: u8 = 4;
+const EROFS_FT_FIFO: u8 = 5;
+const EROFS_FT_SOCK: u8 = 6;
+const EROFS_FT_SYMLINK: u8 = 7;
+
+// EROFS xattr name indexes and the name prefixes they stand for.
+const EROFS_XATTR_PREFIXES: [(u8, &str); 5] = [
+    (1, "user."),
+    (2, "system.posix_acl_access"),
+    (3, "system.posix_acl_default"),
+    (4, "trusted."),
+    (6, "security."),
+];
+
+/// Offset of the device table, following the EROFS superblock and the RAFS extended superblock.
+pub const RAFSV6_DEVICE_TABLE_OFFSET: u64 = RAFSV6_SUPERBLOCK_EXT_OFFSET + 256;
+
+const RAFSV6_SUPER_MAGIC: u32 = 0x5241_4653;
+const RAFSV6_SUPERBLOCK_EXT_OFFSET: u64 = EROFS_SUPER_OFFSET + 128;
+const RAFSV6_SUPERBLOCK_EXT_RESERVED_SIZE: usize = 256 - 72;
+
+/// EROFS superblock on disk data format, 128 bytes at `EROFS_SUPER_OFFSET`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct RafsV6SuperBlock {
+    /// EROFS super magic
+    s_magic: u32,
+    /// crc32c of the superblock, unused
+    s_checksum: u32,
+    /// compatible feature flags
+    s_feature_compat: u32,
+    /// block size is `1 << s_blkszbits`
+    s_blkszbits: u8,
+    /// number of 16-byte superblock extension slots, unused
+    s_extslots: u8,
+    /// nid of the root directory
+    s_root_nid: u16,
+    /// total number of valid inodes
+    s_inos: u64,
+    /// build time of the image
+    s_build_time: u64,
+    s_build_time_nsec: u32,
+    /// total number of blocks of the image
+    s_blocks: u32,
+    /// start block address of inodes, `nid` is relative to it
+    s_meta_blkaddr: u32,
+    /// start block address of shared xattrs, unused
+    s_xattr_blkaddr: u32,
+    s_uuid: [u8; 16],
+    s_volume_name: [u8; 16],
+    /// incompatible feature flags
+    s_feature_incompat: u32,
+    /// available compression algorithms, unused
+    s_u1: u16,
+    /// number of devices besides the primary one, aka. number of data blobs
+    s_extra_devices: u16,
+    /// start of the device table, in unit of device slots
+    s_devt_slotoff: u16,
+    s_reserved: [u8; 38],
+}
+
+impl RafsV6SuperBlock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn detect(&self) -> bool {
+        self.magic() == EROFS_SUPER_MAGIC_V1 && self.s_blkszbits == EROFS_BLOCK_BITS
+    }
+
+    pub fn validate(&self, meta_size: u64) -> Result<()> {
+        let blocks = self.blocks() as u64;
+        if !self.detect()
+            || self.meta_blkaddr() != 0
+            || blocks == 0
+            || blocks * EROFS_BLOCK_SIZE != meta_size
+            || (self.root_nid() as u64) * EROFS_INODE_SLOT_SIZE >= meta_size
+            || self.feature_incompat()
+                & !(EROFS_FEATURE_INCOMPAT_CHUNKED_FILE | EROFS_FEATURE_INCOMPAT_DEVICE_TABLE)
+                != 0
+        {
+            return Err(einval!("invalid superblock"));
+        }
+
+        let devt_offset = self.devt_slotoff() as u64 * EROFS_DEVICE_SLOT_SIZE;
+        if self.extra_devices() > 0
+            && (devt_offset < RAFSV6_DEVICE_TABLE_OFFSET
+                || devt_offset + self.extra_devices() as u64 * EROFS_DEVICE_SLOT_SIZE > meta_size)
+        {
+            return Err(einval!("invalid device table"));
+        }
+
+        Ok(())
+    }
+
+    /// Record the data blobs as EROFS devices, whose slots start from `offset`.
+    pub fn set_devices(&mut self, count: u16, offset: u64) {
+        self.set_extra_devices(count);
+        self.set_devt_slotoff((offset / EROFS_DEVICE_SLOT_SIZE) as u16);
+        if count > 0 {
+            self.s_feature_incompat |= u32::to_le(EROFS_FEATURE_INCOMPAT_DEVICE_TABLE);
+        }
+    }
+
+    impl_pub_getter_setter!(magic, set_magic, s_magic, u32);
+    impl_pub_getter_setter!(root_nid, set_root_nid, s_root_nid, u16);
+    impl_pub_getter_setter!(inos, set_inos, s_inos, u64);
+    impl_pub_getter_setter!(blocks, set_blocks, s_blocks, u32);
+    impl_pub_getter_setter!(meta_blkaddr, set_meta_blkaddr, s_meta_blkaddr, u32);
+    impl_pub_getter_setter!(
+        feature_incompat,
+        set_feature_incompat,
+        s_feature_incompat,
+        u32
+    );
+    impl_pub_getter_setter!(extra_devices, set_extra_devices, s_extra_devices, u16);
+    impl_pub_getter_setter!(devt_slotoff, set_devt_slotoff, s_devt_slotoff, u16);
+
+    pub fn load(&mut self, r: &mut RafsIoReader) -> Result<()> {
+        r.seek(SeekFrom::Start(EROFS_SUPER_OFFSET))?;
+        r.read_exact(self.as_mut())
+    }
+}
+
+impl RafsStore for RafsV6SuperBlock {
+    fn store(&self, w: &mut RafsIoWriter) -> Result<usize> {
+        w.write_all(&[0u8; EROFS_SUPER_OFFSET as usize])?;
+        w.write_all(self.as_ref())?;
+        w.validate_alignment(
+            EROFS_SUPER_OFFSET as usize + self.as_ref().len(),
+            RAFSV6_ALIGNMENT,
+        )
+    }
+}
+
+impl_bootstrap_converter!(RafsV6SuperBlock);
+
+impl Default for RafsV6SuperBlock {
+    fn default() -> Self {
+        Self {
+            s_magic: u32::to_le(EROFS_SUPER_MAGIC_V1),
+            s_checksum: 0,
+            s_feature_compat: 0,
+            s_blkszbits: EROFS_BLOCK_BITS,
+            s_extslots: 0,
+            s_root_nid: 0,
+            s_inos: 0,
+            s_build_time: 0,
+            s_build_time_nsec: 0,
+            s_blocks: 0,
+            s_meta_blkaddr: 0,
+            s_xattr_blkaddr: 0,
+            s_uuid: [0u8; 16],
+            s_volume_name: [0u8; 16],
+            s_feature_incompat: u32::to_le(EROFS_FEATURE_INCOMPAT_CHUNKED_FILE),
+            s_u1: 0,
+            s_extra_devices: 0,
+            s_devt_slotoff: 0,
+            s_reserved: [0u8; 38],
+        }
+    }
+}
+
+impl Display for RafsV6SuperBlock {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "erofs superblock: magic {:x}, root_nid {}, inodes {}, blocks {}, devices {}",
+            self.magic(),
+            self.root_nid(),
+            self.inos(),
+            self.blocks(),
+            self.extra_devices()
+        )
+    }
+}
+
+/// RAFS extended superblock on disk data format, 256 bytes following the EROFS superblock.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct RafsV6SuperBlockExt {
+    /// RAFS super magic
+    s_magic: u32,
+    /// RAFS version
+    s_fs_version: u32,
+    /// superblock flags
+    s_flags: u64,
+    /// size of data chunks
+    s_chunk_size: u32,
+    /// number of entries of the inode table
+    s_inode_table_entries: u32,
+    s_inode_table_offset: u64,
+    s_blob_table_offset: u64,
+    s_blob_table_size: u32,
+    s_extended_blob_table_entries: u32,
+    s_extended_blob_table_offset: u64,
+    s_prefetch_table_offset: u64,
+    s_prefetch_table_entries: u32,
+    s_reserved_pad: u32, // 72 bytes
+    /// Unused area
+    s_reserved: [u8; RAFSV6_SUPERBLOCK_EXT_RESERVED_SIZE],
+}
+
+impl RafsV6SuperBlockExt {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn detect(&self) -> bool {
+        self.magic() == RAFSV6_SUPER_MAGIC && self.version() == RAFS_SUPER_VERSION_V6
+    }
+
+    pub fn validate(&self, meta_size: u64) -> Result<()> {
+        let chunk_size = self.chunk_size() as u64;
+        if !self.detect()
+            || !chunk_size.is_power_of_two()
+            || !(EROFS_BLOCK_SIZE..=EROFS_BLOCK_SIZE << EROFS_CHUNK_FORMAT_BLKBITS_MASK)
+                .contains(&chunk_size)
+        {
+            return Err(einval!("invalid extended superblock"));
+        }
+
+        let tables = [
+            (
+                self.inode_table_offset(),
+                self.inode_table_entries() as u64 * size_of::<u32>() as u64,
+            ),
+            (self.blob_table_offset(), self.blob_table_size() as u64),
+            (
+                self.prefetch_table_offset(),
+                self.prefetch_table_entries() as u64 * size_of::<u32>() as u64,
+            ),
+        ];
+        for (offset, size) in tables.iter() {
+            if *size > 0
+                && (*offset < RAFSV6_SUPERBLOCK_EXT_OFFSET
+                    || offset & (RAFSV6_ALIGNMENT as u64 - 1) != 0
+                    || offset.checked_add(*size).map(|end| end > meta_size) != Some(false))
+            {
+                return Err(einval!("invalid extended superblock"));
+            }
+        }
+        if self.inode_table_entries() == 0 {
+            return Err(einval!("invalid extended superblock"));
+        }
+
+        Ok(())
+    }
+
+    pub fn set_compressor(&mut self, compressor: compress::Algorithm) {
+        let c: RafsSuperFlags = compressor.into();
+        self.s_flags |= c.bits();
+    }
+
+    pub fn set_digester(&mut self, digester: digest::Algorithm) {
+        let c: RafsSuperFlags = digester.into();
+        self.s_flags |= c.bits();
+    }
+
+    pub fn set_explicit_uidgid(&mut self) {
+        self.s_flags |= RafsSuperFlags::EXPLICIT_UID_GID.bits();
+    }
+
+    pub fn set_has_xattr(&mut self) {
+        self.s_flags |= RafsSuperFlags::HAS_XATTR.bits();
+    }
+
+    impl_pub_getter_setter!(magic, set_magic, s_magic, u32);
+    impl_pub_getter_setter!(version, set_version, s_fs_version, u32);
+    impl_pub_getter_setter!(flags, set_flags, s_flags, u64);
+    impl_pub_getter_setter!(chunk_size, set_chunk_size, s_chunk_size, u32);
+    impl_pub_getter_setter!(
+        inode_table_entries,
+        set_inode_table_entries,
+        s_inode_table_entries,
+        u32
+    );
+    impl_pub_getter_setter!(
+        inode_table_offset,
+        set_inode_table_offset,
+        s_inode_table_offset,
+        u64
+    );
+    impl_pub_getter_setter!(blob_table_size, set_blob_table_size, s_blob_table_size, u32);
+    impl_pub_getter_setter!(
+        blob_table_offset,
+        set_blob_table_offset,
+        s_blob_table_offset,
+        u64
+    );
+    impl_pub_getter_setter!(
+        extended_blob_table_offset,
+        set_extended_blob_table_offset,
+        s_extended_blob_table_offset,
+        u64
+    );
+    impl_pub_getter_setter!(
+        extended_blob_table_entries,
+        set_extended_blob_table_entries,
+        s_extended_blob_table_entries,
+        u32
+    );
+    impl_pub_getter_setter!(
+        prefetch_table_offset,
+        set_prefetch_table_offset,
+        s_prefetch_table_offset,
+        u64
+    );
+    impl_pub_getter_setter!(
+        prefetch_table_entries,
+        set_prefetch_table_entries,
+        s_prefetch_table_entries,
+        u32
+    );
+
+    /// Load the extended superblock, which must be called right after `RafsV6SuperBlock::load()`.
+    pub fn load(&mut self, r: &mut RafsIoReader) -> Result<()> {
+        r.read_exact(self.as_mut())
+    }
+}
+
+impl RafsStore for RafsV6SuperBlockExt {
+    fn store(&self, w: &mut RafsIoWriter) -> Result<usize> {
+        w.write_all(self.as_ref())?;
+        w.validate_alignment(self.as_ref().len(), RAFSV6_ALIGNMENT)
+    }
+}
+
+impl_bootstrap_converter!(RafsV6SuperBlockExt);
+
+impl Default for RafsV6SuperBlockExt {
+    fn default() -> Self {
+        Self {
+            s_magic: u32::to_le(RAFSV6_SUPER_MAGIC),
+            s_fs_version: u32::to_le(RAFS_SUPER_VERSION_V6),
+            s_flags: 0,
+            s_chunk_size: u32::to_le(RAFS_DEFAULT_BLOCK_SIZE as u32),
+            s_inode_table_entries: 0,
+            s_inode_table_offset: 0,
+            s_blob_table_offset: 0,
+            s_blob_table_size: 0,
+            s_extended_blob_table_entries: 0,
+            s_extended_blob_table_offset: 0,
+            s_prefetch_table_offset: 0,
+            s_prefetch_table_entries: 0,
+            s_reserved_pad: 0,
+            s_reserved: [0u8; RAFSV6_SUPERBLOCK_EXT_RESERVED_SIZE],
+        }
+    }
+}
+
+impl Display for RafsV6SuperBlockExt {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "extended superblock: magic {:x}, version {:x}, flags {:x}, chunk_size {:x}, inode_table_entries {}",
+            self.magic(),
+            self.version(),
+            self.flags(),
+            self.chunk_size(),
+            self.inode_table_entries()
+        )
+    }
+}
+
+/// EROFS device slot on disk data format, describing a data blob.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct RafsV6Device {
+    /// blob id
+    pub d_tag: [u8; 64],
+    /// size of the uncompressed blob in blocks
+    pub d_blocks: u32,
+    /// unused since devices are not flattened into a single address space
+    pub d_mapped_blkaddr: u32,
+    pub d_reserved: [u8; 56],
+}
+
+impl RafsV6Device {
+    pub fn new(blob_id: &str, blob_cache_size: u64) -> Result<Self> {
+        let id = blob_id.as_bytes();
+        if id.len() > 64 {
+            return Err(einval!(format!("blob id {} is too long", blob_id)));
+        }
+        let blocks = (blob_cache_size + EROFS_BLOCK_SIZE - 1) / EROFS_BLOCK_SIZE;
+        let blocks = u32::try_from(blocks).map_err(|_| einval!("blob is too large"))?;
+
+        let mut dev = Self::default();
+        dev.d_tag[..id.len()].copy_from_slice(id);
+        dev.d_blocks = u32::to_le(blocks);
+
+        Ok(dev)
+    }
+}
+
+impl RafsStore for RafsV6Device {
+    fn store(&self, w: &mut RafsIoWriter) -> Result<usize> {
+        w.write_all(self.as_ref())?;
+        w.validate_alignment(self.as_ref().len(), RAFSV6_ALIGNMENT)
+    }
+}
+
+impl_bootstrap_converter!(RafsV6Device);
+
+impl Default for RafsV6Device {
+    fn default() -> Self {
+        Self {
+            d_tag: [0u8; 64],
+            d_blocks: 0,
+            d_mapped_blkaddr: 0,
+            d_reserved: [0u8; 56],
+        }
+    }
+}
+
+/// Rafs v6 inode on disk layout, which is the EROFS extended inode, 64 bytes.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct RafsV6Inode {
+    /// inode layout and data layout, EROFS_INODE_*
+    pub i_format: u16,
+    /// size of inline xattrs, see `RafsV6XAttrs::icount()`
+    pub i_xattr_icount: u16,
+    pub i_mode: u16,
+    pub i_reserved: u16,
+    pub i_size: u64,
+    /// start block of flat inodes, rdev of special files or chunk format of chunk based inodes
+    pub i_u: u32,
+    /// inode number, ignored by EROFS which identifies inodes by nid
+    pub i_ino: u32,
+    pub i_uid: u32,
+    pub i_gid: u32,
+    pub i_mtime: u64,
+    pub i_mtime_nsec: u32,
+    pub i_nlink: u32,
+    pub i_reserved2: [u8; 16],
+}
+
+impl RafsV6Inode {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn set_data_layout(&mut self, layout: u16) {
+        self.i_format = EROFS_INODE_LAYOUT_EXTENDED | (layout << EROFS_I_DATALAYOUT_BIT);
+    }
+
+    #[inline]
+    pub fn data_layout(&self) -> u16 {
+        (self.i_format >> EROFS_I_DATALAYOUT_BIT) & EROFS_I_DATALAYOUT_MASK
+    }
+
+    /// Set chunk format of chunk based inodes.
+    pub fn set_chunk_size(&mut self, chunk_size: u64) -> Result<()> {
+        if !chunk_size.is_power_of_two() || chunk_size < EROFS_BLOCK_SIZE {
+            return Err(einval!(format!("invalid chunk size {:x}", chunk_size)));
+        }
+        let bits = chunk_size.trailing_zeros() - EROFS_BLOCK_BITS as u32;
+        if bits > EROFS_CHUNK_FORMAT_BLKBITS_MASK as u32 {
+            return Err(einval!(format!("invalid chunk size {:x}", chunk_size)));
+        }
+        self.i_u = (EROFS_CHUNK_FORMAT_INDEXES | bits as u16) as u32;
+
+        Ok(())
+    }
+
+    /// Get size of data chunks of chunk based inodes.
+    #[inline]
+    pub fn chunk_size(&self) -> u64 {
+        EROFS_BLOCK_SIZE << (self.i_u as u16 & EROFS_CHUNK_FORMAT_BLKBITS_MASK)
+    }
+
+    /// Get number of chunk indexes of chunk based inodes.
+    #[inline]
+    pub fn chunk_index_count(&self) -> usize {
+        let chunk_size = self.chunk_size();
+        ((self.i_size + chunk_size - 1) / chunk_size) as usize
+    }
+
+    /// Get size of inline xattrs.
+    #[inline]
+    pub fn xattr_size(&self) -> usize {
+        if self.i_xattr_icount == 0 {
+            0
+        } else {
+            size_of::<RafsV6XAttrIbodyHeader>()
+                + (self.i_xattr_icount as usize - 1) * size_of::<RafsV6XAttrEntry>()
+        }
+    }
+
+    /// Get size of data inlined after the inode and xattrs.
+    #[inline]
+    pub fn inline_size(&self) -> usize {
+        if self.data_layout() == EROFS_INODE_FLAT_INLINE {
+            (self.i_size % EROFS_BLOCK_SIZE) as usize
+        } else {
+            0
+        }
+    }
+
+    /// Get offset of the RAFS inode extension from the inode.
+    pub fn ext_offset(&self) -> usize {
+        let size = size_of::<Self>() + self.xattr_size();
+        let size = match self.data_layout() {
+            EROFS_INODE_CHUNK_BASED => {
+                rafsv6_align(size) + self.chunk_index_count() * size_of::<RafsV6InodeChunkIndex>()
+            }
+            _ => size + self.inline_size(),
+        };
+
+        rafsv6_align(size)
+    }
+
+    #[inline]
+    pub fn is_dir(&self) -> bool {
+        self.i_mode as u32 & libc::S_IFMT == libc::S_IFDIR
+    }
+
+    #[inline]
+    pub fn is_symlink(&self) -> bool {
+        self.i_mode as u32 & libc::S_IFMT == libc::S_IFLNK
+    }
+
+    #[inline]
+    pub fn is_reg(&self) -> bool {
+        self.i_mode as u32 & libc::S_IFMT == libc::S_IFREG
+    }
+
+    #[inline]
+    pub fn is_special(&self) -> bool {
+        !self.is_dir() && !self.is_reg() && !self.is_symlink()
+    }
+}
+
+impl_bootstrap_converter!(RafsV6Inode);
+
+impl RafsStore for RafsV6Inode {
+    fn store(&self, w: &mut RafsIoWriter) -> Result<usize> {
+        w.write_all(self.as_ref())?;
+        w.validate_alignment(self.as_ref().len(), RAFSV6_ALIGNMENT)
+    }
+}
+
+/// Rafs v6 inode extension on disk layout, 80 bytes.
+///
+/// It's followed by the file name, and chunk information entries of regular files.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct RafsV6InodeExt {
+    /// sha256(sha256(chunk) + ...), same as `RafsV5Inode::i_digest`
+    pub e_digest: RafsDigest, // 32
+    /// parent inode number
+    pub e_parent: u64,
+    /// from fs stat()
+    pub e_blocks: u64,
+    pub e_flags: RafsV5InodeFlags,
+    /// for dir, child start index
+    pub e_child_index: u32,
+    /// for dir, means child count without "." and "..".
+    /// for regular file, means chunk info count.
+    pub e_child_count: u32,
+    pub e_projid: u32,
+    /// file name size, [char; e_name_size]
+    pub e_name_size: u16,
+    pub e_reserved: [u8; 10], // 80
+}
+
+impl RafsV6InodeExt {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get size of the extension, file name and chunk information entries.
+    #[inline]
+    pub fn size(&self, inode: &RafsV6Inode) -> usize {
+        let chunks = if inode.is_reg() {
+            self.e_child_count as usize * size_of::<RafsV5ChunkInfo>()
+        } else {
+            0
+        };
+
+        size_of::<Self>() + rafsv6_align(self.e_name_size as usize) + chunks
+    }
+}
+
+impl_bootstrap_converter!(RafsV6InodeExt);
+
+impl RafsStore for RafsV6InodeExt {
+    fn store(&self, w: &mut RafsIoWriter) -> Result<usize> {
+        w.write_all(self.as_ref())?;
+        w.validate_alignment(self.as_ref().len(), RAFSV6_ALIGNMENT)
+    }
+}
+
+/// EROFS chunk index on disk layout, mapping a data chunk to a block of a device.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct RafsV6InodeChunkIndex {
+    /// unused
+    pub c_advise: u16,
+    /// 0 for the bootstrap, `blob_index + 1` for data blobs
+    pub c_device_id: u16,
+    /// start block address of the chunk in the uncompressed blob, `EROFS_NULL_ADDR` for holes
+    pub c_blkaddr: u32,
+}
+
+impl RafsV6InodeChunkIndex {
+    pub fn new(blob_index: u32, decompress_offset: u64) -> Result<Self> {
+        if decompress_offset & (EROFS_BLOCK_SIZE - 1) != 0 {
+            return Err(einval!(format!(
+                "chunk decompress offset {:x} isn't aligned to block size",
+                decompress_offset
+            )));
+        }
+        let device_id = u16::try_from(blob_index + 1).map_err(|_| einval!("too many blobs"))?;
+        let blkaddr = u32::try_from(decompress_offset / EROFS_BLOCK_SIZE)
+            .map_err(|_| einval!("chunk decompress offset is too large"))?;
+
+        Ok(Self {
+            c_advise: 0,
+            c_device_id: u16::to_le(device_id),
+            c_blkaddr: u32::to_le(blkaddr),
+        })
+    }
+
+    pub fn hole() -> Self {
+        Self {
+            c_advise: 0,
+            c_device_id: 0,
+            c_blkaddr: u32::to_le(EROFS_NULL_ADDR),
+        }
+    }
+}
+
+impl_bootstrap_converter!(RafsV6InodeChunkIndex);
+
+impl RafsStore for RafsV6InodeChunkIndex {
+    fn store(&self, w: &mut RafsIoWriter) -> Result<usize> {
+        w.write_all(self.as_ref())?;
+        Ok(self.as_ref().len())
+    }
+}
+
+/// EROFS directory entry on disk layout, 12 bytes.
+///
+/// A directory block starts with entries sorted by name, which are followed by the names. The
+/// number of entries in a block is told by `e_nameoff` of the first entry, and names are not
+/// NUL terminated except the last one of a partial block.
+#[repr(C, packed)]
+#[derive(Clone, Copy, Default)]
+pub struct RafsV6Dirent {
+    /// nid of the target inode
+    pub e_nid: u64,
+    /// start offset of the name in the block
+    pub e_nameoff: u16,
+    /// file type, EROFS_FT_*
+    pub e_file_type: u8,
+    pub e_reserved: u8,
+}
+
+impl_bootstrap_converter!(RafsV6Dirent);
+
+/// Get EROFS file type of directory entries from inode mode.
+pub fn rafsv6_file_type(mode: u32) -> u8 {
+    match mode & libc::S_IFMT {
+        libc::S_IFREG => EROFS_FT_REG_FILE,
+        libc::S_IFDIR => EROFS_FT_DIR,
+        libc::S_IFCHR => EROFS_FT_CHRDEV,
+        libc::S_IFBLK => EROFS_FT_BLKDEV,
+        libc::S_IFIFO => EROFS_FT_FIFO,
+        libc::S_IFSOCK => EROFS_FT_SOCK,
+        libc::S_IFLNK => EROFS_FT_SYMLINK,
+        _ => EROFS_FT_UNKNOWN,
+    }
+}
+
+/// Pack directory entries of (name, nid, file type) into EROFS directory blocks.
+///
+/// Entries must be sorted by name, including "." and "..". Only the last block is not padded, so
+/// the size of the packed data is the size of the directory.
+pub fn rafsv6_pack_dirents(entries: &[(&OsStr, u64, u8)]) -> Result<Vec<u8>> {
+    let block_size = EROFS_BLOCK_SIZE as usize;
+    let dirent_size = size_of::<RafsV6Dirent>();
+    let mut data = Vec::new();
+    let mut start = 0;
+
+    while start < entries.len() {
+        let mut end = start;
+        let mut used = 0;
+        while end < entries.len() {
+            let name_size = entries[end].0.len();
+            if name_size == 0 || name_size > block_size - dirent_size {
+                return Err(einval!("invalid directory entry name"));
+            }
+            if used + dirent_size + name_size > block_size {
+                break;
+            }
+            used += dirent_size + name_size;
+            end += 1;
+        }
+
+        let block_start = data.len();
+        let mut nameoff = (end - start) * dirent_size;
+        for (name, nid, file_type) in &entries[start..end] {
+            let dirent = RafsV6Dirent {
+                e_nid: u64::to_le(*nid),
+                e_nameoff: u16::to_le(nameoff as u16),
+                e_file_type: *file_type,
+                e_reserved: 0,
+            };
+            data.extend_from_slice(dirent.as_ref());
+            nameoff += name.len();
+        }
+        for (name, _, _) in &entries[start..end] {
+            data.extend_from_slice(name.as_bytes());
+        }
+        if end < entries.len() {
+            data.resize(block_start + block_size, 0);
+        }
+        start = end;
+    }
+
+    Ok(data)
+}
+
+/// A parsed EROFS directory block.
+pub struct RafsV6DirBlock<'a> {
+    data: &'a [u8],
+    count: usize,
+}
+
+impl<'a> RafsV6DirBlock<'a> {
+    /// Parse a directory block, `data` is the whole block or the tail of the directory.
+    pub fn new(data: &'a [u8]) -> Result<Self> {
+        let dirent_size = size_of::<RafsV6Dirent>();
+        if data.len() < dirent_size || data.len() > EROFS_BLOCK_SIZE as usize {
+            return Err(einval!("invalid directory block size"));
+        }
+        let dirent: &RafsV6Dirent = (&data[..dirent_size]).try_into()?;
+        let nameoff = u16::from_le(dirent.e_nameoff) as usize;
+        if nameoff < dirent_size || nameoff % dirent_size != 0 || nameoff >= data.len() {
+            return Err(einval!("invalid directory block"));
+        }
+
+        Ok(Self {
+            data,
+            count: nameoff / dirent_size,
+        })
+    }
+
+    /// Get number of entries in the block.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Get the (name, nid, file type) of the entry with index `idx`.
+    pub fn entry(&self, idx: usize) -> Result<(&'a OsStr, u64, u8)> {
+        if idx >= self.count {
+            return Err(enoent!());
+        }
+        let dirent = self.dirent(idx)?;
+        let start = u16::from_le(dirent.e_nameoff) as usize;
+        let end = if idx + 1 < self.count {
+            u16::from_le(self.dirent(idx + 1)?.e_nameoff) as usize
+        } else {
+            // The last name is NUL terminated if the block isn't full.
+            let tail = &self.data[start.min(self.data.len())..];
+            start + tail.iter().position(|c| *c == 0).unwrap_or(tail.len())
+        };
+        if start >= end || end > self.data.len() {
+            return Err(einval!("invalid directory entry"));
+        }
+
+        Ok((
+            bytes_to_os_str(&self.data[start..end]),
+            u64::from_le(dirent.e_nid),
+            dirent.e_file_type,
+        ))
+    }
+
+    /// Binary search an entry by name, return the index of the entry.
+    pub fn lookup(&self, name: &OsStr) -> Result<std::result::Result<usize, usize>> {
+        let mut first = 0;
+        let mut last = self.count;
+
+        while first < last {
+            let pivot = first + ((last - first) >> 1);
+            match self.entry(pivot)?.0.cmp(name) {
+                Ordering::Equal => return Ok(Ok(pivot)),
+                Ordering::Greater => last = pivot,
+                Ordering::Less => first = pivot + 1,
+            }
+        }
+
+        Ok(Err(first))
+    }
+
+    fn dirent(&self, idx: usize) -> Result<&'a RafsV6Dirent> {
+        let dirent_size = size_of::<RafsV6Dirent>();
+        let start = idx * dirent_size;
+        (&self.data[start..start + dirent_size]).try_into()
+    }
+}
+
+/// EROFS inline xattr header on disk layout, 12 bytes.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct RafsV6XAttrIbodyHeader {
+    pub h_reserved: u32,
+    /// number of shared xattrs, always 0
+    pub h_shared_count: u8,
+    pub h_reserved2: [u8; 7],
+}
+
+impl_bootstrap_converter!(RafsV6XAttrIbodyHeader);
+
+/// EROFS xattr entry on disk layout, 4 bytes, followed by the name suffix and the value.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct RafsV6XAttrEntry {
+    /// size of the name suffix
+    pub e_name_len: u8,
+    /// name prefix index, see `EROFS_XATTR_PREFIXES`
+    pub e_name_index: u8,
+    pub e_value_size: u16,
+}
+
+impl_bootstrap_converter!(RafsV6XAttrEntry);
+
+/// Inline xattrs of an inode in EROFS format.
+#[derive(Clone, Default)]
+pub struct RafsV6XAttrs {
+    entries: Vec<(u8, Vec<u8>, XattrValue)>,
+}
+
+impl RafsV6XAttrs {
+    /// Convert RAFS v5 xattrs, names without a prefix supported by EROFS are rejected.
+    pub fn new(xattrs: &RafsV5XAttrs) -> Result<Self> {
+        let mut entries = Vec::new();
+
+        for (name, value) in xattrs.pairs.iter() {
+            let name = name.as_bytes();
+            let (index, prefix) = EROFS_XATTR_PREFIXES
+                .iter()
+                .find(|(_, prefix)| name.starts_with(prefix.as_bytes()))
+                .ok_or_else(|| {
+                    einval!(format!(
+                        "xattr {} isn't supported",
+                        String::from_utf8_lossy(name)
+                    ))
+                })?;
+            let suffix = &name[prefix.len()..];
+            if suffix.len() > u8::MAX as usize || value.len() > u16::MAX as usize {
+                return Err(einval!("xattr is too large"));
+            }
+            entries.push((*index, suffix.to_vec(), value.clone()));
+        }
+        entries.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+
+        let xattrs = Self { entries };
+        if xattrs.size() > (u16::MAX as usize) * size_of::<RafsV6XAttrEntry>() {
+            return Err(einval!("xattrs are too large"));
+        }
+
+        Ok(xattrs)
+    }
+
+    /// Get size of the inline xattrs.
+    pub fn size(&self) -> usize {
+        if self.entries.is_empty() {
+            return 0;
+        }
+
+        let entries: usize = self
+            .entries
+            .iter()
+            .map(|(_, suffix, value)| {
+                rafsv6_xattr_align(size_of::<RafsV6XAttrEntry>() + suffix.len() + value.len())
+            })
+            .sum();
+
+        size_of::<RafsV6XAttrIbodyHeader>() + entries
+    }
+
+    /// Get `i_xattr_icount` of the inode.
+    pub fn icount(&self) -> u16 {
+        let size = self.size();
+        if size == 0 {
+            0
+        } else {
+            ((size - size_of::<RafsV6XAttrIbodyHeader>()) / size_of::<RafsV6XAttrEntry>() + 1)
+                as u16
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl RafsStore for RafsV6XAttrs {
+    fn store(&self, w: &mut RafsIoWriter) -> Result<usize> {
+        if self.entries.is_empty() {
+            return Ok(0);
+        }
+
+        let header = RafsV6XAttrIbodyHeader::default();
+        w.write_all(header.as_ref())?;
+        let mut size = header.as_ref().len();
+
+        for (index, suffix, value) in self.entries.iter() {
+            let entry = RafsV6XAttrEntry {
+                e_name_len: suffix.len() as u8,
+                e_name_index: *index,
+                e_value_size: u16::to_le(value.len() as u16),
+            };
+            w.write_all(entry.as_ref())?;
+            w.write_all(suffix)?;
+            w.write_all(value)?;
+
+            let entry_size = entry.as_ref().len() + suffix.len() + value.len();
+            let padding = rafsv6_xattr_align(entry_size) - entry_size;
+            w.write_padding(padding)?;
+            size += entry_size + padding;
+        }
+
+        Ok(size)
+    }
+}
+
+/// Parse inline xattrs of an inode and invoke the callback for each xattr pair.
+///
+/// The iteration breaks if the callback returns false.
+pub fn parse_rafsv6_xattrs<F>(data: &[u8], mut cb: F) -> Result<()>
+where
+    F: FnMut(XattrName, &[u8]) -> bool,
+{
+    if data.is_empty() {
+        return Ok(());
+    }
+    if data.len() < size_of::<RafsV6XAttrIbodyHeader>() {
+        return Err(einval!("invalid xattr content size"));
+    }
+
+    let mut offset = size_of::<RafsV6XAttrIbodyHeader>();
+    while offset < data.len() {
+        let entry_size = size_of::<RafsV6XAttrEntry>();
+        if offset + entry_size > data.len() {
+            return Err(einval!("invalid xattr entry"));
+        }
+        let entry: &RafsV6XAttrEntry = (&data[offset..offset + entry_size]).try_into()?;
+        let name_start = offset + entry_size;
+        let value_start = name_start + entry.e_name_len as usize;
+        let value_end = value_start + u16::from_le(entry.e_value_size) as usize;
+        if value_end > data.len() {
+            return Err(einval!("invalid xattr entry"));
+        }
+        let prefix = EROFS_XATTR_PREFIXES
+            .iter()
+            .find(|(index, _)| *index == entry.e_name_index)
+            .map(|(_, prefix)| *prefix)
+            .ok_or_else(|| einval!("invalid xattr name index"))?;
+
+        let mut name = prefix.as_bytes().to_vec();
+        name.extend_from_slice(&data[name_start..value_start]);
+        if !cb(name, &data[value_start..value_end]) {
+            break;
+        }
+
+        offset += rafsv6_xattr_align(value_end - offset);
+    }
+
+    Ok(())
+}
+
+/// Rafs v6 inode table, mapping inode numbers to nids.
+#[derive(Clone, Default)]
+pub struct RafsV6InodeTable {
+    pub data: Vec<u32>,
+}
+
+impl RafsV6InodeTable {
+    pub fn new(entries: usize) -> Self {
+        let table_size = rafsv6_align(entries * size_of::<u32>()) / size_of::<u32>();
+        RafsV6InodeTable {
+            data: vec![0; table_size],
+        }
+    }
+
+    #[inline]
+    pub fn size(&self) -> usize {
+        rafsv6_align(self.data.len() * size_of::<u32>())
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn set(&mut self, ino: Inode, nid: u64) -> Result<()> {
+        if ino == 0 || ino > self.data.len() as u64 {
+            return Err(einval!("invalid inode number"));
+        } else if nid * EROFS_INODE_SLOT_SIZE < RAFSV6_DEVICE_TABLE_OFFSET || nid > u32::MAX as u64
+        {
+            return Err(einval!("invalid inode nid"));
+        }
+
+        self.data[(ino - 1) as usize] = u32::to_le(nid as u32);
+
+        Ok(())
+    }
+
+    pub fn get(&self, ino: Inode) -> Result<u64> {
+        if ino == 0 || ino > self.data.len() as u64 {
+            return Err(enoent!());
+        }
+
+        let nid = u32::from_le(self.data[(ino - 1) as usize]) as u64;
+        if nid * EROFS_INODE_SLOT_SIZE < RAFSV6_DEVICE_TABLE_OFFSET {
+            return Err(einval!("invalid inode nid"));
+        }
+
+        Ok(nid)
+    }
+}
+
+impl RafsStore for RafsV6InodeTable {
+    fn store(&self, w: &mut RafsIoWriter) -> Result<usize> {
+        let (_, data, _) = unsafe { self.data.align_to::<u8>() };
+
+        w.write_all(data)?;
+        w.validate_alignment(data.len(), RAFSV6_ALIGNMENT)
+    }
+}
+
+pub fn rafsv6_align(size: usize) -> usize {
+    if size & (RAFSV6_ALIGNMENT - 1) == 0 {
+        size
+    } else {
+        size + (RAFSV6_ALIGNMENT - (size & (RAFSV6_ALIGNMENT - 1)))
+    }
+}
+
+fn rafsv6_xattr_align(size: usize) -> usize {
+    let align = size_of::<RafsV6XAttrEntry>();
+    (size + align - 1) / align * align
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsString;
+
+    #[test]
+    fn test_rafsv6_layout_size() {
+        assert_eq!(size_of::<RafsV6SuperBlock>(), 128);
+        assert_eq!(size_of::<RafsV6SuperBlockExt>(), 256);
+        assert_eq!(size_of::<RafsV6Device>(), 128);
+        assert_eq!(size_of::<RafsV6Inode>(), 64);
+        assert_eq!(size_of::<RafsV6InodeExt>(), 80);
+        assert_eq!(size_of::<RafsV6InodeChunkIndex>(), 8);
+        assert_eq!(size_of::<RafsV6Dirent>(), 12);
+        assert_eq!(size_of::<RafsV6XAttrIbodyHeader>(), 12);
+        assert_eq!(size_of::<RafsV6XAttrEntry>(), 4);
+        assert_eq!(RAFSV6_DEVICE_TABLE_OFFSET, 1408);
+    }
+
+    #[test]
+    fn test_rafsv6_inode_ext_offset() {
+        let mut inode = RafsV6Inode::new();
+        inode.i_mode = libc::S_IFREG as u16 | 0o644;
+        inode.i_size = (1 << 20) * 2 + 1;
+        inode.set_data_layout(EROFS_INODE_CHUNK_BASED);
+        inode.set_chunk_size(1 << 20).unwrap();
+        assert_eq!(inode.chunk_size(), 1 << 20);
+        assert_eq!(inode.chunk_index_count(), 3);
+        assert_eq!(inode.ext_offset(), 64 + 3 * 8);
+        assert!(inode.set_chunk_size(1000).is_err());
+
+        // 12 bytes header and one 4 bytes entry.
+        inode.i_xattr_icount = 2;
+        assert_eq!(inode.xattr_size(), 16);
+        assert_eq!(inode.ext_offset(), 80 + 3 * 8);
+
+        let mut inode = RafsV6Inode::new();
+        inode.i_mode = libc::S_IFLNK as u16 | 0o777;
+        inode.i_size = 5;
+        inode.set_data_layout(EROFS_INODE_FLAT_INLINE);
+        assert_eq!(inode.inline_size(), 5);
+        assert_eq!(inode.ext_offset(), 72);
+    }
+
+    #[test]
+    fn test_rafsv6_dirents() {
+        let names: Vec<OsString> = (0..400)
+            .map(|i| OsString::from(format!("file-{:04}", i)))
+            .collect();
+        let mut entries: Vec<(&OsStr, u64, u8)> = names
+            .iter()
+            .enumerate()
+            .map(|(i, n)| (n.as_os_str(), i as u64 + 100, EROFS_FT_REG_FILE))
+            .collect();
+        entries.push((OsStr::new("."), 1, EROFS_FT_DIR));
+        entries.push((OsStr::new(".."), 2, EROFS_FT_DIR));
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+
+        let data = rafsv6_pack_dirents(&entries).unwrap();
+        let block_size = EROFS_BLOCK_SIZE as usize;
+        // 21 bytes per entry, so 195 entries per block.
+        assert_eq!(data.len(), block_size * 2 + 12 * 21);
+
+        let mut parsed = Vec::new();
+        for block in data.chunks(block_size) {
+            let block = RafsV6DirBlock::new(block).unwrap();
+            for idx in 0..block.len() {
+                parsed.push(block.entry(idx).unwrap());
+            }
+        }
+        assert_eq!(parsed, entries);
+
+        let block = RafsV6DirBlock::new(&data[..block_size]).unwrap();
+        assert_eq!(block.lookup(OsStr::new("..")).unwrap(), Ok(1));
+        assert_eq!(block.lookup(OsStr::new("file-0010")).unwrap(), Ok(12));
+        assert_eq!(block.lookup(OsStr::new("file-0010a")).unwrap(), Err(13));
+        assert!(block.entry(block.len()).is_err());
+
+        assert!(rafsv6_pack_dirents(&[(OsStr::new(""), 1, EROFS_FT_DIR)]).is_err());
+        assert!(RafsV6DirBlock::new(&[0u8; 12]).is_err());
+    }
+
+    #[test]
+    fn test_rafsv6_xattrs() {
+        let mut v5 = RafsV5XAttrs::new();
+        v5.add(OsString::from("user.a"), vec![1, 2, 3]);
+        v5.add(OsString::from("security.capability"), vec![4; 20]);
+        v5.add(OsString::from("system.posix_acl_access"), vec![5; 7]);
+
+        let xattrs = RafsV6XAttrs::new(&v5).unwrap();
+        // 12 + (4 + 1 + 3) + (4 + 0 + 7 + 1) + (4 + 10 + 20 + 2)
+        assert_eq!(xattrs.size(), 68);
+        assert_eq!(xattrs.icount(), 15);
+
+        let mut data = Vec::new();
+        let header = RafsV6XAttrIbodyHeader::default();
+        data.extend_from_slice(header.as_ref());
+        for (index, suffix, value) in xattrs.entries.iter() {
+            let entry = RafsV6XAttrEntry {
+                e_name_len: suffix.len() as u8,
+                e_name_index: *index,
+                e_value_size: value.len() as u16,
+            };
+            data.extend_from_slice(entry.as_ref());
+            data.extend_from_slice(suffix);
+            data.extend_from_slice(value);
+            data.resize(rafsv6_xattr_align(data.len()), 0);
+        }
+        assert_eq!(data.len(), xattrs.size());
+
+        let mut pairs = Vec::new();
+        parse_rafsv6_xattrs(&data, |name, value| {
+            pairs.push((name, value.to_vec()));
+            true
+        })
+        .unwrap();
+        assert_eq!(
+            pairs,
+            vec![
+                (b"user.a".to_vec(), vec![1, 2, 3]),
+                (b"system.posix_acl_access".to_vec(), vec![5; 7]),
+                (b"security.capability".to_vec(), vec![4; 20]),
+            ]
+        );
+        assert!(parse_rafsv6_xattrs(&data[..data.len() - 3], |_, _| true).is_err());
+
+        v5.add(OsString::from("lustre.a"), vec![]);
+        assert!(RafsV6XAttrs::new(&v5).is_err());
+    }
+}
@@ -8,7 +8,7 @@
 use std::collections::HashSet;
 use std::ffi::{OsStr, OsString};
 use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
-use std::io::{Error, Result};
+use std::io::{Error, Result, SeekFrom};
 use std::os::unix::ffi::OsStrExt;
 use std::path::{Component, Path, PathBuf};
 use std::str::FromStr;
@@ -22,18 +22,23 @@ use fuse_backend_rs::abi::linux_abi::Attr;
 use fuse_backend_rs::api::filesystem::{Entry, ROOT_ID};
 use nydus_utils::digest::{self, RafsDigest};
 use storage::compress;
-use storage::device::{RafsBioDesc, RafsBlobEntry, RafsChunkInfo};
+use storage::device::{RafsBio, RafsBioDesc, RafsBlobEntry, RafsChunkInfo, RafsChunkKey};
 
 use self::cached_v5::CachedSuperBlockV5;
 use self::direct_v5::DirectSuperBlockV5;
+use self::direct_v6::DirectSuperBlockV6;
 use self::layout::v5::{RafsV5BlobTable, RafsV5PrefetchTable, RafsV5SuperBlock};
-use self::layout::{XattrName, XattrValue, RAFS_SUPER_VERSION_V4, RAFS_SUPER_VERSION_V5};
+use self::layout::v6::{RafsV6SuperBlock, RafsV6SuperBlockExt};
+use self::layout::{
+    XattrName, XattrValue, RAFS_SUPER_VERSION_V4, RAFS_SUPER_VERSION_V5, RAFS_SUPER_VERSION_V6,
+};
 use self::noop::NoopSuperBlock;
 use crate::fs::{RafsConfig, RAFS_DEFAULT_ATTR_TIMEOUT, RAFS_DEFAULT_ENTRY_TIMEOUT};
 use crate::{RafsError, RafsIoReader, RafsIoWriter, RafsResult};
 
 pub mod cached_v5;
 pub mod direct_v5;
+pub mod direct_v6;
 pub mod layout;
 mod noop;
 
@@ -69,6 +74,8 @@ bitflags! {
         const HAS_XATTR = 0x0000_0020;
         // Data chunks are compressed with gzip
         const COMPRESS_GZIP = 0x0000_0040;
+        // Data chunks are compressed with zstd
+        const COMPRESS_ZSTD = 0x0000_0080;
     }
 }
 
@@ -110,6 +117,7 @@ impl From<RafsSuperFlags> for compress::Algorithm {
             x if x.contains(RafsSuperFlags::COMPRESS_NONE) => compress::Algorithm::None,
             x if x.contains(RafsSuperFlags::COMPRESS_LZ4_BLOCK) => compress::Algorithm::Lz4Block,
             x if x.contains(RafsSuperFlags::COMPRESS_GZIP) => compress::Algorithm::GZip,
+            x if x.contains(RafsSuperFlags::COMPRESS_ZSTD) => compress::Algorithm::Zstd,
             _ => compress::Algorithm::Lz4Block,
         }
     }
@@ -121,6 +129,7 @@ impl From<compress::Algorithm> for RafsSuperFlags {
             compress::Algorithm::None => RafsSuperFlags::COMPRESS_NONE,
             compress::Algorithm::Lz4Block => RafsSuperFlags::COMPRESS_LZ4_BLOCK,
             compress::Algorithm::GZip => RafsSuperFlags::COMPRESS_GZIP,
+            compress::Algorithm::Zstd => RafsSuperFlags::COMPRESS_ZSTD,
         }
     }
 }
@@ -157,8 +166,12 @@ impl RafsSuperMeta {
         self.version == RAFS_SUPER_VERSION_V4 || self.version == RAFS_SUPER_VERSION_V5
     }
 
+    pub fn is_v6(&self) -> bool {
+        self.version == RAFS_SUPER_VERSION_V6
+    }
+
     pub fn get_compressor(&self) -> compress::Algorithm {
-        if self.is_v4_v5() {
+        if self.is_v4_v5() || self.is_v6() {
             self.flags.into()
         } else {
             compress::Algorithm::None
@@ -166,7 +179,7 @@ impl RafsSuperMeta {
     }
 
     pub fn get_digester(&self) -> digest::Algorithm {
-        if self.is_v4_v5() {
+        if self.is_v4_v5() || self.is_v6() {
             self.flags.into()
         } else {
             digest::Algorithm::Blake3
@@ -174,7 +187,7 @@ impl RafsSuperMeta {
     }
 
     pub fn explicit_uidgid(&self) -> bool {
-        if self.is_v4_v5() {
+        if self.is_v4_v5() || self.is_v6() {
             self.flags.contains(RafsSuperFlags::EXPLICIT_UID_GID)
         } else {
             false
@@ -182,7 +195,7 @@ impl RafsSuperMeta {
     }
 
     pub fn has_xattr(&self) -> bool {
-        if self.is_v4_v5() {
+        if self.is_v4_v5() || self.is_v6() {
             self.flags.contains(RafsSuperFlags::HAS_XATTR)
         } else {
             false
@@ -290,15 +303,28 @@ impl RafsSuper {
     }
 
     pub fn update(&self, r: &mut RafsIoReader) -> RafsResult<()> {
-        let mut sb = RafsV5SuperBlock::new();
+        if self.meta.is_v4_v5() {
+            let mut sb = RafsV5SuperBlock::new();
 
-        r.read_exact(sb.as_mut())
-            .map_err(|e| RafsError::ReadMetadata(e, "Updating meta".to_string()))?;
+            r.read_exact(sb.as_mut())
+                .map_err(|e| RafsError::ReadMetadata(e, "Updating meta".to_string()))?;
+        }
         self.superblock.update(r)
     }
 
     /// Load RAFS super block and optionally cache inodes.
     pub fn load(&mut self, r: &mut RafsIoReader) -> Result<()> {
+        // The v6 superblock is at offset 1024 in EROFS format, try it first.
+        let mut sb = RafsV6SuperBlock::new();
+        if sb.load(r).is_ok() && sb.detect() {
+            let mut ext_sb = RafsV6SuperBlockExt::new();
+            ext_sb.load(r)?;
+            if ext_sb.detect() {
+                return self.load_v6(r, &sb, &ext_sb);
+            }
+        }
+        r.seek(SeekFrom::Start(0))?;
+
         let mut sb = RafsV5SuperBlock::new();
         r.read_exact(sb.as_mut())?;
         if sb.detect() {
@@ -334,25 +360,10 @@ impl RafsSuper {
         // Prefer to use the file list specified by daemon for prefetching, then
         // use the file list specified by builder for prefetching.
         if let Some(files) = files {
-            // No need to prefetch blob data for each alias as they share the same range,
-            // we do it once.
-            let mut hardlinks: HashSet<u64> = HashSet::new();
-            let mut head_desc = RafsBioDesc {
-                bi_size: 0,
-                bi_flags: 0,
-                bi_vec: Vec::new(),
-            };
-
             // Try to prefetch according to the list of files specified by the
             // daemon's `--prefetch-files` option.
-            for f_ino in files {
-                self.build_prefetch_desc_v4v5(f_ino, &mut head_desc, &mut hardlinks, fetcher)
-                    .map_err(|e| RafsError::Prefetch(e.to_string()))?;
-            }
-            // The left chunks whose size is smaller than 4MB will be fetched here.
-            fetcher(&mut head_desc);
-            Ok(())
-        } else if self.meta.is_v4_v5() {
+            self.prefetch_files(&files, fetcher)
+        } else if self.meta.is_v4_v5() || self.meta.is_v6() {
             self.prefetch_v4v5(r, fetcher)
         } else {
             Err(RafsError::Prefetch(
@@ -361,6 +372,31 @@ impl RafsSuper {
         }
     }
 
+    /// Prefetch the files or directories, descendants of a directory are prefetched too.
+    pub fn prefetch_files(
+        &self,
+        files: &[Inode],
+        fetcher: &dyn Fn(&mut RafsBioDesc),
+    ) -> RafsResult<()> {
+        // No need to prefetch blob data for each alias as they share the same range,
+        // we do it once.
+        let mut hardlinks: HashSet<u64> = HashSet::new();
+        let mut head_desc = RafsBioDesc {
+            bi_size: 0,
+            bi_flags: 0,
+            bi_vec: Vec::new(),
+        };
+
+        for f_ino in files {
+            self.build_prefetch_desc_v4v5(*f_ino, &mut head_desc, &mut hardlinks, fetcher)
+                .map_err(|e| RafsError::Prefetch(e.to_string()))?;
+        }
+        // The left chunks whose size is smaller than 4MB will be fetched here.
+        fetcher(&mut head_desc);
+
+        Ok(())
+    }
+
     pub fn get_inode(&self, ino: Inode, digest_validate: bool) -> Result<Arc<dyn RafsInode>> {
         self.superblock.get_inode(ino, digest_validate)
     }
@@ -369,6 +405,24 @@ impl RafsSuper {
         self.superblock.get_max_ino()
     }
 
+    /// Collect bios of all data chunks in the filesystem in order of blob layout, chunks
+    /// shared by multiple files are collected only once.
+    pub fn collect_chunks(&self) -> Result<Vec<RafsBio>> {
+        let root = self.get_inode(ROOT_ID, self.validate_digest)?;
+        let mut descendants = Vec::new();
+        root.collect_descendants_inodes(&mut descendants)?;
+
+        let mut chunks = HashSet::new();
+        let mut bios = Vec::new();
+        for inode in descendants {
+            let desc = inode.alloc_bio_desc(0, inode.size() as usize, false)?;
+            collect_unique_chunks(&mut chunks, &mut bios, desc.bi_vec);
+        }
+        bios.sort_by_key(|b| (b.blob.blob_index, b.chunkinfo.compress_offset()));
+
+        Ok(bios)
+    }
+
     fn load_v4v5(&mut self, r: &mut RafsIoReader, sb: &RafsV5SuperBlock) -> Result<()> {
         sb.validate()?;
 
@@ -422,6 +476,44 @@ impl RafsSuper {
         Ok(())
     }
 
+    fn load_v6(
+        &mut self,
+        r: &mut RafsIoReader,
+        sb: &RafsV6SuperBlock,
+        ext_sb: &RafsV6SuperBlockExt,
+    ) -> Result<()> {
+        let meta_size = r.seek(SeekFrom::End(0))?;
+        sb.validate(meta_size)?;
+        ext_sb.validate(meta_size)?;
+
+        self.meta.magic = ext_sb.magic();
+        self.meta.version = ext_sb.version();
+        self.meta.sb_size = (std::mem::size_of::<RafsV6SuperBlock>()
+            + std::mem::size_of::<RafsV6SuperBlockExt>()) as u32;
+        self.meta.block_size = ext_sb.chunk_size();
+        self.meta.flags = RafsSuperFlags::from_bits(ext_sb.flags())
+            .ok_or_else(|| einval!(format!("invalid super flags {:x}", ext_sb.flags())))?;
+        self.meta.inodes_count = sb.inos();
+        self.meta.inode_table_entries = ext_sb.inode_table_entries();
+        self.meta.inode_table_offset = ext_sb.inode_table_offset();
+        self.meta.blob_table_offset = ext_sb.blob_table_offset();
+        self.meta.blob_table_size = ext_sb.blob_table_size();
+        self.meta.extended_blob_table_offset = ext_sb.extended_blob_table_offset();
+        self.meta.extended_blob_table_entries = ext_sb.extended_blob_table_entries();
+        self.meta.prefetch_table_offset = ext_sb.prefetch_table_offset();
+        self.meta.prefetch_table_entries = ext_sb.prefetch_table_entries();
+
+        info!("rafs superblock features: {}", self.meta.flags);
+
+        // The v6 bootstrap is always directly mapped, inodes are in the EROFS format and can't be
+        // cached as v5 ones.
+        let mut inodes = DirectSuperBlockV6::new(&self.meta, self.validate_digest);
+        inodes.load(r)?;
+        self.superblock = Arc::new(inodes);
+
+        Ok(())
+    }
+
     fn store_v4v5(&self, w: &mut RafsIoWriter) -> Result<usize> {
         let mut sb = RafsV5SuperBlock::new();
 
@@ -903,7 +995,60 @@ pub trait RafsInode {
     fn alloc_bio_desc(&self, offset: u64, size: usize, user_io: bool) -> Result<RafsBioDesc>;
 }
 
+// Append bios of chunks not collected yet to `bios`. Chunks of blobs without the extended blob
+// table are identified by digest, since their chunk index isn't trusted.
+fn collect_unique_chunks(
+    chunks: &mut HashSet<RafsChunkKey>,
+    bios: &mut Vec<RafsBio>,
+    new_bios: Vec<RafsBio>,
+) {
+    for bio in new_bios {
+        if chunks.insert(RafsChunkKey::new(&bio.blob, bio.chunkinfo.as_ref())) {
+            bios.push(bio);
+        }
+    }
+}
+
 /// Trait to store Rafs meta block and validate alignment.
 pub trait RafsStore {
     fn store(&self, w: &mut RafsIoWriter) -> Result<usize>;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockChunkInfo;
+    use nydus_utils::digest::RafsDigest;
+
+    #[test]
+    fn test_collect_unique_chunks() {
+        let bio = |blob: &Arc<RafsBlobEntry>, index: u32, digest: u8| {
+            let chunk = MockChunkInfo::mock(0, 0, 0x1000, 0, 0x1000)
+                .with_index(index, RafsDigest { data: [digest; 32] });
+            RafsBio::new(Arc::new(chunk), blob.clone(), 0, 0x1000, 0x1000, false)
+        };
+        let mut chunks = HashSet::new();
+        let mut bios = Vec::new();
+
+        // Distinct chunks sharing an index are all collected if the chunk index isn't trusted.
+        let legacy = Arc::new(RafsBlobEntry::default());
+        collect_unique_chunks(
+            &mut chunks,
+            &mut bios,
+            vec![bio(&legacy, 0, 1), bio(&legacy, 0, 2), bio(&legacy, 1, 1)],
+        );
+        assert_eq!(bios.len(), 2);
+
+        let blob = Arc::new(RafsBlobEntry {
+            blob_index: 1,
+            chunk_count: 2,
+            ..Default::default()
+        });
+        collect_unique_chunks(
+            &mut chunks,
+            &mut bios,
+            vec![bio(&blob, 0, 3), bio(&blob, 0, 4), bio(&blob, 1, 3)],
+        );
+        assert_eq!(bios.len(), 4);
+    }
+}
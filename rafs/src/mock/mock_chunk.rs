@@ -46,6 +46,12 @@ impl MockChunkInfo {
             ..Default::default()
         }
     }
+
+    pub fn with_index(mut self, index: u32, block_id: RafsDigest) -> Self {
+        self.c_index = index;
+        self.c_block_id = Arc::new(block_id);
+        self
+    }
 }
 
 impl RafsChunkInfo for MockChunkInfo {
@@ -0,0 +1,95 @@
+// Copyright 2021 Ant Group. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Export warmed blobcache of an image into a portable archive, and import the archive
+//! into blobcache work directory of another node.
+
+use anyhow::{Context, Result};
+use std::fs::{self, OpenOptions};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+use rafs::metadata::{RafsMode, RafsSuper};
+use rafs::RafsIoReader;
+use storage::cache::archive::{self, ArchiveStat};
+
+fn load_bootstrap(bootstrap_path: &Path) -> Result<RafsSuper> {
+    let mut f_bootstrap: RafsIoReader = Box::new(
+        OpenOptions::new()
+            .read(true)
+            .write(false)
+            .open(bootstrap_path)
+            .with_context(|| format!("failed to open bootstrap file {:?}", bootstrap_path))?,
+    );
+    let mut rs = RafsSuper {
+        mode: RafsMode::Direct,
+        validate_digest: false,
+        ..Default::default()
+    };
+    rs.load(&mut f_bootstrap)
+        .with_context(|| format!("failed to load bootstrap {:?}", bootstrap_path))?;
+
+    Ok(rs)
+}
+
+/// Export cached chunks of all blobs referenced by the bootstrap from blobcache `work_dir`,
+/// only chunks matching digests recorded in the bootstrap are exported.
+pub fn export(
+    bootstrap_path: &Path,
+    work_dir: &Path,
+    compressed: bool,
+    output_path: &Path,
+) -> Result<ArchiveStat> {
+    let rs = load_bootstrap(bootstrap_path)?;
+    let bios = rs.collect_chunks().context("failed to collect chunks")?;
+
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(output_path)
+        .with_context(|| format!("failed to create archive {:?}", output_path))?;
+    let mut writer = BufWriter::new(file);
+    let stat = archive::export(
+        work_dir,
+        compressed,
+        &bios,
+        rs.meta.get_compressor(),
+        rs.meta.get_digester(),
+        &mut writer,
+    )
+    .with_context(|| format!("failed to export blobcache from {:?}", work_dir))?;
+    writer.flush()?;
+
+    Ok(stat)
+}
+
+/// Import an archive into blobcache `work_dir`, only chunks matching digests recorded in
+/// the bootstrap are imported.
+pub fn import(
+    bootstrap_path: &Path,
+    work_dir: &Path,
+    compressed: bool,
+    input_path: &Path,
+) -> Result<ArchiveStat> {
+    let rs = load_bootstrap(bootstrap_path)?;
+    let bios = rs.collect_chunks().context("failed to collect chunks")?;
+
+    let file = OpenOptions::new()
+        .read(true)
+        .open(input_path)
+        .with_context(|| format!("failed to open archive {:?}", input_path))?;
+    let mut reader = BufReader::new(file);
+    fs::create_dir_all(work_dir)
+        .with_context(|| format!("failed to create work dir {:?}", work_dir))?;
+    archive::import(
+        work_dir,
+        compressed,
+        &bios,
+        rs.meta.get_compressor(),
+        rs.meta.get_digester(),
+        &mut reader,
+    )
+    .with_context(|| format!("failed to import blobcache into {:?}", work_dir))
+}
@@ -10,7 +10,7 @@ use std::fs::DirEntry;
 use crate::builder::Builder;
 use crate::core::blob::Blob;
 use crate::core::bootstrap::Bootstrap;
-use crate::core::context::{BlobContext, BlobManager, BootstrapContext, BuildContext};
+use crate::core::context::{BlobContext, BlobManager, BootstrapContext, BuildContext, RafsVersion};
 use crate::core::node::*;
 use crate::core::tree::Tree;
 
@@ -155,6 +155,9 @@ impl Builder for DirectoryBuilder {
         }
 
         // Dump bootstrap file
-        bootstrap.dump_rafsv5(ctx, bootstrap_ctx, blob_mgr)
+        match ctx.fs_version {
+            RafsVersion::V5 => bootstrap.dump_rafsv5(ctx, bootstrap_ctx, blob_mgr),
+            RafsVersion::V6 => bootstrap.dump_rafsv6(ctx, bootstrap_ctx, blob_mgr),
+        }
     }
 }
@@ -361,7 +361,7 @@ impl StargzIndexTreeBuilder {
                     block_id,
                     // Will be set later
                     blob_index: 0,
-                    flags: RafsChunkFlags::COMPRESSED,
+                    flags: RafsChunkFlags::COMPRESSED | RafsChunkFlags::COMPRESS_GZIP,
                     // No available data on entry
                     compress_size: 0,
                     decompress_size: decompress_size as u32,
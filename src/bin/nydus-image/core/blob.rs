@@ -4,17 +4,225 @@
 
 use std::os::unix::ffi::OsStrExt;
 
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
 use anyhow::{Context, Result};
 use sha2::Digest;
-use std::collections::HashMap;
 
 use nydus_utils::digest::{self, DigestHasher, RafsDigest};
 use rafs::metadata::layout::v5::RafsV5ChunkInfo;
+use rafs::metadata::RAFS_DEFAULT_BLOCK_SIZE;
+use storage::compress;
 
 use super::context::{BlobContext, BuildContext, SourceType};
 use super::node::*;
+use crate::core::chunk_dict::ChunkDict;
 use crate::core::layout::BlobLayout;
 
+// The maximum size of chunk data being prepared ahead per worker thread, to limit memory usage.
+const PREPARE_WINDOW_PER_THREAD: u64 = 4 * RAFS_DEFAULT_BLOCK_SIZE;
+
+type PrepareJob = (usize, Arc<ChunkSource>, u32);
+type PrepareResult = (usize, Result<PreparedChunk>);
+
+// Get the chunk at `pos`, which is the index of source and index of chunk in the source, and
+// move `pos` to the next chunk.
+fn next_chunk_pos(sources: &[Arc<ChunkSource>], pos: &mut (usize, u32)) -> Option<(usize, u32)> {
+    let cur = *pos;
+    let source = sources.get(cur.0)?;
+    if cur.1 + 1 < source.chunk_count() {
+        pos.1 += 1;
+    } else {
+        *pos = (cur.0 + 1, 0);
+    }
+    Some(cur)
+}
+
+/// Digest and compress chunks in a worker pool, while handing out prepared chunks in the
+/// order of nodes so that they can be dumped into blob deterministically.
+struct ChunkPreparer {
+    threads: usize,
+    compressor: compress::Algorithm,
+    compress_options: compress::CompressOptions,
+    digester: digest::Algorithm,
+    chunk_dict: Option<Arc<dyn ChunkDict>>,
+    sources: Vec<Arc<ChunkSource>>,
+    // Position and sequence number of the next chunk to hand out.
+    next: (usize, u32),
+    next_seq: usize,
+    // File of the chunk being handed out, if chunks are prepared in the current thread.
+    file: Option<File>,
+    // Position and sequence number of the next chunk to be prepared by workers.
+    next_prepare: (usize, u32),
+    next_prepare_seq: usize,
+    // Size of chunk data being prepared or not handed out yet.
+    inflight: u64,
+    job_tx: Option<mpsc::Sender<PrepareJob>>,
+    result_rx: Option<mpsc::Receiver<PrepareResult>>,
+    // Prepared chunks received out of order.
+    pending: BTreeMap<usize, Result<PreparedChunk>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl ChunkPreparer {
+    fn new(ctx: &BuildContext, chunk_dict: Option<Arc<dyn ChunkDict>>) -> Self {
+        Self {
+            threads: ctx.threads,
+            compressor: ctx.compressor,
+            compress_options: ctx.compress_options,
+            digester: ctx.digester,
+            chunk_dict,
+            sources: Vec::new(),
+            next: (0, 0),
+            next_seq: 0,
+            file: None,
+            next_prepare: (0, 0),
+            next_prepare_seq: 0,
+            inflight: 0,
+            job_tx: None,
+            result_rx: None,
+            pending: BTreeMap::new(),
+            workers: Vec::new(),
+        }
+    }
+
+    /// Start to prepare chunks of `sources`, which are handed out in order.
+    fn start(&mut self, sources: Vec<ChunkSource>) -> Result<()> {
+        self.sources = sources.into_iter().map(Arc::new).collect();
+        // Prepare chunks in the current thread if no worker is needed.
+        if self.threads <= 1 {
+            return Ok(());
+        }
+
+        let (job_tx, job_rx) = mpsc::channel::<PrepareJob>();
+        let (result_tx, result_rx) = mpsc::channel();
+        let job_rx = Arc::new(Mutex::new(job_rx));
+        for i in 0..self.threads {
+            let job_rx = job_rx.clone();
+            let result_tx = result_tx.clone();
+            let (compressor, compress_options, digester) =
+                (self.compressor, self.compress_options, self.digester);
+            let chunk_dict = self.chunk_dict.clone();
+            let worker = thread::Builder::new()
+                .name(format!("nydus-image-worker-{}", i))
+                .spawn(move || {
+                    // File of the last prepared chunk, chunks of a file are mostly prepared by
+                    // the same worker in a row.
+                    let mut file: Option<(Arc<ChunkSource>, File)> = None;
+                    loop {
+                        // The job sender is dropped once all jobs are sent or building fails.
+                        let job = job_rx.lock().unwrap().recv();
+                        let (seq, source, index) = match job {
+                            Ok(job) => job,
+                            Err(_) => break,
+                        };
+                        let opened = file.take().filter(|(s, _)| Arc::ptr_eq(s, &source));
+                        let ret = match opened {
+                            Some((_, f)) => Ok(f),
+                            None => source.open(),
+                        }
+                        .and_then(|f| {
+                            let ret = source.prepare(
+                                &f,
+                                index,
+                                compressor,
+                                &compress_options,
+                                digester,
+                                chunk_dict.as_deref(),
+                            );
+                            file = Some((source, f));
+                            ret
+                        });
+                        if result_tx.send((seq, ret)).is_err() {
+                            break;
+                        }
+                    }
+                })
+                .context("failed to spawn worker thread")?;
+            self.workers.push(worker);
+        }
+        self.job_tx = Some(job_tx);
+        self.result_rx = Some(result_rx);
+
+        Ok(())
+    }
+
+    /// Get the next prepared chunk.
+    fn next_prepared(&mut self) -> Result<PreparedChunk> {
+        if self.workers.is_empty() {
+            let (idx, index) = next_chunk_pos(&self.sources, &mut self.next)
+                .ok_or_else(|| anyhow!("no more chunk to prepare"))?;
+            let source = &self.sources[idx];
+            if index == 0 {
+                self.file = Some(source.open()?);
+            }
+            // Safe to unwrap because the file is opened along with the first chunk.
+            return source.prepare(
+                self.file.as_ref().unwrap(),
+                index,
+                self.compressor,
+                &self.compress_options,
+                self.digester,
+                self.chunk_dict.as_deref(),
+            );
+        }
+
+        let seq = self.next_seq;
+        self.next_seq += 1;
+
+        // Keep a bounded size of chunk data in flight, but at least one chunk.
+        if let Some(job_tx) = self.job_tx.as_ref() {
+            let window = self.threads as u64 * PREPARE_WINDOW_PER_THREAD;
+            while self.inflight < window {
+                let (idx, index) = match next_chunk_pos(&self.sources, &mut self.next_prepare) {
+                    Some(pos) => pos,
+                    None => break,
+                };
+                let source = self.sources[idx].clone();
+                self.inflight += source.chunk_size(index);
+                job_tx
+                    .send((self.next_prepare_seq, source, index))
+                    .map_err(|_| anyhow!("worker threads exited unexpectedly"))?;
+                self.next_prepare_seq += 1;
+            }
+        }
+        if self.next_prepare.0 == self.sources.len() {
+            // Let workers exit once all jobs are done.
+            self.job_tx = None;
+        }
+
+        loop {
+            if let Some(ret) = self.pending.remove(&seq) {
+                if let Ok(chunk) = ret.as_ref() {
+                    self.inflight -= chunk.decompress_size;
+                }
+                return ret;
+            }
+            let (i, ret) = self
+                .result_rx
+                .as_ref()
+                .ok_or_else(|| anyhow!("no more chunk to prepare"))?
+                .recv()
+                .map_err(|_| anyhow!("worker threads exited unexpectedly"))?;
+            self.pending.insert(i, ret);
+        }
+    }
+}
+
+impl Drop for ChunkPreparer {
+    fn drop(&mut self) {
+        // Stop workers from taking more jobs.
+        self.job_tx = None;
+        self.result_rx = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
 pub struct Blob {}
 
 impl Blob {
@@ -35,10 +243,18 @@ impl Blob {
             SourceType::Directory => {
                 let (inodes, prefetch_entries) =
                     BlobLayout::layout_blob_simple(&ctx.prefetch, nodes)?;
+                let mut preparer = ChunkPreparer::new(ctx, blob_ctx.chunk_dict.clone());
+                let sources = inodes
+                    .iter()
+                    .filter_map(|i| nodes[*i].chunk_source())
+                    .collect();
+                preparer.start(sources)?;
                 for (idx, inode) in inodes.iter().enumerate() {
                     let node = &mut nodes[*inode];
                     let size = node
-                        .dump_blob(ctx, blob_ctx, blob_index, chunk_cache)
+                        .dump_blob(ctx, blob_ctx, blob_index, chunk_cache, || {
+                            preparer.next_prepared()
+                        })
                         .context("failed to dump blob chunks")?;
                     if idx < prefetch_entries {
                         debug!("[{}]\treadahead {}", node.overlay, node);
@@ -3,8 +3,9 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::convert::TryFrom;
-use std::ffi::OsString;
+use std::ffi::{OsStr, OsString};
 use std::mem::size_of;
+use std::os::unix::ffi::OsStrExt;
 
 use anyhow::{Context, Result};
 
@@ -12,8 +13,15 @@ use nydus_utils::digest::{DigestHasher, RafsDigest};
 use rafs::metadata::layout::v5::{
     RafsV5ChunkInfo, RafsV5InodeTable, RafsV5SuperBlock, RafsV5XAttrsTable,
 };
+use rafs::metadata::layout::v6::{
+    rafsv6_file_type, rafsv6_pack_dirents, RafsV6Device, RafsV6Inode, RafsV6InodeTable,
+    RafsV6SuperBlock, RafsV6SuperBlockExt, EROFS_BLOCK_SIZE, EROFS_INODE_FLAT_INLINE,
+    EROFS_INODE_FLAT_PLAIN, EROFS_INODE_SLOT_SIZE, RAFSV6_DEVICE_TABLE_OFFSET,
+};
 use rafs::metadata::layout::RAFS_ROOT_INODE;
 use rafs::metadata::{RafsMode, RafsStore, RafsSuper};
+use rafs::RafsIoWriter;
+use storage::compress;
 
 use crate::core::context::{BlobManager, BootstrapContext, BuildContext, SourceType};
 use crate::core::node::*;
@@ -190,7 +198,7 @@ impl Bootstrap {
             .context("failed to load superblock from bootstrap")?;
 
         let lower_compressor = rs.meta.get_compressor();
-        if ctx.compressor != lower_compressor {
+        if !compressor_compatible(ctx.compressor, lower_compressor) {
             bail!(
                 "inconsistent compressor with the lower layer, current {}, lower: {}.",
                 ctx.compressor,
@@ -426,4 +434,367 @@ impl Bootstrap {
 
         Ok((blob_ids, blob_size))
     }
+
+    /// Dump EROFS compatible bootstrap and blob file, return (Vec<blob_id>, blob_size)
+    ///
+    /// The bootstrap is laid out as: superblock, extended superblock, device table, inodes,
+    /// directory and symlink data blocks, inode table, prefetch table and blob tables.
+    pub fn dump_rafsv6(
+        &mut self,
+        ctx: &mut BuildContext,
+        bootstrap_ctx: &mut BootstrapContext,
+        blob_mgr: &mut BlobManager,
+    ) -> Result<(Vec<String>, u64)> {
+        let blob_size = if let Some(blob_ctx) = blob_mgr.current() {
+            if blob_ctx.compressed_blob_size > 0 && ctx.prefetch.policy != PrefetchPolicy::Blob {
+                blob_ctx.blob_readahead_size = 0;
+            }
+            blob_ctx.compressed_blob_size
+        } else {
+            0
+        };
+
+        // Set inode digest, use reverse iteration order to reduce repeated digest calculations.
+        for idx in (0..bootstrap_ctx.nodes.len()).rev() {
+            self.digest_node(ctx, bootstrap_ctx, idx);
+        }
+
+        // Set device table, each data blob is an EROFS device.
+        let blob_table = blob_mgr.to_blob_table()?;
+        let mut devices = Vec::with_capacity(blob_table.entries.len());
+        for entry in blob_table.entries.iter() {
+            devices.push(RafsV6Device::new(&entry.blob_id, entry.blob_cache_size)?);
+        }
+        let devices_count = u16::try_from(devices.len()).context("too many blobs")?;
+        let devices_size = devices.len() * size_of::<RafsV6Device>();
+
+        // Set inodes, hardlinks share the inode of the first link and have no inode of their own.
+        let nodes = &bootstrap_ctx.nodes;
+        let mut inodes = Vec::with_capacity(nodes.len());
+        let mut nids = vec![0u64; nodes.len()];
+        let mut offset = align_to(
+            RAFSV6_DEVICE_TABLE_OFFSET + devices_size as u64,
+            EROFS_INODE_SLOT_SIZE,
+        );
+        for (idx, node) in nodes.iter().enumerate() {
+            if node.inode.i_ino != node.index {
+                continue;
+            }
+            let (inode, xattrs) = node.new_rafsv6_inode()?;
+            // Data inlined into the inode must not cross block boundary.
+            if inode.data_layout() == EROFS_INODE_FLAT_INLINE {
+                let end = size_of::<RafsV6Inode>() + inode.xattr_size() + inode.inline_size();
+                if offset % EROFS_BLOCK_SIZE + end as u64 > EROFS_BLOCK_SIZE {
+                    offset = align_to(offset, EROFS_BLOCK_SIZE);
+                }
+            }
+            nids[idx] = offset / EROFS_INODE_SLOT_SIZE;
+            offset += node.rafsv6_size(&inode) as u64;
+            inodes.push((idx, inode, xattrs));
+        }
+
+        // Set directory and symlink data blocks.
+        let mut data_blocks: Vec<Vec<u8>> = Vec::new();
+        let mut blkaddr = align_to(offset, EROFS_BLOCK_SIZE) / EROFS_BLOCK_SIZE;
+        for (idx, inode, _) in inodes.iter_mut() {
+            let node = &nodes[*idx];
+            let data = if node.is_dir() {
+                let dirents = self.rafsv6_dirents(nodes, &nids, *idx)?;
+                inode.i_size = dirents.len() as u64;
+                dirents
+            } else if inode.is_symlink() && inode.data_layout() == EROFS_INODE_FLAT_PLAIN {
+                node.symlink
+                    .as_ref()
+                    .map(|s| s.as_bytes().to_vec())
+                    .unwrap_or_default()
+            } else {
+                continue;
+            };
+            inode.i_u = u32::try_from(blkaddr).context("too many metadata blocks")?;
+            blkaddr += align_to(data.len() as u64, EROFS_BLOCK_SIZE) / EROFS_BLOCK_SIZE;
+            data_blocks.push(data);
+        }
+        let data_blocks_end = blkaddr * EROFS_BLOCK_SIZE;
+
+        // Set inode table
+        let inode_table_entries = nodes.len() as u32;
+        let mut inode_table = RafsV6InodeTable::new(inode_table_entries as usize);
+        for node in nodes.iter() {
+            inode_table.set(node.index, nids[node.inode.i_ino as usize - 1])?;
+        }
+        let inode_table_offset = data_blocks_end;
+        let inode_table_size = inode_table.size() as u64;
+
+        // Set prefetch table
+        let (prefetch_table_size, prefetch_table_entries) =
+            if let Some(prefetch_table) = ctx.prefetch.get_rafsv5_prefetch_table() {
+                (prefetch_table.size() as u64, prefetch_table.len() as u32)
+            } else {
+                (0, 0u32)
+            };
+
+        // Set blob table
+        let prefetch_table_offset = inode_table_offset + inode_table_size;
+        let blob_table_offset = prefetch_table_offset + prefetch_table_size;
+        let blob_table_size = blob_table.size() as u64;
+        let extended_blob_table_offset = blob_table_offset + blob_table_size;
+        let extended_blob_table_size = blob_table.extended.size() as u64;
+        let extended_blob_table_entries = blob_table.extended.entries();
+        let meta_size = align_to(
+            extended_blob_table_offset + extended_blob_table_size,
+            EROFS_BLOCK_SIZE,
+        );
+
+        // Set super block
+        let mut super_block = RafsV6SuperBlock::new();
+        let root_nid = u16::try_from(nids[0]).context("invalid root inode nid")?;
+        let inodes_count =
+            (bootstrap_ctx.lower_inode_map.len() + bootstrap_ctx.upper_inode_map.len()) as u64;
+        super_block.set_root_nid(root_nid);
+        super_block.set_inos(inodes_count);
+        super_block.set_blocks(
+            u32::try_from(meta_size / EROFS_BLOCK_SIZE).context("bootstrap is too large")?,
+        );
+        super_block.set_devices(devices_count, RAFSV6_DEVICE_TABLE_OFFSET);
+
+        let mut ext_sb = RafsV6SuperBlockExt::new();
+        ext_sb.set_inode_table_offset(inode_table_offset);
+        ext_sb.set_inode_table_entries(inode_table_entries);
+        ext_sb.set_prefetch_table_offset(prefetch_table_offset);
+        ext_sb.set_prefetch_table_entries(prefetch_table_entries);
+        ext_sb.set_blob_table_offset(blob_table_offset);
+        ext_sb.set_blob_table_size(blob_table_size as u32);
+        ext_sb.set_extended_blob_table_offset(extended_blob_table_offset);
+        ext_sb.set_extended_blob_table_entries(u32::try_from(extended_blob_table_entries)?);
+        ext_sb.set_compressor(ctx.compressor);
+        ext_sb.set_digester(ctx.digester);
+        if ctx.explicit_uidgid {
+            ext_sb.set_explicit_uidgid();
+        }
+        if nodes.iter().any(|node| node.inode.has_xattr()) {
+            ext_sb.set_has_xattr();
+        }
+
+        let f_bootstrap = &mut bootstrap_ctx.f_bootstrap;
+
+        // Dump super blocks and device table
+        let mut pos = super_block
+            .store(f_bootstrap)
+            .context("failed to store superblock")? as u64;
+        pos += ext_sb
+            .store(f_bootstrap)
+            .context("failed to store extended superblock")? as u64;
+        for device in devices.iter() {
+            pos += device
+                .store(f_bootstrap)
+                .context("failed to store device table")? as u64;
+        }
+
+        // Dump inodes, xattrs and chunks
+        timing_tracer!(
+            {
+                for (idx, inode, xattrs) in inodes.iter() {
+                    dump_zeros(f_bootstrap, &mut pos, nids[*idx] * EROFS_INODE_SLOT_SIZE)?;
+                    pos += nodes[*idx]
+                        .dump_bootstrap_v6(f_bootstrap, inode, xattrs)
+                        .context("failed to dump bootstrap")? as u64;
+                }
+
+                Ok(())
+            },
+            "dump_bootstrap",
+            Result<()>
+        )?;
+
+        // Dump directory and symlink data blocks
+        for data in data_blocks.iter() {
+            let start = align_to(pos, EROFS_BLOCK_SIZE);
+            dump_zeros(f_bootstrap, &mut pos, start)?;
+            f_bootstrap
+                .write_all(data)
+                .context("failed to store data blocks")?;
+            pos += data.len() as u64;
+        }
+        dump_zeros(f_bootstrap, &mut pos, data_blocks_end)?;
+
+        // Dump inode table
+        pos += inode_table
+            .store(f_bootstrap)
+            .context("failed to store inode table")? as u64;
+
+        // Dump prefetch table
+        if let Some(mut prefetch_table) = ctx.prefetch.get_rafsv5_prefetch_table() {
+            pos += prefetch_table
+                .store(f_bootstrap)
+                .context("failed to store prefetch table")? as u64;
+        }
+
+        // Dump blob table
+        pos += blob_table
+            .store(f_bootstrap)
+            .context("failed to store blob table")? as u64;
+
+        // Dump extended blob table
+        pos += blob_table
+            .store_extended(f_bootstrap)
+            .context("failed to store extended blob table")? as u64;
+
+        // EROFS requires the image to be made up of whole blocks
+        dump_zeros(f_bootstrap, &mut pos, meta_size)?;
+
+        // Flush remaining data in BufWriter to file
+        f_bootstrap.flush()?;
+
+        let blob_ids: Vec<String> = blob_table
+            .entries
+            .iter()
+            .map(|entry| entry.blob_id.clone())
+            .collect();
+
+        Ok((blob_ids, blob_size))
+    }
+
+    /// Pack entries of the directory at `index`, including "." and "..".
+    fn rafsv6_dirents(&self, nodes: &[Node], nids: &[u64], index: usize) -> Result<Vec<u8>> {
+        let dir = &nodes[index];
+        let nid_of = |node: &Node| nids[node.inode.i_ino as usize - 1];
+        let parent_nid = if index == 0 {
+            nids[0]
+        } else {
+            nids[dir.inode.i_parent as usize - 1]
+        };
+        let dir_type = rafsv6_file_type(dir.inode.i_mode);
+
+        let mut entries = vec![
+            (OsStr::new("."), nid_of(dir), dir_type),
+            (OsStr::new(".."), parent_nid, dir_type),
+        ];
+        let start = dir.inode.i_child_index as usize;
+        for child in &nodes[start - 1..start - 1 + dir.inode.i_child_count as usize] {
+            entries.push((
+                child.name(),
+                nid_of(child),
+                rafsv6_file_type(child.inode.i_mode),
+            ));
+        }
+        entries.sort_by(|a, b| a.0.as_bytes().cmp(b.0.as_bytes()));
+
+        rafsv6_pack_dirents(&entries)
+            .with_context(|| format!("failed to pack directory entries of {:?}", dir.path))
+    }
+}
+
+/// Whether chunks compressed by `lower` could be referenced by an image compressed by `current`.
+/// Chunks record their own compression algorithm, so they may be compressed differently, except
+/// for gzip which is special about chunk boundaries.
+pub fn compressor_compatible(current: compress::Algorithm, lower: compress::Algorithm) -> bool {
+    current == lower || (current != compress::Algorithm::GZip && lower != compress::Algorithm::GZip)
+}
+
+fn align_to(offset: u64, align: u64) -> u64 {
+    (offset + align - 1) / align * align
+}
+
+/// Fill the bootstrap with zeros from `pos` up to `end`.
+fn dump_zeros(f_bootstrap: &mut RafsIoWriter, pos: &mut u64, end: u64) -> Result<()> {
+    if end < *pos {
+        bail!("invalid bootstrap offset {:x}, current {:x}", end, pos);
+    }
+    f_bootstrap
+        .write_all(&vec![0u8; (end - *pos) as usize])
+        .context("failed to dump padding to bootstrap")?;
+    *pos = end;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+    use std::os::unix::fs as unix_fs;
+
+    use nydus_utils::digest;
+    use rafs::RafsIoReader;
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::*;
+    use crate::builder::directory::DirectoryBuilder;
+    use crate::builder::Builder;
+    use crate::core::context::RafsVersion;
+    use crate::core::prefetch::Prefetch;
+
+    #[test]
+    fn test_rafsv6_bootstrap_load() {
+        let tmp_dir = TempDir::new().unwrap();
+        let work_dir = tmp_dir.as_path();
+        let source = work_dir.join("source");
+        fs::create_dir_all(source.join("sub/empty")).unwrap();
+        fs::write(source.join("file"), b"rafs v6").unwrap();
+        xattr::set(source.join("file"), "user.key", b"value").unwrap();
+        fs::hard_link(source.join("file"), source.join("sub/hardlink")).unwrap();
+        unix_fs::symlink("../file", source.join("sub/symlink")).unwrap();
+        // Too long to be inlined into the inode.
+        let long_target = "long-target/".repeat(340);
+        unix_fs::symlink(&long_target, source.join("sub/long-symlink")).unwrap();
+
+        let bootstrap_path = work_dir.join("bootstrap");
+        let mut ctx = BuildContext::new(
+            String::new(),
+            RafsVersion::V6,
+            true,
+            compress::Algorithm::Lz4Block,
+            compress::CompressOptions::default(),
+            digest::Algorithm::Blake3,
+            false,
+            WhiteoutSpec::Oci,
+            SourceType::Directory,
+            source,
+            Prefetch::default(),
+            None,
+            1,
+        );
+        let f_bootstrap = Box::new(File::create(&bootstrap_path).unwrap()) as RafsIoWriter;
+        let mut bootstrap_ctx = BootstrapContext::new(f_bootstrap, None);
+        let mut blob_mgr = BlobManager::new();
+        DirectoryBuilder::new()
+            .build(&mut ctx, &mut bootstrap_ctx, &mut blob_mgr)
+            .unwrap();
+        drop(bootstrap_ctx);
+
+        let mut rs = RafsSuper {
+            mode: RafsMode::Direct,
+            ..Default::default()
+        };
+        let mut reader = Box::new(File::open(&bootstrap_path).unwrap()) as RafsIoReader;
+        rs.load(&mut reader).unwrap();
+        assert!(rs.meta.is_v6());
+        assert!(rs.meta.has_xattr());
+
+        let root = rs.get_inode(RAFS_ROOT_INODE, false).unwrap();
+        assert!(root.is_dir());
+        assert_eq!(root.get_child_count(), 2);
+
+        let file = root.get_child_by_name(OsStr::new("file")).unwrap();
+        assert!(file.is_reg());
+        assert_eq!(file.size(), 7);
+        assert_eq!(
+            file.get_xattr(OsStr::new("user.key")).unwrap(),
+            Some(b"value".to_vec())
+        );
+
+        let sub = root.get_child_by_name(OsStr::new("sub")).unwrap();
+        assert_eq!(sub.get_child_count(), 4);
+        let hardlink = sub.get_child_by_name(OsStr::new("hardlink")).unwrap();
+        assert_eq!(hardlink.ino(), file.ino());
+        let symlink = sub.get_child_by_name(OsStr::new("symlink")).unwrap();
+        assert_eq!(symlink.get_symlink().unwrap(), OsString::from("../file"));
+        let long_symlink = sub.get_child_by_name(OsStr::new("long-symlink")).unwrap();
+        assert_eq!(
+            long_symlink.get_symlink().unwrap(),
+            OsString::from(long_target)
+        );
+        let empty = sub.get_child_by_name(OsStr::new("empty")).unwrap();
+        assert!(empty.is_dir());
+        assert_eq!(empty.get_child_count(), 0);
+    }
 }
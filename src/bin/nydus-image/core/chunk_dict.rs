@@ -2,15 +2,17 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::core::bootstrap::compressor_compatible;
 use crate::core::tree::Tree;
-use anyhow::{Context, Result};
-use nydus_utils::digest::RafsDigest;
+use anyhow::{bail, Context, Result};
+use nydus_utils::digest::{self, RafsDigest};
 use rafs::metadata::layout::v5::{RafsV5BlobTable, RafsV5ChunkInfo};
 use rafs::metadata::{RafsMode, RafsSuper};
 use rafs::RafsIoReader;
 use std::collections::{BTreeMap, HashMap};
 use std::fs::OpenOptions;
 use std::sync::{Arc, Mutex};
+use storage::compress;
 
 /// type=path
 /// if no type, use bootstrap
@@ -38,17 +40,23 @@ pub fn import_chunk_dict(arg: &str) -> Result<Arc<dyn ChunkDict>> {
     }
 }
 
-pub trait ChunkDict {
+pub trait ChunkDict: Sync + Send {
     fn get_chunk(&self, digest: &RafsDigest) -> Option<&RafsV5ChunkInfo>;
     fn get_blobs(&self) -> Arc<RafsV5BlobTable>;
     fn set_real_blob_idx(&self, inner_idx: u32, out_idx: u32);
     fn get_real_blob_idx(&self, inner_idx: u32) -> u32;
+    /// Compression algorithm of the bootstrap the dict is built from.
+    fn compressor(&self) -> compress::Algorithm;
+    /// Digest algorithm of chunks in the dict.
+    fn digester(&self) -> digest::Algorithm;
 }
 
 pub struct BootstrapChunkDict {
     m: HashMap<RafsDigest, RafsV5ChunkInfo>,
     blobs: Arc<RafsV5BlobTable>,
     blob_idx_m: Mutex<BTreeMap<u32, u32>>,
+    compressor: compress::Algorithm,
+    digester: digest::Algorithm,
 }
 
 impl ChunkDict for BootstrapChunkDict {
@@ -72,6 +80,14 @@ impl ChunkDict for BootstrapChunkDict {
             .get(&inner_idx)
             .unwrap_or(&inner_idx)
     }
+
+    fn compressor(&self) -> compress::Algorithm {
+        self.compressor
+    }
+
+    fn digester(&self) -> digest::Algorithm {
+        self.digester
+    }
 }
 
 impl BootstrapChunkDict {
@@ -90,11 +106,94 @@ impl BootstrapChunkDict {
         };
         let mut reader = Box::new(file) as RafsIoReader;
         rs.load(&mut reader)?;
+        // Chunks from old bootstraps are stamped with the compressor of the dict, so that they
+        // are still readable when reused by images with a different compressor.
         Tree::from_bootstrap(&rs, Some(&mut m)).context("failed to build tree from bootstrap")?;
         Ok(Self {
             m,
             blobs: rs.superblock.get_blob_table(),
             blob_idx_m: Mutex::new(BTreeMap::new()),
+            compressor: rs.meta.get_compressor(),
+            digester: rs.meta.get_digester(),
         })
     }
 }
+
+/// Check whether chunks of the dict could be reused by an image built with `compressor` and
+/// `digester`. Chunks are deduplicated by digest, so the dict must be built with the same digest
+/// algorithm, and chunks reused from the dict keep their own compressor in chunk flags.
+pub fn check_chunk_dict(
+    chunk_dict: &dyn ChunkDict,
+    compressor: compress::Algorithm,
+    digester: digest::Algorithm,
+) -> Result<()> {
+    if chunk_dict.digester() != digester {
+        bail!(
+            "chunk dict with digester {} mismatches digester {} of the image",
+            chunk_dict.digester(),
+            digester
+        );
+    }
+    if !compressor_compatible(compressor, chunk_dict.compressor()) {
+        bail!(
+            "chunk dict with compressor {} is incompatible with compressor {} of the image",
+            chunk_dict.compressor(),
+            compressor
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct MockChunkDict {
+        compressor: compress::Algorithm,
+        digester: digest::Algorithm,
+    }
+
+    impl ChunkDict for MockChunkDict {
+        fn get_chunk(&self, _digest: &RafsDigest) -> Option<&RafsV5ChunkInfo> {
+            None
+        }
+
+        fn get_blobs(&self) -> Arc<RafsV5BlobTable> {
+            Arc::new(RafsV5BlobTable::new())
+        }
+
+        fn set_real_blob_idx(&self, _inner_idx: u32, _out_idx: u32) {}
+
+        fn get_real_blob_idx(&self, inner_idx: u32) -> u32 {
+            inner_idx
+        }
+
+        fn compressor(&self) -> compress::Algorithm {
+            self.compressor
+        }
+
+        fn digester(&self) -> digest::Algorithm {
+            self.digester
+        }
+    }
+
+    #[test]
+    fn test_check_chunk_dict() {
+        let dict = |compressor| MockChunkDict {
+            compressor,
+            digester: digest::Algorithm::Blake3,
+        };
+        let (lz4, zstd, gzip) = (
+            compress::Algorithm::Lz4Block,
+            compress::Algorithm::Zstd,
+            compress::Algorithm::GZip,
+        );
+
+        check_chunk_dict(&dict(lz4), zstd, digest::Algorithm::Blake3).unwrap();
+        check_chunk_dict(&dict(gzip), gzip, digest::Algorithm::Blake3).unwrap();
+        check_chunk_dict(&dict(gzip), lz4, digest::Algorithm::Blake3).unwrap_err();
+        check_chunk_dict(&dict(zstd), gzip, digest::Algorithm::Blake3).unwrap_err();
+        check_chunk_dict(&dict(lz4), lz4, digest::Algorithm::Sha256).unwrap_err();
+    }
+}
@@ -18,7 +18,7 @@ use vmm_sys_util::tempfile::TempFile;
 
 use rafs::metadata::layout::v5::RafsV5BlobTable;
 use rafs::metadata::layout::v5::RafsV5ChunkInfo;
-use rafs::metadata::{Inode, RAFS_MAX_BLOCK_SIZE};
+use rafs::metadata::Inode;
 use rafs::{RafsIoReader, RafsIoWriter};
 // FIXME: Must image tool depend on storage backend?
 use nydus_utils::digest::{self, RafsDigest};
@@ -56,6 +56,31 @@ impl FromStr for SourceType {
     }
 }
 
+/// Version of the RAFS bootstrap format.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RafsVersion {
+    V5,
+    /// EROFS compatible format, which can be mounted by the in-kernel EROFS driver too.
+    V6,
+}
+
+impl Default for RafsVersion {
+    fn default() -> Self {
+        Self::V5
+    }
+}
+
+impl FromStr for RafsVersion {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "5" => Ok(Self::V5),
+            "6" => Ok(Self::V6),
+            _ => Err(anyhow!("invalid RAFS version")),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum BlobStorage {
     // Won't rename user's specification
@@ -181,8 +206,6 @@ pub struct BlobContext {
     pub chunk_count: u32,
     /// Blob data layout manager
     pub blob_layout: BlobLayout,
-    /// Scratch data buffer for reading from/writing to disk files.
-    pub chunk_data_buf: Vec<u8>,
     /// ChunkDict which would be loaded when builder start
     pub chunk_dict: Option<Arc<dyn ChunkDict>>,
 
@@ -202,7 +225,6 @@ impl BlobContext {
             decompress_offset: 0,
             chunk_count: 0,
             blob_layout: BlobLayout::new(),
-            chunk_data_buf: vec![0u8; RAFS_MAX_BLOCK_SIZE as usize],
             chunk_dict: None,
             writer,
         }
@@ -387,6 +409,8 @@ impl BootstrapContext {
 pub struct BuildContext {
     /// Blob id (user specified or sha256(blob)).
     pub blob_id: String,
+    /// Version of the bootstrap format.
+    pub fs_version: RafsVersion,
 
     /// When filling local blobcache file, chunks are arranged as per the
     /// `decompress_offset` within chunk info. Therefore, provide a new flag
@@ -394,6 +418,8 @@ pub struct BuildContext {
     pub aligned_chunk: bool,
     /// Blob chunk compress flag.
     pub compressor: compress::Algorithm,
+    /// Compression level and minimum space savings of blob chunks.
+    pub compress_options: compress::CompressOptions,
     /// Inode and chunk digest algorithm flag.
     pub digester: digest::Algorithm,
     /// Save host uid gid in each inode.
@@ -413,14 +439,18 @@ pub struct BuildContext {
 
     /// Storage writing blob to single file or a directory.
     pub blob_storage: Option<BlobStorage>,
+    /// Number of worker threads to digest and compress chunks.
+    pub threads: usize,
 }
 
 impl BuildContext {
     #[allow(clippy::too_many_arguments)]
     pub fn new(
         blob_id: String,
+        fs_version: RafsVersion,
         aligned_chunk: bool,
         compressor: compress::Algorithm,
+        compress_options: compress::CompressOptions,
         digester: digest::Algorithm,
         explicit_uidgid: bool,
         whiteout_spec: WhiteoutSpec,
@@ -428,11 +458,14 @@ impl BuildContext {
         source_path: PathBuf,
         prefetch: Prefetch,
         blob_storage: Option<BlobStorage>,
+        threads: usize,
     ) -> Self {
         BuildContext {
             blob_id,
+            fs_version,
             aligned_chunk,
             compressor,
+            compress_options,
             digester,
             explicit_uidgid,
             whiteout_spec,
@@ -442,6 +475,7 @@ impl BuildContext {
 
             prefetch,
             blob_storage,
+            threads,
         }
     }
 }
@@ -20,9 +20,9 @@ impl BlobLayout {
         // NOTE: Don't try to sort readahead files by their sizes,  thus to keep files
         // belonging to the same directory arranged in adjacent in blob file. Together with
         // BFS style collecting descendants inodes, it will have a higher merging possibility.
-        // Later, we might write chunks of data one by one according to inode number order.
-        let mut readahead_files = prefetch.get_file_indexes();
-        readahead_files.sort_unstable();
+        // Readahead files are put in order of access if access traces are given, so that
+        // files accessed in a row are also adjacent in blob file.
+        let readahead_files = prefetch.get_file_indexes();
         for index in &readahead_files {
             let index = *index as usize - 1;
             let node = &nodes[index];
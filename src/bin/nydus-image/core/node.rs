@@ -5,12 +5,15 @@
 //! Node structure to store information for RAFS file system inode.
 
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::ffi::{OsStr, OsString};
 use std::fmt;
 use std::fs::{self, File};
 use std::io::prelude::*;
+use std::mem::size_of;
 use std::os::linux::fs::MetadataExt;
 use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileExt;
 use std::path::{Component, Path, PathBuf};
 use std::str;
 use std::str::FromStr;
@@ -20,17 +23,23 @@ use nix::sys::stat;
 use sha2::digest::Digest;
 
 use nydus_utils::{
-    digest::{DigestHasher, RafsDigest},
+    digest::{self, DigestHasher, RafsDigest},
     div_round_up, try_round_up_4k, ByteSize,
 };
 use rafs::metadata::layout::v5::{
     RafsChunkFlags, RafsV5ChunkInfo, RafsV5Inode, RafsV5InodeFlags, RafsV5InodeWrapper,
     RafsV5XAttrs,
 };
+use rafs::metadata::layout::v6::{
+    rafsv6_align, RafsV6Inode, RafsV6InodeChunkIndex, RafsV6InodeExt, RafsV6XAttrs,
+    EROFS_BLOCK_SIZE, EROFS_INODE_CHUNK_BASED, EROFS_INODE_FLAT_INLINE, EROFS_INODE_FLAT_PLAIN,
+    EROFS_INODE_SLOT_SIZE,
+};
 use rafs::metadata::{Inode, RafsStore, RAFS_DEFAULT_BLOCK_SIZE};
 use rafs::RafsIoWriter;
 use storage::compress;
 
+use crate::core::chunk_dict::ChunkDict;
 use crate::core::context::{BlobContext, BuildContext};
 
 const ROOT_PATH_NAME: &[u8] = &[b'/'];
@@ -192,6 +201,81 @@ impl fmt::Display for Node {
     }
 }
 
+/// Chunk digested and compressed ahead of being dumped into blob, so that chunks can be
+/// prepared concurrently.
+pub struct PreparedChunk {
+    pub block_id: RafsDigest,
+    pub decompress_size: u64,
+    /// Compressed data and whether it's really compressed, `None` if the chunk is found in
+    /// chunk dict so there is no need to compress it.
+    pub data: Option<(Vec<u8>, bool)>,
+}
+
+/// Information to read data chunks of a regular file, detached from `Node` so that it can be
+/// sent to worker threads.
+pub struct ChunkSource {
+    path: PathBuf,
+    size: u64,
+    chunk_count: u32,
+}
+
+impl ChunkSource {
+    pub fn chunk_count(&self) -> u32 {
+        self.chunk_count
+    }
+
+    pub fn chunk_size(&self, index: u32) -> u64 {
+        if index == self.chunk_count - 1 {
+            self.size - (RAFS_DEFAULT_BLOCK_SIZE * index as u64)
+        } else {
+            RAFS_DEFAULT_BLOCK_SIZE
+        }
+    }
+
+    pub fn open(&self) -> Result<File> {
+        File::open(&self.path).with_context(|| format!("failed to open node file {:?}", self.path))
+    }
+
+    /// Read, digest and compress the chunk at `index` of the file opened by `open()`.
+    pub fn prepare(
+        &self,
+        file: &File,
+        index: u32,
+        compressor: compress::Algorithm,
+        compress_options: &compress::CompressOptions,
+        digester: digest::Algorithm,
+        chunk_dict: Option<&dyn ChunkDict>,
+    ) -> Result<PreparedChunk> {
+        let chunk_size = self.chunk_size(index);
+        let mut chunk_data = vec![0u8; chunk_size as usize];
+        file.read_exact_at(&mut chunk_data, RAFS_DEFAULT_BLOCK_SIZE * index as u64)
+            .with_context(|| format!("failed to read node file {:?}", self.path))?;
+
+        // Calculate chunk digest
+        let block_id = RafsDigest::from_buf(&chunk_data, digester);
+
+        // Chunks in chunk dict are always deduplicated, so skip compressing them.
+        let in_dict = chunk_dict
+            .and_then(|d| d.get_chunk(&block_id))
+            .map(|c| c.decompress_size == 0 || c.decompress_size == chunk_size as u32)
+            .unwrap_or(false);
+        let data = if in_dict {
+            None
+        } else {
+            let (compressed, is_compressed) =
+                compress::compress_with_options(&chunk_data, compressor, compress_options)
+                    .with_context(|| format!("failed to compress node file {:?}", self.path))?;
+            Some((compressed.into_owned(), is_compressed))
+        };
+
+        Ok(PreparedChunk {
+            block_id,
+            decompress_size: chunk_size,
+            data,
+        })
+    }
+}
+
 impl Node {
     pub fn new(
         source: PathBuf,
@@ -231,12 +315,29 @@ impl Node {
         }
     }
 
+    /// Get information to read chunks of the node, `None` if it has no data chunk.
+    pub fn chunk_source(&self) -> Option<ChunkSource> {
+        if self.is_reg() && self.inode.i_child_count > 0 {
+            Some(ChunkSource {
+                path: self.path.clone(),
+                size: self.inode.i_size,
+                chunk_count: self.inode.i_child_count,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Dump chunks prepared by `ChunkSource::prepare()` and handed out by `next_chunk` in order
+    /// into blob, chunks are deduplicated against chunk dict and `chunk_cache` here to make the
+    /// blob layout deterministic.
     pub fn dump_blob(
         self: &mut Node,
         ctx: &BuildContext,
         blob_ctx: &mut BlobContext,
         blob_index: u32,
         chunk_cache: &mut HashMap<RafsDigest, RafsV5ChunkInfo>,
+        mut next_chunk: impl FnMut() -> Result<PreparedChunk>,
     ) -> Result<u64> {
         if self.is_dir() {
             return Ok(0);
@@ -251,28 +352,18 @@ impl Node {
 
         let mut blob_size = 0u64;
         let mut inode_hasher = RafsDigest::hasher(ctx.digester);
-        let mut file = File::open(&self.path)
-            .with_context(|| format!("failed to open node file {:?}", self.path))?;
 
         for i in 0..self.inode.i_child_count {
+            let prepared = next_chunk()?;
             // FIXME: Should not assume that block size must be the default one.
             // Use the configured value instead!
             let file_offset = i as u64 * RAFS_DEFAULT_BLOCK_SIZE;
-            let chunk_size = if i == self.inode.i_child_count - 1 {
-                self.inode.i_size - (RAFS_DEFAULT_BLOCK_SIZE * i as u64)
-            } else {
-                RAFS_DEFAULT_BLOCK_SIZE
-            };
+            let chunk_size = prepared.decompress_size;
             let mut chunk = RafsV5ChunkInfo::new();
 
-            let mut chunk_data = &mut blob_ctx.chunk_data_buf[0..chunk_size as usize];
-            file.read_exact(&mut chunk_data)
-                .with_context(|| format!("failed to read node file {:?}", self.path))?;
-
-            // Calculate chunk digest
             // TODO: check for hole chunks. One possible way is to always save
             // a global hole chunk and check for digest duplication
-            chunk.block_id = RafsDigest::from_buf(chunk_data, ctx.digester);
+            chunk.block_id = prepared.block_id;
             // Calculate inode digest
             inode_hasher.digest_update(chunk.block_id.as_ref());
 
@@ -324,9 +415,13 @@ impl Node {
                 }
             }
 
-            // Compress chunk data
-            let (compressed, is_compressed) = compress::compress(&chunk_data, ctx.compressor)
-                .with_context(|| format!("failed to compress node file {:?}", self.path))?;
+            let (compressed, is_compressed) = prepared.data.with_context(|| {
+                format!("chunk {} of node file {:?} is not compressed", i, self.path)
+            })?;
+            if !is_compressed && !ctx.compressor.is_none() {
+                // Compression doesn't save enough space, chunk is stored uncompressed.
+                event_tracer!("uncompressed_chunks", +1);
+            }
             let compressed_size = compressed.len();
             blob_size += compressed_size as u64;
 
@@ -357,7 +452,7 @@ impl Node {
             }
 
             if is_compressed {
-                chunk.flags |= RafsChunkFlags::COMPRESSED;
+                chunk.flags |= RafsChunkFlags::COMPRESSED | ctx.compressor.into();
             }
             chunk.blob_index = blob_index;
             chunk.file_offset = file_offset;
@@ -421,6 +516,156 @@ impl Node {
         Ok(node_size)
     }
 
+    /// Build the RAFS v6 inode and inline xattrs of the node.
+    ///
+    /// Size and block address of directories are left to the caller, which are known only after
+    /// all inodes have been laid out.
+    pub fn new_rafsv6_inode(&self) -> Result<(RafsV6Inode, RafsV6XAttrs)> {
+        let xattrs = RafsV6XAttrs::new(&self.xattrs)
+            .with_context(|| format!("failed to convert xattrs of {:?}", self.path))?;
+        let mut inode = RafsV6Inode::new();
+
+        inode.i_xattr_icount = xattrs.icount();
+        inode.i_mode = self.inode.i_mode as u16;
+        inode.i_ino = u32::try_from(self.inode.i_ino).context("too many inodes")?;
+        inode.i_uid = self.inode.i_uid;
+        inode.i_gid = self.inode.i_gid;
+        inode.i_mtime = self.inode.i_mtime;
+        inode.i_mtime_nsec = self.inode.i_mtime_nsec;
+        inode.i_nlink = self.inode.i_nlink;
+
+        if self.is_dir() {
+            inode.set_data_layout(EROFS_INODE_FLAT_PLAIN);
+        } else if self.is_symlink() {
+            let size = self.symlink.as_ref().map(|s| s.byte_size()).unwrap_or(0);
+            inode.i_size = size as u64;
+            // Inline short symlinks into the inode, EROFS requires inline data not to cross
+            // block boundary.
+            if size_of::<RafsV6Inode>() + xattrs.size() + size < EROFS_BLOCK_SIZE as usize {
+                inode.set_data_layout(EROFS_INODE_FLAT_INLINE);
+            } else {
+                inode.set_data_layout(EROFS_INODE_FLAT_PLAIN);
+            }
+        } else if self.is_reg() {
+            inode.i_size = self.inode.i_size;
+            inode.set_data_layout(EROFS_INODE_CHUNK_BASED);
+            inode.set_chunk_size(RAFS_DEFAULT_BLOCK_SIZE)?;
+        } else {
+            inode.set_data_layout(EROFS_INODE_FLAT_PLAIN);
+            inode.i_u = self.inode.i_rdev;
+        }
+
+        Ok((inode, xattrs))
+    }
+
+    /// Build the RAFS v6 inode extension carrying nydus specific information of the node.
+    pub fn new_rafsv6_inode_ext(&self) -> RafsV6InodeExt {
+        let mut ext = RafsV6InodeExt::new();
+
+        ext.e_digest = self.inode.i_digest;
+        ext.e_parent = self.inode.i_parent;
+        ext.e_blocks = self.inode.i_blocks;
+        ext.e_flags = self.inode.i_flags;
+        ext.e_child_index = self.inode.i_child_index;
+        ext.e_child_count = self.inode.i_child_count;
+        ext.e_projid = self.inode.i_projid;
+        ext.e_name_size = self.inode.i_name_size;
+
+        ext
+    }
+
+    /// Get size of the node in RAFS v6 bootstrap, aligned to EROFS inode slot size.
+    pub fn rafsv6_size(&self, inode: &RafsV6Inode) -> usize {
+        let size = inode.ext_offset() + self.new_rafsv6_inode_ext().size(inode);
+        let slot = EROFS_INODE_SLOT_SIZE as usize;
+
+        (size + slot - 1) / slot * slot
+    }
+
+    /// Dump the node into RAFS v6 bootstrap, the writer must be positioned at the inode slot.
+    pub fn dump_bootstrap_v6(
+        &self,
+        f_bootstrap: &mut RafsIoWriter,
+        inode: &RafsV6Inode,
+        xattrs: &RafsV6XAttrs,
+    ) -> Result<usize> {
+        let mut node_size = inode
+            .store(f_bootstrap)
+            .context("failed to dump inode to bootstrap")?;
+        node_size += xattrs
+            .store(f_bootstrap)
+            .context("failed to dump xattr to bootstrap")?;
+
+        match inode.data_layout() {
+            EROFS_INODE_CHUNK_BASED => {
+                if self.inode.i_child_count as usize != self.chunks.len() {
+                    bail!("invalid chunks count {}: {}", self.chunks.len(), self);
+                }
+                node_size += Self::dump_padding(f_bootstrap, rafsv6_align(node_size) - node_size)?;
+
+                let chunk_size = inode.chunk_size();
+                let mut chunks = self.chunks.iter().peekable();
+                for idx in 0..inode.chunk_index_count() {
+                    let index = match chunks.peek() {
+                        Some(c) if c.file_offset == idx as u64 * chunk_size => {
+                            let c = chunks.next().unwrap();
+                            RafsV6InodeChunkIndex::new(c.blob_index, c.decompress_offset)
+                                .with_context(|| {
+                                    format!("chunk {} of {:?} isn't block aligned", c, self.path)
+                                })?
+                        }
+                        _ => RafsV6InodeChunkIndex::hole(),
+                    };
+                    node_size += index
+                        .store(f_bootstrap)
+                        .context("failed to dump chunk index to bootstrap")?;
+                }
+            }
+            EROFS_INODE_FLAT_INLINE => {
+                if let Some(symlink) = self.symlink.as_ref() {
+                    f_bootstrap
+                        .write_all(symlink.as_bytes())
+                        .context("failed to dump symlink to bootstrap")?;
+                    node_size += symlink.byte_size();
+                }
+            }
+            _ => {}
+        }
+
+        node_size += Self::dump_padding(f_bootstrap, inode.ext_offset() - node_size)?;
+        node_size += self
+            .new_rafsv6_inode_ext()
+            .store(f_bootstrap)
+            .context("failed to dump inode extension to bootstrap")?;
+
+        let name = self.name().as_bytes();
+        f_bootstrap
+            .write_all(name)
+            .context("failed to dump file name to bootstrap")?;
+        node_size += name.len();
+        node_size += Self::dump_padding(f_bootstrap, rafsv6_align(name.len()) - name.len())?;
+
+        if inode.is_reg() {
+            for chunk in &self.chunks {
+                node_size += chunk
+                    .store(f_bootstrap)
+                    .context("failed to dump chunk info to bootstrap")?;
+            }
+        }
+
+        let slot = EROFS_INODE_SLOT_SIZE as usize;
+        node_size += Self::dump_padding(f_bootstrap, (slot - node_size % slot) % slot)?;
+
+        Ok(node_size)
+    }
+
+    fn dump_padding(f_bootstrap: &mut RafsIoWriter, size: usize) -> Result<usize> {
+        f_bootstrap
+            .write_padding(size)
+            .context("failed to dump padding to bootstrap")?;
+        Ok(size)
+    }
+
     fn build_inode_xattr(&mut self) -> Result<()> {
         let file_xattrs = match xattr::list(&self.path) {
             Ok(x) => x,
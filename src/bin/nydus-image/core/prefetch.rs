@@ -2,12 +2,14 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use anyhow::{Context, Error, Result};
 
+use nydus_utils::metrics::{AccessTrace, ACCESS_TRACE_VERSION};
 use rafs::metadata::layout::v5::RafsV5PrefetchTable;
 
 use crate::node::*;
@@ -84,6 +86,61 @@ fn gather_readahead_patterns() -> Result<BTreeMap<PathBuf, Option<u64>>> {
     Ok(files)
 }
 
+/// Load access traces exported by `nydusctl access-trace`, and merge them into a list of files
+/// ordered by the median of their first access time among all runs where they're accessed.
+fn gather_access_traces(trace_paths: &[&Path]) -> Result<Vec<PathBuf>> {
+    let mut traces = Vec::with_capacity(trace_paths.len());
+    for path in trace_paths {
+        let file =
+            File::open(path).with_context(|| format!("failed to open access trace {:?}", path))?;
+        let trace: AccessTrace = serde_json::from_reader(file)
+            .with_context(|| format!("failed to parse access trace {:?}", path))?;
+        if trace.version != ACCESS_TRACE_VERSION {
+            bail!(
+                "unsupported version {} of access trace {:?}",
+                trace.version,
+                path
+            );
+        }
+        traces.push(trace);
+    }
+
+    Ok(merge_access_traces(traces))
+}
+
+fn merge_access_traces(traces: Vec<AccessTrace>) -> Vec<PathBuf> {
+    let mut times: HashMap<PathBuf, Vec<u64>> = HashMap::new();
+    for trace in traces {
+        for entry in trace.files {
+            if !entry.path.starts_with(Path::new("/")) {
+                warn!("Illegal file path {:?} in access trace", entry.path);
+                continue;
+            }
+            times
+                .entry(entry.path)
+                .or_default()
+                .push(entry.first_access_usecs);
+        }
+    }
+
+    let mut files: Vec<(u64, PathBuf)> = times
+        .into_iter()
+        .map(|(path, mut t)| {
+            t.sort_unstable();
+            let mid = t.len() / 2;
+            let median = if t.len() % 2 == 0 {
+                (t[mid - 1] + t[mid]) / 2
+            } else {
+                t[mid]
+            };
+            (median, path)
+        })
+        .collect();
+    files.sort_unstable();
+
+    files.into_iter().map(|(_, path)| path).collect()
+}
+
 #[derive(Default, Clone)]
 pub struct Prefetch {
     pub policy: PrefetchPolicy,
@@ -97,11 +154,30 @@ pub struct Prefetch {
     /// Files from this collection are all regular files and will be persisted to blob following
     /// a certain scheme.
     readahead_files: BTreeMap<PathBuf, u64>,
+
+    /// Rank of files by their access order recorded in access traces, files ranked first are
+    /// put first in prefetch table and blob. Empty if no access trace is given.
+    access_order: HashMap<PathBuf, usize>,
 }
 
 impl Prefetch {
-    pub fn new(policy: PrefetchPolicy) -> Result<Self> {
-        let readahead_patterns = if policy != PrefetchPolicy::None {
+    /// Create prefetch hints from files listed in stdin, or from access traces if any is given,
+    /// in which case `policy` defaults to `Fs`.
+    pub fn new(policy: PrefetchPolicy, trace_paths: &[&Path]) -> Result<Self> {
+        let mut policy = policy;
+        let mut access_order = HashMap::new();
+        let readahead_patterns = if !trace_paths.is_empty() {
+            if policy == PrefetchPolicy::None {
+                policy = PrefetchPolicy::Fs;
+            }
+            let files = gather_access_traces(trace_paths).context("failed to get access traces")?;
+            let mut patterns = BTreeMap::new();
+            for (rank, file) in files.into_iter().enumerate() {
+                patterns.insert(file.clone(), None);
+                access_order.insert(file, rank);
+            }
+            patterns
+        } else if policy != PrefetchPolicy::None {
             gather_readahead_patterns().context("failed to get readahead files")?
         } else {
             BTreeMap::new()
@@ -111,9 +187,15 @@ impl Prefetch {
             policy,
             readahead_patterns,
             readahead_files: BTreeMap::new(),
+            access_order,
         })
     }
 
+    // Files not in access traces, such as descendants of a hinted directory, are ranked last.
+    fn rank(&self, path: &Path) -> usize {
+        self.access_order.get(path).copied().unwrap_or(usize::MAX)
+    }
+
     pub fn insert_if_need(&mut self, node: &Node) {
         let path = node.target();
         let inode = node.inode.i_ino;
@@ -149,18 +231,32 @@ impl Prefetch {
         self.readahead_files.contains_key(node.target())
     }
 
+    /// Get inode indexes of readahead files, in order of access if access traces are given,
+    /// otherwise in order of inode index.
     pub fn get_file_indexes(&self) -> Vec<u64> {
-        let mut indexes: Vec<u64> = self.readahead_files.values().copied().collect();
+        let mut indexes: Vec<(usize, u64)> = self
+            .readahead_files
+            .iter()
+            .map(|(path, index)| (self.rank(path), *index))
+            .collect();
 
         // Later, we might write chunks of data one by one according to inode number order.
         indexes.sort_unstable();
-        indexes
+        indexes.into_iter().map(|(_, index)| index).collect()
     }
 
     pub fn get_rafsv5_prefetch_table(&mut self) -> Option<RafsV5PrefetchTable> {
         if self.policy == PrefetchPolicy::Fs {
             let mut prefetch_table = RafsV5PrefetchTable::new();
-            for i in self.readahead_patterns.values().filter_map(|v| *v) {
+            let mut entries: Vec<(usize, u64)> = self
+                .readahead_patterns
+                .iter()
+                .filter_map(|(path, v)| v.map(|i| (self.rank(path), i)))
+                .collect();
+            // Sort prefetch table by inode index within the same rank, hopefully, it can save
+            // time when mounting rafs because file data is dumped in the same order.
+            entries.sort_unstable();
+            for (_, i) in entries {
                 prefetch_table.add_entry(i as u32);
             }
             Some(prefetch_table)
@@ -173,3 +269,44 @@ impl Prefetch {
         self.readahead_files.clear();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nydus_utils::metrics::AccessTraceEntry;
+
+    fn trace(files: &[(&str, u64)]) -> AccessTrace {
+        AccessTrace {
+            version: ACCESS_TRACE_VERSION,
+            files: files
+                .iter()
+                .map(|(path, t)| AccessTraceEntry {
+                    path: PathBuf::from(path),
+                    first_access_usecs: *t,
+                    nr_read: 1,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_merge_access_traces() {
+        let traces = vec![
+            trace(&[("/a", 0), ("/b", 10), ("/c", 20)]),
+            trace(&[("/b", 0), ("/a", 30), ("/c", 40), ("relative", 50)]),
+            trace(&[("/a", 5), ("/c", 6), ("/d", 1)]),
+        ];
+
+        // Medians: /a 5, /b 5, /c 20, /d 1.
+        let files = merge_access_traces(traces);
+        assert_eq!(
+            files,
+            vec![
+                PathBuf::from("/d"),
+                PathBuf::from("/a"),
+                PathBuf::from("/b"),
+                PathBuf::from("/c"),
+            ]
+        );
+    }
+}
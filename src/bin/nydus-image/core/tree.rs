@@ -24,7 +24,7 @@ use anyhow::Result;
 
 use nydus_utils::digest::RafsDigest;
 use rafs::metadata::layout::v5::{
-    RafsChunkInfo, RafsV5ChunkInfo, RafsV5Inode, RafsV5InodeFlags, RafsV5XAttrs,
+    RafsChunkFlags, RafsChunkInfo, RafsV5ChunkInfo, RafsV5Inode, RafsV5InodeFlags, RafsV5XAttrs,
 };
 use rafs::metadata::layout::{bytes_to_os_str, RAFS_ROOT_INODE};
 use rafs::metadata::{Inode, RafsInode, RafsSuper};
@@ -354,7 +354,14 @@ impl<'a> MetadataTreeBuilder<'a> {
             let chunk_count = child_count;
             for i in 0..chunk_count {
                 let cki = inode.get_chunk_info(i)?;
-                let chunk = cast_rafsv5_chunk_info(cki.as_ref());
+                let mut chunk = cast_rafsv5_chunk_info(cki.as_ref());
+                // Chunks from old bootstraps don't record compression algorithm, record the
+                // global one so that they are still readable when compressor is changed.
+                if chunk.flags.contains(RafsChunkFlags::COMPRESSED)
+                    && chunk.flags.compressor().is_none()
+                {
+                    chunk.flags |= self.rs.meta.get_compressor().into();
+                }
                 chunks.push(chunk);
             }
         }
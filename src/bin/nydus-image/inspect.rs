@@ -134,6 +134,9 @@ impl RafsInspector {
         b.seek_to_offset(layout_profile.super_block_offset as u64)?;
         sb.load(b)
             .map_err(|e| anyhow!("Failed in loading super block, {:?}", e))?;
+        if !sb.detect() {
+            bail!("Unsupported bootstrap, only RAFS v4/v5 bootstrap can be inspected");
+        }
 
         Ok(sb)
     }
@@ -11,6 +11,7 @@ extern crate anyhow;
 #[macro_use]
 mod trace;
 
+mod blobcache;
 mod builder;
 mod core;
 mod inspect;
@@ -42,13 +43,14 @@ use crate::builder::stargz::StargzBuilder;
 use crate::builder::Builder;
 
 use crate::core::context::{
-    BlobManager, BlobStorage, BootstrapContext, BuildContext, SourceType, BUF_WRITER_CAPACITY,
+    BlobManager, BlobStorage, BootstrapContext, BuildContext, RafsVersion, SourceType,
+    BUF_WRITER_CAPACITY,
 };
 use crate::core::node::{self, WhiteoutSpec};
 use crate::core::prefetch::Prefetch;
 use crate::core::tree;
 
-use crate::core::chunk_dict::import_chunk_dict;
+use crate::core::chunk_dict::{check_chunk_dict, import_chunk_dict};
 use nydus_app::{setup_logging, BuildTimeInfo};
 use nydus_utils::digest;
 use rafs::RafsIoReader;
@@ -145,10 +147,26 @@ fn main() -> Result<()> {
                 .arg(
                     Arg::with_name("compressor")
                         .long("compressor")
-                        .help("how blob will be compressed: none, lz4_block (default)")
+                        .help("how blob will be compressed: none, lz4_block (default), gzip, zstd")
                         .takes_value(true)
                         .required(false)
-                        .default_value("lz4_block"),
+                        .default_value("lz4_block")
+                        .possible_values(&["none", "lz4_block", "gzip", "zstd"]),
+                )
+                .arg(
+                    Arg::with_name("compress-level")
+                        .long("compress-level")
+                        .help("compression level of blob chunks: 0-9 for gzip, 1-22 for zstd, lz4_block doesn't support it")
+                        .takes_value(true)
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("compress-min-savings")
+                        .long("compress-min-savings")
+                        .help("minimum space savings in percentage to compress a chunk, otherwise the chunk is stored uncompressed")
+                        .takes_value(true)
+                        .required(false)
+                        .default_value("0"),
                 )
                 .arg(
                     Arg::with_name("digester")
@@ -173,6 +191,15 @@ fn main() -> Result<()> {
                         .required(false)
                         .default_value("none"),
                 )
+                .arg(
+                    Arg::with_name("prefetch-trace")
+                        .long("prefetch-trace")
+                        .help("access trace files exported by `nydusctl access-trace`, files are prefetched in order of access rather than from stdin, with prefetch policy defaulting to fs")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .required(false),
+                )
                 .arg(
                     Arg::with_name("repeatable")
                     .long("repeatable")
@@ -208,6 +235,14 @@ fn main() -> Result<()> {
                         .help("Whether to align chunks into blobcache")
                         .takes_value(false)
                 )
+                .arg(
+                    Arg::with_name("fs-version")
+                        .long("fs-version")
+                        .help("Version of the bootstrap format, 6 is compatible with the in-kernel EROFS driver and implies --aligned-chunk")
+                        .takes_value(true)
+                        .default_value("5")
+                        .possible_values(&["5", "6"])
+                )
                 .arg(
                     Arg::with_name("blob-dir")
                         .long("blob-dir")
@@ -228,6 +263,13 @@ fn main() -> Result<()> {
                         .help("[deprecated!] Blob storage backend config - JSON string, only support localfs for compatibility")
                         .takes_value(true)
                 )
+                .arg(
+                    Arg::with_name("threads")
+                        .long("threads")
+                        .help("number of worker threads to digest and compress chunks")
+                        .takes_value(true)
+                        .default_value("1")
+                )
                 .arg(
                     Arg::with_name("chunk-dict")
                         .long("chunk-dict")
@@ -277,6 +319,68 @@ fn main() -> Result<()> {
                         .takes_value(true)
                 )
         )
+        .subcommand(
+            SubCommand::with_name("export-cache")
+                .about("Export cached chunks of an image from blobcache into an archive")
+                .arg(
+                    Arg::with_name("bootstrap")
+                        .long("bootstrap")
+                        .help("bootstrap file path (required)")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("work-dir")
+                        .long("work-dir")
+                        .help("blobcache work directory (required)")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("compressed")
+                        .long("compressed")
+                        .help("blobcache saves compressed chunk data, aka. `compressed = true` in cache config")
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .help("archive file path (required)")
+                        .required(true)
+                        .takes_value(true),
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("import-cache")
+                .about("Import an archive into blobcache, validating chunks against the image bootstrap")
+                .arg(
+                    Arg::with_name("bootstrap")
+                        .long("bootstrap")
+                        .help("bootstrap file path (required)")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("work-dir")
+                        .long("work-dir")
+                        .help("blobcache work directory (required)")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("compressed")
+                        .long("compressed")
+                        .help("blobcache saves compressed chunk data, aka. `compressed = true` in cache config")
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::with_name("input")
+                        .long("input")
+                        .help("archive file path exported by `export-cache` (required)")
+                        .required(true)
+                        .takes_value(true),
+                )
+        )
         .arg(
             Arg::with_name("log-level")
                 .long("log-level")
@@ -353,6 +457,21 @@ fn main() -> Result<()> {
             }
         }
 
+        let compress_options = compress::CompressOptions {
+            level: matches
+                .value_of("compress-level")
+                .map(|l| l.parse())
+                .transpose()
+                .context("invalid compression level")?,
+            // Safe to unwrap because it has default value.
+            min_savings: matches
+                .value_of("compress-min-savings")
+                .unwrap()
+                .parse()
+                .context("invalid minimum compression savings")?,
+        };
+        compress_options.validate(compressor)?;
+
         let bootstrap_path = Path::new(matches.value_of("bootstrap").unwrap());
 
         // Must specify a path to blob file.
@@ -407,9 +526,29 @@ fn main() -> Result<()> {
             .value_of("prefetch-policy")
             .unwrap_or_default()
             .parse()?;
-        let prefetch = Prefetch::new(prefetch_policy)?;
-
-        let aligned_chunk = matches.is_present("aligned-chunk");
+        let prefetch_traces: Vec<&Path> = matches
+            .values_of("prefetch-trace")
+            .map(|v| v.map(Path::new).collect())
+            .unwrap_or_default();
+        let prefetch = Prefetch::new(prefetch_policy, &prefetch_traces)?;
+
+        // Safe to unwrap because it has default value.
+        let fs_version: RafsVersion = matches.value_of("fs-version").unwrap().parse()?;
+        if fs_version == RafsVersion::V6 && source_type == SourceType::StargzIndex {
+            bail!("RAFS v6 can't be built from stargz index");
+        }
+        // EROFS reads chunks from blob cache files by block, so they must be aligned.
+        let aligned_chunk = matches.is_present("aligned-chunk") || fs_version == RafsVersion::V6;
+
+        // Safe to unwrap because it has default value.
+        let threads: usize = matches
+            .value_of("threads")
+            .unwrap()
+            .parse()
+            .context("invalid number of threads")?;
+        if threads == 0 {
+            bail!("number of threads should be greater than 0");
+        }
 
         let f_bootstrap = Box::new(BufWriter::with_capacity(
             BUF_WRITER_CAPACITY,
@@ -440,8 +579,10 @@ fn main() -> Result<()> {
 
         let mut build_ctx = BuildContext::new(
             blob_id,
+            fs_version,
             aligned_chunk,
             compressor,
+            compress_options,
             digester,
             !repeatable,
             whiteout_spec,
@@ -449,16 +590,17 @@ fn main() -> Result<()> {
             source_path,
             prefetch,
             blob_stor,
+            threads,
         );
 
         let mut bootstrap_ctx = BootstrapContext::new(f_bootstrap, f_parent_bootstrap);
         let mut blob_mgr = BlobManager::new();
 
         if let Some(chunk_dict_arg) = matches.value_of("chunk-dict") {
-            blob_mgr.set_chunk_dict(timing_tracer!(
-                { import_chunk_dict(chunk_dict_arg) },
-                "import_chunk_dict"
-            )?);
+            let chunk_dict =
+                timing_tracer!({ import_chunk_dict(chunk_dict_arg) }, "import_chunk_dict")?;
+            check_chunk_dict(chunk_dict.as_ref(), compressor, digester)?;
+            blob_mgr.set_chunk_dict(chunk_dict);
         }
 
         let mut builder: Box<dyn Builder> = match source_type {
@@ -532,5 +674,38 @@ fn main() -> Result<()> {
         }
     }
 
+    if let Some(matches) = cmd.subcommand_matches("export-cache") {
+        // Safe to unwrap since these arguments are required.
+        let bootstrap_path = Path::new(matches.value_of("bootstrap").unwrap());
+        let work_dir = Path::new(matches.value_of("work-dir").unwrap());
+        let output_path = Path::new(matches.value_of("output").unwrap());
+        let stat = blobcache::export(
+            bootstrap_path,
+            work_dir,
+            matches.is_present("compressed"),
+            output_path,
+        )?;
+
+        info!("Blobcache exported to {:?}: {:?}", output_path, stat);
+    }
+
+    if let Some(matches) = cmd.subcommand_matches("import-cache") {
+        // Safe to unwrap since these arguments are required.
+        let bootstrap_path = Path::new(matches.value_of("bootstrap").unwrap());
+        let work_dir = Path::new(matches.value_of("work-dir").unwrap());
+        let input_path = Path::new(matches.value_of("input").unwrap());
+        let stat = blobcache::import(
+            bootstrap_path,
+            work_dir,
+            matches.is_present("compressed"),
+            input_path,
+        )?;
+
+        if stat.invalid_chunks != 0 {
+            warn!("{} invalid chunks are skipped", stat.invalid_chunks);
+        }
+        info!("Blobcache imported into {:?}: {:?}", work_dir, stat);
+    }
+
     Ok(())
 }
@@ -360,6 +360,107 @@ Prefetch Merging Size:  {merging_size}
     }
 }
 
+pub(crate) struct CommandAccessTrace {}
+
+impl CommandAccessTrace {
+    pub async fn execute(
+        &self,
+        _raw: bool,
+        client: &NydusdClient,
+        params: Option<CommandParams>,
+    ) -> Result<()> {
+        let p = params.unwrap_or_default();
+        let path = match p.get("id") {
+            Some(id) => format!("metrics/pattern?format=trace&id={}", id),
+            None => "metrics/pattern?format=trace".to_string(),
+        };
+        let trace = client.get(&path).await?;
+
+        match p.get("output") {
+            Some(output) => {
+                std::fs::write(output, trace.to_string())?;
+                println!(
+                    "Access trace of {} files is saved to {}",
+                    trace["files"].as_array().map_or(0, |f| f.len()),
+                    output
+                );
+            }
+            None => println!("{}", trace.to_string()),
+        }
+
+        Ok(())
+    }
+}
+
+pub(crate) struct CommandPrefetch {}
+
+impl CommandPrefetch {
+    pub async fn execute(
+        &self,
+        raw: bool,
+        client: &NydusdClient,
+        params: Option<CommandParams>,
+    ) -> Result<()> {
+        let p = params.unwrap();
+
+        match p["action"].as_str() {
+            "add" => {
+                let files: Vec<&str> = p["files"].lines().collect();
+                let cmd = json!({ "files": files }).to_string();
+                client
+                    .post(
+                        "prefetch",
+                        Some(cmd),
+                        Some(vec![("mountpoint", &p["mountpoint"])]),
+                    )
+                    .await
+            }
+            "cancel" => {
+                client
+                    .delete(
+                        "prefetch",
+                        None,
+                        Some(vec![("mountpoint", &p["mountpoint"])]),
+                    )
+                    .await
+            }
+            _ => {
+                let progress = client.get("prefetch").await?;
+                if raw {
+                    println!("{}", progress.to_string());
+                } else {
+                    for (mountpoint, p) in progress.as_object().unwrap() {
+                        print!(
+                            r#"
+Mountpoint:             {mountpoint}
+State:                  {state}
+Planned Chunks:         {planned} = {planned_kb} KB
+Done Chunks:            {done} = {done_kb} KB
+Failed Chunks:          {failed} = {failed_kb} KB
+Cancelled Chunks:       {cancelled} = {cancelled_kb} KB
+Fetched Bytes:          {fetched} = {fetched_kb} KB
+"#,
+                            mountpoint = mountpoint,
+                            state = p["state"].as_str().unwrap_or_default(),
+                            planned = p["planned_chunks"],
+                            planned_kb = p["planned_bytes"].as_u64().unwrap() / 1024,
+                            done = p["done_chunks"],
+                            done_kb = p["done_bytes"].as_u64().unwrap() / 1024,
+                            failed = p["failed_chunks"],
+                            failed_kb = p["failed_bytes"].as_u64().unwrap() / 1024,
+                            cancelled = p["cancelled_chunks"],
+                            cancelled_kb = p["cancelled_bytes"].as_u64().unwrap() / 1024,
+                            fetched = p["fetched_bytes"],
+                            fetched_kb = p["fetched_bytes"].as_u64().unwrap() / 1024,
+                        );
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
 pub(crate) struct CommandMount {}
 
 impl CommandMount {
@@ -21,7 +21,8 @@ mod client;
 mod commands;
 
 use commands::{
-    CommandBackend, CommandBlobcache, CommandDaemon, CommandFsStats, CommandMount, CommandUmount,
+    CommandAccessTrace, CommandBackend, CommandBlobcache, CommandDaemon, CommandFsStats,
+    CommandMount, CommandPrefetch, CommandUmount,
 };
 
 #[tokio::main]
@@ -133,6 +134,62 @@ async fn main() -> Result<()> {
                         .takes_value(true)
                         .index(1),
                 ),
+        )
+        .subcommand(
+            SubCommand::with_name("access-trace")
+                .about("Export files in order of first access, for `nydus-image create --prefetch-trace`")
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .short("O")
+                        .help("Save the access trace into a file rather than print it")
+                        .required(false)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("id")
+                        .long("id")
+                        .help("Rafs instance id, required if multiple rafs are mounted")
+                        .required(false)
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("prefetch")
+                .about("Prefetch files of a mounted rafs, cancel prefetch or query its progress")
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("Prefetch files or directories in background")
+                        .arg(
+                            Arg::with_name("mountpoint")
+                                .long("mountpoint")
+                                .short("m")
+                                .required(true)
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("files")
+                                .help("Absolute paths within the mount")
+                                .required(true)
+                                .multiple(true)
+                                .takes_value(true)
+                                .index(1),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("cancel")
+                        .about("Drop prefetch requests not issued to storage backend yet")
+                        .arg(
+                            Arg::with_name("mountpoint")
+                                .long("mountpoint")
+                                .short("m")
+                                .required(true)
+                                .takes_value(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("status").about("Show prefetch progress of all mounts"),
+                ),
         );
 
     let cmd = app.get_matches();
@@ -222,5 +279,37 @@ async fn main() -> Result<()> {
         cmd.execute(raw, &client, Some(context)).await?
     }
 
+    if let Some(matches) = cmd.subcommand_matches("access-trace") {
+        let mut context = HashMap::new();
+
+        matches
+            .value_of("output")
+            .map(|o| context.insert("output".to_string(), o.to_string()));
+        matches
+            .value_of("id")
+            .map(|i| context.insert("id".to_string(), i.to_string()));
+
+        let cmd = CommandAccessTrace {};
+        cmd.execute(raw, &client, Some(context)).await?
+    }
+
+    if let Some(matches) = cmd.subcommand_matches("prefetch") {
+        let mut context = HashMap::new();
+        let (action, matches) = matches.subcommand();
+        context.insert("action".to_string(), action.to_string());
+
+        if let Some(matches) = matches {
+            matches
+                .value_of("mountpoint")
+                .map(|m| context.insert("mountpoint".to_string(), m.to_string()));
+            matches
+                .values_of("files")
+                .map(|f| context.insert("files".to_string(), f.collect::<Vec<_>>().join("\n")));
+        }
+
+        let cmd = CommandPrefetch {};
+        cmd.execute(raw, &client, Some(context)).await?
+    }
+
     Ok(())
 }
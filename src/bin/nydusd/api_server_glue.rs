@@ -7,6 +7,7 @@ use std::convert::From;
 use std::str::FromStr;
 use std::sync::mpsc::{Receiver, Sender};
 use std::sync::Arc;
+use std::time::Duration;
 
 use event_manager::{EventOps, EventSubscriber, Events};
 use nix::sys::signal::{kill, SIGTERM};
@@ -14,10 +15,12 @@ use nix::unistd::Pid;
 use vmm_sys_util::{epoll::EventSet, eventfd::EventFd};
 
 use nydus_api::http_endpoint::{
-    ApiError, ApiMountCmd, ApiRequest, ApiResponse, ApiResponsePayload, ApiResult, DaemonConf,
-    DaemonErrorKind, MetricsErrorKind,
+    ApiError, ApiMountCmd, ApiRequest, ApiResponse, ApiResponsePayload, ApiResult, BlobcacheGcCmd,
+    BlobcacheScrubCmd, DaemonConf, DaemonErrorKind, MetricsErrorKind, PrefetchCmd,
 };
 use nydus_utils::metrics;
+#[cfg(feature = "backend-faulty")]
+use storage::backend::faulty;
 
 use crate::daemon::{DaemonError, FsBackendMountCmd, FsBackendUmountCmd, NydusDaemon};
 #[cfg(fusedev)]
@@ -79,10 +82,22 @@ impl ApiServer {
                 Self::export_files_metrics(id, latest_read_files)
             }
             ApiRequest::ExportAccessPatterns(id) => Self::export_access_patterns(id),
+            ApiRequest::ExportAccessTrace(id) => Self::export_access_trace(id),
             ApiRequest::ExportBackendMetrics(id) => Self::export_backend_metrics(id),
             ApiRequest::ExportBlobcacheMetrics(id) => Self::export_blobcache_metrics(id),
             ApiRequest::ExportInflightMetrics => self.export_inflight_metrics(),
             ApiRequest::ExportFsBackendInfo(mountpoint) => self.backend_info(&mountpoint),
+            #[cfg(feature = "backend-faulty")]
+            ApiRequest::ExportBackendFaults(id) => Self::export_backend_faults(id),
+            #[cfg(feature = "backend-faulty")]
+            ApiRequest::SetBackendFaults(id, faults) => Self::set_backend_faults(id, faults),
+            ApiRequest::BlobcacheGc(cmd) => self.blobcache_gc(cmd),
+            ApiRequest::BlobcacheScrub(cmd) => self.blobcache_scrub(cmd),
+            ApiRequest::BlobcacheScrubProgress => self.blobcache_scrub_progress(),
+            ApiRequest::Prefetch(mountpoint, cmd) => self.prefetch(mountpoint, cmd),
+            ApiRequest::CancelPrefetch(mountpoint) => self.cancel_prefetch(mountpoint),
+            ApiRequest::PrefetchProgress => self.prefetch_progress(),
+            ApiRequest::PrefetchEvents => Self::prefetch_events(),
             ApiRequest::SendFuseFd => self.send_fuse_fd(),
             ApiRequest::Takeover => self.do_takeover(),
             ApiRequest::Exit => self.do_exit(),
@@ -152,6 +167,12 @@ impl ApiServer {
             .map_err(|e| ApiError::Metrics(MetricsErrorKind::Stats(e)))
     }
 
+    fn export_access_trace(id: Option<String>) -> ApiResponse {
+        metrics::export_files_access_trace(&id)
+            .map(ApiResponsePayload::FsFilesPatterns)
+            .map_err(|e| ApiError::Metrics(MetricsErrorKind::Stats(e)))
+    }
+
     fn export_backend_metrics(id: Option<String>) -> ApiResponse {
         metrics::export_backend_metrics(&id)
             .map(ApiResponsePayload::BackendMetrics)
@@ -164,6 +185,68 @@ impl ApiServer {
             .map_err(|e| ApiError::Metrics(MetricsErrorKind::Stats(e)))
     }
 
+    #[cfg(feature = "backend-faulty")]
+    fn export_backend_faults(id: Option<String>) -> ApiResponse {
+        faulty::export_faults(&id)
+            .map(ApiResponsePayload::BackendFaults)
+            .map_err(ApiError::BackendFaults)
+    }
+
+    #[cfg(feature = "backend-faulty")]
+    fn set_backend_faults(id: Option<String>, faults: String) -> ApiResponse {
+        faulty::set_faults(&id, &faults)
+            .map(|_| ApiResponsePayload::Empty)
+            .map_err(ApiError::BackendFaults)
+    }
+
+    fn blobcache_gc(&self, cmd: BlobcacheGcCmd) -> ApiResponse {
+        let d = self.daemon.as_ref();
+        d.collect_blobcache_garbage(Duration::from_secs(cmd.grace_period), cmd.dry_run)
+            .map(ApiResponsePayload::BlobcacheGc)
+            .map_err(|e| ApiError::DaemonAbnormal(e.into()))
+    }
+
+    fn blobcache_scrub(&self, cmd: BlobcacheScrubCmd) -> ApiResponse {
+        let d = self.daemon.as_ref();
+        d.scrub_blobcache(cmd.rate)
+            .map(|_| ApiResponsePayload::Empty)
+            .map_err(|e| ApiError::DaemonAbnormal(e.into()))
+    }
+
+    fn blobcache_scrub_progress(&self) -> ApiResponse {
+        let d = self.daemon.as_ref();
+        d.blobcache_scrub_progress()
+            .map(ApiResponsePayload::BlobcacheScrubProgress)
+            .map_err(|e| ApiError::DaemonAbnormal(e.into()))
+    }
+
+    fn prefetch(&self, mountpoint: String, cmd: PrefetchCmd) -> ApiResponse {
+        let d = self.daemon.as_ref();
+        d.prefetch_files(&mountpoint, &cmd.files)
+            .map(|_| ApiResponsePayload::Empty)
+            .map_err(|e| ApiError::DaemonAbnormal(e.into()))
+    }
+
+    fn cancel_prefetch(&self, mountpoint: String) -> ApiResponse {
+        let d = self.daemon.as_ref();
+        d.cancel_prefetch(&mountpoint)
+            .map(|_| ApiResponsePayload::Empty)
+            .map_err(|e| ApiError::DaemonAbnormal(e.into()))
+    }
+
+    fn prefetch_progress(&self) -> ApiResponse {
+        let d = self.daemon.as_ref();
+        d.prefetch_progress()
+            .map(ApiResponsePayload::PrefetchProgress)
+            .map_err(|e| ApiError::DaemonAbnormal(e.into()))
+    }
+
+    fn prefetch_events() -> ApiResponse {
+        let events =
+            metrics::export_prefetch_events().map_err(|e| ApiError::Events(format!("{:?}", e)))?;
+        Ok(ApiResponsePayload::Events(events))
+    }
+
     /// Detect if there is fop being hang.
     /// `ApiResponsePayload::Empty` will be converted to http status code 204, which means
     /// there is no requests being processed right now.
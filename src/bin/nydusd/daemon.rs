@@ -6,7 +6,7 @@
 
 use std::any::Any;
 use std::cmp::PartialEq;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::convert::From;
 use std::fmt::{Display, Formatter};
 use std::io::Result;
@@ -20,6 +20,7 @@ use std::sync::{
     Arc, MutexGuard,
 };
 use std::thread;
+use std::time::Duration;
 use std::{error, fmt, io};
 
 use event_manager::{EventOps, EventSubscriber, Events};
@@ -39,8 +40,10 @@ use nydus::FsBackendType;
 use nydus_app::BuildTimeInfo;
 use rafs::{
     fs::{Rafs, RafsConfig},
+    metadata::RafsSuperBlobs,
     trim_backend_config, RafsError, RafsIoRead,
 };
+use storage::cache::blobcache;
 
 use crate::upgrade::{self, UpgradeManager, UpgradeMgrError};
 use crate::EVENT_MANAGER_RUN;
@@ -139,6 +142,12 @@ pub enum DaemonError {
     SessionShutdown(io::Error),
     Downcast(String),
     FsTypeMismatch(String),
+    /// Failed to collect garbage from blobcache work directories.
+    BlobcacheGc(io::Error),
+    /// Failed to start scrubbing blobcache.
+    BlobcacheScrub(io::Error),
+    /// Failed to start or cancel prefetch.
+    Prefetch(io::Error),
 }
 
 impl fmt::Display for DaemonError {
@@ -291,6 +300,111 @@ pub trait NydusDaemon: DaemonStateMachineSubscriber {
     }
     fn export_inflight_ops(&self) -> DaemonResult<Option<String>>;
 
+    /// Remove cache files of blobs not referenced by any mounted rafs from blobcache
+    /// work directories, return a json report of removed files.
+    fn collect_blobcache_garbage(
+        &self,
+        grace_period: Duration,
+        dry_run: bool,
+    ) -> DaemonResult<String> {
+        let mountpoints: Vec<String> = self.backend_collection().0.keys().cloned().collect();
+        let mut in_use = HashSet::new();
+        for mountpoint in mountpoints {
+            if let Some(fs) = self.backend_from_mountpoint(&mountpoint)? {
+                if let Some(rafs) = fs.deref().as_any().downcast_ref::<Rafs>() {
+                    for blob in rafs.sb.superblock.get_blobs() {
+                        in_use.insert(blob.blob_id.clone());
+                    }
+                }
+            }
+        }
+
+        let report = blobcache::collect_garbage(&in_use, grace_period, dry_run)
+            .map_err(DaemonError::BlobcacheGc)?;
+        serde_json::to_string(&report).map_err(DaemonError::Serde)
+    }
+
+    /// Start verifying cached data of all mounted rafs in background, mounts whose cache
+    /// doesn't support scrubbing are skipped.
+    fn scrub_blobcache(&self, rate: u32) -> DaemonResult<()> {
+        let mountpoints: Vec<String> = self.backend_collection().0.keys().cloned().collect();
+        for mountpoint in mountpoints {
+            if let Some(fs) = self.backend_from_mountpoint(&mountpoint)? {
+                if let Some(rafs) = fs.deref().as_any().downcast_ref::<Rafs>() {
+                    match rafs.scrub(rate) {
+                        Err(e) if e.raw_os_error() == Some(libc::ENOSYS) => {
+                            info!("Skip scrubbing cache of {}, {}", mountpoint, e)
+                        }
+                        r => r.map_err(DaemonError::BlobcacheScrub)?,
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Return a json map from mountpoint to progress of scrubbing its blobcache.
+    fn blobcache_scrub_progress(&self) -> DaemonResult<String> {
+        let mountpoints: Vec<String> = self.backend_collection().0.keys().cloned().collect();
+        let mut progress = HashMap::new();
+        for mountpoint in mountpoints {
+            if let Some(fs) = self.backend_from_mountpoint(&mountpoint)? {
+                if let Some(rafs) = fs.deref().as_any().downcast_ref::<Rafs>() {
+                    if let Some(p) = rafs.scrub_progress() {
+                        progress.insert(mountpoint, p);
+                    }
+                }
+            }
+        }
+
+        serde_json::to_string(&progress).map_err(DaemonError::Serde)
+    }
+
+    /// Prefetch files or directories of a mounted rafs in background.
+    fn prefetch_files(&self, mountpoint: &str, files: &[String]) -> DaemonResult<()> {
+        let fs = self
+            .backend_from_mountpoint(mountpoint)?
+            .ok_or(DaemonError::NotFound)?;
+        let rafs = fs
+            .deref()
+            .as_any()
+            .downcast_ref::<Rafs>()
+            .ok_or_else(|| DaemonError::FsTypeMismatch("to rafs".to_string()))?;
+        let files: Vec<PathBuf> = files.iter().map(PathBuf::from).collect();
+        rafs.prefetch_files(&files).map_err(DaemonError::Prefetch)
+    }
+
+    /// Drop prefetch requests of a mounted rafs not issued to storage backend yet.
+    fn cancel_prefetch(&self, mountpoint: &str) -> DaemonResult<()> {
+        let fs = self
+            .backend_from_mountpoint(mountpoint)?
+            .ok_or(DaemonError::NotFound)?;
+        let rafs = fs
+            .deref()
+            .as_any()
+            .downcast_ref::<Rafs>()
+            .ok_or_else(|| DaemonError::FsTypeMismatch("to rafs".to_string()))?;
+        rafs.cancel_prefetch().map_err(DaemonError::Prefetch)
+    }
+
+    /// Return a json map from mountpoint to its prefetch progress.
+    fn prefetch_progress(&self) -> DaemonResult<String> {
+        let mountpoints: Vec<String> = self.backend_collection().0.keys().cloned().collect();
+        let mut progress = HashMap::new();
+        for mountpoint in mountpoints {
+            if let Some(fs) = self.backend_from_mountpoint(&mountpoint)? {
+                if let Some(rafs) = fs.deref().as_any().downcast_ref::<Rafs>() {
+                    if let Some(p) = rafs.prefetch_progress() {
+                        progress.insert(mountpoint, p);
+                    }
+                }
+            }
+        }
+
+        serde_json::to_string(&progress).map_err(DaemonError::Serde)
+    }
+
     // NOTE: This method is not thread-safe, however, it is acceptable as
     // mount/umount/remount/restore_mount is invoked from single thread in FSM
     fn mount(&self, cmd: FsBackendMountCmd) -> DaemonResult<()> {
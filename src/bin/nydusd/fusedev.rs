@@ -408,7 +408,7 @@ pub fn create_nydus_daemon(
     fp: FailoverPolicy,
     mount_cmd: Option<FsBackendMountCmd>,
     bti: BuildTimeInfo,
-) -> Result<Arc<dyn NydusDaemon + Send>> {
+) -> Result<Arc<dyn NydusDaemon + Send + Sync>> {
     let (trigger, events_rx) = channel::<DaemonStateMachineInput>();
     let session = FuseSession::new(Path::new(mountpoint), "rafs", "")?;
 
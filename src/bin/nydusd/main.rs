@@ -27,6 +27,7 @@ use std::sync::{
     Arc, Mutex,
 };
 use std::thread;
+use std::time::Duration;
 use std::{io, process};
 
 use nix::sys::signal;
@@ -261,6 +262,26 @@ fn main() -> Result<()> {
                         Err("Input thread number is not legal".to_string())
                     }
                 }),
+        )
+        .arg(
+            Arg::with_name("blobcache-gc-interval")
+                .long("blobcache-gc-interval")
+                .default_value("0")
+                .help("Interval in seconds to remove cache files of blobs no longer used by any mount, 0 to disable")
+                .takes_value(true)
+                .required(false)
+                .global(true)
+                .validator(|v| v.parse::<u64>().map(|_| ()).map_err(|e| e.to_string())),
+        )
+        .arg(
+            Arg::with_name("blobcache-gc-grace-period")
+                .long("blobcache-gc-grace-period")
+                .default_value("3600")
+                .help("Cache files touched within the period in seconds are not removed by blobcache garbage collection")
+                .takes_value(true)
+                .required(false)
+                .global(true)
+                .validator(|v| v.parse::<u64>().map(|_| ()).map_err(|e| e.to_string())),
         );
 
     #[cfg(feature = "virtiofs")]
@@ -405,6 +426,30 @@ fn main() -> Result<()> {
         })?
     };
 
+    // Safe to unwrap because they have default values and are validated.
+    let gc_interval: u64 = cmd_arguments_parsed
+        .value_of("blobcache-gc-interval")
+        .unwrap()
+        .parse()
+        .unwrap();
+    let gc_grace_period: u64 = cmd_arguments_parsed
+        .value_of("blobcache-gc-grace-period")
+        .unwrap()
+        .parse()
+        .unwrap();
+    if gc_interval != 0 {
+        let d = daemon.clone();
+        thread::Builder::new()
+            .name("blobcache_gc".to_string())
+            .spawn(move || loop {
+                thread::sleep(Duration::from_secs(gc_interval));
+                match d.collect_blobcache_garbage(Duration::from_secs(gc_grace_period), false) {
+                    Ok(report) => info!("Blobcache garbage collected, {}", report),
+                    Err(e) => error!("Failed to collect blobcache garbage, {}", e),
+                }
+            })?;
+    }
+
     let mut http_thread: Option<thread::JoinHandle<Result<()>>> = None;
     let http_exit_evtfd = EventFd::new(0).unwrap();
     if let Some(apisock) = apisock {
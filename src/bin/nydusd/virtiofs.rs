@@ -297,7 +297,7 @@ pub fn create_nydus_daemon(
     vfs: Arc<Vfs>,
     mount_cmd: Option<FsBackendMountCmd>,
     bti: BuildTimeInfo,
-) -> Result<Arc<dyn NydusDaemon + Send>> {
+) -> Result<Arc<dyn NydusDaemon + Send + Sync>> {
     let vu_daemon = VhostUserDaemon::new(
         String::from("vhost-user-fs-backend"),
         Arc::new(RwLock::new(VhostUserFsBackendHandler::new(vfs.clone())?)),
@@ -0,0 +1,376 @@
+// Copyright 2021 Ant Group. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Resolve registry credentials from docker `config.json`, including the external
+//! credential helpers declared by `credHelpers` and `credsStore`.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs::File;
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DOCKER_HUB_SERVER: &str = "https://index.docker.io/v1/";
+const DOCKER_HUB_HOSTS: [&str; 4] = [
+    "docker.io",
+    "index.docker.io",
+    "registry-1.docker.io",
+    "registry.hub.docker.com",
+];
+const CREDENTIAL_HELPER_PREFIX: &str = "docker-credential-";
+const CREDENTIAL_HELPER_NOT_FOUND: &str = "credentials not found";
+// Credential helpers taking longer than it are killed.
+const CREDENTIAL_HELPER_TIMEOUT: Duration = Duration::from_secs(10);
+const CREDENTIAL_HELPER_POLL_INTERVAL: Duration = Duration::from_millis(10);
+// Username returned by credential helpers for identity tokens.
+const IDENTITY_TOKEN_USERNAME: &str = "<token>";
+
+#[derive(Default, Deserialize)]
+struct DockerConfig {
+    #[serde(default)]
+    auths: HashMap<String, DockerAuthConfig>,
+    #[serde(default, rename = "credHelpers")]
+    cred_helpers: HashMap<String, String>,
+    #[serde(default, rename = "credsStore")]
+    creds_store: String,
+}
+
+#[derive(Default, Deserialize)]
+struct DockerAuthConfig {
+    // Base64_encoded(username:password)
+    #[serde(default)]
+    auth: String,
+    #[serde(default)]
+    username: String,
+    #[serde(default)]
+    password: String,
+}
+
+#[derive(Deserialize)]
+struct HelperCredential {
+    #[serde(rename = "Username")]
+    username: String,
+    #[serde(rename = "Secret")]
+    secret: String,
+}
+
+/// Username and password to access a registry.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Credential {
+    pub username: String,
+    pub password: String,
+}
+
+impl Credential {
+    /// Decode credential from base64_encoded(username:password).
+    pub fn from_auth(auth: &str) -> Result<Self> {
+        let auth = base64::decode(auth.as_bytes()).map_err(|e| {
+            einval!(format!(
+                "Invalid base64 encoded registry auth config: {:?}",
+                e
+            ))
+        })?;
+        let auth = std::str::from_utf8(&auth).map_err(|e| {
+            einval!(format!(
+                "Invalid utf-8 encoded registry auth config: {:?}",
+                e
+            ))
+        })?;
+        let auth: Vec<&str> = auth.splitn(2, ':').collect();
+        if auth.len() < 2 {
+            return Err(einval!("Invalid registry auth config"));
+        }
+        Ok(Credential {
+            username: auth[0].to_string(),
+            password: auth[1].to_string(),
+        })
+    }
+
+    /// Get base64_encoded(username:password).
+    pub fn auth(&self) -> String {
+        base64::encode(format!("{}:{}", self.username, self.password))
+    }
+}
+
+/// Normalize registry server address in docker config, e.g. `https://my-registry.com/v1/`,
+/// to the registry host.
+fn normalize_host(server: &str) -> &str {
+    let host = server
+        .trim_start_matches("https://")
+        .trim_start_matches("http://");
+    let host = host.split('/').next().unwrap_or(host);
+    if DOCKER_HUB_HOSTS.contains(&host) {
+        DOCKER_HUB_HOSTS[1]
+    } else {
+        host
+    }
+}
+
+/// Resolver of registry credentials from a docker `config.json` file.
+///
+/// The config file is read on every `resolve()`, so the rotated credentials can be picked up.
+#[derive(Debug)]
+pub struct DockerCredentials {
+    config_path: PathBuf,
+    host: String,
+    // Directory to find credential helpers in, `$PATH` is searched if not set.
+    helper_dir: Option<PathBuf>,
+}
+
+impl DockerCredentials {
+    /// Create a resolver for registry `host`, the config file defaults to
+    /// `$DOCKER_CONFIG/config.json` or `$HOME/.docker/config.json` if `config_path` is empty.
+    pub fn new(config_path: &str, host: &str) -> Self {
+        let config_path = if !config_path.is_empty() {
+            PathBuf::from(config_path)
+        } else if let Some(dir) = env::var_os("DOCKER_CONFIG") {
+            Path::new(&dir).join("config.json")
+        } else {
+            Path::new(&env::var_os("HOME").unwrap_or_default()).join(".docker/config.json")
+        };
+
+        DockerCredentials {
+            config_path,
+            host: host.to_string(),
+            helper_dir: None,
+        }
+    }
+
+    /// Find credential helpers in `dir` instead of `$PATH`.
+    pub fn with_helper_dir(mut self, dir: &Path) -> Self {
+        self.helper_dir = Some(dir.to_path_buf());
+        self
+    }
+
+    /// Resolve credential for the registry, return `None` if no credential is configured.
+    pub fn resolve(&self) -> Result<Option<Credential>> {
+        let file = File::open(&self.config_path).map_err(|e| {
+            error!(
+                "Failed to open docker config {:?}: {:?}",
+                self.config_path, e
+            );
+            e
+        })?;
+        let config: DockerConfig = serde_json::from_reader(file).map_err(|e| einval!(e))?;
+        let host = normalize_host(&self.host);
+
+        if let Some((server, helper)) = config
+            .cred_helpers
+            .iter()
+            .find(|(server, _)| normalize_host(server) == host)
+        {
+            return self.exec_helper(helper, server);
+        }
+
+        let auth = config
+            .auths
+            .iter()
+            .find(|(server, _)| normalize_host(server) == host)
+            .map(|(_, auth)| auth);
+        if let Some(auth) = auth {
+            if !auth.auth.is_empty() {
+                return Credential::from_auth(&auth.auth).map(Some);
+            } else if !auth.username.is_empty() {
+                return Ok(Some(Credential {
+                    username: auth.username.clone(),
+                    password: auth.password.clone(),
+                }));
+            }
+        }
+
+        if !config.creds_store.is_empty() {
+            let server = if host == DOCKER_HUB_HOSTS[1] {
+                DOCKER_HUB_SERVER
+            } else {
+                host
+            };
+            return self.exec_helper(&config.creds_store, server);
+        }
+
+        Ok(None)
+    }
+
+    /// Get credential from docker credential helper as per the protocol:
+    /// https://github.com/docker/docker-credential-helpers
+    fn exec_helper(&self, helper: &str, server: &str) -> Result<Option<Credential>> {
+        let helper = format!("{}{}", CREDENTIAL_HELPER_PREFIX, helper);
+        let program = match self.helper_dir.as_ref() {
+            Some(dir) => dir.join(&helper),
+            None => PathBuf::from(&helper),
+        };
+        debug!("exec `{} get` for registry {}", helper, server);
+
+        let mut child = Command::new(&program)
+            .arg("get")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| {
+                error!(
+                    "Failed to exec docker credential helper {}: {:?}",
+                    helper, e
+                );
+                e
+            })?;
+        // Safe to unwrap because stdin, stdout and stderr are piped.
+        child.stdin.take().unwrap().write_all(server.as_bytes())?;
+        // Drain outputs in background so that the helper never blocks on full pipes.
+        let stdout = Self::read_output(child.stdout.take().unwrap());
+        let stderr = Self::read_output(child.stderr.take().unwrap());
+        let status = Self::wait_helper(&mut child, CREDENTIAL_HELPER_TIMEOUT).map_err(|e| {
+            error!(
+                "Failed to wait docker credential helper {}: {:?}",
+                helper, e
+            );
+            e
+        })?;
+        let stdout = stdout.join().unwrap_or_default();
+
+        if !status.success() {
+            if String::from_utf8_lossy(&stdout).contains(CREDENTIAL_HELPER_NOT_FOUND) {
+                return Ok(None);
+            }
+            return Err(eother!(format!(
+                "docker credential helper {} exited with {}: {}",
+                helper,
+                status,
+                String::from_utf8_lossy(&stderr.join().unwrap_or_default()).trim()
+            )));
+        }
+
+        let cred: HelperCredential = serde_json::from_slice(&stdout).map_err(|e| einval!(e))?;
+        if cred.username == IDENTITY_TOKEN_USERNAME {
+            warn!(
+                "Identity token from docker credential helper {} is not supported",
+                helper
+            );
+            return Ok(None);
+        }
+
+        Ok(Some(Credential {
+            username: cred.username,
+            password: cred.secret,
+        }))
+    }
+
+    fn read_output<R: Read + Send + 'static>(mut r: R) -> thread::JoinHandle<Vec<u8>> {
+        thread::spawn(move || {
+            let mut buf = Vec::new();
+            let _ = r.read_to_end(&mut buf);
+            buf
+        })
+    }
+
+    /// Wait for the helper to exit, kill it if it doesn't exit within `timeout`.
+    fn wait_helper(child: &mut Child, timeout: Duration) -> Result<ExitStatus> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(status) = child.try_wait()? {
+                return Ok(status);
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(Error::new(
+                    ErrorKind::TimedOut,
+                    format!("no response within {:?}", timeout),
+                ));
+            }
+            thread::sleep(CREDENTIAL_HELPER_POLL_INTERVAL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use vmm_sys_util::tempdir::TempDir;
+
+    #[test]
+    fn test_normalize_host() {
+        assert_eq!(
+            normalize_host("https://index.docker.io/v1/"),
+            "index.docker.io"
+        );
+        assert_eq!(normalize_host("registry-1.docker.io"), "index.docker.io");
+        assert_eq!(
+            normalize_host("http://my-registry:5000"),
+            "my-registry:5000"
+        );
+        assert_eq!(normalize_host("my-registry.com"), "my-registry.com");
+    }
+
+    #[test]
+    fn test_resolve_credential() {
+        let dir = TempDir::new().unwrap();
+        let config_path = dir.as_path().join("config.json");
+        let helper_path = dir.as_path().join("docker-credential-test");
+        fs::write(
+            &helper_path,
+            "#!/bin/sh\nread server\n\
+             [ \"$server\" = \"helper.com\" ] || { echo \"credentials not found\"; exit 1; }\n\
+             echo '{\"ServerURL\":\"helper.com\",\"Username\":\"bar\",\"Secret\":\"baz\"}'\n",
+        )
+        .unwrap();
+        fs::set_permissions(&helper_path, fs::Permissions::from_mode(0o755)).unwrap();
+
+        fs::write(
+            &config_path,
+            r#"{
+                "auths": {
+                    "https://index.docker.io/v1/": {"auth": "Zm9vOmJhcg=="},
+                    "other.com": {}
+                },
+                "credHelpers": {"helper.com": "test"},
+                "credsStore": "test"
+            }"#,
+        )
+        .unwrap();
+        let config_path = config_path.to_str().unwrap();
+
+        let cred = DockerCredentials::new(config_path, "registry-1.docker.io")
+            .with_helper_dir(dir.as_path())
+            .resolve()
+            .unwrap()
+            .unwrap();
+        assert_eq!(cred.username, "foo");
+        assert_eq!(cred.password, "bar");
+        assert_eq!(cred.auth(), "Zm9vOmJhcg==");
+
+        let cred = DockerCredentials::new(config_path, "helper.com")
+            .with_helper_dir(dir.as_path())
+            .resolve()
+            .unwrap()
+            .unwrap();
+        assert_eq!(cred.username, "bar");
+        assert_eq!(cred.password, "baz");
+
+        assert!(DockerCredentials::new(config_path, "other.com")
+            .with_helper_dir(dir.as_path())
+            .resolve()
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_credential_helper_timeout() {
+        let dir = TempDir::new().unwrap();
+        let helper_path = dir.as_path().join("docker-credential-hang");
+        fs::write(&helper_path, "#!/bin/sh\nexec sleep 60\n").unwrap();
+        fs::set_permissions(&helper_path, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut child = Command::new(&helper_path).spawn().unwrap();
+        let err =
+            DockerCredentials::wait_helper(&mut child, Duration::from_millis(100)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TimedOut);
+        // The helper should have been killed and reaped.
+        assert!(child.try_wait().unwrap().is_some());
+    }
+}
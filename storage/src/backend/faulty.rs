@@ -0,0 +1,433 @@
+// Copyright 2021 Ant Group. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Fault injecting backend for resilience testing.
+//!
+//! It wraps another backend and injects errors, latency, short reads and corrupted data into
+//! read requests matching the configured rules, which could be updated at runtime by
+//! `set_faults()` through the API server.
+
+use std::collections::HashMap;
+use std::io::Result;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::value::Value;
+
+use nydus_utils::metrics::BackendMetrics;
+
+use crate::backend::retry::RetryPolicy;
+use crate::backend::{BackendError, BackendResult, BlobBackend, CommonConfig};
+use crate::factory::{self, BackendConfig};
+use crate::utils::random;
+
+lazy_static! {
+    static ref FAULT_INJECTORS: RwLock<HashMap<String, Arc<FaultInjector>>> = Default::default();
+}
+
+#[derive(Debug)]
+pub enum FaultyError {
+    Injected(String),
+}
+
+impl From<FaultyError> for BackendError {
+    fn from(error: FaultyError) -> Self {
+        BackendError::Faulty(error)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FaultKind {
+    /// Fail the read request with a retryable error.
+    Error,
+    /// Delay the read request.
+    Latency,
+    /// Return half of the data read from the inner backend.
+    ShortRead,
+    /// Flip a random byte of the data read from the inner backend.
+    Corrupt,
+}
+
+fn default_probability() -> f64 {
+    1.0
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FaultRule {
+    fault: FaultKind,
+    /// Probability to inject the fault into a matching read request.
+    #[serde(default = "default_probability")]
+    probability: f64,
+    /// Only inject into reads of the blob, empty for all blobs.
+    #[serde(default)]
+    blob_id: String,
+    /// Only inject into reads overlapping with the blob range starting from `offset`.
+    #[serde(default)]
+    offset: u64,
+    /// Size of the blob range, 0 for the range to the end of blob.
+    #[serde(default)]
+    size: u64,
+    /// Delay of latency faults, in milliseconds.
+    #[serde(default)]
+    latency: u64,
+    /// Stop injecting the fault after the number of times, 0 for no limit.
+    #[serde(default)]
+    count: u64,
+    /// Number of times the fault has been injected.
+    #[serde(default, skip_deserializing)]
+    injected: AtomicU64,
+}
+
+impl FaultRule {
+    fn matches(&self, blob_id: &str, offset: u64, size: u64) -> bool {
+        if !self.blob_id.is_empty() && self.blob_id != blob_id {
+            return false;
+        }
+        let end = offset.saturating_add(size);
+        end > self.offset && (self.size == 0 || offset < self.offset.saturating_add(self.size))
+    }
+
+    // Decide whether to inject the fault into a matching read request.
+    fn trigger(&self) -> bool {
+        if self.probability < 1.0 && random() >= self.probability {
+            return false;
+        }
+        if self.count == 0 {
+            self.injected.fetch_add(1, Ordering::Relaxed);
+            return true;
+        }
+        self.injected
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+                if n < self.count {
+                    Some(n + 1)
+                } else {
+                    None
+                }
+            })
+            .is_ok()
+    }
+}
+
+fn parse_rules(rules: Value) -> Result<Vec<FaultRule>> {
+    let rules: Vec<FaultRule> = serde_json::from_value(rules).map_err(|e| einval!(e))?;
+    for rule in rules.iter() {
+        if !(0.0..=1.0).contains(&rule.probability) {
+            return Err(einval!(format!(
+                "fault probability {} is not in [0, 1]",
+                rule.probability
+            )));
+        }
+    }
+    Ok(rules)
+}
+
+#[derive(Debug, Default)]
+struct FaultInjector {
+    rules: RwLock<Arc<Vec<FaultRule>>>,
+}
+
+impl FaultInjector {
+    fn rules(&self) -> Arc<Vec<FaultRule>> {
+        self.rules.read().unwrap().clone()
+    }
+
+    fn set_rules(&self, rules: Vec<FaultRule>) {
+        *self.rules.write().unwrap() = Arc::new(rules);
+    }
+}
+
+fn get_injector(id: &Option<String>) -> Result<Arc<FaultInjector>> {
+    let injectors = FAULT_INJECTORS.read().unwrap();
+
+    match id {
+        Some(id) => injectors.get(id).cloned(),
+        None if injectors.len() == 1 => injectors.values().next().cloned(),
+        None => None,
+    }
+    .ok_or_else(|| enoent!("no faulty backend found"))
+}
+
+/// Replace fault rules of the faulty backend with `id`, which could be omitted if there is
+/// only one faulty backend.
+pub fn set_faults(id: &Option<String>, rules: &str) -> Result<()> {
+    let rules = parse_rules(serde_json::from_str(rules).map_err(|e| einval!(e))?)?;
+    get_injector(id)?.set_rules(rules);
+    Ok(())
+}
+
+/// Export fault rules and the number of injected faults of the faulty backend with `id`.
+pub fn export_faults(id: &Option<String>) -> Result<String> {
+    serde_json::to_string(get_injector(id)?.rules().as_ref()).map_err(|e| eother!(e))
+}
+
+#[derive(Clone, Deserialize)]
+struct FaultyConfig {
+    backend: BackendConfig,
+    #[serde(default)]
+    faults: Value,
+    // Retry of failed reads is controlled by the faulty backend, because faults are injected
+    // in front of the inner backend.
+    #[serde(flatten)]
+    common: CommonConfig,
+}
+
+pub struct Faulty {
+    inner: Arc<dyn BlobBackend + Send + Sync>,
+    injector: Arc<FaultInjector>,
+    retry_policy: RetryPolicy,
+    id: Option<String>,
+}
+
+impl Faulty {
+    fn with_backend(
+        inner: Arc<dyn BlobBackend + Send + Sync>,
+        rules: Vec<FaultRule>,
+        retry_policy: RetryPolicy,
+        id: Option<&str>,
+    ) -> Faulty {
+        let injector = Arc::new(FaultInjector::default());
+        injector.set_rules(rules);
+        if let Some(id) = id {
+            FAULT_INJECTORS
+                .write()
+                .unwrap()
+                .insert(id.to_string(), injector.clone());
+        }
+
+        Faulty {
+            inner,
+            injector,
+            retry_policy,
+            id: id.map(|i| i.to_string()),
+        }
+    }
+}
+
+pub fn new(config: Value, id: Option<&str>) -> Result<Faulty> {
+    let id = id.ok_or_else(|| einval!("faulty backend requires an id"))?;
+    let config: FaultyConfig = serde_json::from_value(config).map_err(|e| einval!(e))?;
+    let rules = if config.faults.is_null() {
+        Vec::new()
+    } else {
+        parse_rules(config.faults)?
+    };
+    if config.backend.backend_type == "faulty" {
+        return Err(einval!("faulty backend can't wrap another faulty backend"));
+    }
+    let inner = factory::new_backend(config.backend, id)?;
+
+    Ok(Faulty::with_backend(
+        inner,
+        rules,
+        config.common.retry_policy(),
+        Some(id),
+    ))
+}
+
+impl BlobBackend for Faulty {
+    fn prefetch_blob(
+        &self,
+        blob_id: &str,
+        blob_readahead_offset: u32,
+        blob_readahead_size: u32,
+    ) -> BackendResult<()> {
+        self.inner
+            .prefetch_blob(blob_id, blob_readahead_offset, blob_readahead_size)
+    }
+
+    fn release(&self) {
+        if let Some(id) = self.id.as_ref() {
+            FAULT_INJECTORS.write().unwrap().remove(id);
+        }
+        self.inner.release()
+    }
+
+    #[inline]
+    fn retry_policy(&self) -> Option<&RetryPolicy> {
+        Some(&self.retry_policy)
+    }
+
+    fn metrics(&self) -> &BackendMetrics {
+        self.inner.metrics()
+    }
+
+    fn blob_size(&self, blob_id: &str) -> BackendResult<u64> {
+        self.inner.blob_size(blob_id)
+    }
+
+    fn try_read(&self, blob_id: &str, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+        let mut short_read = false;
+        let mut corrupt = false;
+
+        for rule in self
+            .injector
+            .rules()
+            .iter()
+            .filter(|r| r.matches(blob_id, offset, buf.len() as u64))
+        {
+            if !rule.trigger() {
+                continue;
+            }
+            debug!(
+                "inject {:?} fault into read of blob {} offset {} size {}",
+                rule.fault,
+                blob_id,
+                offset,
+                buf.len()
+            );
+            match rule.fault {
+                FaultKind::Error => {
+                    return Err(FaultyError::Injected(format!(
+                        "read blob {} offset {} size {}",
+                        blob_id,
+                        offset,
+                        buf.len()
+                    ))
+                    .into())
+                }
+                FaultKind::Latency => thread::sleep(Duration::from_millis(rule.latency)),
+                FaultKind::ShortRead => short_read = true,
+                FaultKind::Corrupt => corrupt = true,
+            }
+        }
+
+        let mut len = self.inner.try_read(blob_id, buf, offset)?;
+        if short_read {
+            len /= 2;
+        }
+        if corrupt && len > 0 {
+            let pos = ((random() * len as f64) as usize).min(len - 1);
+            buf[pos] = !buf[pos];
+        }
+
+        Ok(len)
+    }
+
+    fn write(&self, blob_id: &str, buf: &[u8], offset: u64) -> BackendResult<usize> {
+        self.inner.write(blob_id, buf, offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::retry::{CircuitBreakerConfig, RetryConfig};
+    use serde_json::json;
+
+    // Backend filling read buffers with the low byte of blob offset.
+    struct MockBackend {
+        metrics: Arc<BackendMetrics>,
+    }
+
+    impl BlobBackend for MockBackend {
+        fn prefetch_blob(&self, _blob_id: &str, _offset: u32, _size: u32) -> BackendResult<()> {
+            Ok(())
+        }
+
+        fn release(&self) {}
+
+        fn metrics(&self) -> &BackendMetrics {
+            &self.metrics
+        }
+
+        fn blob_size(&self, _blob_id: &str) -> BackendResult<u64> {
+            Ok(0)
+        }
+
+        fn try_read(&self, _blob_id: &str, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+            for (i, b) in buf.iter_mut().enumerate() {
+                *b = (offset as usize + i) as u8;
+            }
+            Ok(buf.len())
+        }
+
+        fn write(&self, _blob_id: &str, _buf: &[u8], _offset: u64) -> BackendResult<usize> {
+            Err(BackendError::Unsupported("".to_string()))
+        }
+    }
+
+    fn new_faulty(rules: Value, retry_limit: u8, id: Option<&str>) -> Faulty {
+        let inner = Arc::new(MockBackend {
+            metrics: Arc::new(BackendMetrics::default()),
+        });
+        let config: RetryConfig = serde_json::from_value(json!({
+            "initial_interval": 1,
+        }))
+        .unwrap();
+        Faulty::with_backend(
+            inner,
+            parse_rules(rules).unwrap(),
+            RetryPolicy::new(retry_limit, config, CircuitBreakerConfig::default()),
+            id,
+        )
+    }
+
+    #[test]
+    fn test_fault_rule() {
+        assert!(parse_rules(json!([{"fault": "error", "probability": 2.0}])).is_err());
+        assert!(parse_rules(json!([{"fault": "unknown"}])).is_err());
+
+        let rules =
+            parse_rules(json!([{"fault": "error", "blob_id": "blob", "offset": 10, "size": 10}]))
+                .unwrap();
+        assert!(!rules[0].matches("other", 10, 1));
+        assert!(!rules[0].matches("blob", 0, 10));
+        assert!(rules[0].matches("blob", 0, 11));
+        assert!(rules[0].matches("blob", 19, 10));
+        assert!(!rules[0].matches("blob", 20, 10));
+
+        let rules = parse_rules(json!([{"fault": "error", "count": 2}])).unwrap();
+        assert!(rules[0].matches("blob", u64::MAX, 1));
+        assert!(rules[0].trigger());
+        assert!(rules[0].trigger());
+        assert!(!rules[0].trigger());
+
+        let rules = parse_rules(json!([{"fault": "error", "probability": 0.0}])).unwrap();
+        assert!(!rules[0].trigger());
+    }
+
+    #[test]
+    fn test_faulty_read() {
+        let mut buf = [0u8; 4];
+
+        // Injected errors are retried.
+        let backend = new_faulty(json!([{"fault": "error", "count": 2}]), 2, None);
+        assert_eq!(backend.read("blob", &mut buf, 0).unwrap(), 4);
+        let backend = new_faulty(json!([{"fault": "error", "count": 2}]), 1, None);
+        assert!(backend.read("blob", &mut buf, 0).is_err());
+
+        let backend = new_faulty(json!([{"fault": "short_read", "offset": 4}]), 0, None);
+        assert_eq!(backend.read("blob", &mut buf, 0).unwrap(), 4);
+        assert_eq!(backend.read("blob", &mut buf, 4).unwrap(), 2);
+
+        let backend = new_faulty(json!([{"fault": "corrupt", "blob_id": "blob"}]), 0, None);
+        assert_eq!(backend.read("blob", &mut buf, 4).unwrap(), 4);
+        assert_ne!(buf, [4, 5, 6, 7]);
+        assert_eq!(backend.read("other", &mut buf, 4).unwrap(), 4);
+        assert_eq!(buf, [4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn test_set_faults() {
+        let backend = new_faulty(json!([]), 0, Some("test_set_faults"));
+        let id = Some("test_set_faults".to_string());
+        let mut buf = [0u8; 4];
+
+        assert!(set_faults(&Some("unknown".to_string()), "[]").is_err());
+        assert!(set_faults(&id, "{}").is_err());
+        set_faults(&id, r#"[{"fault": "error", "count": 1}]"#).unwrap();
+        assert!(backend.read("blob", &mut buf, 0).is_err());
+        assert_eq!(backend.read("blob", &mut buf, 0).unwrap(), 4);
+
+        let faults: Value = serde_json::from_str(&export_faults(&id).unwrap()).unwrap();
+        assert_eq!(faults[0]["fault"], "error");
+        assert_eq!(faults[0]["injected"], 1);
+
+        backend.release();
+        assert!(export_faults(&id).is_err());
+    }
+}
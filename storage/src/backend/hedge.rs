@@ -0,0 +1,693 @@
+// Copyright 2021 Ant Group. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Hedged read to cut the tail latency of remote storage backends.
+//!
+//! The read is issued by a bounded pool of workers while the caller waits. If it has not
+//! completed after a percentile of recent read latency, an identical request is issued and the
+//! first one to complete wins. The losing one is cancelled once it transfers more data, the
+//! caller doesn't wait for it even if it's still connecting or waiting for the response header.
+//! Requests are read into buffers recycled among reads, so that only the winning data is copied
+//! into the caller's buffer.
+
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::io::{Error, ErrorKind, Result, Write};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use nydus_utils::metrics::BackendMetrics;
+
+use crate::backend::retry::RetryPolicy;
+use crate::backend::{BackendResult, BlobBackend};
+
+// Don't hedge until there are enough samples to estimate the latency percentile.
+const HEDGE_MIN_SAMPLES: usize = 32;
+// Number of threads kept issuing requests for a backend, more are spawned on demand if they're
+// all busy, so that slow requests don't hold back others.
+const HEDGE_WORKERS: usize = 4;
+// Upper bound of threads issuing requests for a backend, requests are left queued once there are
+// so many. It also bounds the number of read buffers kept for reuse.
+const HEDGE_MAX_WORKERS: usize = 32;
+// Index of the original request and the hedged one in `HedgeRequest`.
+const PRIMARY: usize = 0;
+const HEDGE: usize = 1;
+
+thread_local! {
+    // Cancellation flag of the read issued by the current thread.
+    static READ_CANCEL: RefCell<Option<Arc<AtomicBool>>> = RefCell::new(None);
+}
+
+/// Check whether the read issued by the current thread has been cancelled.
+pub(crate) fn read_cancelled() -> bool {
+    READ_CANCEL.with(|c| {
+        c.borrow()
+            .as_ref()
+            .map(|c| c.load(AtomicOrdering::Acquire))
+            .unwrap_or(false)
+    })
+}
+
+// Install the cancellation flag for reads issued by the current thread until dropped.
+struct CancelScope;
+
+impl CancelScope {
+    fn new(cancel: Arc<AtomicBool>) -> Self {
+        READ_CANCEL.with(|c| *c.borrow_mut() = Some(cancel));
+        CancelScope
+    }
+}
+
+impl Drop for CancelScope {
+    fn drop(&mut self) {
+        READ_CANCEL.with(|c| *c.borrow_mut() = None);
+    }
+}
+
+/// Writer to receive response body of remote backends into the read buffer, which fails once
+/// the read has lost to its hedged request.
+pub(crate) struct BodyWriter<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl<'a> BodyWriter<'a> {
+    pub(crate) fn new(buf: &'a mut [u8]) -> Self {
+        BodyWriter { buf, pos: 0 }
+    }
+}
+
+impl<'a> Write for BodyWriter<'a> {
+    fn write(&mut self, data: &[u8]) -> Result<usize> {
+        // Not `ErrorKind::Interrupted`, which is retried by `io::copy()`.
+        if read_cancelled() {
+            return Err(Error::new(ErrorKind::Other, "read cancelled"));
+        }
+        let len = std::cmp::min(data.len(), self.buf.len() - self.pos);
+        if len == 0 && !data.is_empty() {
+            return Err(Error::new(
+                ErrorKind::WriteZero,
+                "response body exceeds buffer",
+            ));
+        }
+        self.buf[self.pos..self.pos + len].copy_from_slice(&data[..len]);
+        self.pos += len;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HedgeConfig {
+    /// Enable hedged read.
+    enable: bool,
+    /// Issue the hedged request once the read has not completed after the percentile of
+    /// recent read latency.
+    percentile: f64,
+    /// Lower bound of the delay before issuing the hedged request, in milliseconds.
+    min_delay: u64,
+}
+
+impl Default for HedgeConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            percentile: 95.0,
+            min_delay: 10,
+        }
+    }
+}
+
+impl HedgeConfig {
+    pub fn enabled(&self) -> bool {
+        self.enable
+    }
+}
+
+enum HedgeState {
+    Pending,
+    Running,
+    Done(BackendResult<Vec<u8>>),
+    Cancelled,
+}
+
+impl HedgeState {
+    fn is_finished(&self) -> bool {
+        matches!(self, HedgeState::Done(_) | HedgeState::Cancelled)
+    }
+}
+
+// A read issued as the original request and the hedged one, indexed by `PRIMARY` and `HEDGE`.
+struct HedgeRequest {
+    blob_id: String,
+    size: usize,
+    offset: u64,
+    state: Mutex<[HedgeState; 2]>,
+    cond: Condvar,
+    // Set to cancel the request once the other one wins.
+    cancel: [Arc<AtomicBool>; 2],
+    queue: Weak<(Mutex<HedgeQueue>, Condvar)>,
+}
+
+impl HedgeRequest {
+    // Cancel the request `idx` which has lost with the state lock held, and drop it from the
+    // queue if it's not issued yet so that it doesn't wake up workers at the deadline.
+    fn cancel(&self, state: &mut [HedgeState; 2], idx: usize) {
+        self.cancel[idx].store(true, AtomicOrdering::Release);
+        if let HedgeState::Pending = state[idx] {
+            state[idx] = HedgeState::Cancelled;
+            if let Some(queue) = self.queue.upgrade() {
+                let mut queue = queue.0.lock().unwrap();
+                let requests = std::mem::take(&mut queue.requests).into_vec();
+                queue.requests = requests
+                    .into_iter()
+                    .filter(|q| !(std::ptr::eq(q.req.as_ref(), self) && q.idx == idx))
+                    .collect();
+            }
+        }
+    }
+}
+
+// Request queued to be issued at the deadline, ordered by deadline, the earliest first.
+struct QueuedHedge {
+    req: Arc<HedgeRequest>,
+    idx: usize,
+    deadline: Instant,
+}
+
+impl PartialEq for QueuedHedge {
+    fn eq(&self, other: &Self) -> bool {
+        self.deadline == other.deadline
+    }
+}
+
+impl Eq for QueuedHedge {}
+
+impl PartialOrd for QueuedHedge {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueuedHedge {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.deadline.cmp(&self.deadline)
+    }
+}
+
+#[derive(Default)]
+struct HedgeQueue {
+    requests: BinaryHeap<QueuedHedge>,
+    // Requests being issued by workers.
+    running: Vec<(Arc<HedgeRequest>, usize)>,
+    // Read buffers returned by callers, reused by workers.
+    buffers: Vec<Vec<u8>>,
+    shutdown: bool,
+    // Number of live workers and those waiting for requests.
+    workers: usize,
+    idle: usize,
+}
+
+struct HedgeWorker {
+    inner: Arc<dyn BlobBackend + Send + Sync>,
+    queue: Arc<(Mutex<HedgeQueue>, Condvar)>,
+}
+
+impl HedgeWorker {
+    // Spawn a detached worker accounted in `queue`, which is the locked queue of `worker`,
+    // unless there are `HEDGE_MAX_WORKERS` already.
+    fn spawn(worker: HedgeWorker, queue: &mut HedgeQueue) -> bool {
+        if queue.workers >= HEDGE_MAX_WORKERS {
+            return false;
+        }
+        match thread::Builder::new()
+            .name("nydus-hedged-read".to_string())
+            .spawn(move || worker.run())
+        {
+            Ok(_) => {
+                queue.workers += 1;
+                true
+            }
+            Err(e) => {
+                warn!("Failed to spawn hedged read thread: {:?}", e);
+                false
+            }
+        }
+    }
+
+    fn run(&self) {
+        while let Some((req, idx, buf)) = self.next() {
+            self.issue(&req, idx, buf);
+            let mut queue = self.queue.0.lock().unwrap();
+            queue
+                .running
+                .retain(|(r, i)| !(Arc::ptr_eq(r, &req) && *i == idx));
+        }
+    }
+
+    fn issue(&self, req: &HedgeRequest, idx: usize, mut data: Vec<u8>) {
+        {
+            let mut state = req.state.lock().unwrap();
+            match state[idx] {
+                HedgeState::Pending => state[idx] = HedgeState::Running,
+                _ => return,
+            }
+        }
+
+        if idx == HEDGE {
+            debug!(
+                "issue hedged read of blob {} offset {}",
+                req.blob_id, req.offset
+            );
+            self.inner.metrics().hedge_issued();
+        }
+        // Stale data of a recycled buffer is never returned, it's truncated to the read size.
+        if data.len() < req.size {
+            data.resize(req.size, 0);
+        }
+        data.truncate(req.size);
+        let ret = {
+            let _scope = CancelScope::new(req.cancel[idx].clone());
+            self.inner
+                .try_read(&req.blob_id, &mut data, req.offset)
+                .map(|len| {
+                    data.truncate(len);
+                    data
+                })
+        };
+
+        let mut state = req.state.lock().unwrap();
+        if ret.is_ok() {
+            req.cancel(&mut state, 1 - idx);
+        }
+        state[idx] = HedgeState::Done(ret);
+        req.cond.notify_all();
+    }
+
+    // Wait for the next request whose deadline has been reached, with a buffer to read into.
+    // Workers spawned on demand exit once there's nothing queued.
+    fn next(&self) -> Option<(Arc<HedgeRequest>, usize, Vec<u8>)> {
+        let (lock, cond) = &*self.queue;
+        let mut queue = lock.lock().unwrap();
+        queue.idle += 1;
+        let next = loop {
+            if queue.shutdown {
+                break None;
+            }
+            let now = Instant::now();
+            let timeout = match queue.requests.peek() {
+                Some(req) if req.deadline <= now => break queue.requests.pop(),
+                Some(req) => Some(req.deadline - now),
+                None if queue.workers > HEDGE_WORKERS => break None,
+                None => None,
+            };
+            queue = match timeout {
+                Some(timeout) => cond.wait_timeout(queue, timeout).unwrap().0,
+                None => cond.wait(queue).unwrap(),
+            };
+        };
+        queue.idle -= 1;
+        match next {
+            Some(QueuedHedge { req, idx, .. }) => {
+                queue.running.push((req.clone(), idx));
+                // Keep a worker waiting for the queued requests, which exits once the burst of
+                // slow requests is over.
+                if queue.idle == 0 && !queue.requests.is_empty() {
+                    let worker = HedgeWorker {
+                        inner: self.inner.clone(),
+                        queue: self.queue.clone(),
+                    };
+                    HedgeWorker::spawn(worker, &mut queue);
+                }
+                let buf = queue.buffers.pop().unwrap_or_default();
+                Some((req, idx, buf))
+            }
+            None => {
+                queue.workers -= 1;
+                None
+            }
+        }
+    }
+}
+
+/// Backend wrapper issuing hedged requests for slow reads of the inner backend.
+pub struct HedgedBackend {
+    inner: Arc<dyn BlobBackend + Send + Sync>,
+    percentile: f64,
+    min_delay: Duration,
+    queue: Arc<(Mutex<HedgeQueue>, Condvar)>,
+}
+
+impl HedgedBackend {
+    pub fn new(inner: Arc<dyn BlobBackend + Send + Sync>, config: &HedgeConfig) -> Self {
+        let queue = Arc::new((Mutex::new(HedgeQueue::default()), Condvar::new()));
+        {
+            let mut guard = queue.0.lock().unwrap();
+            for _ in 0..HEDGE_WORKERS {
+                let worker = HedgeWorker {
+                    inner: inner.clone(),
+                    queue: queue.clone(),
+                };
+                HedgeWorker::spawn(worker, &mut guard);
+            }
+        }
+
+        HedgedBackend {
+            inner,
+            percentile: config.percentile,
+            min_delay: Duration::from_millis(config.min_delay),
+            queue,
+        }
+    }
+
+    // Queue the read to be issued at once, and its hedged request to be issued after `delay`.
+    fn hedge(&self, blob_id: &str, size: usize, offset: u64, delay: Duration) -> Arc<HedgeRequest> {
+        let req = Arc::new(HedgeRequest {
+            blob_id: blob_id.to_string(),
+            size,
+            offset,
+            state: Mutex::new([HedgeState::Pending, HedgeState::Pending]),
+            cond: Condvar::new(),
+            cancel: [
+                Arc::new(AtomicBool::new(false)),
+                Arc::new(AtomicBool::new(false)),
+            ],
+            queue: Arc::downgrade(&self.queue),
+        });
+        let now = Instant::now();
+        let (lock, cond) = &*self.queue;
+        let mut queue = lock.lock().unwrap();
+        for (idx, deadline) in [(PRIMARY, now), (HEDGE, now + delay)].iter() {
+            queue.requests.push(QueuedHedge {
+                req: req.clone(),
+                idx: *idx,
+                deadline: *deadline,
+            });
+        }
+        if queue.idle == 0 {
+            let worker = HedgeWorker {
+                inner: self.inner.clone(),
+                queue: self.queue.clone(),
+            };
+            HedgeWorker::spawn(worker, &mut queue);
+        }
+        cond.notify_one();
+
+        req
+    }
+
+    // Return the buffer of a completed read to be reused by workers.
+    fn recycle(&self, buf: Vec<u8>) {
+        let mut queue = self.queue.0.lock().unwrap();
+        if queue.buffers.len() < HEDGE_MAX_WORKERS {
+            queue.buffers.push(buf);
+        }
+    }
+}
+
+impl Drop for HedgedBackend {
+    fn drop(&mut self) {
+        let (lock, cond) = &*self.queue;
+        let requests = {
+            let mut queue = lock.lock().unwrap();
+            queue.shutdown = true;
+            let mut requests: Vec<_> = queue.requests.drain().map(|q| q.req).collect();
+            requests.extend(queue.running.drain(..).map(|(req, _)| req));
+            requests
+        };
+        cond.notify_all();
+        // Workers are detached rather than joined, since they may be stuck in reads of the
+        // inner backend which never notice the cancellation.
+        for req in requests {
+            let mut state = req.state.lock().unwrap();
+            req.cancel(&mut state, PRIMARY);
+            req.cancel(&mut state, HEDGE);
+        }
+    }
+}
+
+impl BlobBackend for HedgedBackend {
+    fn prefetch_blob(
+        &self,
+        blob_id: &str,
+        blob_readahead_offset: u32,
+        blob_readahead_size: u32,
+    ) -> BackendResult<()> {
+        self.inner
+            .prefetch_blob(blob_id, blob_readahead_offset, blob_readahead_size)
+    }
+
+    fn release(&self) {
+        self.inner.release()
+    }
+
+    #[inline]
+    fn retry_policy(&self) -> Option<&RetryPolicy> {
+        self.inner.retry_policy()
+    }
+
+    fn metrics(&self) -> &BackendMetrics {
+        self.inner.metrics()
+    }
+
+    fn blob_size(&self, blob_id: &str) -> BackendResult<u64> {
+        self.inner.blob_size(blob_id)
+    }
+
+    fn try_read(&self, blob_id: &str, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+        let delay = match self
+            .metrics()
+            .latency_percentile(self.percentile, HEDGE_MIN_SAMPLES)
+        {
+            Some(delay) => delay.max(self.min_delay),
+            None => return self.inner.try_read(blob_id, buf, offset),
+        };
+
+        let req = self.hedge(blob_id, buf.len(), offset, delay);
+        let mut state = req.state.lock().unwrap();
+        loop {
+            for idx in [PRIMARY, HEDGE].iter() {
+                if let HedgeState::Done(Ok(_)) = state[*idx] {
+                    if *idx == HEDGE {
+                        self.metrics().hedge_won();
+                    }
+                    req.cancel(&mut state, 1 - *idx);
+                    let data = match std::mem::replace(&mut state[*idx], HedgeState::Cancelled) {
+                        HedgeState::Done(Ok(data)) => data,
+                        _ => unreachable!(),
+                    };
+                    drop(state);
+                    let len = data.len();
+                    buf[..len].copy_from_slice(&data);
+                    self.recycle(data);
+                    return Ok(len);
+                }
+            }
+            // Fail if the read fails before being hedged, otherwise the hedged request may
+            // still succeed.
+            if state[PRIMARY].is_finished() {
+                req.cancel(&mut state, HEDGE);
+            }
+            if state[PRIMARY].is_finished() && state[HEDGE].is_finished() {
+                let primary = std::mem::replace(&mut state[PRIMARY], HedgeState::Cancelled);
+                let hedge = std::mem::replace(&mut state[HEDGE], HedgeState::Cancelled);
+                match (primary, hedge) {
+                    (HedgeState::Done(Err(e)), _) | (_, HedgeState::Done(Err(e))) => return Err(e),
+                    // A request is only cancelled after the other one succeeds.
+                    _ => unreachable!(),
+                }
+            }
+            state = req.cond.wait(state).unwrap();
+        }
+    }
+
+    fn write(&self, blob_id: &str, buf: &[u8], offset: u64) -> BackendResult<usize> {
+        self.inner.write(blob_id, buf, offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::BackendError;
+    use std::sync::atomic::AtomicUsize;
+
+    // Backend whose read of sequence `slow_read` stalls until cancelled, or until `unstall()` if
+    // it stalls before receiving any data so can't notice the cancellation.
+    struct SlowBackend {
+        slow_read: usize,
+        reads: AtomicUsize,
+        cancelled: AtomicBool,
+        stalled: Option<(Mutex<bool>, Condvar)>,
+        metrics: Arc<BackendMetrics>,
+    }
+
+    impl SlowBackend {
+        fn new(slow_read: usize) -> Self {
+            SlowBackend {
+                slow_read,
+                reads: AtomicUsize::new(0),
+                cancelled: AtomicBool::new(false),
+                stalled: None,
+                metrics: Arc::new(BackendMetrics::default()),
+            }
+        }
+
+        fn new_stalled(slow_read: usize) -> Self {
+            SlowBackend {
+                stalled: Some((Mutex::new(true), Condvar::new())),
+                ..Self::new(slow_read)
+            }
+        }
+
+        fn unstall(&self) {
+            if let Some((lock, cond)) = self.stalled.as_ref() {
+                *lock.lock().unwrap() = false;
+                cond.notify_all();
+            }
+        }
+    }
+
+    impl BlobBackend for SlowBackend {
+        fn prefetch_blob(&self, _blob_id: &str, _offset: u32, _size: u32) -> BackendResult<()> {
+            Ok(())
+        }
+
+        fn release(&self) {}
+
+        fn metrics(&self) -> &BackendMetrics {
+            &self.metrics
+        }
+
+        fn blob_size(&self, _blob_id: &str) -> BackendResult<u64> {
+            Ok(0)
+        }
+
+        fn try_read(&self, _blob_id: &str, buf: &mut [u8], _offset: u64) -> BackendResult<usize> {
+            let seq = self.reads.fetch_add(1, AtomicOrdering::SeqCst);
+            if seq == self.slow_read {
+                if let Some((lock, cond)) = self.stalled.as_ref() {
+                    let mut stalled = lock.lock().unwrap();
+                    while *stalled {
+                        stalled = cond.wait(stalled).unwrap();
+                    }
+                    return Err(BackendError::Unsupported("stalled".to_string()));
+                }
+                // Stall until cancelled by the winning hedged request.
+                while !read_cancelled() {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                self.cancelled.store(true, AtomicOrdering::SeqCst);
+                return Err(BackendError::Unsupported("cancelled".to_string()));
+            }
+            for b in buf.iter_mut() {
+                *b = seq as u8;
+            }
+            Ok(buf.len())
+        }
+
+        fn write(&self, _blob_id: &str, _buf: &[u8], _offset: u64) -> BackendResult<usize> {
+            Err(BackendError::Unsupported("".to_string()))
+        }
+    }
+
+    #[test]
+    fn test_hedged_read() {
+        let inner = Arc::new(SlowBackend::new(HEDGE_MIN_SAMPLES));
+        let backend = HedgedBackend::new(inner.clone(), &HedgeConfig::default());
+        let mut buf = [0u8; 4];
+
+        // No hedging without enough latency samples.
+        for _ in 0..HEDGE_MIN_SAMPLES {
+            backend.read("blob", &mut buf, 0).unwrap();
+        }
+        assert_eq!(inner.reads.load(AtomicOrdering::SeqCst), HEDGE_MIN_SAMPLES);
+
+        // The slow read is hedged, the hedged request wins and cancels the slow one.
+        assert_eq!(backend.read("blob", &mut buf, 0).unwrap(), 4);
+        assert_eq!(buf, [HEDGE_MIN_SAMPLES as u8 + 1; 4]);
+        // The caller doesn't wait for the loser, which notices the cancellation on its own.
+        for _ in 0..1000 {
+            if inner.cancelled.load(AtomicOrdering::SeqCst) {
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(inner.cancelled.load(AtomicOrdering::SeqCst));
+        let metrics = serde_json::to_value(backend.metrics()).unwrap();
+        assert_eq!(metrics["hedges_issued"], 1);
+        assert_eq!(metrics["hedges_won"], 1);
+    }
+
+    #[test]
+    fn test_hedged_read_stalled_before_data() {
+        let inner = Arc::new(SlowBackend::new_stalled(HEDGE_MIN_SAMPLES));
+        let backend = HedgedBackend::new(inner.clone(), &HedgeConfig::default());
+        let mut buf = [0u8; 4];
+
+        for _ in 0..HEDGE_MIN_SAMPLES {
+            backend.read("blob", &mut buf, 0).unwrap();
+        }
+        // The hedged request wins while the slow read is still stalled, and the caller returns
+        // without waiting for it.
+        assert_eq!(backend.read("blob", &mut buf, 0).unwrap(), 4);
+        assert_eq!(buf, [HEDGE_MIN_SAMPLES as u8 + 1; 4]);
+        assert!(*inner.stalled.as_ref().unwrap().0.lock().unwrap());
+        let metrics = serde_json::to_value(backend.metrics()).unwrap();
+        assert_eq!(metrics["hedges_won"], 1);
+
+        inner.unstall();
+        drop(backend);
+    }
+
+    #[test]
+    fn test_hedged_read_not_issued() {
+        let inner = Arc::new(SlowBackend::new(usize::MAX));
+        let config = HedgeConfig {
+            enable: true,
+            percentile: 95.0,
+            min_delay: 60_000,
+        };
+        let backend = HedgedBackend::new(inner.clone(), &config);
+        let mut buf = [0u8; 4];
+
+        for _ in 0..HEDGE_MIN_SAMPLES {
+            backend.read("blob", &mut buf, 0).unwrap();
+        }
+        // Reads completing before the delay are not hedged, no request is issued on their behalf.
+        for _ in 0..4 {
+            backend.try_read("blob", &mut buf, 1).unwrap();
+        }
+        // Hedged requests of completed reads are dropped from the queue at once.
+        assert!(backend.queue.0.lock().unwrap().requests.is_empty());
+        drop(backend);
+        assert_eq!(
+            inner.reads.load(AtomicOrdering::SeqCst),
+            HEDGE_MIN_SAMPLES + 4
+        );
+        let metrics = serde_json::to_value(inner.metrics()).unwrap();
+        assert_eq!(metrics["hedges_issued"], 0);
+    }
+
+    #[test]
+    fn test_hedge_workers_bounded() {
+        let inner: Arc<dyn BlobBackend + Send + Sync> = Arc::new(SlowBackend::new(usize::MAX));
+        let queue = Arc::new((Mutex::new(HedgeQueue::default()), Condvar::new()));
+        let mut guard = queue.0.lock().unwrap();
+        guard.workers = HEDGE_MAX_WORKERS;
+        let worker = HedgeWorker {
+            inner,
+            queue: queue.clone(),
+        };
+        assert!(!HedgeWorker::spawn(worker, &mut guard));
+        assert_eq!(guard.workers, HEDGE_MAX_WORKERS);
+    }
+}
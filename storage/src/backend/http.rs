@@ -0,0 +1,244 @@
+// Copyright 2021 Ant Group. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Storage backend driver to access blobs on plain HTTP(S) servers with range requests,
+//! such as static file servers and CDNs.
+
+use std::collections::HashMap;
+use std::io::Result;
+use std::sync::Arc;
+
+use reqwest::header::{HeaderName, HeaderValue, CONTENT_LENGTH};
+use reqwest::{Method, StatusCode};
+use url::Url;
+
+use crate::backend::hedge::BodyWriter;
+use crate::backend::request::{HeaderMap, Request, RequestError};
+use crate::backend::retry::RetryPolicy;
+use crate::backend::{BackendError, BackendResult};
+use crate::backend::{BlobBackend, CommonConfig};
+
+use nydus_utils::metrics::BackendMetrics;
+
+const BLOB_ID_PLACEHOLDER: &str = "{blob_id}";
+
+#[derive(Debug)]
+pub enum HttpError {
+    Request(RequestError),
+    ConstructHeader(String),
+    Transport(reqwest::Error),
+    Response(String),
+}
+
+impl From<HttpError> for BackendError {
+    fn from(error: HttpError) -> Self {
+        BackendError::Http(error)
+    }
+}
+
+#[derive(Debug)]
+pub struct Http {
+    request: Arc<Request>,
+    // URL template to access blobs, `{blob_id}` is replaced with the blob id.
+    url: String,
+    // Static headers attached to every request.
+    headers: HeaderMap,
+    retry_policy: RetryPolicy,
+    metrics: Option<Arc<BackendMetrics>>,
+}
+
+#[derive(Clone, Deserialize)]
+struct HttpConfig {
+    /// URL template of blobs, for example:
+    /// url: https://cdn.example.com/blobs/{blob_id}
+    url: String,
+    /// Static headers sent with every request, for example authorization
+    /// tokens required by the CDN.
+    #[serde(default)]
+    headers: HashMap<String, String>,
+}
+
+pub fn new(config: serde_json::value::Value, id: Option<&str>) -> Result<Http> {
+    let common_config: CommonConfig =
+        serde_json::from_value(config.clone()).map_err(|e| einval!(e))?;
+    let retry_policy = common_config.retry_policy();
+    let request = Request::new(common_config)?;
+
+    let config: HttpConfig = serde_json::from_value(config).map_err(|e| einval!(e))?;
+
+    if !config.url.contains(BLOB_ID_PLACEHOLDER) {
+        return Err(einval!(format!(
+            "http backend url {} does not contain {}",
+            config.url, BLOB_ID_PLACEHOLDER
+        )));
+    }
+    let url = Url::parse(&config.url.replace(BLOB_ID_PLACEHOLDER, "blob"))
+        .map_err(|e| einval!(format!("invalid http backend url {}: {}", config.url, e)))?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(einval!(format!(
+            "unsupported scheme of http backend url {}",
+            config.url
+        )));
+    }
+
+    let mut headers = HeaderMap::new();
+    for (name, value) in config.headers.iter() {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|e| einval!(format!("invalid http header name {}: {}", name, e)))?;
+        let value = HeaderValue::from_str(value)
+            .map_err(|e| einval!(format!("invalid value of http header {}: {}", name, e)))?;
+        headers.insert(name, value);
+    }
+
+    Ok(Http {
+        request,
+        url: config.url,
+        headers,
+        retry_policy,
+        metrics: id.map(|i| BackendMetrics::new(i, "http")),
+    })
+}
+
+impl Http {
+    fn url(&self, blob_id: &str) -> String {
+        self.url.replace(BLOB_ID_PLACEHOLDER, blob_id)
+    }
+}
+
+impl BlobBackend for Http {
+    #[inline]
+    fn retry_policy(&self) -> Option<&RetryPolicy> {
+        Some(&self.retry_policy)
+    }
+
+    fn metrics(&self) -> &BackendMetrics {
+        // Safe because nydusd must have backend attached with id, only image builder can no id
+        // but use backend instance to upload blob.
+        self.metrics.as_ref().unwrap()
+    }
+
+    fn release(&self) {
+        self.metrics()
+            .release()
+            .unwrap_or_else(|e| error!("{:?}", e))
+    }
+
+    fn prefetch_blob(
+        &self,
+        _blob_id: &str,
+        _blob_readahead_offset: u32,
+        _blob_readahead_size: u32,
+    ) -> BackendResult<()> {
+        Err(BackendError::Unsupported(
+            "Http backend does not support prefetch as per on-disk blob entries".to_string(),
+        ))
+    }
+
+    fn blob_size(&self, blob_id: &str) -> BackendResult<u64> {
+        let url = self.url(blob_id);
+
+        let resp = self
+            .request
+            .call::<&[u8]>(
+                Method::HEAD,
+                url.as_str(),
+                None,
+                None,
+                self.headers.clone(),
+                true,
+            )
+            .map_err(HttpError::Request)?;
+
+        let content_length = resp
+            .headers()
+            .get(CONTENT_LENGTH)
+            .ok_or_else(|| HttpError::Response("invalid content length".to_string()))?;
+
+        Ok(content_length
+            .to_str()
+            .map_err(|err| HttpError::Response(format!("invalid content length: {:?}", err)))?
+            .parse::<u64>()
+            .map_err(|err| HttpError::Response(format!("invalid content length: {:?}", err)))?)
+    }
+
+    /// read ranged data from http server
+    fn try_read(&self, blob_id: &str, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+        let url = self.url(blob_id);
+
+        let mut headers = self.headers.clone();
+        let end_at = offset + buf.len() as u64 - 1;
+        let range = format!("bytes={}-{}", offset, end_at);
+        headers.insert(
+            "Range",
+            range
+                .as_str()
+                .parse()
+                .map_err(|e| HttpError::ConstructHeader(format!("{}", e)))?,
+        );
+
+        // Safe because the the call() is a synchronous operation.
+        let mut resp = self
+            .request
+            .call::<&[u8]>(Method::GET, url.as_str(), None, None, headers, true)
+            .map_err(HttpError::Request)?;
+
+        // Servers not supporting range requests respond the whole blob with 200 OK,
+        // which is only acceptable when it fits into the request range.
+        if resp.status() != StatusCode::PARTIAL_CONTENT
+            && (offset != 0 || resp.content_length().unwrap_or(u64::MAX) > buf.len() as u64)
+        {
+            return Err(HttpError::Response(format!(
+                "server does not support range request, status {}",
+                resp.status()
+            ))
+            .into());
+        }
+
+        Ok(resp
+            .copy_to(&mut BodyWriter::new(buf))
+            .map_err(HttpError::Transport)
+            .map(|size| size as usize)?)
+    }
+
+    fn write(&self, _blob_id: &str, _buf: &[u8], _offset: u64) -> BackendResult<usize> {
+        Err(BackendError::Unsupported(
+            "Http backend does not support write operation".to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_http_config() {
+        let config = serde_json::json!({
+            "url": "https://cdn.example.com/blobs/{blob_id}",
+            "headers": {"X-Token": "abc"},
+        });
+        let http = new(config, None).unwrap();
+        assert_eq!(http.url("xyz"), "https://cdn.example.com/blobs/xyz");
+        assert_eq!(http.headers.get("x-token").unwrap(), "abc");
+
+        new(
+            serde_json::json!({"url": "https://cdn.example.com/blobs/"}),
+            None,
+        )
+        .unwrap_err();
+        new(
+            serde_json::json!({"url": "ftp://cdn.example.com/{blob_id}"}),
+            None,
+        )
+        .unwrap_err();
+        new(
+            serde_json::json!({
+                "url": "https://cdn.example.com/{blob_id}",
+                "headers": {"X Token": "abc"},
+            }),
+            None,
+        )
+        .unwrap_err();
+    }
+}
@@ -2,27 +2,56 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
+use std::thread;
+
 use vm_memory::VolatileSlice;
 
 use nydus_utils::metrics::{BackendMetrics, ERROR_HOLDER};
 
+#[cfg(feature = "backend-faulty")]
+use crate::backend::faulty::FaultyError;
+use crate::backend::hedge::HedgeConfig;
+#[cfg(feature = "backend-http")]
+use crate::backend::http::HttpError;
 #[cfg(feature = "backend-localfs")]
 use crate::backend::localfs::LocalFsError;
+#[cfg(feature = "backend-oci")]
+use crate::backend::oci::OciError;
 #[cfg(feature = "backend-oss")]
 use crate::backend::oss::OssError;
 #[cfg(feature = "backend-registry")]
 use crate::backend::registry::RegistryError;
+use crate::backend::retry::{CircuitBreakerConfig, RetryConfig, RetryPolicy};
+#[cfg(feature = "backend-s3")]
+use crate::backend::s3::S3Error;
 use crate::utils::copyv;
 use crate::StorageError;
 
+#[cfg(feature = "backend-registry")]
+pub mod docker_config;
+#[cfg(feature = "backend-faulty")]
+pub mod faulty;
+pub mod hedge;
+#[cfg(feature = "backend-http")]
+pub mod http;
 #[cfg(feature = "backend-localfs")]
 pub mod localfs;
+#[cfg(feature = "backend-oci")]
+pub mod oci;
 #[cfg(feature = "backend-oss")]
 pub mod oss;
 #[cfg(feature = "backend-registry")]
 pub mod registry;
-#[cfg(any(feature = "backend-oss", feature = "backend-registry"))]
+#[cfg(any(
+    feature = "backend-http",
+    feature = "backend-oss",
+    feature = "backend-registry",
+    feature = "backend-s3"
+))]
 pub mod request;
+pub mod retry;
+#[cfg(feature = "backend-s3")]
+pub mod s3;
 
 #[derive(Debug, Clone, Deserialize)]
 #[serde(default)]
@@ -33,20 +62,99 @@ pub struct ProxyConfig {
     check_interval: u64,
 }
 
+/// TLS options for backends accessing storage servers through HTTPS.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TlsConfig {
+    /// PEM encoded CA bundle to verify server certificates, in addition to system CAs.
+    ca_file: String,
+    /// PEM encoded client certificate chain for mutual TLS.
+    cert_file: String,
+    /// PEM encoded private key of the client certificate.
+    key_file: String,
+    /// Disable verification of server certificates and host names, never use in production.
+    skip_verify: bool,
+    /// Interval in seconds to check cert files for changes and reload them, 0 to disable.
+    reload_interval: u64,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            ca_file: String::new(),
+            cert_file: String::new(),
+            key_file: String::new(),
+            skip_verify: false,
+            reload_interval: 10,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum BackendError {
     Unsupported(String),
     CopyData(StorageError),
+    CircuitOpen(String),
+    #[cfg(feature = "backend-faulty")]
+    Faulty(FaultyError),
     #[cfg(feature = "backend-registry")]
     Registry(RegistryError),
     #[cfg(feature = "backend-localfs")]
     LocalFs(LocalFsError),
+    #[cfg(feature = "backend-oci")]
+    Oci(OciError),
     #[cfg(feature = "backend-oss")]
     Oss(OssError),
+    #[cfg(feature = "backend-s3")]
+    S3(S3Error),
+    #[cfg(feature = "backend-http")]
+    Http(HttpError),
 }
 
 pub type BackendResult<T> = std::result::Result<T, BackendError>;
 
+impl BackendError {
+    /// Check whether the failed request may succeed if retried, errors caused by invalid
+    /// configuration or request like authentication failure and non-existent blob are not.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            BackendError::Unsupported(_)
+            | BackendError::CopyData(_)
+            | BackendError::CircuitOpen(_) => false,
+            #[cfg(feature = "backend-faulty")]
+            BackendError::Faulty(_) => true,
+            #[cfg(feature = "backend-registry")]
+            BackendError::Registry(e) => match e {
+                RegistryError::Request(e) => e.is_retryable(),
+                RegistryError::Url(_) | RegistryError::Scheme(_) | RegistryError::Auth(_) => false,
+                _ => true,
+            },
+            #[cfg(feature = "backend-localfs")]
+            BackendError::LocalFs(_) => false,
+            #[cfg(feature = "backend-oci")]
+            BackendError::Oci(_) => false,
+            #[cfg(feature = "backend-oss")]
+            BackendError::Oss(e) => match e {
+                OssError::Request(e) => e.is_retryable(),
+                OssError::Auth(_) | OssError::Url(_) | OssError::ConstructHeader(_) => false,
+                _ => true,
+            },
+            #[cfg(feature = "backend-s3")]
+            BackendError::S3(e) => match e {
+                S3Error::Request(e) => e.is_retryable(),
+                S3Error::Auth(_) | S3Error::ConstructHeader(_) => false,
+                _ => true,
+            },
+            #[cfg(feature = "backend-http")]
+            BackendError::Http(e) => match e {
+                HttpError::Request(e) => e.is_retryable(),
+                HttpError::ConstructHeader(_) => false,
+                _ => true,
+            },
+        }
+    }
+}
+
 impl Default for ProxyConfig {
     fn default() -> Self {
         Self {
@@ -62,22 +170,44 @@ impl Default for ProxyConfig {
 #[serde(default)]
 pub struct CommonConfig {
     proxy: ProxyConfig,
+    tls: TlsConfig,
     timeout: u64,
     connect_timeout: u64,
     retry_limit: u8,
+    retry_policy: RetryConfig,
+    circuit_breaker: CircuitBreakerConfig,
+    hedge: HedgeConfig,
 }
 
 impl Default for CommonConfig {
     fn default() -> Self {
         Self {
             proxy: ProxyConfig::default(),
+            tls: TlsConfig::default(),
             timeout: 5,
             connect_timeout: 5,
             retry_limit: 0,
+            retry_policy: RetryConfig::default(),
+            circuit_breaker: CircuitBreakerConfig::default(),
+            hedge: HedgeConfig::default(),
         }
     }
 }
 
+impl CommonConfig {
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy::new(
+            self.retry_limit,
+            self.retry_policy.clone(),
+            self.circuit_breaker.clone(),
+        )
+    }
+
+    pub fn hedge(&self) -> &HedgeConfig {
+        &self.hedge
+    }
+}
+
 /// Rafs blob backend API
 pub trait BlobBackend {
     /// prefetch blob if supported
@@ -90,9 +220,10 @@ pub trait BlobBackend {
 
     fn release(&self);
 
+    /// Policy to retry failed read requests, never retry if not available.
     #[inline]
-    fn retry_limit(&self) -> u8 {
-        0
+    fn retry_policy(&self) -> Option<&RetryPolicy> {
+        None
     }
 
     fn metrics(&self) -> &BackendMetrics;
@@ -102,22 +233,38 @@ pub trait BlobBackend {
 
     /// Read a range of data from blob into the provided slice
     fn read(&self, blob_id: &str, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
-        let mut retry_count = self.retry_limit();
+        let policy = self.retry_policy();
+        let mut retry_count = 0;
         let begin_time = self.metrics().begin();
         loop {
-            let ret = self.try_read(blob_id, buf, offset);
+            let ret = match policy {
+                Some(policy) if !policy.allow(self.metrics()) => Err(BackendError::CircuitOpen(
+                    "backend keeps failing, fail fast before cool down".to_string(),
+                )),
+                Some(policy) => {
+                    let ret = self.try_read(blob_id, buf, offset);
+                    policy.record(self.metrics(), matches!(&ret, Err(e) if e.is_retryable()));
+                    ret
+                }
+                None => self.try_read(blob_id, buf, offset),
+            };
             match ret {
                 Ok(size) => {
                     self.metrics().end(&begin_time, buf.len(), false);
                     return Ok(size);
                 }
                 Err(err) => {
-                    if retry_count > 0 {
+                    let backoff = policy
+                        .filter(|_| err.is_retryable())
+                        .and_then(|p| p.backoff(retry_count, &begin_time));
+                    if let Some(backoff) = backoff {
+                        retry_count += 1;
                         warn!(
-                            "Read from backend failed: {:?}, retry count {}",
-                            err, retry_count
+                            "Read from backend failed: {:?}, retry count {} after {:?}",
+                            err, retry_count, backoff
                         );
-                        retry_count -= 1;
+                        self.metrics().retry();
+                        thread::sleep(backoff);
                     } else {
                         self.metrics().end(&begin_time, buf.len(), true);
                         ERROR_HOLDER
@@ -168,7 +315,11 @@ pub trait BlobBackend {
     fn write(&self, blob_id: &str, buf: &[u8], offset: u64) -> BackendResult<usize>;
 }
 
-#[cfg(any(feature = "backend-oss", feature = "backend-registry"))]
+#[cfg(any(
+    feature = "backend-oss",
+    feature = "backend-registry",
+    feature = "backend-s3"
+))]
 fn default_http_scheme() -> String {
     "https".to_string()
 }
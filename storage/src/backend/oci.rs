@@ -0,0 +1,408 @@
+// Copyright 2021 Ant Group. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Storage backend driver to access blobs in OCI image layout directories, as specified by
+//! https://github.com/opencontainers/image-spec/blob/main/image-layout.md
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Error, Read, Result};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+use nix::sys::uio;
+use sha2::{Digest, Sha256, Sha512};
+use vm_memory::VolatileSlice;
+
+use crate::backend::{BackendError, BackendResult, BlobBackend};
+use crate::utils::{readahead, readv, MemSliceCursor};
+
+use nydus_utils::metrics::BackendMetrics;
+
+const OCI_LAYOUT_FILE: &str = "oci-layout";
+const OCI_LAYOUT_VERSION: &str = "1.0.0";
+const OCI_BLOBS_DIR: &str = "blobs";
+// Blob id without algorithm prefix is treated as a sha256 digest.
+const DEFAULT_DIGEST_ALGORITHM: &str = "sha256";
+const VERIFY_BUFFER_SIZE: usize = 0x10_0000;
+
+#[derive(Debug)]
+pub enum OciError {
+    BlobId(String),
+    BlobFile(Error),
+    Digest(String),
+    ReadBlob(nix::Error),
+    ReadVecBlob(Error),
+}
+
+type OciResult<T> = std::result::Result<T, OciError>;
+
+impl From<OciError> for BackendError {
+    fn from(error: OciError) -> Self {
+        BackendError::Oci(error)
+    }
+}
+
+#[derive(Deserialize)]
+struct OciLayoutMarker {
+    #[serde(rename = "imageLayoutVersion")]
+    image_layout_version: String,
+}
+
+#[derive(Clone, Deserialize)]
+struct OciConfig {
+    /// Root directories of OCI image layouts, searched in order for blobs.
+    roots: Vec<String>,
+    /// Verify digest of the whole blob on first open.
+    #[serde(default)]
+    verify_digest: bool,
+    /// Readahead hinted range of blob on prefetch.
+    #[serde(default)]
+    readahead: bool,
+}
+
+// Blob file opened once, None until opened, or the error message if its digest mismatches.
+type OciBlobFile = Mutex<Option<std::result::Result<Arc<File>, String>>>;
+
+pub struct Oci {
+    roots: Vec<PathBuf>,
+    verify_digest: bool,
+    readahead: bool,
+    // blobid-File map
+    file_table: RwLock<HashMap<String, Arc<OciBlobFile>>>,
+    metrics: Option<Arc<BackendMetrics>>,
+}
+
+/// Validate the `oci-layout` marker file of an OCI image layout root.
+fn check_layout(root: &Path) -> Result<()> {
+    let marker = root.join(OCI_LAYOUT_FILE);
+    let file = File::open(&marker).map_err(|e| {
+        error!("Failed to open OCI layout marker {:?}: {:?}", marker, e);
+        e
+    })?;
+    let marker: OciLayoutMarker = serde_json::from_reader(file)
+        .map_err(|e| einval!(format!("invalid OCI layout marker in {:?}: {}", root, e)))?;
+    if marker.image_layout_version != OCI_LAYOUT_VERSION {
+        return Err(einval!(format!(
+            "unsupported OCI image layout version {} in {:?}",
+            marker.image_layout_version, root
+        )));
+    }
+    Ok(())
+}
+
+pub fn new(config: serde_json::value::Value, id: Option<&str>) -> Result<Oci> {
+    let config: OciConfig = serde_json::from_value(config).map_err(|e| einval!(e))?;
+
+    if config.roots.is_empty() {
+        return Err(einval!("at least one OCI layout root is required"));
+    }
+    let roots = config.roots.iter().map(PathBuf::from).collect::<Vec<_>>();
+    for root in roots.iter() {
+        check_layout(root)?;
+    }
+
+    Ok(Oci {
+        roots,
+        verify_digest: config.verify_digest,
+        readahead: config.readahead,
+        file_table: RwLock::new(HashMap::new()),
+        metrics: id.map(|i| BackendMetrics::new(i, "oci")),
+    })
+}
+
+/// Split blob id into digest algorithm and hex encoded digest, blob id is in the form of
+/// `<algorithm>:<hex>` or just `<hex>` for sha256.
+fn parse_blob_id(blob_id: &str) -> OciResult<(&str, &str)> {
+    let (algorithm, hex) = match blob_id.find(':') {
+        Some(pos) => (&blob_id[..pos], &blob_id[pos + 1..]),
+        None => (DEFAULT_DIGEST_ALGORITHM, blob_id),
+    };
+    let len = match algorithm {
+        "sha256" => 64,
+        "sha512" => 128,
+        _ => {
+            return Err(OciError::BlobId(format!(
+                "unsupported digest algorithm of blob {}",
+                blob_id
+            )))
+        }
+    };
+    // Also make sure the blob id can't escape from the blobs directory.
+    if hex.len() != len || !hex.bytes().all(|c| matches!(c, b'0'..=b'9' | b'a'..=b'f')) {
+        return Err(OciError::BlobId(format!("invalid blob id {}", blob_id)));
+    }
+    Ok((algorithm, hex))
+}
+
+fn hash_file<D: Digest>(file: &mut File) -> Result<String> {
+    let mut hasher = D::new();
+    let mut buf = vec![0u8; VERIFY_BUFFER_SIZE];
+    loop {
+        let size = file.read(&mut buf)?;
+        if size == 0 {
+            break;
+        }
+        hasher.update(&buf[..size]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+impl Oci {
+    fn get_blob_path(&self, blob_id: &str) -> OciResult<PathBuf> {
+        let (algorithm, hex) = parse_blob_id(blob_id)?;
+        for root in self.roots.iter() {
+            let path = root.join(OCI_BLOBS_DIR).join(algorithm).join(hex);
+            if path.is_file() {
+                return Ok(path);
+            }
+        }
+        Err(OciError::BlobFile(enoent!(format!(
+            "blob {} not found in OCI layouts",
+            blob_id
+        ))))
+    }
+
+    /// Verify digest of the blob by reading through `file`, which is then used to serve reads.
+    fn verify_blob(&self, blob_id: &str, path: &Path, file: &mut File) -> OciResult<()> {
+        // Safe to unwrap since the blob path is found by a valid blob id.
+        let (algorithm, hex) = parse_blob_id(blob_id).unwrap();
+        let digest = match algorithm {
+            "sha512" => hash_file::<Sha512>(file),
+            _ => hash_file::<Sha256>(file),
+        }
+        .map_err(OciError::BlobFile)?;
+        if digest != hex {
+            return Err(OciError::Digest(format!(
+                "digest of blob {:?} mismatch, expect {} got {}",
+                path, hex, digest
+            )));
+        }
+        Ok(())
+    }
+
+    fn get_blob_file(&self, blob_id: &str) -> OciResult<Arc<File>> {
+        // Don't expect poisoned lock here.
+        let blob = self.file_table.read().unwrap().get(blob_id).cloned();
+        let blob = match blob {
+            Some(blob) => blob,
+            None => self
+                .file_table
+                .write()
+                .unwrap()
+                .entry(blob_id.to_string())
+                .or_default()
+                .clone(),
+        };
+
+        // Others opening the same blob wait for the result instead of verifying it again.
+        let mut guard = blob.lock().unwrap();
+        match guard.as_ref() {
+            Some(Ok(file)) => return Ok(file.clone()),
+            Some(Err(e)) => return Err(OciError::Digest(e.clone())),
+            None => {}
+        }
+
+        let path = self.get_blob_path(blob_id)?;
+        let mut file = File::open(&path).map_err(OciError::BlobFile)?;
+        if self.verify_digest {
+            // The blob won't change, so don't verify it again on later reads if the digest
+            // mismatches, but leave it to be verified again on I/O errors which may be transient.
+            match self.verify_blob(blob_id, &path, &mut file) {
+                Ok(()) => {}
+                Err(OciError::Digest(e)) => {
+                    let msg = format!("failed to verify blob {}: {}", blob_id, e);
+                    *guard = Some(Err(msg.clone()));
+                    return Err(OciError::Digest(msg));
+                }
+                Err(e) => return Err(e),
+            }
+            info!("verified digest of blob {:?}", path);
+        }
+        let file = Arc::new(file);
+        *guard = Some(Ok(file.clone()));
+
+        Ok(file)
+    }
+}
+
+impl BlobBackend for Oci {
+    fn prefetch_blob(
+        &self,
+        blob_id: &str,
+        blob_readahead_offset: u32,
+        blob_readahead_size: u32,
+    ) -> BackendResult<()> {
+        let file = self.get_blob_file(blob_id)?;
+        if !self.readahead || blob_readahead_size == 0 {
+            return Ok(());
+        }
+
+        let blob_size = file.metadata().map_err(OciError::BlobFile)?.len();
+        let end = blob_readahead_offset as u64 + blob_readahead_size as u64;
+        if end <= blob_size {
+            info!(
+                "kick off hinted blob readahead offset {} len {}",
+                blob_readahead_offset, blob_readahead_size
+            );
+            readahead(file.as_raw_fd(), blob_readahead_offset as u64, end);
+        }
+
+        Ok(())
+    }
+
+    fn metrics(&self) -> &BackendMetrics {
+        // Safe because nydusd must have backend attached with id, only image builder can no id
+        // but use backend instance to upload blob.
+        self.metrics.as_ref().unwrap()
+    }
+
+    fn release(&self) {
+        self.metrics()
+            .release()
+            .unwrap_or_else(|e| error!("{:?}", e))
+    }
+
+    fn blob_size(&self, blob_id: &str) -> BackendResult<u64> {
+        let file = self.get_blob_file(blob_id)?;
+        let meta = file.metadata().map_err(OciError::BlobFile)?;
+        Ok(meta.len())
+    }
+
+    fn try_read(&self, blob_id: &str, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+        let file = self.get_blob_file(blob_id)?;
+
+        debug!(
+            "oci blob file reading: offset={}, size={} from={}",
+            offset,
+            buf.len(),
+            blob_id,
+        );
+        let len = uio::pread(file.as_raw_fd(), buf, offset as i64).map_err(OciError::ReadBlob)?;
+
+        Ok(len)
+    }
+
+    fn readv(
+        &self,
+        blob_id: &str,
+        bufs: &[VolatileSlice],
+        offset: u64,
+        max_size: usize,
+    ) -> BackendResult<usize> {
+        let file = self.get_blob_file(blob_id)?;
+        let mut c = MemSliceCursor::new(bufs);
+        let iovec = c.consume(max_size);
+        Ok(readv(file.as_raw_fd(), &iovec, offset).map_err(OciError::ReadVecBlob)?)
+    }
+
+    fn write(&self, _blob_id: &str, _buf: &[u8], _offset: u64) -> BackendResult<usize> {
+        Err(BackendError::Unsupported(
+            "OCI layout backend does not support write operation".to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use vmm_sys_util::tempdir::TempDir;
+
+    const BLOB_DATA: &[u8] = b"nydus";
+    // sha256sum of BLOB_DATA
+    const BLOB_DIGEST: &str = "90cee432cf4a9d12a4b263bc3a2746d2a386d2ea62491f52052cb55e227a88d3";
+
+    fn create_layout(blob: Option<(&str, &[u8])>) -> TempDir {
+        let dir = TempDir::new().unwrap();
+        let root = dir.as_path();
+        fs::write(
+            root.join(OCI_LAYOUT_FILE),
+            r#"{"imageLayoutVersion": "1.0.0"}"#,
+        )
+        .unwrap();
+        fs::create_dir_all(root.join("blobs/sha256")).unwrap();
+        if let Some((digest, data)) = blob {
+            fs::write(root.join("blobs/sha256").join(digest), data).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_parse_blob_id() {
+        assert_eq!(parse_blob_id(BLOB_DIGEST).unwrap(), ("sha256", BLOB_DIGEST));
+        let blob_id = format!("sha256:{}", BLOB_DIGEST);
+        assert_eq!(parse_blob_id(&blob_id).unwrap(), ("sha256", BLOB_DIGEST));
+        assert!(parse_blob_id(&format!("md5:{}", BLOB_DIGEST)).is_err());
+        assert!(parse_blob_id("../../../etc/passwd").is_err());
+        assert!(parse_blob_id(&BLOB_DIGEST.to_uppercase()).is_err());
+    }
+
+    #[test]
+    fn test_oci_layout() {
+        let empty = create_layout(None);
+        let layout = create_layout(Some((BLOB_DIGEST, BLOB_DATA)));
+        let config = serde_json::json!({
+            "roots": [empty.as_path(), layout.as_path()],
+            "verify_digest": true,
+        });
+        let oci = new(config, None).unwrap();
+
+        assert_eq!(oci.blob_size(BLOB_DIGEST).unwrap(), BLOB_DATA.len() as u64);
+        let mut buf = vec![0u8; 3];
+        assert_eq!(oci.try_read(BLOB_DIGEST, &mut buf, 2).unwrap(), 3);
+        assert_eq!(buf, &BLOB_DATA[2..]);
+        assert!(oci.try_read(&"0".repeat(64), &mut buf, 0).is_err());
+
+        // Corrupted blob is rejected on first open.
+        let digest = "0".repeat(64);
+        let corrupted = create_layout(Some((&digest, BLOB_DATA)));
+        let config = serde_json::json!({
+            "roots": [corrupted.as_path()],
+            "verify_digest": true,
+        });
+        let oci = new(config, None).unwrap();
+        assert!(matches!(
+            oci.try_read(&digest, &mut buf, 0),
+            Err(BackendError::Oci(OciError::Digest(_)))
+        ));
+        // The result of verification is kept, the blob is not verified again.
+        let blob_path = corrupted.as_path().join("blobs/sha256").join(&digest);
+        fs::remove_file(&blob_path).unwrap();
+        assert!(matches!(
+            oci.try_read(&digest, &mut buf, 0),
+            Err(BackendError::Oci(OciError::Digest(_)))
+        ));
+
+        // Reads are served by the verified file even if the blob is replaced later.
+        let blob_path = layout.as_path().join("blobs/sha256").join(BLOB_DIGEST);
+        let oci = new(
+            serde_json::json!({ "roots": [layout.as_path()], "verify_digest": true }),
+            None,
+        )
+        .unwrap();
+        assert_eq!(oci.try_read(BLOB_DIGEST, &mut buf, 0).unwrap(), 3);
+        fs::remove_file(&blob_path).unwrap();
+        fs::write(&blob_path, b"replaced").unwrap();
+        assert_eq!(oci.try_read(BLOB_DIGEST, &mut buf, 0).unwrap(), 3);
+        assert_eq!(buf, &BLOB_DATA[..3]);
+        assert_eq!(oci.blob_size(BLOB_DIGEST).unwrap(), BLOB_DATA.len() as u64);
+
+        // I/O errors while hashing are not taken as digest mismatch, so they are not kept.
+        let mut file = File::create(layout.as_path().join("write-only")).unwrap();
+        assert!(matches!(
+            oci.verify_blob(BLOB_DIGEST, &blob_path, &mut file),
+            Err(OciError::BlobFile(_))
+        ));
+
+        // Directory without layout marker is rejected.
+        let dir = TempDir::new().unwrap();
+        assert!(new(serde_json::json!({ "roots": [dir.as_path()] }), None).is_err());
+    }
+}
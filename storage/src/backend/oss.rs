@@ -11,7 +11,9 @@ use reqwest::header::CONTENT_LENGTH;
 use reqwest::Method;
 use sha1::Sha1;
 
+use crate::backend::hedge::BodyWriter;
 use crate::backend::request::{HeaderMap, Request, RequestError};
+use crate::backend::retry::RetryPolicy;
 use crate::backend::{default_http_scheme, BackendError, BackendResult};
 use crate::backend::{BlobBackend, CommonConfig};
 
@@ -47,7 +49,7 @@ pub struct Oss {
     object_prefix: String,
     endpoint: String,
     bucket_name: String,
-    retry_limit: u8,
+    retry_policy: RetryPolicy,
     metrics: Option<Arc<BackendMetrics>>,
     id: Option<String>,
 }
@@ -145,7 +147,7 @@ impl Oss {
 pub fn new(config: serde_json::value::Value, id: Option<&str>) -> Result<Oss> {
     let common_config: CommonConfig =
         serde_json::from_value(config.clone()).map_err(|e| einval!(e))?;
-    let retry_limit = common_config.retry_limit;
+    let retry_policy = common_config.retry_policy();
     let request = Request::new(common_config)?;
 
     let config: OssConfig = serde_json::from_value(config).map_err(|e| einval!(e))?;
@@ -158,7 +160,7 @@ pub fn new(config: serde_json::value::Value, id: Option<&str>) -> Result<Oss> {
         access_key_secret: config.access_key_secret,
         bucket_name: config.bucket_name,
         request,
-        retry_limit,
+        retry_policy,
         metrics: id.map(|i| BackendMetrics::new(i, "oss")),
         id: id.map(|i| i.to_string()),
     })
@@ -166,8 +168,8 @@ pub fn new(config: serde_json::value::Value, id: Option<&str>) -> Result<Oss> {
 
 impl BlobBackend for Oss {
     #[inline]
-    fn retry_limit(&self) -> u8 {
-        self.retry_limit
+    fn retry_policy(&self) -> Option<&RetryPolicy> {
+        Some(&self.retry_policy)
     }
 
     fn metrics(&self) -> &BackendMetrics {
@@ -218,7 +220,7 @@ impl BlobBackend for Oss {
     }
 
     /// read ranged data from oss object
-    fn try_read(&self, blob_id: &str, mut buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+    fn try_read(&self, blob_id: &str, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
         let query = &[];
         let (resource, url) = self.url(blob_id, query);
 
@@ -243,7 +245,7 @@ impl BlobBackend for Oss {
             .map_err(OssError::Request)?;
 
         Ok(resp
-            .copy_to(&mut buf)
+            .copy_to(&mut BodyWriter::new(buf))
             .map_err(OssError::Transport)
             .map(|size| size as usize)?)
     }
@@ -4,7 +4,10 @@
 
 use std::collections::HashMap;
 use std::io::{Error, Read, Result};
-use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, RwLock, Weak};
+use std::thread;
+use std::time::Duration;
 
 use reqwest::blocking::Response;
 pub use reqwest::header::HeaderMap;
@@ -12,15 +15,21 @@ use reqwest::header::{HeaderValue, CONTENT_LENGTH};
 use reqwest::{Method, StatusCode};
 use url::{ParseError, Url};
 
+use crate::backend::docker_config::{Credential, DockerCredentials};
+use crate::backend::hedge::BodyWriter;
 use crate::backend::request::{is_success_status, respond, ReqBody, Request, RequestError};
+use crate::backend::retry::RetryPolicy;
 use crate::backend::{default_http_scheme, BackendError, BackendResult};
 use crate::backend::{BlobBackend, CommonConfig};
-use nydus_utils::metrics::BackendMetrics;
+use nydus_utils::metrics::{BackendMetrics, MirrorMetrics};
 
 const REGISTRY_CLIENT_ID: &str = "nydus-registry-client";
 const HEADER_AUTHORIZATION: &str = "Authorization";
 const HEADER_WWW_AUTHENTICATE: &str = "www-authenticate";
 
+const MIRROR_CHECK_INTERVAL: u64 = 5;
+const MIRROR_FAILURE_LIMIT: u32 = 3;
+
 #[derive(Default)]
 struct Cache(RwLock<String>);
 #[derive(Default)]
@@ -83,29 +92,62 @@ impl HashCache {
     }
 }
 
-pub struct Registry {
-    request: Arc<Request>,
+/// Authentication information for a registry server.
+#[derive(Default)]
+struct RegistryAuth {
+    // Registry username and password, which may be updated when re-resolved from docker config
+    credential: RwLock<Option<Credential>>,
+    // Resolver of credentials from docker config, if auth is not configured inline
+    docker_credentials: Option<DockerCredentials>,
+    // Cache bearer token (get from registry authentication server) or basic authentication auth string.
+    // We need use it to reduce the pressure on token authentication server or reduce the base64 compute workload for every request.
+    // Use RwLock here to avoid using mut backend trait object.
+    // Example: RwLock<"Bearer <token>">
+    //          RwLock<"Basic base64(<username:password>)">
+    cached_auth: Cache,
+}
+
+/// A registry server serving the image repo, either the origin registry or a mirror of it.
+struct Endpoint {
     // HTTP scheme like: https, http
     scheme: String,
     host: String,
+    auth: RegistryAuth,
+}
+
+/// Health status of a registry mirror.
+///
+/// A mirror is marked as unhealthy after `failure_limit` consecutive failed requests, and requests
+/// skip unhealthy mirrors until the health probe finds the mirror available again.
+struct MirrorHealth {
+    metrics: Arc<MirrorMetrics>,
+    // Consecutive failures since the last successful request
+    failures: AtomicU32,
+    failure_limit: u32,
+    ping_url: String,
+    check_interval: Duration,
+}
+
+struct Mirror {
+    endpoint: Endpoint,
+    health: Arc<MirrorHealth>,
+}
+
+pub struct Registry {
+    request: Arc<Request>,
     // Image repo name like: library/ubuntu
     repo: String,
-    // Base64 encoded registry auth
-    auth: Option<String>,
-    username: String,
-    password: String,
+    // The origin registry server
+    origin: Endpoint,
+    // Mirrors of the origin registry in priority order, requests are sent to healthy mirrors
+    // first and fall back to the origin registry at last.
+    mirrors: Vec<Mirror>,
     // Retry limit for read operation
-    retry_limit: u8,
+    retry_policy: RetryPolicy,
     // Scheme specified for blob server
     blob_url_scheme: String,
     // Replace registry redirected url host with the given host
     blob_redirected_host: String,
-    // Cache bearer token (get from registry authentication server) or basic authentication auth string.
-    // We need use it to reduce the pressure on token authentication server or reduce the base64 compute workload for every request.
-    // Use RwLock here to avoid using mut backend trait object.
-    // Example: RwLock<"Bearer <token>">
-    //          RwLock<"Basic base64(<username:password>)">
-    cached_auth: Cache,
     // Cache 30X redirect url
     // Example: RwLock<HashMap<"<blob_id>", "<redirected_url>">>
     cached_redirect: HashCache,
@@ -126,10 +168,47 @@ struct RegistryConfig {
     // to authorize registry requests.
     #[serde(default)]
     registry_token: Option<String>,
+    // Resolve credentials from docker config.json and credential helpers if `auth`
+    // is absent. Empty string means `$DOCKER_CONFIG/config.json` or `~/.docker/config.json`.
+    #[serde(default)]
+    docker_config: Option<String>,
     #[serde(default)]
     blob_url_scheme: String,
     #[serde(default)]
     blob_redirected_host: String,
+    // Pull-through mirrors of the registry, tried in order before the origin registry.
+    #[serde(default)]
+    mirrors: Vec<MirrorConfig>,
+}
+
+#[derive(Clone, Deserialize)]
+struct MirrorConfig {
+    #[serde(default = "default_http_scheme")]
+    scheme: String,
+    host: String,
+    // Base64_encoded(username:password) for the mirror.
+    #[serde(default)]
+    auth: Option<String>,
+    // Bearer token for the mirror.
+    #[serde(default)]
+    registry_token: Option<String>,
+    // URL to probe health of an unhealthy mirror, default to `<scheme>://<host>/v2/`.
+    #[serde(default)]
+    ping_url: String,
+    // Interval of health probe, in seconds, at least 1.
+    #[serde(default = "default_mirror_check_interval")]
+    check_interval: u64,
+    // Consecutive failures to mark the mirror as unhealthy.
+    #[serde(default = "default_mirror_failure_limit")]
+    failure_limit: u32,
+}
+
+fn default_mirror_check_interval() -> u64 {
+    MIRROR_CHECK_INTERVAL
+}
+
+fn default_mirror_failure_limit() -> u32 {
+    MIRROR_FAILURE_LIMIT
 }
 
 #[derive(Clone, Deserialize)]
@@ -171,73 +250,225 @@ fn trim(val: Option<String>) -> Option<String> {
     }
 }
 
-#[allow(clippy::useless_let_if_seq)]
+impl RegistryAuth {
+    fn new(
+        auth: Option<String>,
+        registry_token: Option<String>,
+        docker_config: Option<&str>,
+        host: &str,
+    ) -> Result<Self> {
+        let auth = trim(auth);
+        let registry_token = trim(registry_token);
+
+        let (credential, docker_credentials) = if let Some(auth) = &auth {
+            (Some(Credential::from_auth(auth)?), None)
+        } else if let Some(docker_config) = docker_config {
+            let docker_credentials = DockerCredentials::new(docker_config, host);
+            let credential = docker_credentials.resolve().unwrap_or_else(|e| {
+                warn!("Failed to resolve credential for registry {}: {}", host, e);
+                None
+            });
+            (credential, Some(docker_credentials))
+        } else {
+            (None, None)
+        };
+
+        let cached_auth = if let Some(registry_token) = registry_token {
+            // Store the registry bearer token to cached_auth, prefer to
+            // use the token stored in cached_auth to request registry.
+            Cache::new(format!("Bearer {}", registry_token))
+        } else {
+            Cache::new(String::new())
+        };
+
+        Ok(RegistryAuth {
+            credential: RwLock::new(credential),
+            docker_credentials,
+            cached_auth,
+        })
+    }
+
+    /// Get base64_encoded(username:password)
+    fn auth(&self) -> Option<String> {
+        self.credential.read().unwrap().as_ref().map(|c| c.auth())
+    }
+
+    fn credential(&self) -> Credential {
+        self.credential.read().unwrap().clone().unwrap_or_default()
+    }
+
+    /// Re-resolve credential from docker config, return true if the credential has been rotated.
+    fn refresh(&self) -> bool {
+        let docker_credentials = match self.docker_credentials.as_ref() {
+            Some(docker_credentials) => docker_credentials,
+            None => return false,
+        };
+        let credential = match docker_credentials.resolve() {
+            Ok(credential) => credential,
+            Err(e) => {
+                warn!("Failed to re-resolve registry credential: {}", e);
+                return false;
+            }
+        };
+
+        let mut guard = self.credential.write().unwrap();
+        if *guard == credential {
+            return false;
+        }
+        info!("Registry credential has been rotated, use the new one");
+        *guard = credential;
+        drop(guard);
+
+        let last = self.cached_auth.get();
+        self.cached_auth.set(last, String::new());
+        true
+    }
+}
+
+impl MirrorHealth {
+    fn ok(&self) -> bool {
+        self.metrics.is_healthy()
+    }
+
+    fn success(&self) {
+        self.failures.store(0, Ordering::Relaxed);
+    }
+
+    fn failure(&self) {
+        self.metrics.failover();
+        let failures = self.failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures >= self.failure_limit && self.ok() {
+            warn!(
+                "Registry mirror {} failed {} times, mark it as unhealthy",
+                self.metrics.host(),
+                failures
+            );
+            self.metrics.set_healthy(false);
+        }
+    }
+
+    fn recover(&self) {
+        info!("Registry mirror {} becomes healthy", self.metrics.host());
+        self.failures.store(0, Ordering::Relaxed);
+        self.metrics.set_healthy(true);
+    }
+}
+
+/// Spawn thread to probe the health of an unhealthy mirror, the thread exits
+/// once the mirror is dropped together with the registry backend.
+fn start_mirror_probe(request: Arc<Request>, health: Weak<MirrorHealth>) -> Result<()> {
+    thread::Builder::new()
+        .name("nydus-registry-mirror-probe".to_string())
+        .spawn(move || {
+            while let Some(health) = health.upgrade() {
+                if !health.ok() {
+                    let resp = request.call::<&[u8]>(
+                        Method::GET,
+                        health.ping_url.as_str(),
+                        None,
+                        None,
+                        HeaderMap::new(),
+                        false,
+                    );
+                    // The `/v2/` API may respond 401 for registries requiring auth,
+                    // which still means the mirror is available.
+                    if let Ok(resp) = resp {
+                        if resp.status() < StatusCode::INTERNAL_SERVER_ERROR {
+                            health.recover();
+                        }
+                    }
+                }
+                let interval = health.check_interval;
+                drop(health);
+                thread::sleep(interval);
+            }
+        })
+        .map(|_| ())
+}
+
 pub fn new(config: serde_json::value::Value, id: Option<&str>) -> Result<Registry> {
     let common_config: CommonConfig =
         serde_json::from_value(config.clone()).map_err(|e| einval!(e))?;
-    let retry_limit = common_config.retry_limit;
+    let retry_policy = common_config.retry_policy();
     let request = Request::new(common_config)?;
 
     let config: RegistryConfig = serde_json::from_value(config).map_err(|e| einval!(e))?;
-
-    let auth = trim(config.auth);
-    let registry_token = trim(config.registry_token);
-
-    let (username, password) = if let Some(auth) = &auth {
-        let auth = base64::decode(auth.as_bytes()).map_err(|e| {
-            einval!(format!(
-                "Invalid base64 encoded registry auth config: {:?}",
-                e
-            ))
-        })?;
-        let auth = std::str::from_utf8(&auth).map_err(|e| {
-            einval!(format!(
-                "Invalid utf-8 encoded registry auth config: {:?}",
-                e
-            ))
-        })?;
-        let auth: Vec<&str> = auth.splitn(2, ':').collect();
-        if auth.len() < 2 {
-            return Err(einval!("Invalid registry auth config"));
-        }
-        (auth[0].to_string(), auth[1].to_string())
-    } else {
-        (String::new(), String::new())
+    let metrics = id.map(|i| BackendMetrics::new(i, "registry"));
+
+    let auth = RegistryAuth::new(
+        config.auth,
+        config.registry_token,
+        config.docker_config.as_deref(),
+        &config.host,
+    )?;
+    let origin = Endpoint {
+        scheme: config.scheme,
+        host: config.host,
+        auth,
     };
 
-    let cached_auth = if let Some(registry_token) = registry_token {
-        // Store the registry bearer token to cached_auth, prefer to
-        // use the token stored in cached_auth to request registry.
-        Cache::new(format!("Bearer {}", registry_token))
-    } else {
-        Cache::new(String::new())
-    };
+    let mut mirrors = Vec::new();
+    for mirror in config.mirrors {
+        let ping_url = if mirror.ping_url.is_empty() {
+            format!("{}://{}/v2/", mirror.scheme, mirror.host)
+        } else {
+            mirror.ping_url
+        };
+        let mirror_metrics = match metrics.as_ref() {
+            Some(metrics) => metrics.new_mirror(&mirror.host),
+            None => MirrorMetrics::new(&mirror.host),
+        };
+        let health = Arc::new(MirrorHealth {
+            metrics: mirror_metrics,
+            failures: AtomicU32::new(0),
+            failure_limit: std::cmp::max(mirror.failure_limit, 1),
+            ping_url,
+            // Probing without interval would busy loop.
+            check_interval: Duration::from_secs(std::cmp::max(mirror.check_interval, 1)),
+        });
+        start_mirror_probe(request.clone(), Arc::downgrade(&health))?;
+        let auth = RegistryAuth::new(
+            mirror.auth,
+            mirror.registry_token,
+            config.docker_config.as_deref(),
+            &mirror.host,
+        )?;
+        mirrors.push(Mirror {
+            endpoint: Endpoint {
+                scheme: mirror.scheme,
+                host: mirror.host,
+                auth,
+            },
+            health,
+        });
+    }
 
     Ok(Registry {
         request,
-        scheme: config.scheme,
-        host: config.host,
         repo: config.repo,
-        auth,
-        cached_auth,
-        username,
-        password,
-        retry_limit,
+        origin,
+        mirrors,
+        retry_policy,
         blob_url_scheme: config.blob_url_scheme,
         blob_redirected_host: config.blob_redirected_host,
         cached_redirect: HashCache::new(),
-        metrics: id.map(|i| BackendMetrics::new(i, "registry")),
+        metrics,
     })
 }
 
 impl Registry {
-    fn url(&self, path: &str, query: &[&str]) -> std::result::Result<String, ParseError> {
+    fn url(
+        &self,
+        endpoint: &Endpoint,
+        path: &str,
+        query: &[&str],
+    ) -> std::result::Result<String, ParseError> {
         let path = if !query.is_empty() {
             format!("/v2/{}{}?{}", self.repo, path, query.join("&"))
         } else {
             format!("/v2/{}{}", self.repo, path)
         };
-        let url = format!("{}://{}", self.scheme, self.host.as_str());
+        let url = format!("{}://{}", endpoint.scheme, endpoint.host.as_str());
         let url = Url::parse(url.as_str())?;
         let url = url.join(path.as_str())?;
 
@@ -245,18 +476,19 @@ impl Registry {
     }
 
     /// Request registry authentication server to get bearer token
-    fn get_token(&self, auth: BearerAuth) -> Result<String> {
+    fn get_token(&self, auth: BearerAuth, registry_auth: &RegistryAuth) -> RegistryResult<String> {
         // The information needed for getting token needs to be placed both in
         // the query and in the body to be compatible with different registry
         // implementations, which have been tested on these platforms:
         // docker hub, harbor, github ghcr, aliyun acr.
 
+        let credential = registry_auth.credential();
         let query = vec![
             ("service", auth.service.as_str()),
             ("scope", auth.scope.as_str()),
             ("grant_type", "password"),
-            ("username", self.username.as_str()),
-            ("password", self.password.as_str()),
+            ("username", credential.username.as_str()),
+            ("password", credential.password.as_str()),
             ("client_id", REGISTRY_CLIENT_ID),
         ];
 
@@ -278,11 +510,23 @@ impl Registry {
                 Some(query),
                 Some(ReqBody::Form(form)),
                 headers,
-                true,
+                false,
             )
-            .map_err(|e| einval!(format!("registry auth server request failed {:?}", e)))?;
+            .map_err(|e| {
+                RegistryError::Common(format!("registry auth server request failed {:?}", e))
+            })?;
+        // The credential may be rotated or revoked, let caller re-resolve it.
+        if [StatusCode::UNAUTHORIZED, StatusCode::FORBIDDEN].contains(&token_resp.status()) {
+            return Err(RegistryError::Auth(format!(
+                "registry auth server denied the credential with status {}",
+                token_resp.status()
+            )));
+        }
+        let token_resp = respond(token_resp).map_err(|e| {
+            RegistryError::Common(format!("registry auth server request failed {:?}", e))
+        })?;
         let ret: TokenResponse = token_resp.json().map_err(|e| {
-            einval!(format!(
+            RegistryError::Common(format!(
                 "registry auth server response decode failed: {:?}",
                 e
             ))
@@ -290,15 +534,14 @@ impl Registry {
         Ok(ret.token)
     }
 
-    fn get_auth_header(&self, auth: Auth) -> Result<String> {
+    fn get_auth_header(&self, auth: Auth, registry_auth: &RegistryAuth) -> RegistryResult<String> {
         match auth {
-            Auth::Basic(_) => self
-                .auth
-                .as_ref()
+            Auth::Basic(_) => registry_auth
+                .auth()
                 .map(|auth| format!("Basic {}", auth))
-                .ok_or_else(|| einval!("invalid auth config")),
+                .ok_or_else(|| RegistryError::Auth("invalid auth config".to_string())),
             Auth::Bearer(auth) => {
-                let token = self.get_token(auth)?;
+                let token = self.get_token(auth, registry_auth)?;
                 Ok(format!("Bearer {}", token))
             }
         }
@@ -306,7 +549,7 @@ impl Registry {
 
     /// Parse `www-authenticate` response header respond from registry server
     /// The header format like: `Bearer realm="https://auth.my-registry.com/token",service="my-registry.com",scope="repository:test/repo:pull,push"`
-    fn parse_auth(&self, source: &HeaderValue) -> Option<Auth> {
+    fn parse_auth(&self, source: &HeaderValue, registry_auth: &RegistryAuth) -> Option<Auth> {
         let source = source.to_str().unwrap();
         let source: Vec<&str> = source.splitn(2, ' ').collect();
         if source.len() < 2 {
@@ -343,9 +586,8 @@ impl Registry {
                     return None;
                 }
 
-                let header = self
-                    .auth
-                    .as_ref()
+                let header = registry_auth
+                    .auth()
                     .map(|auth| HeaderValue::from_str(&format!("Basic {}", auth)).unwrap());
 
                 Some(Auth::Bearer(BearerAuth {
@@ -387,6 +629,7 @@ impl Registry {
     /// Response: status: 200 Ok
     fn request<R: Read + Send + 'static>(
         &self,
+        endpoint: &Endpoint,
         method: Method,
         url: &str,
         data: Option<ReqBody<R>>,
@@ -395,7 +638,7 @@ impl Registry {
     ) -> RegistryResult<Response> {
         // Try get authorization header from cache for this request
         let mut last_cached_auth = String::new();
-        let cached_auth = self.cached_auth.get();
+        let cached_auth = endpoint.auth.cached_auth.get();
         if !cached_auth.is_empty() {
             last_cached_auth = cached_auth.clone();
             headers.insert(
@@ -420,12 +663,19 @@ impl Registry {
             .map_err(RegistryError::Request)?;
 
         if resp.status() == StatusCode::UNAUTHORIZED {
-            if let Some(resp_auth_header) = resp.headers().get(HEADER_WWW_AUTHENTICATE) {
+            if let Some(resp_auth_header) = resp.headers().get(HEADER_WWW_AUTHENTICATE).cloned() {
+                // Credentials resolved from docker config may be rotated, re-resolve them
+                // and try again once if the registry or its auth server rejects them.
+                let mut refreshed = false;
                 // Get token from registry authorization server
-                if let Some(auth) = self.parse_auth(resp_auth_header) {
-                    let auth_header = self
-                        .get_auth_header(auth)
-                        .map_err(|e| RegistryError::Common(e.to_string()))?;
+                while let Some(auth) = self.parse_auth(&resp_auth_header, &endpoint.auth) {
+                    let auth_header = match self.get_auth_header(auth, &endpoint.auth) {
+                        Err(RegistryError::Auth(_)) if !refreshed && endpoint.auth.refresh() => {
+                            refreshed = true;
+                            continue;
+                        }
+                        ret => ret?,
+                    };
                     headers.insert(
                         HEADER_AUTHORIZATION,
                         HeaderValue::from_str(auth_header.as_str()).unwrap(),
@@ -434,13 +684,17 @@ impl Registry {
                     // Try to request registry server with `authorization` header again
                     let resp = self
                         .request
-                        .call(method, url, None, data, headers, catch_status)
+                        .call::<&[u8]>(method.clone(), url, None, None, headers.clone(), false)
                         .map_err(RegistryError::Request)?;
 
                     let status = resp.status();
+                    if status == StatusCode::UNAUTHORIZED && !refreshed && endpoint.auth.refresh() {
+                        refreshed = true;
+                        continue;
+                    }
                     if is_success_status(status) {
                         // Cache authorization header for next request
-                        self.cached_auth.set(last_cached_auth, auth_header)
+                        endpoint.auth.cached_auth.set(last_cached_auth, auth_header)
                     }
                     if !catch_status {
                         return Ok(resp);
@@ -457,6 +711,49 @@ impl Registry {
         respond(resp).map_err(RegistryError::Request)
     }
 
+    /// Request healthy mirrors in order and fall back to the origin registry at last,
+    /// a mirror is skipped on connection errors or 5xx responses.
+    fn request_with_failover(
+        &self,
+        method: Method,
+        path: &str,
+        headers: HeaderMap,
+    ) -> RegistryResult<Response> {
+        for mirror in self.mirrors.iter().filter(|m| m.health.ok()) {
+            let url = self
+                .url(&mirror.endpoint, path, &[])
+                .map_err(RegistryError::Url)?;
+            match self.request::<&[u8]>(
+                &mirror.endpoint,
+                method.clone(),
+                url.as_str(),
+                None,
+                headers.clone(),
+                false,
+            ) {
+                Ok(resp) if resp.status() < StatusCode::INTERNAL_SERVER_ERROR => {
+                    mirror.health.success();
+                    return Ok(resp);
+                }
+                Ok(resp) => warn!(
+                    "Request registry mirror {} failed with status {}, try next server",
+                    mirror.endpoint.host,
+                    resp.status()
+                ),
+                Err(err) => warn!(
+                    "Request registry mirror {} failed: {:?}, try next server",
+                    mirror.endpoint.host, err
+                ),
+            }
+            mirror.health.failure();
+        }
+
+        let url = self
+            .url(&self.origin, path, &[])
+            .map_err(RegistryError::Url)?;
+        self.request::<&[u8]>(&self.origin, method, url.as_str(), None, headers, false)
+    }
+
     /// Read data from registry server
     ///
     /// Step:
@@ -471,12 +768,11 @@ impl Registry {
     fn _try_read(
         &self,
         blob_id: &str,
-        mut buf: &mut [u8],
+        buf: &mut [u8],
         offset: u64,
         allow_retry: bool,
     ) -> RegistryResult<usize> {
-        let url = format!("/blobs/sha256:{}", blob_id);
-        let url = self.url(url.as_str(), &[]).map_err(RegistryError::Url)?;
+        let path = format!("/blobs/sha256:{}", blob_id);
 
         let mut headers = HeaderMap::new();
         let end_at = offset + buf.len() as u64 - 1;
@@ -487,17 +783,22 @@ impl Registry {
         let cached_redirect = self.cached_redirect.get(blob_id);
 
         if let Some(cached_redirect) = cached_redirect {
-            resp = self
-                .request
-                .call::<&[u8]>(
-                    Method::GET,
-                    cached_redirect.as_str(),
-                    None,
-                    None,
-                    headers,
-                    false,
-                )
-                .map_err(RegistryError::Request)?;
+            resp = match self.request.call::<&[u8]>(
+                Method::GET,
+                cached_redirect.as_str(),
+                None,
+                None,
+                headers,
+                false,
+            ) {
+                Ok(resp) => resp,
+                Err(err) => {
+                    // The blob server may be gone, e.g. a failed mirror, request
+                    // the registry servers again for a new location next time.
+                    self.cached_redirect.remove(blob_id);
+                    return Err(RegistryError::Request(err));
+                }
+            };
 
             // The request has expired or has been denied, need to re-request
             if allow_retry
@@ -512,8 +813,7 @@ impl Registry {
                 return self._try_read(blob_id, buf, offset, false);
             }
         } else {
-            resp =
-                self.request::<&[u8]>(Method::GET, url.as_str(), None, headers.clone(), false)?;
+            resp = self.request_with_failover(Method::GET, &path, headers.clone())?;
             let status = resp.status();
             // Handle redirect request and cache redirect url
             if vec![
@@ -565,7 +865,7 @@ impl Registry {
             }
         }
 
-        resp.copy_to(&mut buf)
+        resp.copy_to(&mut BodyWriter::new(buf))
             .map_err(RegistryError::Transport)
             .map(|size| size as usize)
     }
@@ -573,8 +873,8 @@ impl Registry {
 
 impl BlobBackend for Registry {
     #[inline]
-    fn retry_limit(&self) -> u8 {
-        self.retry_limit
+    fn retry_policy(&self) -> Option<&RetryPolicy> {
+        Some(&self.retry_policy)
     }
 
     fn metrics(&self) -> &BackendMetrics {
@@ -601,12 +901,9 @@ impl BlobBackend for Registry {
     }
 
     fn blob_size(&self, blob_id: &str) -> BackendResult<u64> {
-        let url = self
-            .url(&format!("/blobs/sha256:{}", blob_id), &[])
-            .map_err(RegistryError::Url)?;
-
-        let resp =
-            self.request::<&[u8]>(Method::HEAD, url.as_str(), None, HeaderMap::new(), true)?;
+        let path = format!("/blobs/sha256:{}", blob_id);
+        let resp = self.request_with_failover(Method::HEAD, &path, HeaderMap::new())?;
+        let resp = respond(resp).map_err(RegistryError::Request)?;
 
         let content_length = resp
             .headers()
@@ -629,3 +926,60 @@ impl BlobBackend for Registry {
         Ok(_buf.len())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_mirrors() {
+        let config = serde_json::json!({
+            "host": "registry.example.com",
+            "repo": "test/repo",
+            "mirrors": [
+                {"host": "127.0.0.1:1", "scheme": "http", "failure_limit": 2, "check_interval": 0},
+                {"host": "mirror.example.com", "ping_url": "https://mirror.example.com/ping"},
+            ],
+        });
+        let registry = new(config, None).unwrap();
+        assert_eq!(registry.mirrors.len(), 2);
+        assert_eq!(
+            registry.mirrors[0].health.ping_url,
+            "http://127.0.0.1:1/v2/"
+        );
+        assert_eq!(
+            registry.mirrors[1].health.ping_url,
+            "https://mirror.example.com/ping"
+        );
+        assert_eq!(
+            registry.mirrors[1].health.failure_limit,
+            MIRROR_FAILURE_LIMIT
+        );
+        assert_eq!(
+            registry.mirrors[0].health.check_interval,
+            Duration::from_secs(1)
+        );
+        assert_eq!(
+            registry.mirrors[1].health.check_interval,
+            Duration::from_secs(MIRROR_CHECK_INTERVAL)
+        );
+        assert_eq!(
+            registry
+                .url(&registry.mirrors[0].endpoint, "/blobs/sha256:abc", &[])
+                .unwrap(),
+            "http://127.0.0.1:1/v2/test/repo/blobs/sha256:abc"
+        );
+
+        let health = &registry.mirrors[0].health;
+        assert!(health.ok());
+        health.failure();
+        assert!(health.ok());
+        health.success();
+        health.failure();
+        assert!(health.ok());
+        health.failure();
+        assert!(!health.ok());
+        health.recover();
+        assert!(health.ok());
+    }
+}
@@ -3,22 +3,28 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::collections::HashMap;
+use std::fs;
 use std::io::Read;
 use std::io::Result;
 use std::str::FromStr;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
+use arc_swap::ArcSwap;
+use openssl::pkcs12::Pkcs12;
+use openssl::pkey::PKey;
+use openssl::stack::Stack;
+use openssl::x509::X509;
 use reqwest::{
     self,
     blocking::{Body, Client, Response},
     redirect::Policy,
-    Method, StatusCode, Url,
+    Certificate, Identity, Method, StatusCode, Url,
 };
 
-use crate::backend::CommonConfig;
+use crate::backend::{CommonConfig, TlsConfig};
 
 pub use reqwest::header::HeaderMap;
 
@@ -26,11 +32,27 @@ const HEADER_AUTHORIZATION: &str = "Authorization";
 
 #[derive(Debug)]
 pub enum RequestError {
-    ErrorWithMsg(String),
+    Status(StatusCode, String),
     Common(reqwest::Error),
     Format(reqwest::Error),
 }
 
+impl RequestError {
+    /// Check whether the request may succeed if retried, e.g. on connection failure,
+    /// timeout or server error.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            RequestError::Status(status, _) => {
+                status.is_server_error()
+                    || *status == StatusCode::REQUEST_TIMEOUT
+                    || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            RequestError::Common(e) => !e.is_builder() && !e.is_redirect(),
+            RequestError::Format(_) => true,
+        }
+    }
+}
+
 pub type RequestResult<T> = std::result::Result<T, RequestError>;
 
 #[derive(Clone)]
@@ -94,15 +116,16 @@ impl ProxyHealth {
 
 #[derive(Debug)]
 struct Proxy {
-    client: Client,
+    client: ArcSwap<Client>,
     health: ProxyHealth,
     fallback: bool,
 }
 
 #[derive(Debug)]
 pub struct Request {
-    client: Client,
+    client: ArcSwap<Client>,
     proxy: Option<Proxy>,
+    config: CommonConfig,
 }
 
 pub fn is_success_status(status: StatusCode) -> bool {
@@ -113,8 +136,67 @@ pub fn respond(resp: Response) -> RequestResult<Response> {
     if is_success_status(resp.status()) {
         return Ok(resp);
     }
+    let status = resp.status();
     let msg = resp.text().map_err(RequestError::Format)?;
-    Err(RequestError::ErrorWithMsg(msg))
+    Err(RequestError::Status(status, msg))
+}
+
+fn read_pem_certs(path: &str) -> Result<Vec<X509>> {
+    let pem = fs::read(path).map_err(|e| {
+        error!("Failed to read cert file {}: {:?}", path, e);
+        e
+    })?;
+    let certs = X509::stack_from_pem(&pem)
+        .map_err(|e| einval!(format!("invalid cert file {}: {}", path, e)))?;
+    if certs.is_empty() {
+        return Err(einval!(format!("no certificate found in {}", path)));
+    }
+    Ok(certs)
+}
+
+/// Load all CA certificates from a PEM encoded bundle.
+fn load_ca_certs(ca_file: &str) -> Result<Vec<Certificate>> {
+    read_pem_certs(ca_file)?
+        .iter()
+        .map(|cert| {
+            let der = cert.to_der().map_err(|e| einval!(e))?;
+            Certificate::from_der(&der).map_err(|e| einval!(e))
+        })
+        .collect()
+}
+
+/// Load client identity from PEM encoded certificate chain and private key. The native TLS
+/// backend only accepts identity in PKCS #12 format, so repack them.
+fn load_identity(cert_file: &str, key_file: &str) -> Result<Identity> {
+    let certs = read_pem_certs(cert_file)?;
+    let key = fs::read(key_file).map_err(|e| {
+        error!("Failed to read key file {}: {:?}", key_file, e);
+        e
+    })?;
+    let key = PKey::private_key_from_pem(&key)
+        .map_err(|e| einval!(format!("invalid key file {}: {}", key_file, e)))?;
+
+    let mut chain = Stack::new().map_err(|e| eother!(e))?;
+    for cert in certs.iter().skip(1) {
+        chain.push(cert.clone()).map_err(|e| eother!(e))?;
+    }
+    let mut builder = Pkcs12::builder();
+    builder.ca(chain);
+    let der = builder
+        .build("", "", &key, &certs[0])
+        .and_then(|p| p.to_der())
+        .map_err(|e| einval!(format!("invalid client cert or key: {}", e)))?;
+
+    Identity::from_pkcs12_der(&der, "").map_err(|e| einval!(e))
+}
+
+/// Get modification time of configured cert files, used to detect changes.
+fn tls_files_mtime(tls: &TlsConfig) -> Vec<Option<SystemTime>> {
+    [&tls.ca_file, &tls.cert_file, &tls.key_file]
+        .iter()
+        .filter(|path| !path.is_empty())
+        .map(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+        .collect()
 }
 
 impl Request {
@@ -139,11 +221,82 @@ impl Request {
             cb = cb.proxy(reqwest::Proxy::all(proxy).map_err(|e| einval!(e))?)
         }
 
+        let tls = &config.tls;
+        if !tls.ca_file.is_empty() {
+            for cert in load_ca_certs(&tls.ca_file)? {
+                cb = cb.add_root_certificate(cert);
+            }
+        }
+        match (tls.cert_file.is_empty(), tls.key_file.is_empty()) {
+            (false, false) => cb = cb.identity(load_identity(&tls.cert_file, &tls.key_file)?),
+            (true, true) => {}
+            _ => {
+                return Err(einval!(
+                    "both cert_file and key_file are required for mutual TLS"
+                ))
+            }
+        }
+        if tls.skip_verify {
+            cb = cb
+                .danger_accept_invalid_certs(true)
+                .danger_accept_invalid_hostnames(true);
+        }
+
         cb.build().map_err(|e| einval!(e))
     }
 
+    /// Rebuild all clients with the current cert files, the old clients are kept if failed.
+    fn reload_clients(&self) -> Result<()> {
+        let client = Self::build_client("", &self.config)?;
+        if let Some(proxy) = &self.proxy {
+            let proxy_client = Self::build_client(&self.config.proxy.url, &self.config)?;
+            proxy.client.store(Arc::new(proxy_client));
+        }
+        self.client.store(Arc::new(client));
+        Ok(())
+    }
+
+    /// Spawn thread to reload clients once cert files are changed, for example rotated
+    /// by cert-manager. The thread exits after the request is dropped.
+    fn start_tls_reload(
+        request: Weak<Request>,
+        interval: Duration,
+        mut mtimes: Vec<Option<SystemTime>>,
+    ) {
+        let ret = thread::Builder::new()
+            .name("nydus-tls-reload".to_string())
+            .spawn(move || loop {
+                thread::sleep(interval);
+                let request = match request.upgrade() {
+                    Some(request) => request,
+                    None => break,
+                };
+                let current = tls_files_mtime(&request.config.tls);
+                if current == mtimes {
+                    continue;
+                }
+                match request.reload_clients() {
+                    Ok(()) => {
+                        info!("TLS cert files changed, backend clients reloaded");
+                        mtimes = current;
+                    }
+                    // Cert files may be partially updated, try again next time.
+                    Err(e) => warn!("Failed to reload TLS cert files: {:?}", e),
+                }
+            });
+        if let Err(e) = ret {
+            error!("Failed to spawn TLS cert reload thread: {:?}", e);
+        }
+    }
+
     pub fn new(config: CommonConfig) -> Result<Arc<Request>> {
         info!("backend config: {:?}", config);
+        if config.tls.skip_verify {
+            warn!(
+                "!!! TLS verification of backend servers is DISABLED by `skip_verify`, \
+                 connections are vulnerable to man-in-the-middle attacks !!!"
+            );
+        }
         let client = Self::build_client("", &config)?;
         let proxy = if !config.proxy.url.is_empty() {
             let ping_url = if !config.proxy.ping_url.is_empty() {
@@ -152,7 +305,7 @@ impl Request {
                 None
             };
             Some(Proxy {
-                client: Self::build_client(&config.proxy.url, &config)?,
+                client: ArcSwap::from_pointee(Self::build_client(&config.proxy.url, &config)?),
                 health: ProxyHealth::new(config.proxy.check_interval, ping_url),
                 fallback: config.proxy.fallback,
             })
@@ -160,7 +313,21 @@ impl Request {
             None
         };
 
-        let request = Arc::new(Request { client, proxy });
+        let mtimes = tls_files_mtime(&config.tls);
+        let reload_interval = config.tls.reload_interval;
+        let request = Arc::new(Request {
+            client: ArcSwap::from_pointee(client),
+            proxy,
+            config: config.clone(),
+        });
+
+        if !mtimes.is_empty() && reload_interval != 0 {
+            Self::start_tls_reload(
+                Arc::downgrade(&request),
+                Duration::from_secs(reload_interval),
+                mtimes,
+            );
+        }
 
         if let Some(proxy) = &request.proxy {
             let request = request.clone();
@@ -266,7 +433,7 @@ impl Request {
                     _ => None,
                 };
                 let result = self.call_inner(
-                    &proxy.client,
+                    &proxy.client.load(),
                     method.clone(),
                     url,
                     &query,
@@ -295,7 +462,7 @@ impl Request {
             }
         }
         self.call_inner(
-            &self.client,
+            &self.client.load(),
             method,
             url,
             &query,
@@ -306,3 +473,79 @@ impl Request {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::asn1::Asn1Time;
+    use openssl::hash::MessageDigest;
+    use openssl::rsa::Rsa;
+    use openssl::x509::X509NameBuilder;
+    use vmm_sys_util::tempdir::TempDir;
+
+    fn self_signed_cert() -> (Vec<u8>, Vec<u8>) {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "nydus").unwrap();
+        let name = name.build();
+
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+
+        (
+            builder.build().to_pem().unwrap(),
+            key.private_key_to_pem_pkcs8().unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_tls_config() {
+        let dir = TempDir::new().unwrap();
+        let cert_file = dir.as_path().join("cert.pem");
+        let key_file = dir.as_path().join("key.pem");
+        let (cert, key) = self_signed_cert();
+        fs::write(&cert_file, &cert).unwrap();
+        fs::write(&key_file, &key).unwrap();
+
+        let config: CommonConfig = serde_json::from_value(serde_json::json!({
+            "tls": {
+                "ca_file": cert_file,
+                "cert_file": cert_file,
+                "key_file": key_file,
+                "reload_interval": 0,
+            }
+        }))
+        .unwrap();
+        let request = Request::new(config.clone()).unwrap();
+        let mtimes = tls_files_mtime(&config.tls);
+        assert_eq!(mtimes.len(), 3);
+        assert!(mtimes.iter().all(|t| t.is_some()));
+
+        // Rotated cert files are picked up by reload.
+        let (cert, key) = self_signed_cert();
+        fs::write(&cert_file, &cert).unwrap();
+        fs::write(&key_file, &key).unwrap();
+        request.reload_clients().unwrap();
+
+        // Broken cert files are rejected, the old clients remain.
+        fs::write(&key_file, "invalid").unwrap();
+        request.reload_clients().unwrap_err();
+
+        let mut config = config;
+        config.tls.key_file = String::new();
+        Request::new(config.clone()).unwrap_err();
+        config.tls.cert_file = String::new();
+        config.tls.ca_file = key_file.to_str().unwrap().to_string();
+        Request::new(config).unwrap_err();
+    }
+}
@@ -0,0 +1,267 @@
+// Copyright 2021 Ant Group. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Retry policy of backend read requests, with exponential backoff and a circuit breaker
+//! to fail fast when the storage server keeps failing.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use nydus_utils::metrics::{BackendMetrics, CircuitState};
+
+use crate::utils::random;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RetryConfig {
+    /// Backoff interval before the first retry, in milliseconds.
+    initial_interval: u64,
+    /// Upper bound of backoff interval, in milliseconds.
+    max_interval: u64,
+    /// Multiplier applied to backoff interval after each retry.
+    multiplier: f64,
+    /// Randomization factor in [0, 1], the actual backoff interval is picked from
+    /// [interval * (1 - jitter), interval * (1 + jitter)].
+    jitter: f64,
+    /// Stop retrying once the read request has taken longer than it, in milliseconds,
+    /// 0 for no limit.
+    max_elapsed_time: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            initial_interval: 100,
+            max_interval: 5000,
+            multiplier: 2.0,
+            jitter: 0.5,
+            max_elapsed_time: 30000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CircuitBreakerConfig {
+    /// Open the circuit after the number of consecutive failed requests, 0 to disable.
+    failure_threshold: u32,
+    /// Fail fast for the period in seconds after circuit opened, then let a trial request
+    /// through to probe the storage server.
+    cool_down: u64,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 0,
+            cool_down: 30,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum BreakerState {
+    Closed(u32),
+    Open(Instant),
+    // A trial request is in flight.
+    HalfOpen,
+}
+
+#[derive(Debug)]
+struct CircuitBreaker {
+    failure_threshold: u32,
+    cool_down: Duration,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    fn allow(&self, metrics: &BackendMetrics) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            BreakerState::Closed(_) => true,
+            BreakerState::Open(since) if since.elapsed() >= self.cool_down => {
+                *state = BreakerState::HalfOpen;
+                metrics
+                    .circuit_breaker()
+                    .set_state(CircuitState::HalfOpen, self.failure_threshold);
+                true
+            }
+            _ => {
+                metrics.circuit_breaker().reject();
+                false
+            }
+        }
+    }
+
+    fn record(&self, metrics: &BackendMetrics, failed: bool) {
+        let mut state = self.state.lock().unwrap();
+        let failures = match *state {
+            _ if !failed => 0,
+            BreakerState::Closed(failures) => failures + 1,
+            // Concurrent requests allowed before the circuit opened.
+            BreakerState::Open(_) => return,
+            BreakerState::HalfOpen => self.failure_threshold,
+        };
+
+        if failures >= self.failure_threshold {
+            warn!(
+                "Circuit breaker opened after {} consecutive backend failures, fail fast in {:?}",
+                failures, self.cool_down
+            );
+            *state = BreakerState::Open(Instant::now());
+            metrics
+                .circuit_breaker()
+                .set_state(CircuitState::Open, failures);
+        } else {
+            if failures == 0 {
+                if let BreakerState::HalfOpen = *state {
+                    info!("Circuit breaker closed, backend recovered");
+                }
+            }
+            *state = BreakerState::Closed(failures);
+            metrics
+                .circuit_breaker()
+                .set_state(CircuitState::Closed, failures);
+        }
+    }
+}
+
+/// Policy to retry failed backend read requests.
+#[derive(Debug)]
+pub struct RetryPolicy {
+    retry_limit: u8,
+    config: RetryConfig,
+    breaker: Option<CircuitBreaker>,
+}
+
+impl RetryPolicy {
+    pub fn new(retry_limit: u8, config: RetryConfig, breaker: CircuitBreakerConfig) -> Self {
+        let breaker = if breaker.failure_threshold != 0 {
+            Some(CircuitBreaker {
+                failure_threshold: breaker.failure_threshold,
+                cool_down: Duration::from_secs(breaker.cool_down),
+                state: Mutex::new(BreakerState::Closed(0)),
+            })
+        } else {
+            None
+        };
+
+        RetryPolicy {
+            retry_limit,
+            config,
+            breaker,
+        }
+    }
+
+    /// Check whether the request is allowed by the circuit breaker.
+    pub fn allow(&self, metrics: &BackendMetrics) -> bool {
+        self.breaker
+            .as_ref()
+            .map(|b| b.allow(metrics))
+            .unwrap_or(true)
+    }
+
+    /// Record result of a request to the circuit breaker. Only retryable errors should count
+    /// as failures, others like non-existent blobs mean the storage server is still serving.
+    pub fn record(&self, metrics: &BackendMetrics, failed: bool) {
+        if let Some(breaker) = self.breaker.as_ref() {
+            breaker.record(metrics, failed);
+        }
+    }
+
+    /// Get backoff interval before the next retry of a retryable error, or `None` if the
+    /// request should not be retried anymore. `retry_count` is the number of retries done.
+    pub fn backoff(&self, retry_count: u8, begin: &SystemTime) -> Option<Duration> {
+        if retry_count >= self.retry_limit {
+            return None;
+        }
+
+        let config = &self.config;
+        let interval = (config.initial_interval as f64
+            * config.multiplier.max(1.0).powi(retry_count as i32))
+        .min(config.max_interval as f64);
+        let jitter = config.jitter.clamp(0.0, 1.0);
+        let interval = interval * (1.0 - jitter + 2.0 * jitter * random());
+        let interval = Duration::from_millis(interval as u64);
+
+        if config.max_elapsed_time != 0 {
+            let elapsed = SystemTime::elapsed(begin).unwrap_or_default();
+            if elapsed + interval > Duration::from_millis(config.max_elapsed_time) {
+                return None;
+            }
+        }
+
+        Some(interval)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let config: RetryConfig = serde_json::from_value(serde_json::json!({
+            "initial_interval": 100,
+            "max_interval": 300,
+            "jitter": 0.0,
+            "max_elapsed_time": 0,
+        }))
+        .unwrap();
+        let policy = RetryPolicy::new(4, config, CircuitBreakerConfig::default());
+        let begin = SystemTime::now();
+        let intervals = (0..5)
+            .map(|i| policy.backoff(i, &begin))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            intervals,
+            vec![
+                Some(Duration::from_millis(100)),
+                Some(Duration::from_millis(200)),
+                Some(Duration::from_millis(300)),
+                Some(Duration::from_millis(300)),
+                None
+            ]
+        );
+
+        let config: RetryConfig = serde_json::from_value(serde_json::json!({
+            "initial_interval": 1000,
+            "max_elapsed_time": 500,
+        }))
+        .unwrap();
+        let policy = RetryPolicy::new(4, config, CircuitBreakerConfig::default());
+        assert!(policy.backoff(0, &begin).is_none());
+    }
+
+    #[test]
+    fn test_circuit_breaker() {
+        let metrics = BackendMetrics::default();
+        let breaker: CircuitBreakerConfig = serde_json::from_value(serde_json::json!({
+            "failure_threshold": 2,
+            "cool_down": 0,
+        }))
+        .unwrap();
+        let policy = RetryPolicy::new(0, RetryConfig::default(), breaker);
+
+        policy.record(&metrics, true);
+        assert_eq!(metrics.circuit_breaker().state(), CircuitState::Closed);
+        policy.record(&metrics, false);
+        policy.record(&metrics, true);
+        assert_eq!(metrics.circuit_breaker().state(), CircuitState::Closed);
+        policy.record(&metrics, true);
+        assert_eq!(metrics.circuit_breaker().state(), CircuitState::Open);
+
+        // Only one trial request is allowed after cool down.
+        assert!(policy.allow(&metrics));
+        assert_eq!(metrics.circuit_breaker().state(), CircuitState::HalfOpen);
+        assert!(!policy.allow(&metrics));
+        policy.record(&metrics, true);
+        assert_eq!(metrics.circuit_breaker().state(), CircuitState::Open);
+
+        assert!(policy.allow(&metrics));
+        policy.record(&metrics, false);
+        assert_eq!(metrics.circuit_breaker().state(), CircuitState::Closed);
+        assert!(policy.allow(&metrics));
+    }
+}
@@ -0,0 +1,492 @@
+// Copyright 2021 Ant Group. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Export cached chunks of blobs from a blobcache work directory into a portable archive,
+//! and import the archive into another work directory, so a node can be pre-seeded with
+//! cache warmed on a golden node.
+//!
+//! The archive is composed of little-endian fields:
+//! - header: magic | version | flags | blob count, each in u32
+//! - for each blob: blob id length in u32 | blob id | chunk count in u32
+//! - for each chunk: chunk index in u32 | data size in u32 | chunk data as in cache file
+//!
+//! Chunk indexes of a blob are exactly the ready chunks recorded by its chunk map.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{Read, Result, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::sync::Arc;
+
+use nix::sys::uio;
+
+use crate::cache::blobcache::open_blob_file;
+use crate::cache::chunkmap::{indexed, indexed::IndexedChunkMap, ChunkMap};
+use crate::compress;
+use crate::device::{RafsBio, RafsBlobEntry, RafsChunkInfo};
+use crate::utils::{alloc_buf, digest_check};
+use crate::RAFS_MAX_BLOCK_SIZE;
+
+use nydus_utils::digest;
+
+/// The magic number of blobcache archive, stored as bytes of string "NBCA".
+const MAGIC: u32 = 0x4143_424E;
+const VERSION: u32 = 1;
+/// Chunk data is saved compressed in cache files.
+const FLAG_COMPRESSED: u32 = 0x1;
+/// Compressed chunk data may be a bit larger than the original one.
+const MAX_CHUNK_DATA_SIZE: u32 = 2 * RAFS_MAX_BLOCK_SIZE as u32;
+
+/// Statistics of exporting or importing a blobcache archive.
+#[derive(Debug, Default, Serialize)]
+pub struct ArchiveStat {
+    pub blobs: u32,
+    /// Chunks exported, or imported into the work directory.
+    pub chunks: u64,
+    /// Size of chunk data in above chunks.
+    pub bytes: u64,
+    /// Chunks skipped in importing because of failing in validation.
+    pub invalid_chunks: u64,
+}
+
+fn read_u32(r: &mut dyn Read) -> Result<u32> {
+    let mut buf = [0u8; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn write_u32(w: &mut dyn Write, v: u32) -> Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+// Group chunks by blob in order of blob index, each chunk is indexed by its chunk index.
+fn group_chunks(bios: &[RafsBio]) -> BTreeMap<u32, (Arc<RafsBlobEntry>, HashMap<u32, &RafsBio>)> {
+    let mut blobs = BTreeMap::new();
+    for bio in bios {
+        blobs
+            .entry(bio.blob.blob_index)
+            .or_insert_with(|| (bio.blob.clone(), HashMap::new()))
+            .1
+            .insert(bio.chunkinfo.index(), bio);
+    }
+    blobs
+}
+
+fn chunk_offset_size(chunk: &dyn RafsChunkInfo, compressed: bool) -> (u64, u32) {
+    if compressed {
+        (chunk.compress_offset(), chunk.compress_size())
+    } else {
+        (chunk.decompress_offset(), chunk.decompress_size())
+    }
+}
+
+fn read_chunk(file: &File, chunk: &dyn RafsChunkInfo, compressed: bool) -> Result<Vec<u8>> {
+    let (offset, size) = chunk_offset_size(chunk, compressed);
+    let mut data = alloc_buf(size as usize);
+    let n = uio::pread(file.as_raw_fd(), &mut data, offset as i64).map_err(|_| last_error!())?;
+    if n != data.len() {
+        return Err(eio!(format!("short read of chunk index {}", chunk.index())));
+    }
+    Ok(data)
+}
+
+/// Export ready chunks among `bios` from blobcache work directory `work_dir` into `w`,
+/// blobs without cache files are skipped. `compressed` tells whether the blobcache saves
+/// compressed chunk data, which is checked against chunk digests, and `compressor` is the
+/// default compression algorithm of chunks.
+pub fn export(
+    work_dir: &Path,
+    compressed: bool,
+    bios: &[RafsBio],
+    compressor: compress::Algorithm,
+    digester: digest::Algorithm,
+    w: &mut dyn Write,
+) -> Result<ArchiveStat> {
+    // Stargz doesn't carry chunk digests, so there's nothing to validate against.
+    if compressor == compress::Algorithm::GZip {
+        return Err(enosys!("can't export blobcache of gzip compressed blobs"));
+    }
+
+    let mut stat = ArchiveStat::default();
+    let mut blobs = Vec::new();
+
+    for (blob, chunks) in group_chunks(bios).values() {
+        let blob_path = work_dir.join(&blob.blob_id);
+        let chunk_map_path = work_dir.join(format!("{}.{}", blob.blob_id, indexed::FILE_SUFFIX));
+        if !blob.with_extended_blob_table() || !blob_path.exists() || !chunk_map_path.exists() {
+            info!("Skip exporting blob {} not cached", blob.blob_id);
+            continue;
+        }
+
+        let blob_path = blob_path.to_string_lossy().to_string();
+        let file = open_blob_file(&blob_path)?;
+        let chunk_map = IndexedChunkMap::new(&blob_path, blob.chunk_count)?;
+        let mut ready = Vec::new();
+        for bio in chunks.values() {
+            let chunk = bio.chunkinfo.as_ref();
+            if !chunk_map.has_ready(chunk, false)? {
+                continue;
+            }
+            // Chunk data is found at wrong places if the blobcache layout isn't as claimed.
+            let data = read_chunk(&file, chunk, compressed)?;
+            if let Err(e) = validate_chunk(chunk, &data, compressed, compressor, digester) {
+                warn!(
+                    "Chunk index {} of blob {} is invalid, {}",
+                    chunk.index(),
+                    blob.blob_id,
+                    e
+                );
+                stat.invalid_chunks += 1;
+                continue;
+            }
+            ready.push(bio.chunkinfo.clone());
+        }
+        ready.sort_by_key(|c| c.index());
+        blobs.push((blob.clone(), file, ready));
+    }
+
+    if stat.invalid_chunks > 0 && blobs.iter().all(|(_, _, chunks)| chunks.is_empty()) {
+        return Err(einval!(format!(
+            "no chunk is valid, blobcache may not save {} chunk data",
+            if compressed {
+                "compressed"
+            } else {
+                "uncompressed"
+            }
+        )));
+    }
+
+    write_u32(w, MAGIC)?;
+    write_u32(w, VERSION)?;
+    write_u32(w, if compressed { FLAG_COMPRESSED } else { 0 })?;
+    write_u32(w, blobs.len() as u32)?;
+
+    for (blob, file, chunks) in blobs {
+        write_u32(w, blob.blob_id.len() as u32)?;
+        w.write_all(blob.blob_id.as_bytes())?;
+        write_u32(w, chunks.len() as u32)?;
+        for chunk in chunks {
+            let data = read_chunk(&file, chunk.as_ref(), compressed)?;
+            write_u32(w, chunk.index())?;
+            write_u32(w, data.len() as u32)?;
+            w.write_all(&data)?;
+            stat.chunks += 1;
+            stat.bytes += data.len() as u64;
+        }
+        stat.blobs += 1;
+    }
+
+    Ok(stat)
+}
+
+// Check chunk data from archive against the chunk digest.
+fn validate_chunk(
+    chunk: &dyn RafsChunkInfo,
+    data: &[u8],
+    compressed: bool,
+    compressor: compress::Algorithm,
+    digester: digest::Algorithm,
+) -> Result<()> {
+    let (_, size) = chunk_offset_size(chunk, compressed);
+    if data.len() != size as usize {
+        return Err(einval!(format!(
+            "chunk size {} doesn't match {}",
+            data.len(),
+            size
+        )));
+    }
+
+    let valid = if compressed && chunk.is_compressed() {
+        let mut d = alloc_buf(chunk.decompress_size() as usize);
+        let compressor = chunk.flags().compressor().unwrap_or(compressor);
+        compress::decompress(data, None, &mut d, compressor)?;
+        digest_check(&d, chunk.block_id(), digester)
+    } else {
+        digest_check(data, chunk.block_id(), digester)
+    };
+    if !valid {
+        return Err(einval!("chunk digest mismatches"));
+    }
+
+    Ok(())
+}
+
+/// Import an archive from `r` into blobcache work directory `work_dir`, chunk data is
+/// validated against `bios` of the filesystem bootstrap before being marked as ready.
+/// `cache_compressed` tells whether the blobcache saves compressed chunk data, which must
+/// match the archive. `compressor` is the default compression algorithm of chunks.
+pub fn import(
+    work_dir: &Path,
+    cache_compressed: bool,
+    bios: &[RafsBio],
+    compressor: compress::Algorithm,
+    digester: digest::Algorithm,
+    r: &mut dyn Read,
+) -> Result<ArchiveStat> {
+    // Stargz doesn't carry chunk digests, so there's nothing to validate against.
+    if compressor == compress::Algorithm::GZip {
+        return Err(enosys!("can't import blobcache of gzip compressed blobs"));
+    }
+    if read_u32(r)? != MAGIC {
+        return Err(einval!("invalid blobcache archive magic"));
+    }
+    let version = read_u32(r)?;
+    if version != VERSION {
+        return Err(einval!(format!(
+            "unsupported blobcache archive version {}",
+            version
+        )));
+    }
+    let compressed = read_u32(r)? & FLAG_COMPRESSED != 0;
+    if compressed != cache_compressed {
+        return Err(einval!(format!(
+            "blobcache archive of {} chunk data mismatches the blobcache",
+            if compressed {
+                "compressed"
+            } else {
+                "uncompressed"
+            }
+        )));
+    }
+    let blob_count = read_u32(r)?;
+
+    let blobs: HashMap<String, (Arc<RafsBlobEntry>, HashMap<u32, &RafsBio>)> = group_chunks(bios)
+        .into_iter()
+        .map(|(_, (blob, chunks))| (blob.blob_id.clone(), (blob, chunks)))
+        .collect();
+    let mut stat = ArchiveStat::default();
+
+    for _ in 0..blob_count {
+        let id_len = read_u32(r)?;
+        if id_len as usize > libc::PATH_MAX as usize {
+            return Err(einval!("invalid blob id in blobcache archive"));
+        }
+        let mut blob_id = vec![0u8; id_len as usize];
+        r.read_exact(&mut blob_id)?;
+        let blob_id = String::from_utf8(blob_id).map_err(|e| einval!(e))?;
+        let (blob, chunks) = blobs.get(&blob_id).ok_or_else(|| {
+            einval!(format!(
+                "blob {} in blobcache archive is not used by the bootstrap",
+                blob_id
+            ))
+        })?;
+        if !blob.with_extended_blob_table() {
+            return Err(einval!(format!("blob {} has no chunk map", blob_id)));
+        }
+
+        let blob_path = work_dir.join(&blob_id).to_string_lossy().to_string();
+        let file = open_blob_file(&blob_path)?;
+        let chunk_map = IndexedChunkMap::new(&blob_path, blob.chunk_count)?;
+        let chunk_count = read_u32(r)?;
+        for _ in 0..chunk_count {
+            let index = read_u32(r)?;
+            let size = read_u32(r)?;
+            if size > MAX_CHUNK_DATA_SIZE {
+                return Err(einval!(format!("invalid chunk size {} in archive", size)));
+            }
+            let mut data = alloc_buf(size as usize);
+            r.read_exact(&mut data)?;
+
+            let chunk = match chunks.get(&index) {
+                Some(bio) => bio.chunkinfo.as_ref(),
+                None => {
+                    warn!("Chunk index {} of blob {} is unknown", index, blob_id);
+                    stat.invalid_chunks += 1;
+                    continue;
+                }
+            };
+            if let Err(e) = validate_chunk(chunk, &data, compressed, compressor, digester) {
+                warn!(
+                    "Chunk index {} of blob {} is invalid, {}",
+                    index, blob_id, e
+                );
+                stat.invalid_chunks += 1;
+                continue;
+            }
+
+            if !chunk_map.has_ready(chunk, false)? {
+                let (offset, _) = chunk_offset_size(chunk, compressed);
+                let n = uio::pwrite(file.as_raw_fd(), &data, offset as i64)
+                    .map_err(|_| last_error!())?;
+                if n != data.len() {
+                    return Err(eio!(format!(
+                        "short write of chunk index {} in blob {}",
+                        index, blob_id
+                    )));
+                }
+                chunk_map.set_ready(chunk)?;
+            }
+            stat.chunks += 1;
+            stat.bytes += size as u64;
+        }
+        stat.blobs += 1;
+    }
+
+    Ok(stat)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::blobcache::blob_cache_tests::MockChunkInfo;
+    use crate::device::RafsChunkFlags;
+    use vmm_sys_util::tempdir::TempDir;
+
+    fn bios(data: &[Vec<u8>]) -> Vec<RafsBio> {
+        let blob = Arc::new(RafsBlobEntry {
+            chunk_count: data.len() as u32,
+            blob_id: "blob-1".to_string(),
+            ..Default::default()
+        });
+        let mut offset = 0;
+        data.iter()
+            .enumerate()
+            .map(|(i, d)| {
+                let (c, _) = compress::compress(d, compress::Algorithm::Lz4Block).unwrap();
+                let chunk = MockChunkInfo {
+                    block_id: digest::RafsDigest::from_buf(d, digest::Algorithm::Blake3),
+                    flags: RafsChunkFlags::COMPRESSED,
+                    index: i as u32,
+                    compress_offset: offset,
+                    compress_size: c.len() as u32,
+                    decompress_offset: (i * d.len()) as u64,
+                    decompress_size: d.len() as u32,
+                    ..Default::default()
+                };
+                offset += c.len() as u64;
+                RafsBio::new(Arc::new(chunk), blob.clone(), 0, d.len(), 0x1000, false)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_export_import() {
+        let data = (0..4u8).map(|i| vec![i; 0x1000]).collect::<Vec<_>>();
+        let bios = bios(&data);
+
+        for compressed in &[false, true] {
+            let src = TempDir::new().unwrap();
+            let dst = TempDir::new().unwrap();
+
+            // Cache chunk 0 and 2 in the source work directory, with chunk 2 corrupted.
+            let blob_path = src.as_path().join("blob-1").to_string_lossy().to_string();
+            let file = open_blob_file(&blob_path).unwrap();
+            let chunk_map = IndexedChunkMap::new(&blob_path, 4).unwrap();
+            for i in &[0, 2] {
+                let chunk = bios[*i].chunkinfo.as_ref();
+                let (offset, size) = chunk_offset_size(chunk, *compressed);
+                let d = if *i == 2 {
+                    vec![0xffu8; size as usize]
+                } else if *compressed {
+                    compress::compress(&data[*i], compress::Algorithm::Lz4Block)
+                        .unwrap()
+                        .0
+                        .to_vec()
+                } else {
+                    data[*i].clone()
+                };
+                uio::pwrite(file.as_raw_fd(), &d, offset as i64).unwrap();
+                chunk_map.set_ready(chunk).unwrap();
+            }
+
+            // Corrupted chunks are not exported.
+            let mut archive = Vec::new();
+            let stat = export(
+                src.as_path(),
+                *compressed,
+                &bios,
+                compress::Algorithm::Lz4Block,
+                digest::Algorithm::Blake3,
+                &mut archive,
+            )
+            .unwrap();
+            assert_eq!(stat.blobs, 1);
+            assert_eq!(stat.chunks, 1);
+            assert_eq!(stat.invalid_chunks, 1);
+
+            // Chunk data doesn't match the blobcache layout claimed wrongly.
+            assert!(export(
+                src.as_path(),
+                !*compressed,
+                &bios,
+                compress::Algorithm::Lz4Block,
+                digest::Algorithm::Blake3,
+                &mut Vec::new(),
+            )
+            .is_err());
+
+            // Archive is rejected by blobcache of different layout.
+            assert!(import(
+                dst.as_path(),
+                !*compressed,
+                &bios,
+                compress::Algorithm::Lz4Block,
+                digest::Algorithm::Blake3,
+                &mut archive.as_slice(),
+            )
+            .is_err());
+
+            // Corrupted chunks in archive are skipped.
+            let mut corrupted = archive.clone();
+            let len = corrupted.len();
+            corrupted[len - 1] ^= 0xff;
+            let stat = import(
+                dst.as_path(),
+                *compressed,
+                &bios,
+                compress::Algorithm::Lz4Block,
+                digest::Algorithm::Blake3,
+                &mut corrupted.as_slice(),
+            )
+            .unwrap();
+            assert_eq!(stat.chunks, 0);
+            assert_eq!(stat.invalid_chunks, 1);
+
+            let stat = import(
+                dst.as_path(),
+                *compressed,
+                &bios,
+                compress::Algorithm::Lz4Block,
+                digest::Algorithm::Blake3,
+                &mut archive.as_slice(),
+            )
+            .unwrap();
+            assert_eq!(stat.blobs, 1);
+            assert_eq!(stat.chunks, 1);
+            assert_eq!(stat.invalid_chunks, 0);
+
+            let blob_path = dst.as_path().join("blob-1").to_string_lossy().to_string();
+            let chunk_map = IndexedChunkMap::new(&blob_path, 4).unwrap();
+            assert!(chunk_map
+                .has_ready(bios[0].chunkinfo.as_ref(), false)
+                .unwrap());
+            for bio in &bios[1..] {
+                assert!(!chunk_map.has_ready(bio.chunkinfo.as_ref(), false).unwrap());
+            }
+
+            // Archive of blobs not used by the bootstrap is rejected.
+            let other = bios
+                .iter()
+                .map(|b| {
+                    let mut b = b.clone();
+                    b.blob = Arc::new(RafsBlobEntry {
+                        blob_id: "blob-2".to_string(),
+                        ..(*b.blob).clone()
+                    });
+                    b
+                })
+                .collect::<Vec<_>>();
+            assert!(import(
+                dst.as_path(),
+                *compressed,
+                &other,
+                compress::Algorithm::Lz4Block,
+                digest::Algorithm::Blake3,
+                &mut archive.as_slice(),
+            )
+            .is_err());
+        }
+    }
+}
@@ -2,18 +2,21 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs::{self, File, OpenOptions};
 use std::io::{ErrorKind, Result, Seek, SeekFrom};
 use std::mem::ManuallyDrop;
 use std::num::NonZeroU32;
 use std::ops::DerefMut;
+use std::os::unix::fs::MetadataExt;
 use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::path::{Path, PathBuf};
 use std::sync::{
-    atomic::{AtomicU32, Ordering},
-    Arc, Mutex, RwLock,
+    atomic::{AtomicU32, AtomicU64, Ordering},
+    Arc, Mutex, RwLock, Weak,
 };
 use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
 
 use nix::sys::uio;
 use nix::unistd::dup;
@@ -29,33 +32,128 @@ use vm_memory::VolatileSlice;
 
 use crate::backend::BlobBackend;
 use crate::cache::chunkmap::{
-    digested::DigestedChunkMap, indexed::IndexedChunkMap, BlobChunkMap, ChunkMap,
+    digested, digested::DigestedChunkMap, indexed, indexed::IndexedChunkMap, BlobChunkMap, ChunkMap,
 };
+use crate::cache::memcache::ChunkMemCache;
+use crate::cache::prefetch::{PrefetchPriority, PrefetchProgress, PrefetchQueue, UserIoGuard};
 use crate::cache::RafsCache;
 use crate::cache::*;
 use crate::device::{BlobPrefetchControl, RafsBio, RafsBlobEntry};
 use crate::factory::CacheConfig;
-use crate::utils::{alloc_buf, copyv, readv, MemSliceCursor};
+use crate::utils::{alloc_buf, copyv, ofd_lock, readv, MemSliceCursor};
 use crate::{StorageError, RAFS_DEFAULT_BLOCK_SIZE};
 
+use nydus_utils::digest::RAFS_DIGEST_LENGTH;
+use nydus_utils::div_round_up;
 use nydus_utils::metrics::{BlobcacheMetrics, Metric};
 
 pub const SINGLE_INFLIGHT_WAIT_TIMEOUT: u64 = 2000;
 
+lazy_static! {
+    /// Work directories of all blobcache instances ever created, garbage collection scans them.
+    /// The value tells whether the work directory was configured explicitly.
+    static ref WORK_DIRS: Mutex<HashMap<PathBuf, bool>> = Default::default();
+}
+/// Interval in seconds to check disk usage of the blob cache when its size is limited.
+const EVICTION_CHECK_INTERVAL: u64 = 5;
+/// Work directory of the blob cache if not configured, garbage collection never scans it.
+const DEFAULT_WORK_DIR: &str = ".";
+
+struct BlobCacheEntry {
+    file: File,
+    size: u64,
+    chunk_map: Arc<dyn ChunkMap + Sync + Send>,
+    blob_id: String,
+    // Logical time of the latest access, the least recently used blob is evicted first.
+    last_access: AtomicU64,
+    // Shared by whoever reads or writes the cache file, taken exclusively by eviction.
+    evict_lock: Arc<RwLock<()>>,
+}
+
+impl BlobCacheEntry {
+    /// Disk space in bytes really taken by the cache file.
+    fn disk_usage(&self) -> Result<u64> {
+        Ok(self.file.metadata()?.blocks() * 512)
+    }
+
+    /// Drop all cached data of the blob, return how many bytes are freed.
+    /// Return None if the blob is in use by other nydusd instances sharing the work directory.
+    fn evict(&self) -> Result<Option<u64>> {
+        let _guard = self.evict_lock.write().unwrap();
+        // Upgrade the in-use lock, which fails if anybody else holds the blob open.
+        if !ofd_lock(self.file.as_raw_fd(), libc::F_WRLCK, 0, 0, false)? {
+            return Ok(None);
+        }
+        let ret = self.punch();
+        ofd_lock(self.file.as_raw_fd(), libc::F_RDLCK, 0, 0, true)?;
+        ret.map(Some)
+    }
+
+    fn punch(&self) -> Result<u64> {
+        let usage = self.disk_usage()?;
+
+        // Mark chunks as not ready before dropping data, so nobody trusts the holes.
+        self.chunk_map.clear()?;
+        // Round up to whole blocks, otherwise the tailing partial block is zeroed but not freed.
+        let metadata = self.file.metadata()?;
+        let len = div_round_up(metadata.len(), metadata.blksize()) * metadata.blksize();
+        let ret = unsafe {
+            libc::fallocate(
+                self.file.as_raw_fd(),
+                libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+                0,
+                len as libc::off_t,
+            )
+        };
+        if ret != 0 {
+            let err = last_error!();
+            if err.raw_os_error() != Some(libc::EOPNOTSUPP) {
+                return Err(err);
+            }
+            // The underlying file system can't punch holes, so drop the whole file content.
+            self.file.set_len(0)?;
+        }
+
+        Ok(usage.saturating_sub(self.disk_usage()?))
+    }
+}
+
+/// Disk usage thresholds of the blob cache, in unit of bytes.
+#[derive(Clone, Copy)]
+struct EvictionWatermark {
+    // Start evicting blobs once disk usage exceeds it.
+    high: u64,
+    // Stop evicting blobs once disk usage drops below it.
+    low: u64,
+}
+
 struct BlobCacheState {
-    /// Index blob info by blob index, HashMap<blob_index, (blob_file, blob_size, Arc<ChunkMap>)>.
-    blob_map: HashMap<u32, (File, u64, Arc<dyn ChunkMap + Sync + Send>)>,
+    /// Index blob info by blob index.
+    blob_map: HashMap<u32, Arc<BlobCacheEntry>>,
     work_dir: String,
     backend_size_valid: bool,
     metrics: Arc<BlobcacheMetrics>,
     backend: Arc<dyn BlobBackend + Sync + Send>,
+    // Logical clock to order blob accesses.
+    access_clock: AtomicU64,
 }
 
 impl BlobCacheState {
     fn get(&self, blob: &RafsBlobEntry) -> Option<(RawFd, u64, Arc<dyn ChunkMap + Sync + Send>)> {
+        self.blob_map.get(&blob.blob_index).map(|entry| {
+            entry.last_access.store(
+                self.access_clock.fetch_add(1, Ordering::Relaxed),
+                Ordering::Relaxed,
+            );
+            (entry.file.as_raw_fd(), entry.size, entry.chunk_map.clone())
+        })
+    }
+
+    /// Get the lock to prevent the blob from being evicted while accessing its cache file.
+    fn evict_lock(&self, blob: &RafsBlobEntry) -> Option<Arc<RwLock<()>>> {
         self.blob_map
             .get(&blob.blob_index)
-            .map(|(file, size, chunk_map)| (file.as_raw_fd(), *size, chunk_map.clone()))
+            .map(|entry| entry.evict_lock.clone())
     }
 
     fn set(
@@ -67,11 +165,7 @@ impl BlobCacheState {
         }
 
         let blob_file_path = format!("{}/{}", self.work_dir, blob.blob_id);
-        let file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .read(true)
-            .open(&blob_file_path)?;
+        let file = open_blob_file(&blob_file_path)?;
         let fd = file.as_raw_fd();
 
         let size = if self.backend_size_valid {
@@ -91,11 +185,21 @@ impl BlobCacheState {
                 blob.chunk_count,
             )?)) as Arc<dyn ChunkMap + Sync + Send>
         } else {
-            Arc::new(BlobChunkMap::from(DigestedChunkMap::new())) as Arc<dyn ChunkMap + Sync + Send>
+            Arc::new(BlobChunkMap::from(DigestedChunkMap::open(
+                &blob_file_path,
+                &file,
+            )?)) as Arc<dyn ChunkMap + Sync + Send>
         };
 
-        self.blob_map
-            .insert(blob.blob_index, (file, size, chunk_map.clone()));
+        let entry = BlobCacheEntry {
+            file,
+            size,
+            chunk_map: chunk_map.clone(),
+            blob_id: blob.blob_id.clone(),
+            last_access: AtomicU64::new(self.access_clock.fetch_add(1, Ordering::Relaxed)),
+            evict_lock: Arc::new(RwLock::new(())),
+        };
+        self.blob_map.insert(blob.blob_index, Arc::new(entry));
 
         self.metrics
             .underlying_files
@@ -107,6 +211,28 @@ impl BlobCacheState {
     }
 }
 
+/// Open the cache file and mark it as in use by a shared file lock, which protects it from
+/// being evicted or collected as garbage by other nydusd instances sharing the work directory.
+pub(crate) fn open_blob_file(path: &str) -> Result<File> {
+    loop {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .read(true)
+            .open(path)?;
+        ofd_lock(file.as_raw_fd(), libc::F_RDLCK, 0, 0, true)?;
+
+        // The file may have been removed by garbage collection before getting locked.
+        let locked = file.metadata()?;
+        match fs::metadata(path) {
+            Ok(m) if m.dev() == locked.dev() && m.ino() == locked.ino() => return Ok(file),
+            Ok(_) => {}
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
 struct PrefetchContext {
     pub enable: bool,
     pub threads_count: usize,
@@ -157,10 +283,12 @@ pub struct BlobCache {
     // some concepts come from GCRA like "cells". GCRA is a sort of improved "Leaky Bucket"
     // firstly invented from ATM network technology. Wrap the limiter into Throttle!
     limiter: Option<Arc<RateLimiter<NotKeyed, InMemoryState, QuantaClock>>>,
-    mr_sender: Arc<Mutex<Option<spmc::Sender<MergedBackendRequest>>>>,
-    mr_receiver: Option<spmc::Receiver<MergedBackendRequest>>,
+    prefetch_queue: Option<Arc<PrefetchQueue>>,
     metrics: Arc<BlobcacheMetrics>,
     runtime: Arc<Runtime>,
+    eviction: Option<EvictionWatermark>,
+    mem_cache: Option<Arc<ChunkMemCache>>,
+    scrub_progress: Arc<Mutex<ScrubProgress>>,
 }
 
 #[allow(dead_code)]
@@ -331,6 +459,7 @@ impl BlobCache {
     fn delay_persist(
         &self,
         fd: RawFd,
+        blob: &RafsBlobEntry,
         chunk_map: &Arc<dyn ChunkMap + Send + Sync>,
         chunk_info: &Arc<dyn RafsChunkInfo>,
         buffer: Arc<DataBuffer>,
@@ -338,10 +467,20 @@ impl BlobCache {
         let delayed_chunk = chunk_info.clone();
         let delayed_chunk_map = chunk_map.clone();
         let compressed = self.is_compressed;
+        let evict_lock = self.cache.read().unwrap().evict_lock(blob);
         self.metrics.buffered_backend_size.add(buffer.size() as u64);
         let metrics = self.metrics.clone();
         self.runtime.spawn(async move {
             metrics.buffered_backend_size.sub(buffer.size() as u64);
+            // Don't block the runtime if the blob is being evicted, just give up caching the chunk.
+            let _evict_guard = match evict_lock.as_ref().map(|l| l.try_read()) {
+                Some(Ok(guard)) => Some(guard),
+                Some(Err(_)) => {
+                    delayed_chunk_map.finish(delayed_chunk.as_ref());
+                    return;
+                }
+                None => None,
+            };
             match Self::persist_chunk(compressed, fd, delayed_chunk.as_ref(), buffer.slice()) {
                 Err(e) => {
                     error!(
@@ -435,27 +574,48 @@ impl BlobCache {
         debug!("total backend data {}KB", blob_size / 1024);
 
         if !continuous_chunks.is_empty() {
-            let mut chunks =
-                self.read_chunks(blob_id, blob_offset, blob_size as usize, &continuous_chunks)?;
+            let (fd, _, chunk_map) = self
+                .cache
+                .write()
+                .expect("Expect cache lock not poisoned")
+                .set(blob_entry)
+                .map_err(|e| {
+                    error!("Set chunk map error!");
+                    e
+                })?;
+            // Compressed blob cache saves raw chunk data from backend.
+            let persist_raw = |cki: &dyn RafsChunkInfo, raw: &[u8]| {
+                Self::persist_compressed_chunk(fd, &chunk_map, cki, raw)
+            };
+            let raw_hook = if self.is_compressed {
+                Some(&persist_raw as &dyn Fn(&dyn RafsChunkInfo, &[u8]))
+            } else {
+                None
+            };
+
+            let mut chunks = self.read_chunks(
+                blob_id,
+                blob_offset,
+                blob_size as usize,
+                &continuous_chunks,
+                raw_hook,
+            )?;
             assert_eq!(continuous_chunks.len(), chunks.len());
             debug!("total backend io size {}", blob_size);
-            // TODO: The locking granularity below is a little big. We
-            // don't have to hold blobcache mutex when writing files.
-            // But prefetch io is usually limited. So it is low priority.
-            let mut cache_guard = self.cache.write().expect("Expect cache lock not poisoned");
-            let (fd, _, chunk_map) = cache_guard.set(blob_entry).map_err(|e| {
-                error!("Set chunk map error!");
-                e
-            })?;
 
             let len = continuous_chunks.len();
             for (i, c) in continuous_chunks.iter().rev().enumerate() {
                 // FIXME: What if ready after backend IO completion?
                 let d = Arc::new(DataBuffer::Allocated(chunks.pop().unwrap()));
                 if chunk_tags[len - 1 - i] {
+                    if let Some(ref mem_cache) = self.mem_cache {
+                        mem_cache.insert(blob_entry, c.as_ref(), d.slice());
+                    }
                     buffer_holder.push(d.clone());
                 }
-                self.delay_persist(fd, &chunk_map, c, d);
+                if !self.is_compressed {
+                    self.delay_persist(fd, blob_entry, &chunk_map, c, d);
+                }
             }
 
             buffer_holder.reverse();
@@ -544,19 +704,7 @@ impl BlobCache {
                     d.mut_slice(),
                     Some(&|raw| {
                         if self.is_compressed {
-                            Self::persist_chunk(true, fd, chunk.as_ref(), raw).unwrap_or_else(
-                                |e| {
-                                    error!(
-                                        "Failed in writing compressed blob cache, {},index {}",
-                                        e,
-                                        chunk.index()
-                                    );
-                                    0
-                                },
-                            );
-                            chunk_map
-                                .set_ready(chunk.as_ref())
-                                .unwrap_or_else(|e| error!("set ready failed, {}", e));
+                            Self::persist_compressed_chunk(fd, chunk_map, chunk.as_ref(), raw);
                         }
                     }),
                 )?;
@@ -564,7 +712,7 @@ impl BlobCache {
                     d = d.try_to_own();
                     buffer_holder = Arc::new(d);
                     let delayed_buffer = buffer_holder.clone();
-                    self.delay_persist(fd, &chunk_map, chunk, delayed_buffer);
+                    self.delay_persist(fd, blob, &chunk_map, chunk, delayed_buffer);
                     Ok(buffer_holder.as_ref())
                 } else {
                     Ok(&d)
@@ -574,6 +722,9 @@ impl BlobCache {
                 // Thanks to above curly bracket, we can clean tracer up if any of the steps fails.
                 {chunk_map.finish(chunk.as_ref());e})?
         };
+        if let Some(ref mem_cache) = self.mem_cache {
+            mem_cache.insert(blob, chunk.as_ref(), owned_buffer.slice());
+        }
 
         let read_size = copyv(
             &[owned_buffer.slice()],
@@ -630,6 +781,9 @@ impl BlobCache {
                     self.cache.write().unwrap().set(blob)?
                 }
             };
+            // Keep the blob from being evicted until all regions of the request are dispatched.
+            let evict_lock = self.cache.read().unwrap().evict_lock(blob);
+            let _evict_guard = evict_lock.as_ref().map(|l| l.read().unwrap());
             for (i, chunk) in req.chunks.iter().enumerate() {
                 let has_ready = chunk_map.has_ready(chunk.as_ref(), true)?;
                 // Hit cache if cache ready
@@ -779,6 +933,37 @@ impl BlobCache {
         Ok(total_read)
     }
 
+    /// Serve user IO from the in-memory chunk cache, only if all chunks of it are there.
+    fn read_mem_cache(&self, bios: &[RafsBio], bufs: &[VolatileSlice]) -> Option<Result<usize>> {
+        let mem_cache = self.mem_cache.as_ref()?;
+        let mut chunk_buffers = Vec::new();
+        let mut missed = 0;
+        let mut offset = None;
+        let mut user_size = 0;
+
+        for bio in bios.iter().filter(|b| b.user_io) {
+            match mem_cache.get(&bio.blob, bio.chunkinfo.as_ref()) {
+                Some(d) => chunk_buffers.push(d),
+                None => missed += 1,
+            }
+            offset.get_or_insert(bio.offset);
+            user_size += bio.size;
+        }
+        if missed != 0 {
+            self.metrics.mem_cache_misses.add(missed);
+            return None;
+        }
+        let offset = offset?;
+        self.metrics.mem_cache_hits.add(chunk_buffers.len() as u64);
+
+        let chunk_buffers: Vec<&[u8]> = chunk_buffers.iter().map(|b| b.as_slice()).collect();
+        Some(
+            copyv(&chunk_buffers, bufs, offset as usize, user_size, 0, 0)
+                .map(|(n, _)| n)
+                .map_err(|e| eother!(e)),
+        )
+    }
+
     fn read_blobcache_chunk(
         &self,
         fd: RawFd,
@@ -792,8 +977,9 @@ impl BlobCache {
             cki.decompress_offset()
         };
 
+        let compressor = self.chunk_compressor(cki);
         let mut d;
-        let raw_chunk = if self.is_compressed && self.compressor() != compress::Algorithm::GZip {
+        let raw_chunk = if self.is_compressed && compressor != compress::Algorithm::GZip {
             // Need to put compressed data into a temporary buffer so as to perform decompression.
             //
             // gzip is special that it doesn't carry compress_size, instead, we make an IO stream out
@@ -807,7 +993,7 @@ impl BlobCache {
         };
 
         let mut raw_stream = None;
-        if self.compressor() != compress::Algorithm::GZip {
+        if compressor != compress::Algorithm::GZip {
             debug!(
                 "reading blobcache file fd {} offset {} size {}",
                 fd,
@@ -836,7 +1022,7 @@ impl BlobCache {
             raw_chunk,
             raw_stream,
             chunk,
-            self.is_compressed,
+            self.is_compressed && cki.is_compressed(),
             need_validate,
         )?;
 
@@ -854,6 +1040,28 @@ impl BlobCache {
         readv(fd, &iovec, offset)
     }
 
+    // Save raw chunk data from backend into compressed blob cache and mark it as ready.
+    fn persist_compressed_chunk(
+        fd: RawFd,
+        chunk_map: &Arc<dyn ChunkMap + Send + Sync>,
+        cki: &dyn RafsChunkInfo,
+        raw: &[u8],
+    ) {
+        match Self::persist_chunk(true, fd, cki, raw) {
+            Err(e) => {
+                error!(
+                    "Failed in writing compressed blob cache, {}, index {}",
+                    e,
+                    cki.index()
+                );
+                chunk_map.finish(cki)
+            }
+            Ok(_) => chunk_map
+                .set_ready(cki)
+                .unwrap_or_else(|e| error!("set ready failed, {}", e)),
+        }
+    }
+
     /// Persist a single chunk into local blob cache file. We have to write to the cache
     /// file in unit of chunk size
     fn persist_chunk(
@@ -887,6 +1095,161 @@ impl BlobCache {
         Ok(n)
     }
 
+    /// Verify data of the chunk if it's ready in cache, and mark it not ready if corrupted.
+    /// Return None if the chunk is not cached.
+    fn scrub_chunk(
+        &self,
+        bio: &RafsBio,
+        limiter: Option<&RateLimiter<NotKeyed, InMemoryState, QuantaClock>>,
+    ) -> Option<Result<()>> {
+        let blob = &bio.blob;
+        let chunk = bio.chunkinfo.as_ref();
+        // Look up the blob directly, scrubbing shouldn't make it recently used.
+        let mut entry = self
+            .cache
+            .read()
+            .unwrap()
+            .blob_map
+            .get(&blob.blob_index)
+            .cloned();
+        if entry.is_none() {
+            // Open cache files left by previous nydusd instances, but never create them.
+            let work_dir = self.cache.read().unwrap().work_dir.clone();
+            if !Path::new(&work_dir).join(&blob.blob_id).exists() {
+                return None;
+            }
+            let mut cache = self.cache.write().unwrap();
+            if let Err(e) = cache.set(blob) {
+                return Some(Err(e));
+            }
+            entry = cache.blob_map.get(&blob.blob_index).cloned();
+        }
+        let entry = entry?;
+
+        let _evict_guard = entry.evict_lock.read().unwrap();
+        if !entry.chunk_map.has_ready_nowait(chunk).unwrap_or(false) {
+            return None;
+        }
+        if let Some(limiter) = limiter {
+            let size = if self.is_compressed {
+                chunk.compress_size()
+            } else {
+                chunk.decompress_size()
+            };
+            if let Some(cells) = NonZeroU32::new(size) {
+                if let Err(e) = limiter
+                    .check_n(cells)
+                    .or_else(|_| block_on(limiter.until_n_ready(cells)))
+                {
+                    error!("{}: give up rate-limiting", e);
+                }
+            }
+        }
+
+        let mut d = alloc_buf(chunk.decompress_size() as usize);
+        let ret = self.read_blobcache_chunk(entry.file.as_raw_fd(), chunk, &mut d, true);
+        if ret.is_err() {
+            entry
+                .chunk_map
+                .unset_ready(chunk)
+                .unwrap_or_else(|e| error!("Failed to unset chunk ready, {}", e));
+        }
+        Some(ret)
+    }
+
+    fn scrub_chunks(&self, bios: Vec<RafsBio>, rate: u32) {
+        // Same as prefetch, the limit must not be less than the chunk size.
+        let limiter = NonZeroU32::new(rate).map(|v| {
+            let v = std::cmp::max(v.get(), RAFS_DEFAULT_BLOCK_SIZE as u32);
+            RateLimiter::direct(Quota::per_second(NonZeroU32::new(v).unwrap()))
+        });
+
+        for bio in &bios {
+            // Stop once the blob cache is released.
+            if !self.scrub_progress.lock().unwrap().running {
+                info!("Blobcache scrubbing is stopped");
+                return;
+            }
+
+            let ret = self.scrub_chunk(bio, limiter.as_ref());
+            let mut progress = self.scrub_progress.lock().unwrap();
+            progress.scanned_chunks += 1;
+            match ret {
+                Some(Ok(_)) => progress.verified_chunks += 1,
+                Some(Err(e)) => {
+                    warn!(
+                        "Chunk index {} of blob {} is corrupted in blobcache, {}",
+                        bio.chunkinfo.index(),
+                        bio.blob.blob_id,
+                        e
+                    );
+                    progress.corrupted_chunks.push(CorruptedChunk {
+                        blob_id: bio.blob.blob_id.clone(),
+                        chunk_index: bio.chunkinfo.index(),
+                        error: e.to_string(),
+                    });
+                }
+                None => {}
+            }
+        }
+
+        let mut progress = self.scrub_progress.lock().unwrap();
+        progress.running = false;
+        info!(
+            "Blobcache scrubbing is done, {} chunks verified, {} corrupted",
+            progress.verified_chunks,
+            progress.corrupted_chunks.len()
+        );
+    }
+
+    /// Evict least recently used blobs until disk usage of the blob cache drops below
+    /// the low watermark, if it has exceeded the high watermark.
+    ///
+    /// Blobs also opened by other nydusd instances sharing the work directory can't be evicted,
+    /// so disk usage may stay above the watermark if most of it is taken by shared blobs.
+    fn evict(&self) -> Result<()> {
+        let watermark = match self.eviction {
+            Some(w) => w,
+            None => return Ok(()),
+        };
+
+        let mut usage = 0;
+        let mut candidates = Vec::new();
+        for entry in self.cache.read().unwrap().blob_map.values() {
+            let used = entry.disk_usage()?;
+            if used != 0 {
+                usage += used;
+                candidates.push(entry.clone());
+            }
+        }
+        if usage <= watermark.high {
+            return Ok(());
+        }
+
+        candidates.sort_by_key(|entry| entry.last_access.load(Ordering::Relaxed));
+        for entry in candidates {
+            if usage <= watermark.low {
+                break;
+            }
+            let freed = match entry.evict()? {
+                Some(freed) => freed,
+                None => {
+                    debug!("Skip evicting blob {} shared by others", entry.blob_id);
+                    continue;
+                }
+            };
+            info!(
+                "Evict blob {} from blobcache, {} bytes freed",
+                entry.blob_id, freed
+            );
+            usage = usage.saturating_sub(freed);
+            self.metrics.evictions.inc();
+            self.metrics.evicted_bytes.add(freed);
+        }
+
+        Ok(())
+    }
+
     fn convert_to_merge_request(continuous_bios: &[&RafsBio]) -> MergedBackendRequest {
         let first = continuous_bios[0];
         let mut mr = MergedBackendRequest::new(first.chunkinfo.clone(), first.blob.clone(), first);
@@ -912,28 +1275,216 @@ impl BlobCache {
     fn generate_merged_requests_for_prefetch(
         &self,
         bios: &mut [RafsBio],
-        tx: &mut spmc::Sender<MergedBackendRequest>,
+        queue: &PrefetchQueue,
+        priority: PrefetchPriority,
         merging_size: usize,
     ) {
-        let limiter = |merged_size: u32| {
-            if let Some(ref limiter) = self.limiter {
-                let cells = NonZeroU32::new(merged_size).unwrap();
-                if let Err(e) = limiter
-                    .check_n(cells)
-                    .or_else(|_| block_on(limiter.until_n_ready(cells)))
+        self.generate_merged_requests(bios, merging_size, true, &mut |mr: MergedBackendRequest| {
+            if !queue.push(mr, priority) {
+                debug!("Prefetch is stopped, drop prefetch request");
+            }
+        })
+    }
+
+    // Prefetch workers stay alive for later requests once started, so check whether there're
+    // pending requests as well.
+    fn is_prefetch_active(&self) -> bool {
+        self.prefetch_ctx.is_working()
+            && self
+                .prefetch_queue
+                .as_ref()
+                .map_or(false, |q| q.is_active())
+    }
+
+    // Throttle prefetch requests issued to storage backend by the bandwidth limit.
+    fn limit_prefetch(&self, merged_size: u32) {
+        if let Some(ref limiter) = self.limiter {
+            let cells = NonZeroU32::new(merged_size).unwrap();
+            if let Err(e) = limiter
+                .check_n(cells)
+                .or_else(|_| block_on(limiter.until_n_ready(cells)))
+            {
+                // `InsufficientCapacity` is the only possible error
+                // Have to give up to avoid dead-loop
+                error!("{}: give up rate-limiting", e);
+            }
+        }
+    }
+
+    // Move pending prefetch requests containing chunks needed by the user read to the
+    // front, and hold prefetch back while the user read waits for storage backend.
+    fn yield_prefetch(&self, bios: &[RafsBio]) -> Option<UserIoGuard<'_>> {
+        let queue = self.prefetch_queue.as_ref()?;
+        if !self.is_prefetch_active() {
+            return None;
+        }
+
+        let mut missed = false;
+        for bio in bios {
+            if !self.is_chunk_cached(bio.chunkinfo.as_ref(), &bio.blob) {
+                queue.promote(&bio.blob, bio.chunkinfo.as_ref());
+                missed = true;
+            }
+        }
+
+        if missed {
+            Some(queue.user_io())
+        } else {
+            None
+        }
+    }
+
+    // Fetch uncached chunks of a prefetch request from storage backend into blobcache, return
+    // bytes read from storage backend.
+    fn prefetch_merged_request(&self, mr: &MergedBackendRequest) -> Result<u64> {
+        let blob_offset = mr.blob_offset;
+        let blob_size = mr.blob_size;
+        let continuous_chunks = &mr.chunks;
+        let blob_id = &mr.blob_entry.blob_id;
+        let mut issue_batch: bool;
+
+        trace!(
+            "Merged req id {} req offset {} size {}",
+            blob_id,
+            blob_offset,
+            blob_size
+        );
+
+        if blob_size == 0 {
+            return Ok(0);
+        }
+
+        issue_batch = false;
+        // An immature trick here to detect if chunk already resides in
+        // blob cache file. Hopefully, we can have a more clever and agile
+        // way in the future. Principe is that if all chunks are Ready,
+        // abort this Merged Request. It might involve extra stress
+        // to local file system.
+        let ee = self
+            .cache
+            .read()
+            .expect("Expect cache lock not poisoned")
+            .get(&mr.blob_entry);
+
+        let (fd, _, chunk_map) = if let Some(be) = ee {
+            be
+        } else {
+            self.cache
+                .write()
+                .expect("Expect cache lock not poisoned")
+                .set(&mr.blob_entry)?
+        };
+        let evict_lock = self
+            .cache
+            .read()
+            .expect("Expect cache lock not poisoned")
+            .evict_lock(&mr.blob_entry);
+        let _evict_guard = evict_lock.as_ref().map(|l| l.read().unwrap());
+
+        for c in continuous_chunks {
+            if chunk_map.has_ready(c.as_ref(), false).unwrap_or_default() {
+                continue;
+            }
+
+            if !&mr.blob_entry.with_extended_blob_table() {
+                // Always validate if chunk's hash is equal to `block_id` by which
+                // blobcache judges if the data is up-to-date.
+                let d_size = c.decompress_size() as usize;
+                if self
+                    .read_blobcache_chunk(fd, c.as_ref(), alloc_buf(d_size).as_mut_slice(), true)
+                    .is_err()
                 {
-                    // `InsufficientCapacity` is the only possible error
-                    // Have to give up to avoid dead-loop
-                    error!("{}: give up rate-limiting", e);
+                    // Aha, we have a not integrated chunk here. Issue the entire
+                    // merged request from backend to boost.
+                    issue_batch = true;
+                    break;
+                } else {
+                    let _ = chunk_map
+                        .set_ready(c.as_ref())
+                        .map_err(|e| error!("Failed to set chunk ready: {:?}", e));
                 }
+            } else {
+                issue_batch = true;
+            }
+        }
+
+        if !issue_batch {
+            for c in continuous_chunks {
+                chunk_map.finish(c.as_ref());
+            }
+            return Ok(0);
+        }
+
+        self.limit_prefetch(blob_size);
+        // Record how much prefetch data is requested from storage backend.
+        // So the average backend merged request size will be prefetch_data_amount/prefetch_mr_count.
+        // We can measure merging possibility by this.
+        self.metrics.prefetch_mr_count.inc();
+        self.metrics.prefetch_data_amount.add(blob_size as u64);
+
+        let persist_raw = |cki: &dyn RafsChunkInfo, raw: &[u8]| {
+            if !chunk_map.has_ready_nowait(cki).unwrap_or_default() {
+                BlobCache::persist_compressed_chunk(fd, &chunk_map, cki, raw)
             }
         };
+        let raw_hook = if self.is_compressed {
+            Some(&persist_raw as &dyn Fn(&dyn RafsChunkInfo, &[u8]))
+        } else {
+            None
+        };
 
-        self.generate_merged_requests(bios, merging_size, true, &mut |mr: MergedBackendRequest| {
-            limiter(mr.blob_size);
-            // Safe to unwrap because channel won't be closed.
-            tx.send(mr).unwrap();
-        })
+        let chunks = match self.read_chunks(
+            blob_id,
+            blob_offset,
+            blob_size as usize,
+            continuous_chunks,
+            raw_hook,
+        ) {
+            Ok(chunks) => chunks,
+            Err(e) => {
+                // Before issue a merged backend request, we already mark
+                // them as `OnTrip` inflight.
+                for c in continuous_chunks.iter().map(|i| i.as_ref()) {
+                    chunk_map.finish(c);
+                }
+                return Err(e);
+            }
+        };
+        if self.is_compressed {
+            return Ok(blob_size as u64);
+        }
+
+        // TODO: The locking granularity below is a little big. We
+        // don't have to hold blobcache mutex when writing files.
+        // But prefetch io is usually limited. So it is low priority.
+        let mut cache_guard = self.cache.write().expect("Expect cache lock not poisoned");
+        let (fd, _, chunk_map) = cache_guard.set(&mr.blob_entry).map_err(|e| {
+            error!("Set cache index error!");
+            e
+        })?;
+        let mut result = Ok(blob_size as u64);
+        for (i, c) in continuous_chunks.iter().enumerate() {
+            if !chunk_map.has_ready_nowait(c.as_ref()).unwrap_or_default() {
+                // Write multiple chunks once
+                match BlobCache::persist_chunk(
+                    self.is_compressed,
+                    fd,
+                    c.as_ref(),
+                    chunks[i].as_slice(),
+                ) {
+                    Err(e) => {
+                        error!("Failed to cache chunk: {}", e);
+                        chunk_map.finish(c.as_ref());
+                        result = Err(e);
+                    }
+                    Ok(_) => chunk_map
+                        .set_ready(c.as_ref())
+                        .unwrap_or_else(|e| error!("Failed to set chunk ready: {:?}", e)),
+                }
+            }
+        }
+
+        result
     }
 
     fn generate_merged_requests_for_user(
@@ -1012,11 +1563,29 @@ impl BlobCache {
         }
     }
 }
-// TODO: This function is too long... :-(
+fn kick_eviction_worker(cache: Weak<BlobCache>) {
+    thread::Builder::new()
+        .name("blobcache_eviction".to_string())
+        .spawn(move || loop {
+            thread::sleep(Duration::from_secs(EVICTION_CHECK_INTERVAL));
+            // Exit once the blob cache is released.
+            match cache.upgrade() {
+                Some(blobcache) => blobcache
+                    .evict()
+                    .unwrap_or_else(|e| error!("Failed to evict blobcache, {:?}", e)),
+                None => break,
+            }
+        })
+        .map(|_| ())
+        .unwrap_or_else(|e| error!("Create eviction worker failed, {:?}", e));
+}
+
 fn kick_prefetch_workers(cache: Arc<BlobCache>) {
+    // Safe to unwrap because the queue must be established before prefetch workers
+    let queue = cache.prefetch_queue.clone().unwrap();
     for num in 0..cache.prefetch_ctx.threads_count {
         let blobcache = cache.clone();
-        let rx = blobcache.mr_receiver.clone();
+        let queue = queue.clone();
         // TODO: We now don't define prefetch policy. Prefetch works according to hints coming
         // from on-disk prefetch table or input arguments while nydusd starts. So better
         // we can have method to kill prefetch threads. But hopefully, we can add
@@ -1031,145 +1600,12 @@ fn kick_prefetch_workers(cache: Arc<BlobCache>) {
                     .metrics
                     .prefetch_workers
                     .fetch_add(1, Ordering::Relaxed);
-                // Safe because channel must be established before prefetch workers
-                'wait_mr: while let Ok(mr) = rx.as_ref().unwrap().recv() {
-                    let blob_offset = mr.blob_offset;
-                    let blob_size = mr.blob_size;
-                    let continuous_chunks = &mr.chunks;
-                    let blob_id = &mr.blob_entry.blob_id;
-                    let mut issue_batch: bool;
-
-                    trace!(
-                        "Merged req id {} req offset {} size {}",
-                        blob_id,
-                        blob_offset,
-                        blob_size
-                    );
-
-                    if blob_size == 0 {
-                        continue;
-                    }
-
-                    issue_batch = false;
-                    // An immature trick here to detect if chunk already resides in
-                    // blob cache file. Hopefully, we can have a more clever and agile
-                    // way in the future. Principe is that if all chunks are Ready,
-                    // abort this Merged Request. It might involve extra stress
-                    // to local file system.
-                    let ee = blobcache
-                        .cache
-                        .read()
-                        .expect("Expect cache lock not poisoned")
-                        .get(&mr.blob_entry);
-
-                    let (fd, _, chunk_map) = if let Some(be) = ee {
-                        be
-                    } else {
-                        match blobcache
-                            .cache
-                            .write()
-                            .expect("Expect cache lock not poisoned")
-                            .set(&mr.blob_entry)
-                        {
-                            Err(err) => {
-                                error!("{}", err);
-                                continue;
-                            }
-                            Ok(be) => be,
-                        }
-                    };
-
-                    for c in continuous_chunks {
-                        if chunk_map.has_ready(c.as_ref(), false).unwrap_or_default() {
-                            continue;
-                        }
-
-                        if !&mr.blob_entry.with_extended_blob_table() {
-                            // Always validate if chunk's hash is equal to `block_id` by which
-                            // blobcache judges if the data is up-to-date.
-                            let d_size = c.decompress_size() as usize;
-                            if blobcache
-                                .read_blobcache_chunk(
-                                    fd,
-                                    c.as_ref(),
-                                    alloc_buf(d_size).as_mut_slice(),
-                                    true,
-                                )
-                                .is_err()
-                            {
-                                // Aha, we have a not integrated chunk here. Issue the entire
-                                // merged request from backend to boost.
-                                issue_batch = true;
-                                break;
-                            } else {
-                                let _ = chunk_map
-                                    .set_ready(c.as_ref())
-                                    .map_err(|e| error!("Failed to set chunk ready: {:?}", e));
-                            }
-                        } else {
-                            issue_batch = true;
-                        }
-                    }
-
-                    if !issue_batch {
-                        for c in continuous_chunks {
-                            chunk_map.finish(c.as_ref());
-                        }
-                        continue 'wait_mr;
-                    }
-
-                    // Record how much prefetch data is requested from storage backend.
-                    // So the average backend merged request size will be prefetch_data_amount/prefetch_mr_count.
-                    // We can measure merging possibility by this.
-                    blobcache.metrics.prefetch_mr_count.inc();
-                    blobcache.metrics.prefetch_data_amount.add(blob_size as u64);
-
-                    if let Ok(chunks) = blobcache.read_chunks(
-                        blob_id,
-                        blob_offset,
-                        blob_size as usize,
-                        &continuous_chunks,
-                    ) {
-                        // TODO: The locking granularity below is a little big. We
-                        // don't have to hold blobcache mutex when writing files.
-                        // But prefetch io is usually limited. So it is low priority.
-                        let mut cache_guard = blobcache
-                            .cache
-                            .write()
-                            .expect("Expect cache lock not poisoned");
-                        if let Ok((fd, _, chunk_map)) = cache_guard
-                            .set(&mr.blob_entry)
-                            .map_err(|_| error!("Set cache index error!"))
-                        {
-                            for (i, c) in continuous_chunks.iter().enumerate() {
-                                if !chunk_map.has_ready_nowait(c.as_ref()).unwrap_or_default() {
-                                    // Write multiple chunks once
-                                    match BlobCache::persist_chunk(
-                                        blobcache.is_compressed,
-                                        fd,
-                                        c.as_ref(),
-                                        chunks[i].as_slice(),
-                                    ) {
-                                        Err(e) => {
-                                            error!("Failed to cache chunk: {}", e);
-                                            chunk_map.finish(c.as_ref())
-                                        }
-                                        Ok(_) => {
-                                            chunk_map.set_ready(c.as_ref()).unwrap_or_else(|e| {
-                                                error!("Failed to set chunk ready: {:?}", e)
-                                            })
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    } else {
-                        // Before issue a merged backend request, we already mark
-                        // them as `OnTrip` inflight.
-                        for c in continuous_chunks.iter().map(|i| i.as_ref()) {
-                            chunk_map.finish(c);
-                        }
+                while let Some(mr) = queue.pop() {
+                    let result = blobcache.prefetch_merged_request(&mr);
+                    if let Err(e) = result.as_ref() {
+                        error!("Failed to prefetch blob {}, {}", mr.blob_entry.blob_id, e);
                     }
+                    queue.finish(&mr, &result);
                 }
                 blobcache
                     .metrics
@@ -1209,8 +1645,13 @@ impl RafsCache for BlobCache {
     fn read(&self, bios: &mut [RafsBio], bufs: &[VolatileSlice]) -> Result<usize> {
         self.metrics.total.inc();
 
+        if let Some(r) = self.read_mem_cache(bios, bufs) {
+            return r;
+        }
+        let _user_io = self.yield_prefetch(bios);
+
         // Try to get rid of effect from prefetch.
-        if self.prefetch_ctx.is_working() {
+        if self.is_prefetch_active() {
             if let Some(ref limiter) = self.limiter {
                 if let Some(v) = NonZeroU32::new(bufs.len() as u32) {
                     // Even fails in getting tokens, continue to read
@@ -1238,23 +1679,47 @@ impl RafsCache for BlobCache {
 
     fn release(&self) {
         self.metrics.release().unwrap_or_else(|e| error!("{:?}", e));
+        self.scrub_progress.lock().unwrap().running = false;
 
         // TODO: Cache is responsible to release backend's resources
         self.backend().release()
     }
 
-    fn prefetch(&self, bios: &mut [RafsBio]) -> StorageResult<usize> {
+    fn prefetch(&self, bios: &mut [RafsBio], priority: PrefetchPriority) -> StorageResult<usize> {
         let merging_size = self.prefetch_ctx.merging_size;
         self.metrics.prefetch_unmerged_chunks.add(bios.len() as u64);
-        if let Some(mr_sender) = self.mr_sender.lock().unwrap().as_mut() {
-            self.generate_merged_requests_for_prefetch(bios, mr_sender, merging_size);
+        if let Some(queue) = self.prefetch_queue.as_ref() {
+            self.generate_merged_requests_for_prefetch(bios, queue, priority, merging_size);
         }
         Ok(0)
     }
 
+    fn begin_prefetch(&self) {
+        if let Some(queue) = self.prefetch_queue.as_ref() {
+            queue.begin();
+        }
+    }
+
+    fn end_prefetch(&self) {
+        if let Some(queue) = self.prefetch_queue.as_ref() {
+            queue.end();
+        }
+    }
+
+    fn cancel_prefetch(&self) -> StorageResult<()> {
+        if let Some(queue) = self.prefetch_queue.as_ref() {
+            queue.cancel();
+        }
+        Ok(())
+    }
+
+    fn prefetch_progress(&self) -> Option<PrefetchProgress> {
+        self.prefetch_queue.as_ref().map(|q| q.progress())
+    }
+
     fn stop_prefetch(&self) -> StorageResult<()> {
-        if let Some(s) = self.mr_sender.lock().unwrap().take() {
-            drop(s);
+        if let Some(queue) = self.prefetch_queue.as_ref() {
+            queue.close();
         }
 
         let mut guard = self
@@ -1281,6 +1746,38 @@ impl RafsCache for BlobCache {
         }
     }
 
+    fn scrub(&self, bios: Vec<RafsBio>, rate: u32) -> Result<()> {
+        // Stargz doesn't carry chunk digests, so there's nothing to verify against.
+        if self.compressor() == compress::Algorithm::GZip {
+            return Err(enosys!("can't scrub blobcache of gzip compressed blobs"));
+        }
+        {
+            let mut progress = self.scrub_progress.lock().unwrap();
+            if progress.running {
+                return Err(ealready!("blobcache is being scrubbed"));
+            }
+            *progress = ScrubProgress {
+                running: true,
+                total_chunks: bios.len() as u64,
+                ..Default::default()
+            };
+        }
+
+        let cache = self.clone();
+        thread::Builder::new()
+            .name("blobcache_scrub".to_string())
+            .spawn(move || cache.scrub_chunks(bios, rate))
+            .map(|_| ())
+            .map_err(|e| {
+                self.scrub_progress.lock().unwrap().running = false;
+                eother!(e)
+            })
+    }
+
+    fn scrub_progress(&self) -> Option<ScrubProgress> {
+        Some(self.scrub_progress.lock().unwrap().clone())
+    }
+
     #[inline]
     fn digester(&self) -> digest::Algorithm {
         self.digester
@@ -1299,12 +1796,53 @@ impl RafsCache for BlobCache {
 
 #[derive(Clone, Deserialize)]
 struct BlobCacheConfig {
-    #[serde(default = "default_work_dir")]
-    work_dir: String,
+    // Defaults to `DEFAULT_WORK_DIR` if not configured.
+    #[serde(default)]
+    work_dir: Option<String>,
+    // Maximum disk usage of cache files in unit of bytes, zero means unlimited.
+    #[serde(default)]
+    max_size: u64,
+    // Percentages of `max_size` to start and stop evicting blobs.
+    #[serde(default = "default_high_watermark")]
+    high_watermark: u64,
+    #[serde(default = "default_low_watermark")]
+    low_watermark: u64,
+}
+
+fn default_high_watermark() -> u64 {
+    95
 }
 
-fn default_work_dir() -> String {
-    ".".to_string()
+fn default_low_watermark() -> u64 {
+    80
+}
+
+impl BlobCacheConfig {
+    fn work_dir(&self) -> &str {
+        self.work_dir.as_deref().unwrap_or(DEFAULT_WORK_DIR)
+    }
+
+    fn eviction_watermark(&self) -> Result<Option<EvictionWatermark>> {
+        if self.max_size == 0 {
+            return Ok(None);
+        }
+        if self.high_watermark == 0
+            || self.high_watermark > 100
+            || self.low_watermark >= self.high_watermark
+        {
+            return Err(einval!(format!(
+                "invalid blobcache watermarks, high {} low {}",
+                self.high_watermark, self.low_watermark
+            )));
+        }
+
+        // Percentages are at most 100, so the results fit in u64.
+        let watermark = |pct: u64| (self.max_size as u128 * pct as u128 / 100) as u64;
+        Ok(Some(EvictionWatermark {
+            high: watermark(self.high_watermark),
+            low: watermark(self.low_watermark),
+        }))
+    }
 }
 
 pub fn new(
@@ -1316,24 +1854,26 @@ pub fn new(
 ) -> Result<Arc<BlobCache>> {
     let blob_config: BlobCacheConfig =
         serde_json::from_value(config.cache_config).map_err(|e| einval!(e))?;
+    let eviction = blob_config.eviction_watermark()?;
     let work_dir = {
-        let path = fs::metadata(&blob_config.work_dir)
+        let work_dir = blob_config.work_dir();
+        let path = fs::metadata(work_dir)
             .or_else(|_| {
-                fs::create_dir_all(&blob_config.work_dir)?;
-                fs::metadata(&blob_config.work_dir)
+                fs::create_dir_all(work_dir)?;
+                fs::metadata(work_dir)
             })
             .map_err(|e| {
                 last_error!(format!(
                     "fail to stat blobcache work_dir {}: {}",
-                    blob_config.work_dir, e
+                    work_dir, e
                 ))
             })?;
         if path.is_dir() {
-            Ok(blob_config.work_dir.as_str())
+            Ok(work_dir)
         } else {
             Err(enoent!(format!(
                 "blobcache work_dir {} is not a directory",
-                blob_config.work_dir
+                work_dir
             )))
         }
     }?;
@@ -1356,15 +1896,18 @@ pub fn new(
         Arc::new(RateLimiter::direct(Quota::per_second(v)))
     });
 
-    let mut enabled = false;
-    let (tx, rx) = if config.prefetch_worker.enable {
-        let (send, recv) = spmc::channel::<MergedBackendRequest>();
-        enabled = true;
-        (Some(send), Some(recv))
+    let prefetch_queue = if config.prefetch_worker.enable {
+        Some(Arc::new(PrefetchQueue::new(id)))
     } else {
-        (None, None)
+        None
     };
 
+    *WORK_DIRS
+        .lock()
+        .unwrap()
+        .entry(fs::canonicalize(work_dir).unwrap_or_else(|_| PathBuf::from(work_dir)))
+        .or_default() |= blob_config.work_dir.is_some();
+
     let metrics = BlobcacheMetrics::new(id, work_dir);
     let cache = Arc::new(BlobCache {
         cache: Arc::new(RwLock::new(BlobCacheState {
@@ -1373,6 +1916,7 @@ pub fn new(
             backend_size_valid: compressor == compress::Algorithm::GZip,
             metrics: metrics.clone(),
             backend: backend.clone(),
+            access_clock: AtomicU64::new(0),
         })),
         validate: config.cache_validate,
         is_compressed: config.cache_compressed,
@@ -1381,30 +1925,185 @@ pub fn new(
         compressor,
         digester,
         limiter,
-        mr_sender: Arc::new(Mutex::new(tx)),
-        mr_receiver: rx,
+        prefetch_queue,
         metrics,
         runtime: Arc::new(Runtime::new().unwrap()),
+        eviction,
+        mem_cache: if config.mem_cache_size != 0 {
+            Some(Arc::new(ChunkMemCache::new(config.mem_cache_size)))
+        } else {
+            None
+        },
+        scrub_progress: Arc::new(Mutex::new(ScrubProgress::default())),
     });
 
-    if enabled {
+    if cache.prefetch_queue.is_some() {
         kick_prefetch_workers(cache.clone());
     }
+    if eviction.is_some() {
+        kick_eviction_worker(Arc::downgrade(&cache));
+    }
 
     Ok(cache)
 }
 
+/// Result of a blobcache garbage collection pass.
+#[derive(Default, Serialize)]
+pub struct GcReport {
+    pub dry_run: bool,
+    /// Cache files removed, or to be removed in dry-run mode.
+    pub files: Vec<String>,
+    /// Disk space in bytes taken by above files.
+    pub freed_bytes: u64,
+    /// Files not looking like cache files and work directories not configured explicitly,
+    /// all of them are left untouched.
+    pub skipped: Vec<String>,
+}
+
+/// Remove cache files of blobs which are neither in `in_use` nor touched within `grace_period`
+/// from work directories of all blobcache instances. Nothing is removed if `dry_run` is set.
+pub fn collect_garbage(
+    in_use: &HashSet<String>,
+    grace_period: Duration,
+    dry_run: bool,
+) -> Result<GcReport> {
+    let work_dirs = WORK_DIRS.lock().unwrap().clone();
+    collect_work_dirs_garbage(work_dirs, in_use, grace_period, dry_run)
+}
+
+fn collect_work_dirs_garbage(
+    work_dirs: HashMap<PathBuf, bool>,
+    in_use: &HashSet<String>,
+    grace_period: Duration,
+    dry_run: bool,
+) -> Result<GcReport> {
+    let mut report = GcReport {
+        dry_run,
+        ..Default::default()
+    };
+
+    for (work_dir, explicit) in work_dirs {
+        // The default work directory is likely the current directory of nydusd, which may hold
+        // files of others.
+        if !explicit {
+            warn!(
+                "Skip collecting garbage from blobcache work_dir {:?} not configured explicitly",
+                work_dir
+            );
+            report.skipped.push(work_dir.to_string_lossy().to_string());
+            continue;
+        }
+        collect_work_dir_garbage(&work_dir, in_use, grace_period, dry_run, &mut report)?;
+    }
+
+    Ok(report)
+}
+
+fn collect_work_dir_garbage(
+    work_dir: &Path,
+    in_use: &HashSet<String>,
+    grace_period: Duration,
+    dry_run: bool,
+    report: &mut GcReport,
+) -> Result<()> {
+    // HashMap<blob_id, (cache_files, latest_touched_time)>
+    let mut blobs: HashMap<String, (Vec<(PathBuf, u64)>, SystemTime)> = HashMap::new();
+    let chunk_map_suffix = format!(".{}", indexed::FILE_SUFFIX);
+    let digest_log_suffix = format!(".{}", digested::FILE_SUFFIX);
+
+    for entry in fs::read_dir(work_dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().to_string();
+        let blob_id = name
+            .strip_suffix(chunk_map_suffix.as_str())
+            .or_else(|| name.strip_suffix(digest_log_suffix.as_str()))
+            .unwrap_or(&name)
+            .to_string();
+        if !is_blob_id(&blob_id) {
+            debug!(
+                "Skip collecting file {:?} not owned by blobcache",
+                entry.path()
+            );
+            report
+                .skipped
+                .push(entry.path().to_string_lossy().to_string());
+            continue;
+        }
+        if in_use.contains(&blob_id) {
+            continue;
+        }
+
+        let touched = std::cmp::max(metadata.modified()?, metadata.accessed()?);
+        let blob = blobs
+            .entry(blob_id)
+            .or_insert_with(|| (Vec::new(), SystemTime::UNIX_EPOCH));
+        blob.0.push((entry.path(), metadata.blocks() * 512));
+        blob.1 = std::cmp::max(blob.1, touched);
+    }
+
+    let now = SystemTime::now();
+    for (blob_id, (files, touched)) in blobs {
+        // Files touched in future because of clock skew are treated as fresh ones.
+        if now.duration_since(touched).unwrap_or_default() < grace_period {
+            continue;
+        }
+        // Blobs in use by any nydusd instance sharing the work directory hold a shared lock on
+        // the cache file, keep the exclusive lock until the files are removed.
+        let blob_file_path = work_dir.join(&blob_id);
+        let _blob_file = match OpenOptions::new().write(true).open(&blob_file_path) {
+            Ok(f) => {
+                if !ofd_lock(f.as_raw_fd(), libc::F_WRLCK, 0, 0, false)? {
+                    debug!("Skip collecting blob {} in use by others", blob_id);
+                    continue;
+                }
+                Some(f)
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        info!(
+            "Collect garbage blob {} from blobcache {:?}, dry-run {}",
+            blob_id, work_dir, dry_run
+        );
+        for (path, size) in files {
+            // Go on collecting other files, the failed one is left to the next pass.
+            if !dry_run {
+                if let Err(e) = fs::remove_file(&path) {
+                    warn!("Failed to remove garbage file {:?}: {}", path, e);
+                    continue;
+                }
+            }
+            report.files.push(path.to_string_lossy().to_string());
+            report.freed_bytes += size;
+        }
+    }
+
+    Ok(())
+}
+
+/// Blob ids are hex strings of blob digests, the only names of cache files in work directory.
+fn is_blob_id(name: &str) -> bool {
+    name.len() == RAFS_DIGEST_LENGTH * 2 && name.bytes().all(|c| c.is_ascii_hexdigit())
+}
+
 #[cfg(test)]
 pub mod blob_cache_tests {
     use std::alloc::{alloc_zeroed, Layout};
+    use std::collections::{HashMap, HashSet};
     use std::slice::from_raw_parts;
     use std::sync::Arc;
+    use std::time::Duration;
 
     use vm_memory::{VolatileMemory, VolatileSlice};
     use vmm_sys_util::tempdir::TempDir;
 
     use crate::backend::{BackendResult, BlobBackend};
-    use crate::cache::{blobcache, MergedBackendRequest, PrefetchWorker, RafsCache};
+    use crate::cache::prefetch::{PrefetchPriority, PrefetchQueue};
+    use crate::cache::{blobcache, PrefetchWorker, RafsCache};
     use crate::compress;
     use crate::device::{RafsBio, RafsBlobEntry, RafsChunkFlags, RafsChunkInfo};
     use crate::factory::CacheConfig;
@@ -1413,7 +2112,7 @@ pub mod blob_cache_tests {
 
     use nydus_utils::{
         digest::{self, RafsDigest},
-        metrics::BackendMetrics,
+        metrics::{BackendMetrics, Metric},
     };
 
     struct MockBackend {
@@ -1456,6 +2155,44 @@ pub mod blob_cache_tests {
         }
     }
 
+    // Backend serving blob data from memory.
+    struct MemBackend {
+        data: Vec<u8>,
+        metrics: Arc<BackendMetrics>,
+    }
+
+    impl BlobBackend for MemBackend {
+        fn try_read(&self, _blob_id: &str, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+            let offset = offset as usize;
+            let len = std::cmp::min(buf.len(), self.data.len() - offset);
+            buf[..len].copy_from_slice(&self.data[offset..offset + len]);
+            Ok(len)
+        }
+
+        fn write(&self, _blob_id: &str, _buf: &[u8], _offset: u64) -> BackendResult<usize> {
+            Ok(0)
+        }
+
+        fn blob_size(&self, _blob_id: &str) -> BackendResult<u64> {
+            Ok(self.data.len() as u64)
+        }
+
+        fn release(&self) {}
+
+        fn prefetch_blob(
+            &self,
+            _blob_id: &str,
+            _blob_readahead_offset: u32,
+            _blob_readahead_size: u32,
+        ) -> BackendResult<()> {
+            Ok(())
+        }
+
+        fn metrics(&self) -> &BackendMetrics {
+            &self.metrics
+        }
+    }
+
     #[derive(Default, Clone)]
     pub struct MockChunkInfo {
         pub block_id: RafsDigest,
@@ -1514,6 +2251,7 @@ pub mod blob_cache_tests {
             cache_compressed: false,
             cache_type: String::from("blobcache"),
             cache_config: serde_json::from_str(&s).unwrap(),
+            mem_cache_size: 0,
             prefetch_worker: PrefetchWorker::default(),
         };
         let blob_cache = blobcache::new(
@@ -1581,6 +2319,499 @@ pub mod blob_cache_tests {
         assert_eq!(r2, &expect[50..]);
     }
 
+    #[test]
+    fn test_compressed_cache_zstd() {
+        // Chunks may record compression algorithm other than the global one.
+        for (compressor, flags) in &[
+            (compress::Algorithm::Zstd, RafsChunkFlags::COMPRESSED),
+            (
+                compress::Algorithm::Lz4Block,
+                RafsChunkFlags::COMPRESSED | RafsChunkFlags::COMPRESS_ZSTD,
+            ),
+        ] {
+            let tmp_dir = TempDir::new().unwrap();
+            let s = format!(
+                r###"
+        {{
+            "work_dir": {:?}
+        }}
+        "###,
+                tmp_dir.as_path().to_path_buf().join("cache"),
+            );
+
+            let cache_config = CacheConfig {
+                cache_validate: true,
+                cache_compressed: true,
+                cache_type: String::from("blobcache"),
+                cache_config: serde_json::from_str(&s).unwrap(),
+                mem_cache_size: 0,
+                prefetch_worker: PrefetchWorker::default(),
+            };
+
+            let expect = vec![3u8; 4096];
+            let (compressed, _) = compress::compress(&expect, compress::Algorithm::Zstd).unwrap();
+            let compressed = compressed.to_vec();
+            let blob_cache = blobcache::new(
+                cache_config,
+                Arc::new(MemBackend {
+                    data: compressed.clone(),
+                    metrics: BackendMetrics::new("zstd", "mock"),
+                }) as Arc<dyn BlobBackend + Send + Sync>,
+                *compressor,
+                digest::Algorithm::Blake3,
+                "zstd",
+            )
+            .unwrap();
+
+            let blob_id = "blobcache-zstd";
+            let chunk = MockChunkInfo {
+                block_id: RafsDigest::from_buf(&expect, digest::Algorithm::Blake3),
+                flags: *flags,
+                compress_size: compressed.len() as u32,
+                decompress_size: expect.len() as u32,
+                ..Default::default()
+            };
+            let bio = RafsBio::new(
+                Arc::new(chunk),
+                Arc::new(RafsBlobEntry {
+                    chunk_count: 1,
+                    readahead_offset: 0,
+                    readahead_size: 0,
+                    blob_id: blob_id.to_string(),
+                    blob_index: 0,
+                    blob_cache_size: 0,
+                    compressed_blob_size: compressed.len() as u64,
+                }),
+                100,
+                100,
+                RAFS_DEFAULT_BLOCK_SIZE as u32,
+                true,
+            );
+
+            // Read from backend then from the compressed blobcache file.
+            for _ in 0..2 {
+                let r = unsafe {
+                    let layout = Layout::from_size_align(100, 1).unwrap();
+                    let ptr = alloc_zeroed(layout);
+                    let vs = VolatileSlice::new(ptr, 100);
+                    blob_cache.read(&mut [bio.clone()], &[vs]).unwrap();
+                    Vec::from(from_raw_parts(ptr, 100))
+                };
+                assert_eq!(r, &expect[100..200]);
+            }
+
+            let cached = std::fs::read(tmp_dir.as_path().join("cache").join(blob_id)).unwrap();
+            assert_eq!(&cached[..compressed.len()], compressed.as_slice());
+        }
+    }
+
+    #[test]
+    fn test_blobcache_mem_cache() {
+        let tmp_dir = TempDir::new().unwrap();
+        let s = format!(
+            r###"
+        {{
+            "work_dir": {:?}
+        }}
+        "###,
+            tmp_dir.as_path().to_path_buf().join("cache"),
+        );
+
+        let cache_config = CacheConfig {
+            cache_validate: true,
+            cache_compressed: false,
+            cache_type: String::from("blobcache"),
+            cache_config: serde_json::from_str(&s).unwrap(),
+            mem_cache_size: 0x10000,
+            prefetch_worker: PrefetchWorker::default(),
+        };
+
+        let expect = vec![5u8; 4096];
+        let (compressed, _) = compress::compress(&expect, compress::Algorithm::Zstd).unwrap();
+        let compressed = compressed.to_vec();
+        let blob_cache = blobcache::new(
+            cache_config,
+            Arc::new(MemBackend {
+                data: compressed.clone(),
+                metrics: BackendMetrics::new("mem_cache", "mock"),
+            }) as Arc<dyn BlobBackend + Send + Sync>,
+            compress::Algorithm::Zstd,
+            digest::Algorithm::Blake3,
+            "mem_cache",
+        )
+        .unwrap();
+
+        let chunk = MockChunkInfo {
+            block_id: RafsDigest::from_buf(&expect, digest::Algorithm::Blake3),
+            flags: RafsChunkFlags::COMPRESSED,
+            compress_size: compressed.len() as u32,
+            decompress_size: expect.len() as u32,
+            ..Default::default()
+        };
+        let bio = RafsBio::new(
+            Arc::new(chunk),
+            Arc::new(RafsBlobEntry {
+                chunk_count: 1,
+                blob_id: "blobcache-mem".to_string(),
+                compressed_blob_size: compressed.len() as u64,
+                ..Default::default()
+            }),
+            100,
+            100,
+            RAFS_DEFAULT_BLOCK_SIZE as u32,
+            true,
+        );
+
+        // The first read misses the in-memory chunk cache and fills it.
+        for (hits, misses) in &[(0, 1), (1, 1), (2, 1)] {
+            let r = unsafe {
+                let layout = Layout::from_size_align(100, 1).unwrap();
+                let ptr = alloc_zeroed(layout);
+                let vs = VolatileSlice::new(ptr, 100);
+                assert_eq!(blob_cache.read(&mut [bio.clone()], &[vs]).unwrap(), 100);
+                Vec::from(from_raw_parts(ptr, 100))
+            };
+            assert_eq!(r, &expect[100..200]);
+            assert_eq!(blob_cache.metrics.mem_cache_hits.count(), *hits);
+            assert_eq!(blob_cache.metrics.mem_cache_misses.count(), *misses);
+        }
+    }
+
+    #[test]
+    fn test_blobcache_scrub() {
+        let tmp_dir = TempDir::new().unwrap();
+        let s = format!(
+            r###"
+        {{
+            "work_dir": {:?}
+        }}
+        "###,
+            tmp_dir.as_path().to_path_buf().join("cache"),
+        );
+
+        let cache_config = CacheConfig {
+            cache_validate: false,
+            cache_compressed: true,
+            cache_type: String::from("blobcache"),
+            cache_config: serde_json::from_str(&s).unwrap(),
+            mem_cache_size: 0,
+            prefetch_worker: PrefetchWorker::default(),
+        };
+
+        let expect = vec![7u8; 4096];
+        let (compressed, _) = compress::compress(&expect, compress::Algorithm::Zstd).unwrap();
+        let compressed = compressed.to_vec();
+        let blob_cache = blobcache::new(
+            cache_config,
+            Arc::new(MemBackend {
+                data: compressed.clone(),
+                metrics: BackendMetrics::new("scrub", "mock"),
+            }) as Arc<dyn BlobBackend + Send + Sync>,
+            compress::Algorithm::Zstd,
+            digest::Algorithm::Blake3,
+            "scrub",
+        )
+        .unwrap();
+
+        let blob_id = "blobcache-scrub";
+        let chunk = MockChunkInfo {
+            block_id: RafsDigest::from_buf(&expect, digest::Algorithm::Blake3),
+            flags: RafsChunkFlags::COMPRESSED,
+            compress_size: compressed.len() as u32,
+            decompress_size: expect.len() as u32,
+            ..Default::default()
+        };
+        let bio = RafsBio::new(
+            Arc::new(chunk),
+            Arc::new(RafsBlobEntry {
+                chunk_count: 1,
+                blob_id: blob_id.to_string(),
+                compressed_blob_size: compressed.len() as u64,
+                ..Default::default()
+            }),
+            0,
+            100,
+            RAFS_DEFAULT_BLOCK_SIZE as u32,
+            true,
+        );
+        let scrub = || {
+            blob_cache.scrub(vec![bio.clone()], 0).unwrap();
+            loop {
+                let progress = blob_cache.scrub_progress().unwrap();
+                if !progress.running {
+                    break progress;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        };
+
+        // Nothing is cached yet.
+        let progress = scrub();
+        assert_eq!(progress.scanned_chunks, 1);
+        assert_eq!(progress.verified_chunks, 0);
+
+        let r = unsafe {
+            let layout = Layout::from_size_align(100, 1).unwrap();
+            let ptr = alloc_zeroed(layout);
+            let vs = VolatileSlice::new(ptr, 100);
+            blob_cache.read(&mut [bio.clone()], &[vs]).unwrap();
+            Vec::from(from_raw_parts(ptr, 100))
+        };
+        assert_eq!(r, &expect[..100]);
+        assert!(blob_cache.is_chunk_cached(bio.chunkinfo.as_ref(), &bio.blob));
+
+        let progress = scrub();
+        assert_eq!(progress.verified_chunks, 1);
+        assert!(progress.corrupted_chunks.is_empty());
+
+        // Corrupt the cache file, the chunk is fetched from backend again afterwards.
+        let cache_file = tmp_dir.as_path().join("cache").join(blob_id);
+        std::fs::write(&cache_file, vec![0u8; compressed.len()]).unwrap();
+        let progress = scrub();
+        assert_eq!(progress.verified_chunks, 0);
+        assert_eq!(progress.corrupted_chunks.len(), 1);
+        assert_eq!(progress.corrupted_chunks[0].blob_id, blob_id);
+        assert!(!blob_cache.is_chunk_cached(bio.chunkinfo.as_ref(), &bio.blob));
+    }
+
+    #[test]
+    fn test_blobcache_eviction() {
+        let tmp_dir = TempDir::new().unwrap();
+        let s = format!(
+            r###"
+        {{
+            "work_dir": {:?},
+            "max_size": 10000,
+            "high_watermark": 60,
+            "low_watermark": 50
+        }}
+        "###,
+            tmp_dir.as_path().to_path_buf().join("cache"),
+        );
+
+        let cache_config = CacheConfig {
+            cache_validate: true,
+            cache_compressed: true,
+            cache_type: String::from("blobcache"),
+            cache_config: serde_json::from_str(&s).unwrap(),
+            mem_cache_size: 0,
+            prefetch_worker: PrefetchWorker::default(),
+        };
+
+        let expect = vec![3u8; 4096];
+        let (compressed, _) = compress::compress(&expect, compress::Algorithm::Zstd).unwrap();
+        let compressed = compressed.to_vec();
+        let blob_cache = blobcache::new(
+            cache_config,
+            Arc::new(MemBackend {
+                data: compressed.clone(),
+                metrics: BackendMetrics::new("eviction", "mock"),
+            }) as Arc<dyn BlobBackend + Send + Sync>,
+            compress::Algorithm::Zstd,
+            digest::Algorithm::Blake3,
+            "eviction",
+        )
+        .unwrap();
+
+        let chunk = Arc::new(MockChunkInfo {
+            block_id: RafsDigest::from_buf(&expect, digest::Algorithm::Blake3),
+            flags: RafsChunkFlags::COMPRESSED,
+            compress_size: compressed.len() as u32,
+            decompress_size: expect.len() as u32,
+            ..Default::default()
+        });
+        let blobs = (0..2)
+            .map(|i| {
+                Arc::new(RafsBlobEntry {
+                    chunk_count: 1,
+                    readahead_offset: 0,
+                    readahead_size: 0,
+                    blob_id: format!("blobcache-eviction-{}", i),
+                    blob_index: i,
+                    blob_cache_size: 0,
+                    compressed_blob_size: compressed.len() as u64,
+                })
+            })
+            .collect::<Vec<_>>();
+        let read = |blob: &Arc<RafsBlobEntry>| {
+            let bio = RafsBio::new(
+                chunk.clone(),
+                blob.clone(),
+                0,
+                100,
+                RAFS_DEFAULT_BLOCK_SIZE as u32,
+                true,
+            );
+            let r = unsafe {
+                let layout = Layout::from_size_align(100, 1).unwrap();
+                let ptr = alloc_zeroed(layout);
+                let vs = VolatileSlice::new(ptr, 100);
+                blob_cache.read(&mut [bio], &[vs]).unwrap();
+                Vec::from(from_raw_parts(ptr, 100))
+            };
+            assert_eq!(r, &expect[..100]);
+        };
+
+        read(&blobs[0]);
+        read(&blobs[1]);
+        assert!(blob_cache.is_chunk_cached(chunk.as_ref(), &blobs[0]));
+        assert!(blob_cache.is_chunk_cached(chunk.as_ref(), &blobs[1]));
+
+        // Only the least recently used blob is evicted to get below the low watermark.
+        blob_cache.evict().unwrap();
+        assert!(!blob_cache.is_chunk_cached(chunk.as_ref(), &blobs[0]));
+        assert!(blob_cache.is_chunk_cached(chunk.as_ref(), &blobs[1]));
+        assert_eq!(blob_cache.metrics.evictions.count(), 1);
+        assert!(blob_cache.metrics.evicted_bytes.count() > 0);
+
+        // Evicted blob is fetched from backend again.
+        read(&blobs[0]);
+        assert!(blob_cache.is_chunk_cached(chunk.as_ref(), &blobs[0]));
+    }
+
+    #[test]
+    fn test_eviction_watermark() {
+        let config: blobcache::BlobCacheConfig = serde_json::from_value(serde_json::json!({
+            "max_size": 150,
+            "high_watermark": 60,
+            "low_watermark": 50
+        }))
+        .unwrap();
+        let watermark = config.eviction_watermark().unwrap().unwrap();
+        assert_eq!(watermark.high, 90);
+        assert_eq!(watermark.low, 75);
+
+        let config: blobcache::BlobCacheConfig = serde_json::from_value(serde_json::json!({
+            "max_size": u64::MAX,
+            "high_watermark": 100,
+            "low_watermark": 50
+        }))
+        .unwrap();
+        let watermark = config.eviction_watermark().unwrap().unwrap();
+        assert_eq!(watermark.high, u64::MAX);
+        assert_eq!(watermark.low, u64::MAX / 2);
+    }
+
+    #[test]
+    fn test_collect_garbage() {
+        let tmp_dir = TempDir::new().unwrap();
+        let work_dir = tmp_dir.as_path();
+        let blob_ids: Vec<String> = (1..=4).map(|i| format!("{:064x}", i)).collect();
+        let blob_path =
+            |i: usize, suffix: &str| work_dir.join(format!("{}{}", blob_ids[i], suffix));
+        for (i, suffix) in &[
+            (0, ""),
+            (0, ".chunk_map"),
+            (1, ""),
+            (1, ".chunk_map"),
+            (1, ".digest_log"),
+            (3, ""),
+            (3, ".chunk_map"),
+        ] {
+            std::fs::write(blob_path(*i, suffix), b"data").unwrap();
+        }
+        std::fs::create_dir(blob_path(2, "")).unwrap();
+        // Files not owned by blobcache are never collected.
+        std::fs::write(work_dir.join("notes.txt"), b"data").unwrap();
+        std::fs::write(blob_path(0, ".bak"), b"data").unwrap();
+        let in_use = [blob_ids[1].clone()].iter().cloned().collect();
+        // Blobs opened by other nydusd instances sharing the work directory are in use as well.
+        let _blob4 = blobcache::open_blob_file(blob_path(3, "").to_str().unwrap()).unwrap();
+
+        // Fresh files are kept within the grace period.
+        let mut report = blobcache::GcReport::default();
+        blobcache::collect_work_dir_garbage(
+            work_dir,
+            &in_use,
+            Duration::from_secs(3600),
+            false,
+            &mut report,
+        )
+        .unwrap();
+        assert!(report.files.is_empty());
+
+        let mut report = blobcache::GcReport::default();
+        blobcache::collect_work_dir_garbage(
+            work_dir,
+            &in_use,
+            Duration::from_secs(0),
+            true,
+            &mut report,
+        )
+        .unwrap();
+        report.files.sort();
+        assert_eq!(
+            report.files,
+            vec![
+                blob_path(0, "").to_string_lossy().to_string(),
+                blob_path(0, ".chunk_map").to_string_lossy().to_string(),
+            ]
+        );
+        report.skipped.sort();
+        assert_eq!(
+            report.skipped,
+            vec![
+                blob_path(0, ".bak").to_string_lossy().to_string(),
+                work_dir.join("notes.txt").to_string_lossy().to_string(),
+            ]
+        );
+        assert!(blob_path(0, "").exists());
+
+        let mut report = blobcache::GcReport::default();
+        blobcache::collect_work_dir_garbage(
+            work_dir,
+            &in_use,
+            Duration::from_secs(0),
+            false,
+            &mut report,
+        )
+        .unwrap();
+        assert_eq!(report.files.len(), 2);
+        assert!(!blob_path(0, "").exists());
+        assert!(!blob_path(0, ".chunk_map").exists());
+        assert!(blob_path(0, ".bak").exists());
+        assert!(blob_path(1, "").exists());
+        assert!(blob_path(1, ".chunk_map").exists());
+        assert!(blob_path(1, ".digest_log").exists());
+        assert!(blob_path(2, "").exists());
+        assert!(blob_path(3, "").exists());
+        assert!(blob_path(3, ".chunk_map").exists());
+        assert!(work_dir.join("notes.txt").exists());
+    }
+
+    #[test]
+    fn test_collect_garbage_default_work_dir() {
+        let tmp_dir = TempDir::new().unwrap();
+        let work_dir = tmp_dir.as_path();
+        let blob_id = format!("{:064x}", 1);
+        std::fs::write(work_dir.join(&blob_id), b"data").unwrap();
+
+        let mut work_dirs = HashMap::new();
+        work_dirs.insert(work_dir.to_path_buf(), false);
+        let report = blobcache::collect_work_dirs_garbage(
+            work_dirs,
+            &HashSet::new(),
+            Duration::from_secs(0),
+            false,
+        )
+        .unwrap();
+        assert!(report.files.is_empty());
+        assert_eq!(report.skipped, vec![work_dir.to_string_lossy().to_string()]);
+        assert!(work_dir.join(&blob_id).exists());
+
+        let mut work_dirs = HashMap::new();
+        work_dirs.insert(work_dir.to_path_buf(), true);
+        let report = blobcache::collect_work_dirs_garbage(
+            work_dirs,
+            &HashSet::new(),
+            Duration::from_secs(0),
+            false,
+        )
+        .unwrap();
+        assert_eq!(report.files.len(), 1);
+        assert!(!work_dir.join(&blob_id).exists());
+    }
+
     #[test]
     fn test_merge_bio() {
         let tmp_dir = TempDir::new().unwrap();
@@ -1598,6 +2829,7 @@ pub mod blob_cache_tests {
             cache_compressed: false,
             cache_type: String::from("blobcache"),
             cache_config: serde_json::from_str(&s).unwrap(),
+            mem_cache_size: 0,
             prefetch_worker: PrefetchWorker::default(),
         };
 
@@ -1637,15 +2869,16 @@ pub mod blob_cache_tests {
             true,
         );
 
-        let (mut send, recv) = spmc::channel::<MergedBackendRequest>();
+        let queue = PrefetchQueue::default();
         let mut bios = vec![bio];
 
         blob_cache.generate_merged_requests_for_prefetch(
             &mut bios,
-            &mut send,
+            &queue,
+            PrefetchPriority::Hinted,
             merging_size as usize,
         );
-        let mr = recv.recv().unwrap();
+        let mr = queue.pop().unwrap();
 
         assert_eq!(mr.blob_offset, single_chunk.compress_offset());
         assert_eq!(mr.blob_size, single_chunk.compress_size());
@@ -1698,13 +2931,14 @@ pub mod blob_cache_tests {
         );
 
         let mut bios = vec![bio1, bio2];
-        let (mut send, recv) = spmc::channel::<MergedBackendRequest>();
+        let queue = PrefetchQueue::default();
         blob_cache.generate_merged_requests_for_prefetch(
             &mut bios,
-            &mut send,
+            &queue,
+            PrefetchPriority::Hinted,
             merging_size as usize,
         );
-        let mr = recv.recv().unwrap();
+        let mr = queue.pop().unwrap();
 
         assert_eq!(mr.blob_offset, chunk1.compress_offset());
         assert_eq!(
@@ -1760,18 +2994,19 @@ pub mod blob_cache_tests {
         );
 
         let mut bios = vec![bio1, bio2];
-        let (mut send, recv) = spmc::channel::<MergedBackendRequest>();
+        let queue = PrefetchQueue::default();
         blob_cache.generate_merged_requests_for_prefetch(
             &mut bios,
-            &mut send,
+            &queue,
+            PrefetchPriority::Hinted,
             merging_size as usize,
         );
 
-        let mr = recv.recv().unwrap();
+        let mr = queue.pop().unwrap();
         assert_eq!(mr.blob_offset, chunk1.compress_offset());
         assert_eq!(mr.blob_size, chunk1.compress_size());
 
-        let mr = recv.recv().unwrap();
+        let mr = queue.pop().unwrap();
         assert_eq!(mr.blob_offset, chunk2.compress_offset());
         assert_eq!(mr.blob_size, chunk2.compress_size());
 
@@ -1823,18 +3058,19 @@ pub mod blob_cache_tests {
         );
 
         let mut bios = vec![bio1, bio2];
-        let (mut send, recv) = spmc::channel::<MergedBackendRequest>();
+        let queue = PrefetchQueue::default();
         blob_cache.generate_merged_requests_for_prefetch(
             &mut bios,
-            &mut send,
+            &queue,
+            PrefetchPriority::Hinted,
             merging_size as usize,
         );
 
-        let mr = recv.recv().unwrap();
+        let mr = queue.pop().unwrap();
         assert_eq!(mr.blob_offset, chunk1.compress_offset());
         assert_eq!(mr.blob_size, chunk1.compress_size());
 
-        let mr = recv.recv().unwrap();
+        let mr = queue.pop().unwrap();
         assert_eq!(mr.blob_offset, chunk2.compress_offset());
         assert_eq!(mr.blob_size, chunk2.compress_size());
 
@@ -1909,21 +3145,22 @@ pub mod blob_cache_tests {
         );
 
         let mut bios = vec![bio1, bio2, bio3];
-        let (mut send, recv) = spmc::channel::<MergedBackendRequest>();
+        let queue = PrefetchQueue::default();
         blob_cache.generate_merged_requests_for_prefetch(
             &mut bios,
-            &mut send,
+            &queue,
+            PrefetchPriority::Hinted,
             merging_size as usize,
         );
 
-        let mr = recv.recv().unwrap();
+        let mr = queue.pop().unwrap();
         assert_eq!(mr.blob_offset, chunk1.compress_offset());
         assert_eq!(
             mr.blob_size,
             chunk1.compress_size() + chunk2.compress_size()
         );
 
-        let mr = recv.recv().unwrap();
+        let mr = queue.pop().unwrap();
         assert_eq!(mr.blob_offset, chunk3.compress_offset());
         assert_eq!(mr.blob_size, chunk3.compress_size());
     }
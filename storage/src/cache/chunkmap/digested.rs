@@ -3,23 +3,177 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::collections::HashMap;
-use std::io::Result;
-use std::sync::RwLock;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Result, Write};
+use std::os::unix::io::AsRawFd;
+use std::sync::{Mutex, RwLock};
 
-use nydus_utils::digest::RafsDigest;
+use nydus_utils::digest::{self, RafsDigest, RAFS_DIGEST_LENGTH};
 
 use super::ChunkMap;
 use crate::cache::chunkmap::{ChunkIndexGetter, NoWaitSupport};
 use crate::device::RafsChunkInfo;
+use crate::utils::ofd_lock;
+
+/// The magic number of blob digest_log file, it's ASCII hex of string "DLOG".
+const MAGIC: u32 = 0x444C_4F47;
+const VERSION: u32 = 1;
+/// The name suffix of blob digest_log file, named $blob_id.digest_log.
+pub(crate) const FILE_SUFFIX: &str = "digest_log";
+/// The header of blob digest_log file: magic | version, each in u32.
+const HEADER_SIZE: usize = 8;
+/// Each record is: chunk digest | state in u8 | reserved [u8; 3] | checksum in u32.
+const RECORD_SIZE: usize = RAFS_DIGEST_LENGTH + 8;
+const STATE_OFFSET: usize = RAFS_DIGEST_LENGTH;
+const CHECKSUM_OFFSET: usize = RAFS_DIGEST_LENGTH + 4;
+const STATE_NOT_READY: u8 = 0;
+const STATE_READY: u8 = 1;
+
+fn record_checksum(record: &[u8]) -> u32 {
+    let digest = RafsDigest::from_buf(&record[..CHECKSUM_OFFSET], digest::Algorithm::Blake3);
+    let mut checksum = [0u8; 4];
+    checksum.copy_from_slice(&digest.data[..4]);
+    u32::from_le_bytes(checksum)
+}
+
+/// The append-only log persisting state changes of chunks, so ready chunks are still known
+/// after restart. Records from a crashed writer or corrupted ones are dropped on loading,
+/// together with all records after them.
+struct DigestLog {
+    file: File,
+    path: String,
+}
+
+impl DigestLog {
+    /// Open the digest log of the blob, and replay it into a map of ready chunks. The log
+    /// is reset if the blob cache file is empty, since none of the chunks is cached anymore,
+    /// or if it's not a valid digest log.
+    ///
+    /// The log is shared by all nydusd instances using the blob, so it's only repaired with
+    /// the blob cache file locked exclusively. Otherwise no log is returned, and chunk states
+    /// are kept in memory only.
+    fn open(
+        blob_path: &str,
+        blob_file: &File,
+    ) -> Result<(Option<Self>, HashMap<RafsDigest, bool>)> {
+        let path = format!("{}.{}", blob_path, FILE_SUFFIX);
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)
+            .map_err(|err| {
+                einval!(format!(
+                    "failed to open/create blob digest_log file {:?}: {:?}",
+                    path, err
+                ))
+            })?;
+
+        let mut log = DigestLog { file, path };
+        let mut cache = HashMap::new();
+        let mut content = Vec::new();
+        log.file.read_to_end(&mut content)?;
+        let blob_size = blob_file.metadata()?.len();
+
+        let mut valid_size = 0;
+        if content.len() < HEADER_SIZE || blob_size == 0 {
+            if !content.is_empty() && blob_size != 0 {
+                warn!("blob digest_log file may be corrupted: {:?}", log.path);
+            }
+        } else if !Self::check_header(&content) {
+            warn!(
+                "invalid blob digest_log file header, reset it: {:?}",
+                log.path
+            );
+        } else {
+            valid_size = HEADER_SIZE;
+            for record in content[HEADER_SIZE..].chunks_exact(RECORD_SIZE) {
+                let mut checksum = [0u8; 4];
+                checksum.copy_from_slice(&record[CHECKSUM_OFFSET..]);
+                if u32::from_le_bytes(checksum) != record_checksum(record) {
+                    break;
+                }
+                let mut digest = RafsDigest::default();
+                digest.data.copy_from_slice(&record[..RAFS_DIGEST_LENGTH]);
+                match record[STATE_OFFSET] {
+                    STATE_READY => cache.insert(digest, true),
+                    STATE_NOT_READY => cache.remove(&digest),
+                    _ => break,
+                };
+                valid_size += RECORD_SIZE;
+            }
+        }
+        if valid_size != 0 && valid_size == content.len() {
+            return Ok((Some(log), cache));
+        }
+
+        // Truncating the log may drop records appended by others, upgrade the in-use lock,
+        // which fails if anybody else holds the blob open.
+        let fd = blob_file.as_raw_fd();
+        if !ofd_lock(fd, libc::F_WRLCK, 0, 0, false)? {
+            warn!(
+                "blob digest_log file {:?} is in use by others, keep chunk states in memory",
+                log.path
+            );
+            return Ok((None, cache));
+        }
+        let ret = if valid_size == 0 {
+            log.reset()
+        } else {
+            warn!(
+                "drop {} bytes of invalid records from blob digest_log file {:?}",
+                content.len() - valid_size,
+                log.path
+            );
+            log.file.set_len(valid_size as u64)
+        };
+        ofd_lock(fd, libc::F_RDLCK, 0, 0, true)?;
+        ret?;
+
+        Ok((Some(log), cache))
+    }
+
+    fn check_header(content: &[u8]) -> bool {
+        let mut magic = [0u8; 4];
+        let mut version = [0u8; 4];
+        magic.copy_from_slice(&content[..4]);
+        version.copy_from_slice(&content[4..HEADER_SIZE]);
+        u32::from_le_bytes(magic) == MAGIC && u32::from_le_bytes(version) == VERSION
+    }
+
+    fn append(&mut self, digest: &RafsDigest, state: u8) -> Result<()> {
+        let mut record = [0u8; RECORD_SIZE];
+        record[..RAFS_DIGEST_LENGTH].copy_from_slice(&digest.data);
+        record[STATE_OFFSET] = state;
+        let checksum = record_checksum(&record);
+        record[CHECKSUM_OFFSET..].copy_from_slice(&checksum.to_le_bytes());
+        // Written with a single append, so records from nydusd instances sharing the log
+        // never interleave.
+        self.file.write_all(&record)
+    }
+
+    /// Drop all records, the blob cache file must be locked exclusively by the caller.
+    fn reset(&mut self) -> Result<()> {
+        self.file.set_len(0)?;
+        let mut header = [0u8; HEADER_SIZE];
+        header[..4].copy_from_slice(&MAGIC.to_le_bytes());
+        header[4..].copy_from_slice(&VERSION.to_le_bytes());
+        self.file.write_all(&header)
+    }
+}
 
 /// The DigestedChunkMap is an implementation that uses a hash map
 /// (HashMap<chunk_digest, has_ready>) to records whether a chunk has been
 /// cached by the blobcache. it is used to be compatible with the previous
 /// old nydus bootstrap format.
+///
+/// The chunk state is kept in memory only if created by `new()`, or also persisted into
+/// a file named $blob_id.digest_log if opened by `open()`.
 #[derive(Default)]
 pub struct DigestedChunkMap {
     /// HashMap<chunk_digest, has_ready>
     cache: RwLock<HashMap<RafsDigest, bool>>,
+    log: Option<Mutex<DigestLog>>,
 }
 
 impl NoWaitSupport for DigestedChunkMap {}
@@ -28,6 +182,25 @@ impl DigestedChunkMap {
     pub fn new() -> Self {
         Self {
             cache: RwLock::new(HashMap::new()),
+            log: None,
+        }
+    }
+
+    /// Create a chunk map persisted into the digest log of the blob, with chunks recorded
+    /// as ready by previous runs loaded. The `blob_file` should be opened by `open_blob_file()`.
+    pub fn open(blob_path: &str, blob_file: &File) -> Result<Self> {
+        let (log, cache) = DigestLog::open(blob_path, blob_file)?;
+
+        Ok(Self {
+            cache: RwLock::new(cache),
+            log: log.map(Mutex::new),
+        })
+    }
+
+    fn append(&self, digest: &RafsDigest, state: u8) -> Result<()> {
+        match self.log.as_ref() {
+            Some(log) => log.lock().unwrap().append(digest, state),
+            None => Ok(()),
         }
     }
 }
@@ -38,7 +211,27 @@ impl ChunkMap for DigestedChunkMap {
     }
 
     fn set_ready(&self, chunk: &dyn RafsChunkInfo) -> Result<()> {
-        self.cache.write().unwrap().insert(*chunk.block_id(), true);
+        let mut cache = self.cache.write().unwrap();
+        if cache.insert(*chunk.block_id(), true).is_none() {
+            self.append(chunk.block_id(), STATE_READY)?;
+        }
+        Ok(())
+    }
+
+    fn unset_ready(&self, chunk: &dyn RafsChunkInfo) -> Result<()> {
+        let mut cache = self.cache.write().unwrap();
+        if cache.remove(chunk.block_id()).is_some() {
+            self.append(chunk.block_id(), STATE_NOT_READY)?;
+        }
+        Ok(())
+    }
+
+    fn clear(&self) -> Result<()> {
+        let mut cache = self.cache.write().unwrap();
+        cache.clear();
+        if let Some(log) = self.log.as_ref() {
+            log.lock().unwrap().reset()?;
+        }
         Ok(())
     }
 }
@@ -2,25 +2,32 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
-use std::fs::OpenOptions;
+use std::fs::{File, OpenOptions};
 use std::io::Result;
 use std::os::unix::io::AsRawFd;
 use std::sync::atomic::{AtomicU8, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use nydus_utils::div_round_up;
 
 use super::ChunkMap;
-use crate::cache::chunkmap::{ChunkIndexGetter, NoWaitSupport};
+use crate::cache::blobcache::SINGLE_INFLIGHT_WAIT_TIMEOUT;
+use crate::cache::chunkmap::{ChunkClaim, ChunkIndexGetter, NoWaitSupport};
 use crate::device::RafsChunkInfo;
-use crate::utils::readahead;
+use crate::utils::{ofd_lock, readahead};
 
 /// The magic number of blob chunk_map file, it's ASCII hex of string "BMAP".
 const MAGIC: u32 = 0x424D_4150;
 /// The name suffix of blob chunk_map file, named $blob_id.chunk_map.
-const FILE_SUFFIX: &str = "chunk_map";
+pub(crate) const FILE_SUFFIX: &str = "chunk_map";
 /// The header of blob chunk_map file.
 const HEADER_SIZE: usize = 4096;
 const HEADER_RESERVED_SIZE: usize = HEADER_SIZE - 4;
+/// Chunk claim locks are placed beyond the bitmap, one byte for each chunk.
+const CLAIM_LOCK_OFFSET: u64 = 1 << 32;
+/// Interval in milliseconds to check whether the chunk claimed by another process is released.
+const CLAIM_RETRY_INTERVAL: u64 = 10;
 
 /// The blob chunk map file header, 4096 bytes.
 #[repr(C)]
@@ -40,10 +47,15 @@ struct Header {
 /// For example: the bitmap file layout is [0b00000000, 0b00000000],
 /// when blobcache calls set_ready(3), the layout should be changed
 /// to [0b00010000, 0b00000000].
+///
+/// Before fetching a chunk from backend, the nydusd instance claims it by an open file
+/// description lock on the chunk_map file, so others wait for it instead of fetching the
+/// same chunk again.
 pub struct IndexedChunkMap {
     chunk_count: u32,
     size: usize,
     base: *const u8,
+    file: File,
 }
 
 unsafe impl Send for IndexedChunkMap {}
@@ -119,6 +131,7 @@ impl IndexedChunkMap {
             chunk_count,
             size: expected_size as usize,
             base: base as *const u8,
+            file,
         })
     }
 
@@ -204,4 +217,57 @@ impl ChunkMap for IndexedChunkMap {
         }
         Ok(())
     }
+
+    fn unset_ready(&self, chunk: &dyn RafsChunkInfo) -> Result<()> {
+        let index = chunk.index();
+        let _ = self.validate_index(index)?;
+        let start = HEADER_SIZE + (index as usize >> 3);
+        let atomic_value = unsafe { &*{ self.base.add(start) as *const AtomicU8 } };
+        atomic_value.fetch_and(!Self::index_to_mask(index), Ordering::AcqRel);
+        Ok(())
+    }
+
+    fn claim(&self, chunk: &dyn RafsChunkInfo) -> Result<ChunkClaim> {
+        let index = chunk.index();
+        let _ = self.validate_index(index)?;
+        let offset = CLAIM_LOCK_OFFSET + index as u64;
+        let deadline = Instant::now() + Duration::from_millis(SINGLE_INFLIGHT_WAIT_TIMEOUT);
+
+        loop {
+            if self.is_chunk_ready(index).0 {
+                return Ok(ChunkClaim::Ready);
+            }
+            if ofd_lock(self.file.as_raw_fd(), libc::F_WRLCK, offset, 1, false)? {
+                // Double check since the chunk may be persisted by the process just releasing it.
+                if self.is_chunk_ready(index).0 {
+                    self.release(chunk);
+                    return Ok(ChunkClaim::Ready);
+                }
+                return Ok(ChunkClaim::Claimed);
+            }
+            if Instant::now() >= deadline {
+                warn!(
+                    "Waiting for another process fetching chunk index {} expires",
+                    index
+                );
+                return Ok(ChunkClaim::Unclaimed);
+            }
+            thread::sleep(Duration::from_millis(CLAIM_RETRY_INTERVAL));
+        }
+    }
+
+    fn release(&self, chunk: &dyn RafsChunkInfo) {
+        let offset = CLAIM_LOCK_OFFSET + chunk.index() as u64;
+        ofd_lock(self.file.as_raw_fd(), libc::F_UNLCK, offset, 1, false)
+            .map(|_| ())
+            .unwrap_or_else(|e| error!("Failed to release chunk claim, {}", e));
+    }
+
+    fn clear(&self) -> Result<()> {
+        for start in HEADER_SIZE..self.size {
+            let atomic_value = unsafe { &*{ self.base.add(start) as *const AtomicU8 } };
+            atomic_value.store(0, Ordering::Release);
+        }
+        Ok(())
+    }
 }
@@ -10,12 +10,24 @@ use crate::{StorageError, StorageResult};
 use std::collections::HashMap;
 use std::fmt::Display;
 use std::hash::Hash;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Condvar, Mutex, WaitTimeoutResult};
 use std::time::Duration;
 
 pub mod digested;
 pub mod indexed;
 
+/// Result of claiming to fetch a chunk from backend.
+#[derive(Debug, PartialEq)]
+pub enum ChunkClaim {
+    /// The chunk is claimed, which should be released once it's persisted or fails to be fetched.
+    Claimed,
+    /// The chunk is not claimed, but should be fetched anyway.
+    Unclaimed,
+    /// The chunk gets ready meanwhile, so no need to fetch it.
+    Ready,
+}
+
 /// only to mark ChunkMap who doesn't support wait
 pub trait NoWaitSupport {}
 
@@ -28,6 +40,22 @@ pub trait ChunkMap {
     fn has_ready_nowait(&self, _chunk: &dyn RafsChunkInfo) -> Result<bool> {
         Ok(false)
     }
+    /// Mark the chunk as not ready, used when the cached chunk data is found corrupted.
+    fn unset_ready(&self, _chunk: &dyn RafsChunkInfo) -> Result<()> {
+        Ok(())
+    }
+    /// Mark all chunks as not ready, used when the cached blob data is evicted.
+    fn clear(&self) -> Result<()> {
+        Ok(())
+    }
+    /// Claim fetching the chunk from backend among processes sharing the chunk map, wait
+    /// for the process which has claimed it if any.
+    fn claim(&self, _chunk: &dyn RafsChunkInfo) -> Result<ChunkClaim> {
+        Ok(ChunkClaim::Unclaimed)
+    }
+    /// Give up the claim when the chunk is persisted or fails to be fetched, only to be called
+    /// by the one who gets `ChunkClaim::Claimed`.
+    fn release(&self, _chunk: &dyn RafsChunkInfo) {}
 }
 
 /// convert RafsChunkInfo to ChunkMap inner index
@@ -46,6 +74,9 @@ enum Status {
 struct ChunkSlot {
     on_trip: Mutex<Status>,
     condvar: Condvar,
+    // Whether the chunk is claimed by the thread fetching it, among processes sharing the chunk
+    // map, so the claim should be released once the slot is done.
+    claimed: AtomicBool,
 }
 
 impl ChunkSlot {
@@ -53,6 +84,7 @@ impl ChunkSlot {
         ChunkSlot {
             on_trip: Mutex::new(Status::Inflight),
             condvar: Condvar::new(),
+            claimed: AtomicBool::new(false),
         }
     }
 
@@ -147,7 +179,24 @@ where
                 if self.c.has_ready(chunk, false)? {
                     return Ok(true);
                 }
-                guard.insert(index, Arc::new(ChunkSlot::new()));
+                let slot = Arc::new(ChunkSlot::new());
+                guard.insert(index, slot.clone());
+                drop(guard);
+
+                // This thread is going to fetch the chunk, but another process sharing the
+                // chunk map may be fetching it as well.
+                match self.c.claim(chunk) {
+                    Ok(ChunkClaim::Claimed) => slot.claimed.store(true, Ordering::Release),
+                    Ok(ChunkClaim::Unclaimed) => {}
+                    Ok(ChunkClaim::Ready) => {
+                        self.finish(chunk);
+                        return Ok(true);
+                    }
+                    Err(e) => {
+                        self.finish(chunk);
+                        return Err(e);
+                    }
+                }
             }
         }
         Ok(ready)
@@ -163,6 +212,11 @@ where
         let index = C::get_index(chunk);
         let mut guard = self.inflight_tracer.lock().unwrap();
         if let Some(i) = guard.remove(&index) {
+            // Only release the claim taken for the slot, the chunk may be persisted by others
+            // than the one fetching it, e.g. prefetch.
+            if i.claimed.load(Ordering::Acquire) {
+                self.c.release(chunk);
+            }
             i.done();
         }
     }
@@ -170,6 +224,22 @@ where
     fn has_ready_nowait(&self, chunk: &dyn RafsChunkInfo) -> Result<bool> {
         self.c.has_ready(chunk, false)
     }
+
+    fn unset_ready(&self, chunk: &dyn RafsChunkInfo) -> Result<()> {
+        self.c.unset_ready(chunk)
+    }
+
+    fn clear(&self) -> Result<()> {
+        self.c.clear()
+    }
+
+    fn claim(&self, chunk: &dyn RafsChunkInfo) -> Result<ChunkClaim> {
+        self.c.claim(chunk)
+    }
+
+    fn release(&self, chunk: &dyn RafsChunkInfo) {
+        self.c.release(chunk)
+    }
 }
 
 #[cfg(test)]
@@ -184,6 +254,7 @@ mod tests {
     use super::indexed::IndexedChunkMap;
     use super::*;
     use crate::cache::blobcache::blob_cache_tests::MockChunkInfo;
+    use crate::cache::blobcache::open_blob_file;
     use crate::device::{RafsChunkFlags, RafsChunkInfo};
     use nydus_utils::digest::Algorithm::Blake3;
     use nydus_utils::digest::{Algorithm, RafsDigest};
@@ -330,6 +401,155 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_chunk_map_clear() {
+        let dir = TempDir::new().unwrap();
+        let blob_path = dir.as_path().join("blob-1");
+        let blob_path = blob_path.as_os_str().to_str().unwrap().to_string();
+        let chunk_count = 100;
+
+        let indexed_chunk_map: Arc<dyn ChunkMap> = Arc::new(BlobChunkMap::from(
+            IndexedChunkMap::new(&blob_path, chunk_count).unwrap(),
+        ));
+        let digested_chunk_map: Arc<dyn ChunkMap> =
+            Arc::new(BlobChunkMap::from(DigestedChunkMap::new()));
+        let chunks = (0..chunk_count).map(Chunk::new).collect::<Vec<_>>();
+
+        for chunk_map in &[indexed_chunk_map, digested_chunk_map] {
+            iterate(&chunks, chunk_map.as_ref(), chunk_count);
+            // Other chunks sharing the same byte of bitmap stay ready.
+            chunk_map.unset_ready(chunks[3].as_ref()).unwrap();
+            assert!(!chunk_map.has_ready_nowait(chunks[3].as_ref()).unwrap());
+            assert!(chunk_map.has_ready_nowait(chunks[2].as_ref()).unwrap());
+            assert!(chunk_map.has_ready_nowait(chunks[4].as_ref()).unwrap());
+            chunk_map.clear().unwrap();
+            for chunk in chunks.iter() {
+                assert!(!chunk_map.has_ready_nowait(chunk.as_ref()).unwrap());
+            }
+        }
+
+        // Chunk ready state is shared through the chunk_map file.
+        let indexed_chunk_map = IndexedChunkMap::new(&blob_path, chunk_count).unwrap();
+        for chunk in chunks.iter() {
+            assert!(!indexed_chunk_map.has_ready(chunk.as_ref(), false).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_digested_chunk_map_persist() {
+        let dir = TempDir::new().unwrap();
+        let blob_path = dir.as_path().join("blob-1");
+        let blob_path = blob_path.as_os_str().to_str().unwrap().to_string();
+        let log_path = format!("{}.{}", blob_path, digested::FILE_SUFFIX);
+        let chunks = (0..10).map(Chunk::new).collect::<Vec<_>>();
+        std::fs::write(&blob_path, [0u8; 4096]).unwrap();
+        let blob_file = open_blob_file(&blob_path).unwrap();
+
+        let chunk_map = DigestedChunkMap::open(&blob_path, &blob_file).unwrap();
+        iterate(&chunks, &chunk_map, 10);
+        chunk_map.set_ready(chunks[0].as_ref()).unwrap();
+        chunk_map.unset_ready(chunks[1].as_ref()).unwrap();
+        drop(chunk_map);
+
+        // Ready chunks are loaded from the digest log.
+        let chunk_map = DigestedChunkMap::open(&blob_path, &blob_file).unwrap();
+        assert!(!chunk_map.has_ready(chunks[1].as_ref(), false).unwrap());
+        for chunk in chunks.iter().skip(2) {
+            assert!(chunk_map.has_ready(chunk.as_ref(), false).unwrap());
+        }
+        drop(chunk_map);
+
+        // Records after the corrupted one are dropped, as well as the partial one.
+        let mut log = std::fs::read(&log_path).unwrap();
+        let record_size = log.len() / 11;
+        log[8 + record_size * 5] ^= 0xff;
+        log.extend_from_slice(&[0u8; 7]);
+        std::fs::write(&log_path, &log).unwrap();
+        let chunk_map = DigestedChunkMap::open(&blob_path, &blob_file).unwrap();
+        for (i, chunk) in chunks.iter().enumerate() {
+            let ready = chunk_map.has_ready(chunk.as_ref(), false).unwrap();
+            assert_eq!(ready, i < 5, "chunk {}", i);
+        }
+        assert_eq!(
+            std::fs::metadata(&log_path).unwrap().len(),
+            8 + record_size as u64 * 5
+        );
+
+        // New records are appended after the valid ones.
+        chunk_map.set_ready(chunks[9].as_ref()).unwrap();
+        chunk_map.clear().unwrap();
+        chunk_map.set_ready(chunks[8].as_ref()).unwrap();
+        drop(chunk_map);
+        let chunk_map = DigestedChunkMap::open(&blob_path, &blob_file).unwrap();
+        for (i, chunk) in chunks.iter().enumerate() {
+            let ready = chunk_map.has_ready(chunk.as_ref(), false).unwrap();
+            assert_eq!(ready, i == 8, "chunk {}", i);
+        }
+        drop(chunk_map);
+
+        // The log with a foreign header is reset.
+        std::fs::write(&log_path, b"foreign digest_log").unwrap();
+        let chunk_map = DigestedChunkMap::open(&blob_path, &blob_file).unwrap();
+        assert!(!chunk_map.has_ready(chunks[8].as_ref(), false).unwrap());
+        chunk_map.set_ready(chunks[7].as_ref()).unwrap();
+        drop(chunk_map);
+        let chunk_map = DigestedChunkMap::open(&blob_path, &blob_file).unwrap();
+        assert!(chunk_map.has_ready(chunks[7].as_ref(), false).unwrap());
+        drop(chunk_map);
+
+        // The broken log is left untouched if the blob is in use by others.
+        let blob_file2 = open_blob_file(&blob_path).unwrap();
+        std::fs::write(&log_path, b"foreign digest_log").unwrap();
+        let chunk_map = DigestedChunkMap::open(&blob_path, &blob_file).unwrap();
+        chunk_map.set_ready(chunks[6].as_ref()).unwrap();
+        assert!(chunk_map.has_ready(chunks[6].as_ref(), false).unwrap());
+        assert_eq!(std::fs::read(&log_path).unwrap(), b"foreign digest_log");
+        drop(chunk_map);
+        drop(blob_file2);
+
+        // The log is discarded if the blob cache file is gone.
+        std::fs::write(&blob_path, b"").unwrap();
+        let chunk_map = DigestedChunkMap::open(&blob_path, &blob_file).unwrap();
+        assert!(!chunk_map.has_ready(chunks[8].as_ref(), false).unwrap());
+    }
+
+    #[test]
+    fn test_chunk_map_claim() {
+        let dir = TempDir::new().unwrap();
+        let blob_path = dir.as_path().join("blob-1");
+        let blob_path = blob_path.as_os_str().to_str().unwrap().to_string();
+        let chunk_count = 100;
+
+        // Chunk maps opening the same file separately behave like being in different processes.
+        let chunk_map1 = Arc::new(BlobChunkMap::from(
+            IndexedChunkMap::new(&blob_path, chunk_count).unwrap(),
+        ));
+        let chunk_map2 = Arc::new(BlobChunkMap::from(
+            IndexedChunkMap::new(&blob_path, chunk_count).unwrap(),
+        ));
+        let chunk1 = Chunk::new(1);
+        let chunk2 = Chunk::new(2);
+
+        assert!(!chunk_map1.has_ready(chunk1.as_ref(), true).unwrap());
+        assert!(!chunk_map1.has_ready(chunk2.as_ref(), true).unwrap());
+
+        // Wait for the chunk claimed by others until it gets ready.
+        let map = chunk_map2.clone();
+        let chunk = chunk1.clone();
+        let t = thread::spawn(move || map.has_ready(chunk.as_ref(), true).unwrap());
+        thread::sleep(Duration::from_millis(100));
+        chunk_map1.set_ready(chunk1.as_ref()).unwrap();
+        assert!(t.join().unwrap());
+
+        // Failing to fetch the chunk passes the claim on to others.
+        let map = chunk_map2.clone();
+        let chunk = chunk2.clone();
+        let t = thread::spawn(move || map.has_ready(chunk.as_ref(), true).unwrap());
+        thread::sleep(Duration::from_millis(100));
+        chunk_map1.finish(chunk2.as_ref());
+        assert!(!t.join().unwrap());
+    }
+
     #[test]
     fn test_chunk_map_perf() {
         let dir = TempDir::new().unwrap();
@@ -8,6 +8,7 @@ use std::sync::Arc;
 use vm_memory::VolatileSlice;
 
 use crate::backend::BlobBackend;
+use crate::cache::memcache::ChunkMemCache;
 use crate::cache::*;
 use crate::device::{BlobPrefetchControl, RafsBio};
 use crate::factory::CacheConfig;
@@ -15,12 +16,33 @@ use crate::utils::{alloc_buf, copyv};
 use crate::{compress, StorageResult};
 
 use nydus_utils::digest;
+use nydus_utils::metrics::{BlobcacheMetrics, Metric};
 
 pub struct DummyCache {
     pub backend: Arc<dyn BlobBackend + Sync + Send>,
     validate: bool,
     compressor: compress::Algorithm,
     digester: digest::Algorithm,
+    // Decompressed chunks cached in memory and metrics of it, only when enabled.
+    mem_cache: Option<ChunkMemCache>,
+    metrics: Option<Arc<BlobcacheMetrics>>,
+}
+
+impl DummyCache {
+    fn get_mem_cache(&self, bio: &RafsBio) -> Option<Arc<Vec<u8>>> {
+        let data = self
+            .mem_cache
+            .as_ref()?
+            .get(&bio.blob, bio.chunkinfo.as_ref());
+        if let Some(ref metrics) = self.metrics {
+            if data.is_some() {
+                metrics.mem_cache_hits.inc();
+            } else {
+                metrics.mem_cache_misses.inc();
+            }
+        }
+        data
+    }
 }
 
 impl RafsCache for DummyCache {
@@ -36,7 +58,7 @@ impl RafsCache for DummyCache {
     }
 
     fn read(&self, bios: &mut [RafsBio], bufs: &[VolatileSlice]) -> Result<usize> {
-        let mut buffer_holder: Vec<Vec<u8>> = Vec::new();
+        let mut buffer_holder: Vec<Arc<Vec<u8>>> = Vec::new();
         let offset = bios[0].offset;
         let mut user_size = 0;
 
@@ -48,23 +70,30 @@ impl RafsCache for DummyCache {
             }
 
             user_size += bio.size;
+            if let Some(d) = self.get_mem_cache(bio) {
+                buffer_holder.push(d);
+                continue;
+            }
+
             let chunk = &bio.chunkinfo;
-            let mut reuse = false;
             let d_size = chunk.decompress_size() as usize;
-            let one_chunk_buf =
-                if bufs.len() == 1 && bios_len == 1 && offset == 0 && bufs[0].len() >= d_size {
-                    // Use the destination buffer to received the decompressed data.
-                    reuse = true;
-                    unsafe { std::slice::from_raw_parts_mut(bufs[0].as_ptr(), d_size) }
-                } else {
-                    let d = alloc_buf(d_size);
-                    buffer_holder.push(d);
-                    buffer_holder.last_mut().unwrap().as_mut_slice()
-                };
-            self.read_backend_chunk(&bio.blob, chunk.as_ref(), one_chunk_buf, None)?;
-            if reuse {
+            if bufs.len() == 1 && bios_len == 1 && offset == 0 && bufs[0].len() >= d_size {
+                // Use the destination buffer to received the decompressed data.
+                let one_chunk_buf =
+                    unsafe { std::slice::from_raw_parts_mut(bufs[0].as_ptr(), d_size) };
+                self.read_backend_chunk(&bio.blob, chunk.as_ref(), one_chunk_buf, None)?;
+                if let Some(ref mem_cache) = self.mem_cache {
+                    mem_cache.insert(&bio.blob, chunk.as_ref(), one_chunk_buf);
+                }
                 return Ok(one_chunk_buf.len());
             }
+
+            let mut d = alloc_buf(d_size);
+            self.read_backend_chunk(&bio.blob, chunk.as_ref(), d.as_mut_slice(), None)?;
+            if let Some(ref mem_cache) = self.mem_cache {
+                mem_cache.insert(&bio.blob, chunk.as_ref(), &d);
+            }
+            buffer_holder.push(Arc::new(d));
         }
 
         let chunk_buffers: Vec<&[u8]> = buffer_holder.iter().map(|b| b.as_slice()).collect();
@@ -100,7 +129,7 @@ impl RafsCache for DummyCache {
     }
 
     /// Prefetch works when blobcache is enabled
-    fn prefetch(&self, _bios: &mut [RafsBio]) -> StorageResult<usize> {
+    fn prefetch(&self, _bios: &mut [RafsBio], _priority: PrefetchPriority) -> StorageResult<usize> {
         Ok(0)
     }
 
@@ -109,6 +138,9 @@ impl RafsCache for DummyCache {
     }
 
     fn release(&self) {
+        if let Some(ref metrics) = self.metrics {
+            metrics.release().unwrap_or_else(|e| error!("{:?}", e));
+        }
         self.backend().release()
     }
 
@@ -122,11 +154,23 @@ pub fn new(
     backend: Arc<dyn BlobBackend + Sync + Send>,
     compressor: compress::Algorithm,
     digester: digest::Algorithm,
+    id: &str,
 ) -> Result<DummyCache> {
+    let (mem_cache, metrics) = if config.mem_cache_size != 0 {
+        (
+            Some(ChunkMemCache::new(config.mem_cache_size)),
+            Some(BlobcacheMetrics::new(id, "")),
+        )
+    } else {
+        (None, None)
+    };
+
     Ok(DummyCache {
         backend,
         validate: config.cache_validate,
         compressor,
         digester,
+        mem_cache,
+        metrics,
     })
 }
@@ -0,0 +1,169 @@
+// Copyright 2021 Ant Group. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! A bounded in-memory cache of decompressed chunks, so hot chunks are neither read from
+//! the blob cache file nor decompressed again on every access.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use crate::device::{RafsBlobEntry, RafsChunkInfo, RafsChunkKey};
+
+#[derive(Default)]
+struct ChunkMemCacheState {
+    // Chunk data and its logical time of the latest access.
+    chunks: HashMap<RafsChunkKey, (Arc<Vec<u8>>, u64)>,
+    // Chunks ordered by access time, the least recently used one is dropped first.
+    lru: BTreeMap<u64, RafsChunkKey>,
+    clock: u64,
+    // Total size of cached chunk data in unit of bytes.
+    size: usize,
+}
+
+impl ChunkMemCacheState {
+    fn touch(&mut self, key: RafsChunkKey) -> Option<Arc<Vec<u8>>> {
+        let clock = self.clock;
+        let (data, tick) = self.chunks.get_mut(&key)?;
+        self.lru.remove(tick);
+        *tick = clock;
+        self.lru.insert(clock, key);
+        self.clock += 1;
+        Some(data.clone())
+    }
+}
+
+pub struct ChunkMemCache {
+    capacity: usize,
+    state: Mutex<ChunkMemCacheState>,
+}
+
+impl ChunkMemCache {
+    /// Create a cache holding at most `capacity` bytes of decompressed chunk data.
+    pub fn new(capacity: usize) -> Self {
+        ChunkMemCache {
+            capacity,
+            state: Mutex::new(ChunkMemCacheState::default()),
+        }
+    }
+
+    pub fn get(&self, blob: &RafsBlobEntry, chunk: &dyn RafsChunkInfo) -> Option<Arc<Vec<u8>>> {
+        self.state
+            .lock()
+            .unwrap()
+            .touch(RafsChunkKey::new(blob, chunk))
+    }
+
+    /// Cache decompressed data of the chunk, dropping least recently used chunks if needed.
+    pub fn insert(&self, blob: &RafsBlobEntry, chunk: &dyn RafsChunkInfo, data: &[u8]) {
+        if data.len() > self.capacity {
+            return;
+        }
+
+        let key = RafsChunkKey::new(blob, chunk);
+        let mut state = self.state.lock().unwrap();
+        if state.touch(key).is_some() {
+            return;
+        }
+
+        while state.size + data.len() > self.capacity {
+            let (tick, victim) = match state.lru.iter().next() {
+                Some((tick, victim)) => (*tick, *victim),
+                None => break,
+            };
+            state.lru.remove(&tick);
+            if let Some((d, _)) = state.chunks.remove(&victim) {
+                state.size -= d.len();
+            }
+        }
+
+        let clock = state.clock;
+        state.chunks.insert(key, (Arc::new(data.to_vec()), clock));
+        state.lru.insert(clock, key);
+        state.clock += 1;
+        state.size += data.len();
+    }
+
+    /// Total size of cached chunk data in unit of bytes.
+    pub fn size(&self) -> usize {
+        self.state.lock().unwrap().size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::blobcache::blob_cache_tests::MockChunkInfo;
+    use nydus_utils::digest::RafsDigest;
+
+    fn chunk(index: u32) -> MockChunkInfo {
+        let mut chunk = MockChunkInfo::new();
+        chunk.index = index;
+        chunk
+    }
+
+    #[test]
+    fn test_chunk_mem_cache() {
+        let blob = RafsBlobEntry {
+            chunk_count: 8,
+            ..Default::default()
+        };
+        let cache = ChunkMemCache::new(300);
+
+        cache.insert(&blob, &chunk(0), &[0u8; 100]);
+        cache.insert(&blob, &chunk(1), &[1u8; 100]);
+        cache.insert(&blob, &chunk(2), &[2u8; 100]);
+        assert_eq!(cache.size(), 300);
+        assert_eq!(cache.get(&blob, &chunk(1)).unwrap().as_slice(), &[1u8; 100]);
+
+        // Chunk 0 is the least recently used one.
+        cache.insert(&blob, &chunk(3), &[3u8; 100]);
+        assert_eq!(cache.size(), 300);
+        assert!(cache.get(&blob, &chunk(0)).is_none());
+        assert!(cache.get(&blob, &chunk(1)).is_some());
+        assert!(cache.get(&blob, &chunk(2)).is_some());
+        assert!(cache.get(&blob, &chunk(3)).is_some());
+
+        // Chunks of other blobs are keyed separately.
+        let other = RafsBlobEntry {
+            blob_index: 1,
+            chunk_count: 8,
+            ..Default::default()
+        };
+        assert!(cache.get(&other, &chunk(3)).is_none());
+
+        // Chunks larger than the cache are never cached.
+        cache.insert(&blob, &chunk(4), &[4u8; 301]);
+        assert!(cache.get(&blob, &chunk(4)).is_none());
+        assert_eq!(cache.size(), 300);
+
+        cache.insert(&other, &chunk(0), &[0u8; 250]);
+        assert_eq!(cache.size(), 250);
+        assert!(cache.get(&blob, &chunk(3)).is_none());
+    }
+
+    #[test]
+    fn test_chunk_mem_cache_without_extended_blob_table() {
+        let blob = RafsBlobEntry::default();
+        let cache = ChunkMemCache::new(300);
+        let digested_chunk = |index: u32, digest: u8| {
+            let mut chunk = chunk(index);
+            chunk.block_id = RafsDigest { data: [digest; 32] };
+            chunk
+        };
+
+        // Chunk index isn't trusted, chunks are keyed by digest instead.
+        cache.insert(&blob, &digested_chunk(0, 0), &[0u8; 100]);
+        assert!(cache.get(&blob, &digested_chunk(0, 1)).is_none());
+        cache.insert(&blob, &digested_chunk(0, 1), &[1u8; 100]);
+        assert_eq!(cache.size(), 200);
+        assert_eq!(
+            cache.get(&blob, &digested_chunk(0, 0)).unwrap().as_slice(),
+            &[0u8; 100]
+        );
+        assert_eq!(
+            cache.get(&blob, &digested_chunk(3, 1)).unwrap().as_slice(),
+            &[1u8; 100]
+        );
+    }
+}
@@ -12,15 +12,19 @@ use std::sync::Arc;
 use vm_memory::VolatileSlice;
 
 use crate::backend::BlobBackend;
+use crate::cache::prefetch::{PrefetchPriority, PrefetchProgress};
 use crate::device::{BlobPrefetchControl, RafsBio, RafsBlobEntry, RafsChunkInfo};
 use crate::utils::{alloc_buf, digest_check};
 use crate::{compress, StorageResult};
 
 use nydus_utils::digest;
 
+pub mod archive;
 pub mod blobcache;
 pub mod chunkmap;
 pub mod dummycache;
+pub mod memcache;
+pub mod prefetch;
 
 /// A segment is always a continuous part in a single chunk, which is later copied
 /// from to user buffer memory.
@@ -107,6 +111,28 @@ impl MergedBackendRequest {
     }
 }
 
+/// A cached chunk whose data fails in verification.
+#[derive(Clone, Debug, Serialize)]
+pub struct CorruptedChunk {
+    pub blob_id: String,
+    pub chunk_index: u32,
+    pub error: String,
+}
+
+/// Progress of scrubbing cached data, corrupted chunks are marked not ready so they are
+/// fetched from backend again on next access.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ScrubProgress {
+    pub running: bool,
+    /// Chunks to be walked through.
+    pub total_chunks: u64,
+    /// Chunks walked through so far.
+    pub scanned_chunks: u64,
+    /// Chunks ready in cache and verified so far.
+    pub verified_chunks: u64,
+    pub corrupted_chunks: Vec<CorruptedChunk>,
+}
+
 #[derive(Clone, Default)]
 pub struct PrefetchWorker {
     pub enable: bool,
@@ -130,9 +156,25 @@ pub trait RafsCache {
     /// Get the size of a blob
     fn blob_size(&self, blob: &RafsBlobEntry) -> Result<u64>;
 
-    fn prefetch(&self, bio: &mut [RafsBio]) -> StorageResult<usize>;
+    fn prefetch(&self, bio: &mut [RafsBio], priority: PrefetchPriority) -> StorageResult<usize>;
     fn stop_prefetch(&self) -> StorageResult<()>;
 
+    /// Mark the start of issuing a batch of prefetch requests, prefetch is not complete until
+    /// the batch ends by `end_prefetch()`.
+    fn begin_prefetch(&self) {}
+
+    fn end_prefetch(&self) {}
+
+    /// Drop prefetch requests not issued yet, prefetch workers keep serving later requests.
+    fn cancel_prefetch(&self) -> StorageResult<()> {
+        Ok(())
+    }
+
+    /// Progress of prefetch since the cache is created.
+    fn prefetch_progress(&self) -> Option<PrefetchProgress> {
+        None
+    }
+
     /// Release cache
     fn release(&self);
 
@@ -142,6 +184,14 @@ pub trait RafsCache {
     fn compressor(&self) -> compress::Algorithm;
     fn need_validate(&self) -> bool;
 
+    /// Get compression algorithm of a chunk, falling back to the global compressor if
+    /// the chunk doesn't record it.
+    fn chunk_compressor(&self, cki: &dyn RafsChunkInfo) -> compress::Algorithm {
+        cki.flags()
+            .compressor()
+            .unwrap_or_else(|| self.compressor())
+    }
+
     /// Read a whole chunk directly from *backend*.
     /// The fetched chunk could be compressed or not by different compressors.
     /// It depends on `cki` how to describe the chunk data.
@@ -164,7 +214,7 @@ pub trait RafsCache {
             // gzip is special that it doesn't carry compress_size, instead, we can read as much
             // as chunk_decompress_size compressed data per chunk, decompress as much as necessary to fill in chunk
             // that has the original uncompressed data size.
-            let c_size = if self.compressor() != compress::Algorithm::GZip {
+            let c_size = if self.chunk_compressor(cki) != compress::Algorithm::GZip {
                 cki.compress_size() as usize
             } else {
                 // Per man(1) gzip
@@ -251,10 +301,11 @@ pub trait RafsCache {
         need_validate: bool,
     ) -> Result<usize> {
         if need_decompress {
-            compress::decompress(raw_chunk, raw_stream, chunk, self.compressor()).map_err(|e| {
-                error!("failed to decompress chunk: {}", e);
-                e
-            })?;
+            compress::decompress(raw_chunk, raw_stream, chunk, self.chunk_compressor(cki))
+                .map_err(|e| {
+                    error!("failed to decompress chunk: {}", e);
+                    e
+                })?;
         } else if raw_chunk.as_ptr() != chunk.as_ptr() {
             // Sometimes, caller directly put data into consumer provided buffer.
             // Then we don't have to copy data between slices.
@@ -276,12 +327,14 @@ pub trait RafsCache {
     /// range [`blob_offset`..`blob_offset` + `blob_size`] exactly covers more than one
     /// chunks and `cki_set` can correctly describe how to extract chunk from batched buffer.
     /// Afterwards, several chunks are returned, caller does not have to decompress them.
+    /// `raw_hook` provides caller a chance to read fetched raw data of each chunk.
     fn read_chunks(
         &self,
         blob_id: &str,
         blob_offset: u64,
         blob_size: usize,
         cki_set: &[Arc<dyn RafsChunkInfo>],
+        raw_hook: Option<&dyn Fn(&dyn RafsChunkInfo, &[u8])>,
     ) -> Result<Vec<Vec<u8>>> {
         let mut c_buf = alloc_buf(blob_size);
         let mut chunks: Vec<Vec<u8>> = Vec::new();
@@ -303,15 +356,19 @@ pub trait RafsCache {
             // TODO: Also check if adjacent here?
             let offset_merged = (cki.compress_offset() - blob_offset) as usize;
             let size_merged = cki.compress_size() as usize;
+            let raw_chunk = &c_buf[offset_merged..(offset_merged + size_merged)];
             let mut chunk = alloc_buf(cki.decompress_size() as usize);
             self.process_raw_chunk(
                 cki.as_ref(),
-                &c_buf[offset_merged..(offset_merged + size_merged)],
+                raw_chunk,
                 None,
                 &mut chunk,
                 cki.is_compressed(),
                 self.need_validate(),
             )?;
+            if let Some(hook) = raw_hook {
+                hook(cki.as_ref(), raw_chunk)
+            }
             chunks.push(chunk);
         }
 
@@ -319,4 +376,15 @@ pub trait RafsCache {
     }
 
     fn is_chunk_cached(&self, chunk: &dyn RafsChunkInfo, blob: &RafsBlobEntry) -> bool;
+
+    /// Start verifying cached data of chunks described by `bios` in background, reading
+    /// at most `rate` bytes per second, zero means unlimited.
+    fn scrub(&self, _bios: Vec<RafsBio>, _rate: u32) -> Result<()> {
+        Err(enosys!("the cache doesn't support scrubbing"))
+    }
+
+    /// Progress and findings of the latest scrubbing.
+    fn scrub_progress(&self) -> Option<ScrubProgress> {
+        None
+    }
 }
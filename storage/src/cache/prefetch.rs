@@ -0,0 +1,616 @@
+// Copyright 2021 Ant Group. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! A priority queue scheduling prefetch requests among prefetch workers. Requests of higher
+//! priority are issued first, and prefetch yields to user reads waiting for data from the
+//! storage backend.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Result;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use nydus_utils::metrics::{ERROR_HOLDER, PREFETCH_EVENT_HOLDER};
+
+use super::MergedBackendRequest;
+use crate::device::{RafsBlobEntry, RafsChunkInfo};
+
+/// The longest time in milliseconds a prefetch worker holds back for user reads, so that
+/// prefetch is throttled rather than starved under continuous user IO.
+const USER_IO_YIELD_TIMEOUT: u64 = 100;
+
+/// Priority of prefetch requests, requests of higher priority are issued first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PrefetchPriority {
+    /// Prefetch the whole filesystem.
+    Blob,
+    /// Prefetch files hinted by the prefetch table or `prefetch_files`.
+    Hinted,
+    /// Pending prefetch requests containing chunks needed by user reads.
+    OnDemand,
+}
+
+/// State of the latest round of prefetch, a round starts when prefetch requests are queued
+/// while no round is running, and ends when all of its requests are done or cancelled.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PrefetchState {
+    /// Nothing is ever prefetched.
+    Idle,
+    Running,
+    /// All chunks are prefetched.
+    Complete,
+    /// Prefetch is cancelled before all chunks are prefetched.
+    Cancelled,
+    /// Some chunks failed to be prefetched.
+    Failed,
+}
+
+impl Default for PrefetchState {
+    fn default() -> Self {
+        PrefetchState::Idle
+    }
+}
+
+/// Progress of prefetch of a mount since it's mounted, bytes are in compressed size of chunks.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct PrefetchProgress {
+    pub state: PrefetchState,
+    /// Chunks ever queued for prefetch.
+    pub planned_chunks: u64,
+    pub planned_bytes: u64,
+    /// Chunks prefetched, including those already cached.
+    pub done_chunks: u64,
+    pub done_bytes: u64,
+    /// Chunks failed to be fetched from storage backend or cached.
+    pub failed_chunks: u64,
+    pub failed_bytes: u64,
+    /// Chunks dropped from the queue by cancelling.
+    pub cancelled_chunks: u64,
+    pub cancelled_bytes: u64,
+    /// Bytes actually read from storage backend by prefetch.
+    pub fetched_bytes: u64,
+}
+
+impl PrefetchProgress {
+    fn outstanding_chunks(&self) -> u64 {
+        self.planned_chunks - self.done_chunks - self.failed_chunks - self.cancelled_chunks
+    }
+}
+
+fn chunks_size(mr: &MergedBackendRequest) -> u64 {
+    mr.chunks.iter().map(|c| c.compress_size() as u64).sum()
+}
+
+// (priority, sequence), the highest priority and the earliest one comes first.
+type RequestKey = (Reverse<PrefetchPriority>, u64);
+
+#[derive(Default)]
+struct PrefetchQueueState {
+    requests: BTreeMap<RequestKey, MergedBackendRequest>,
+    // (blob index, chunk index) -> pending request containing the chunk
+    chunks: HashMap<(u32, u32), RequestKey>,
+    seq: u64,
+    closed: bool,
+    progress: PrefetchProgress,
+    // Number of threads issuing prefetch requests, the round can't end until they're all done.
+    producers: usize,
+    // Requests from producers running when prefetch is cancelled are dropped.
+    dropping: bool,
+    round_failed: bool,
+    round_cancelled: bool,
+}
+
+impl PrefetchQueueState {
+    fn insert(&mut self, mr: MergedBackendRequest, priority: PrefetchPriority) {
+        let key = (Reverse(priority), self.seq);
+        self.seq += 1;
+        for c in &mr.chunks {
+            self.chunks
+                .insert((mr.blob_entry.blob_index, c.index()), key);
+        }
+        self.requests.insert(key, mr);
+    }
+
+    fn remove(&mut self, key: &RequestKey) -> Option<MergedBackendRequest> {
+        let mr = self.requests.remove(key)?;
+        for c in &mr.chunks {
+            let chunk = (mr.blob_entry.blob_index, c.index());
+            // The chunk may have been queued again by a later request.
+            if self.chunks.get(&chunk) == Some(key) {
+                self.chunks.remove(&chunk);
+            }
+        }
+        Some(mr)
+    }
+
+    fn start_round(&mut self) {
+        if self.progress.state != PrefetchState::Running {
+            self.progress.state = PrefetchState::Running;
+            self.round_failed = false;
+            self.round_cancelled = false;
+        }
+    }
+
+    // End the round if there's nothing left to do, return true if it ends.
+    fn try_end_round(&mut self) -> bool {
+        if self.progress.state != PrefetchState::Running
+            || self.producers != 0
+            || self.progress.outstanding_chunks() != 0
+        {
+            return false;
+        }
+
+        self.progress.state = if self.round_cancelled {
+            PrefetchState::Cancelled
+        } else if self.round_failed {
+            PrefetchState::Failed
+        } else {
+            PrefetchState::Complete
+        };
+        true
+    }
+}
+
+pub(super) struct PrefetchQueue {
+    // Identity of the cache, i.e. mountpoint of the rafs, used in events.
+    id: String,
+    state: Mutex<PrefetchQueueState>,
+    cond: Condvar,
+    // Number of user reads waiting for data from the storage backend.
+    user_io: AtomicUsize,
+    yield_timeout: Duration,
+}
+
+impl Default for PrefetchQueue {
+    fn default() -> Self {
+        PrefetchQueue {
+            id: String::new(),
+            state: Mutex::new(PrefetchQueueState::default()),
+            cond: Condvar::new(),
+            user_io: AtomicUsize::new(0),
+            yield_timeout: Duration::from_millis(USER_IO_YIELD_TIMEOUT),
+        }
+    }
+}
+
+/// Prefetch holds back until all user reads are done, or for `USER_IO_YIELD_TIMEOUT` at most.
+pub(super) struct UserIoGuard<'a> {
+    queue: &'a PrefetchQueue,
+}
+
+impl<'a> Drop for UserIoGuard<'a> {
+    fn drop(&mut self) {
+        if self.queue.user_io.fetch_sub(1, Ordering::AcqRel) == 1 {
+            // Take the lock so no worker misses the wakeup between checking and waiting.
+            let _guard = self.queue.state.lock().unwrap();
+            self.queue.cond.notify_all();
+        }
+    }
+}
+
+impl PrefetchQueue {
+    pub(super) fn new(id: &str) -> Self {
+        PrefetchQueue {
+            id: id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Queue a prefetch request, return false if the queue is closed.
+    pub(super) fn push(&self, mr: MergedBackendRequest, priority: PrefetchPriority) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return false;
+        }
+
+        state.start_round();
+        state.progress.planned_chunks += mr.chunks.len() as u64;
+        state.progress.planned_bytes += chunks_size(&mr);
+        if state.dropping {
+            state.progress.cancelled_chunks += mr.chunks.len() as u64;
+            state.progress.cancelled_bytes += chunks_size(&mr);
+            return true;
+        }
+
+        state.insert(mr, priority);
+        self.cond.notify_one();
+        true
+    }
+
+    /// Mark the start of issuing a batch of prefetch requests, so that the round is not
+    /// considered finished before `end()` even if all requests queued so far are done.
+    pub(super) fn begin(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.start_round();
+        // Requests from batches cancelled but still being issued are dropped until they all
+        // end, as well as those from batches begun meanwhile.
+        if state.producers == 0 {
+            state.dropping = false;
+        }
+        state.producers += 1;
+    }
+
+    pub(super) fn end(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.producers -= 1;
+        if state.producers == 0 {
+            state.dropping = false;
+        }
+        self.check_round_end(&mut state);
+    }
+
+    /// Take the request of the highest priority, block until there's one. Return None if
+    /// the queue is closed and all requests are taken.
+    pub(super) fn pop(&self) -> Option<MergedBackendRequest> {
+        let mut state = self.state.lock().unwrap();
+        let mut yield_start: Option<Instant> = None;
+
+        loop {
+            if state.requests.is_empty() {
+                if state.closed {
+                    return None;
+                }
+                state = self.cond.wait(state).unwrap();
+                continue;
+            }
+
+            if self.user_io.load(Ordering::Acquire) != 0 {
+                let start = *yield_start.get_or_insert_with(Instant::now);
+                if let Some(left) = self.yield_timeout.checked_sub(start.elapsed()) {
+                    state = self.cond.wait_timeout(state, left).unwrap().0;
+                    continue;
+                }
+            }
+
+            // Safe to unwrap since the queue is not empty.
+            let key = *state.requests.keys().next().unwrap();
+            return state.remove(&key);
+        }
+    }
+
+    /// Account a request taken from the queue by its result, which is bytes read from storage
+    /// backend on success.
+    pub(super) fn finish(&self, mr: &MergedBackendRequest, result: &Result<u64>) {
+        let mut state = self.state.lock().unwrap();
+        match result {
+            Ok(fetched) => {
+                state.progress.done_chunks += mr.chunks.len() as u64;
+                state.progress.done_bytes += chunks_size(mr);
+                state.progress.fetched_bytes += fetched;
+            }
+            Err(_) => {
+                state.progress.failed_chunks += mr.chunks.len() as u64;
+                state.progress.failed_bytes += chunks_size(mr);
+                state.round_failed = true;
+            }
+        }
+        self.check_round_end(&mut state);
+    }
+
+    /// Drop all pending requests and those from batches being issued, requests already taken
+    /// by workers still go on.
+    pub(super) fn cancel(&self) {
+        let mut state = self.state.lock().unwrap();
+        let requests = std::mem::take(&mut state.requests);
+        state.chunks.clear();
+        for mr in requests.values() {
+            state.progress.cancelled_chunks += mr.chunks.len() as u64;
+            state.progress.cancelled_bytes += chunks_size(mr);
+        }
+        if state.progress.state == PrefetchState::Running {
+            state.round_cancelled = true;
+            state.dropping = state.producers != 0;
+        }
+        self.check_round_end(&mut state);
+    }
+
+    // Publish an event on the end of a round, so that others know whether the data is ready.
+    fn check_round_end(&self, state: &mut PrefetchQueueState) {
+        if state.try_end_round() {
+            let p = &state.progress;
+            let event = format!(
+                "prefetch of {} is {:?}: {} of {} chunks done, {} failed, {} cancelled",
+                self.id,
+                p.state,
+                p.done_chunks,
+                p.planned_chunks,
+                p.failed_chunks,
+                p.cancelled_chunks
+            );
+            info!("{}", event);
+            // Orchestration waits for the event among daemon events, it's also kept apart from
+            // errors for those only interested in prefetch.
+            for holder in [&*ERROR_HOLDER, &*PREFETCH_EVENT_HOLDER].iter() {
+                holder.lock().unwrap().push(&event).unwrap_or_else(|e| {
+                    warn!("failed to hold prefetch event {:?}: {:?}", event, e)
+                });
+            }
+        }
+    }
+
+    /// Whether there're queued chunks not done or cancelled yet.
+    pub(super) fn is_active(&self) -> bool {
+        self.state.lock().unwrap().progress.outstanding_chunks() != 0
+    }
+
+    pub(super) fn progress(&self) -> PrefetchProgress {
+        self.state.lock().unwrap().progress.clone()
+    }
+
+    /// Move the pending request containing the chunk to the front of the queue.
+    pub(super) fn promote(&self, blob: &RafsBlobEntry, chunk: &dyn RafsChunkInfo) {
+        let mut state = self.state.lock().unwrap();
+        let key = match state.chunks.get(&(blob.blob_index, chunk.index())) {
+            Some(key) if key.0 != Reverse(PrefetchPriority::OnDemand) => *key,
+            _ => return,
+        };
+        if let Some(mr) = state.remove(&key) {
+            state.insert(mr, PrefetchPriority::OnDemand);
+        }
+    }
+
+    /// Hold back prefetch until the returned guard is dropped.
+    pub(super) fn user_io(&self) -> UserIoGuard<'_> {
+        self.user_io.fetch_add(1, Ordering::AcqRel);
+        UserIoGuard { queue: self }
+    }
+
+    /// No more requests are accepted, workers exit after pending requests are taken.
+    pub(super) fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.cond.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{mpsc, Arc, Barrier};
+    use std::thread;
+
+    use super::*;
+    use crate::cache::blobcache::blob_cache_tests::MockChunkInfo;
+    use crate::device::RafsBio;
+
+    fn request(blob: &Arc<RafsBlobEntry>, indexes: &[u32]) -> MergedBackendRequest {
+        let chunks = indexes
+            .iter()
+            .map(|i| {
+                let chunk = MockChunkInfo {
+                    index: *i,
+                    compress_offset: *i as u64 * 0x1000,
+                    compress_size: 0x1000,
+                    ..Default::default()
+                };
+                Arc::new(chunk) as Arc<dyn RafsChunkInfo>
+            })
+            .collect::<Vec<_>>();
+        let bio = RafsBio::new(chunks[0].clone(), blob.clone(), 0, 0x1000, 0x1000, false);
+        let mut mr = MergedBackendRequest::new(chunks[0].clone(), blob.clone(), &bio);
+        for c in &chunks[1..] {
+            mr.merge_one_chunk(c.clone(), &bio);
+        }
+        mr
+    }
+
+    fn chunk(index: u32) -> MockChunkInfo {
+        MockChunkInfo {
+            index,
+            ..Default::default()
+        }
+    }
+
+    fn pop_index(queue: &PrefetchQueue) -> u32 {
+        queue.pop().unwrap().chunks[0].index()
+    }
+
+    #[test]
+    fn test_prefetch_queue_priority() {
+        let blob = Arc::new(RafsBlobEntry::default());
+        let queue = PrefetchQueue::default();
+
+        assert!(queue.push(request(&blob, &[0, 1]), PrefetchPriority::Blob));
+        assert!(queue.push(request(&blob, &[2, 3]), PrefetchPriority::Blob));
+        assert!(queue.push(request(&blob, &[4]), PrefetchPriority::Hinted));
+        assert!(queue.push(request(&blob, &[5]), PrefetchPriority::Hinted));
+
+        // Requests of higher priority come first, and in order of queueing among the same
+        // priority, except those needed by user reads.
+        queue.promote(&blob, &chunk(3));
+        queue.promote(&blob, &chunk(9));
+        assert_eq!(pop_index(&queue), 2);
+        assert_eq!(pop_index(&queue), 4);
+        assert_eq!(pop_index(&queue), 5);
+
+        // Promoting a chunk already taken does nothing.
+        queue.promote(&blob, &chunk(4));
+        queue.close();
+        assert!(!queue.push(request(&blob, &[6]), PrefetchPriority::Hinted));
+        assert_eq!(pop_index(&queue), 0);
+        assert!(queue.pop().is_none());
+    }
+
+    #[test]
+    fn test_prefetch_queue_cancel() {
+        let blob = Arc::new(RafsBlobEntry::default());
+        let queue = PrefetchQueue::default();
+
+        queue.push(request(&blob, &[0, 1]), PrefetchPriority::Hinted);
+        queue.push(request(&blob, &[2, 3, 4]), PrefetchPriority::Hinted);
+        let mr = queue.pop().unwrap();
+        assert!(queue.is_active());
+
+        // Requests already taken are not affected by cancelling.
+        queue.cancel();
+        queue.promote(&blob, &chunk(3));
+        assert!(queue.is_active());
+        assert_eq!(queue.progress().state, PrefetchState::Running);
+        queue.finish(&mr, &Ok(0x2000));
+        assert!(!queue.is_active());
+        assert_eq!(queue.progress().state, PrefetchState::Cancelled);
+
+        // Prefetch can be started again after cancelling.
+        queue.push(request(&blob, &[5]), PrefetchPriority::Hinted);
+        assert!(queue.is_active());
+        let mr = queue.pop().unwrap();
+        queue.finish(&mr, &Ok(0x1000));
+
+        let progress = queue.progress();
+        assert_eq!(progress.state, PrefetchState::Complete);
+        assert_eq!(progress.planned_chunks, 6);
+        assert_eq!(progress.planned_bytes, 0x6000);
+        assert_eq!(progress.done_chunks, 3);
+        assert_eq!(progress.cancelled_chunks, 3);
+        assert_eq!(progress.cancelled_bytes, 0x3000);
+        assert_eq!(progress.fetched_bytes, 0x3000);
+    }
+
+    #[test]
+    fn test_prefetch_queue_state() {
+        let blob = Arc::new(RafsBlobEntry::default());
+        let queue = PrefetchQueue::default();
+        assert_eq!(queue.progress().state, PrefetchState::Idle);
+
+        // The round doesn't end before the batch ends, even if all queued requests are done.
+        queue.begin();
+        assert_eq!(queue.progress().state, PrefetchState::Running);
+        queue.push(request(&blob, &[0]), PrefetchPriority::Hinted);
+        let mr = queue.pop().unwrap();
+        queue.finish(&mr, &Err(eio!()));
+        assert_eq!(queue.progress().state, PrefetchState::Running);
+        queue.push(request(&blob, &[1]), PrefetchPriority::Hinted);
+        let mr = queue.pop().unwrap();
+        queue.finish(&mr, &Ok(0x1000));
+        queue.end();
+        let progress = queue.progress();
+        assert_eq!(progress.state, PrefetchState::Failed);
+        assert_eq!(progress.failed_chunks, 1);
+        assert_eq!(progress.failed_bytes, 0x1000);
+        assert_eq!(progress.done_chunks, 1);
+
+        // Requests from a batch being issued are dropped after cancelling.
+        queue.begin();
+        queue.cancel();
+        assert!(queue.push(request(&blob, &[2]), PrefetchPriority::Hinted));
+        assert!(!queue.is_active());
+        assert_eq!(queue.progress().state, PrefetchState::Running);
+        queue.end();
+        assert_eq!(queue.progress().state, PrefetchState::Cancelled);
+
+        // An empty batch completes at once.
+        queue.begin();
+        queue.end();
+        assert_eq!(queue.progress().state, PrefetchState::Complete);
+        assert_eq!(queue.progress().cancelled_chunks, 1);
+    }
+
+    #[test]
+    fn test_prefetch_queue_event() {
+        let queue = PrefetchQueue::new("/prefetch-event");
+        queue.begin();
+        queue.end();
+
+        let event = "prefetch of /prefetch-event is Complete";
+        for events in &[
+            nydus_utils::metrics::export_events().unwrap(),
+            nydus_utils::metrics::export_prefetch_events().unwrap(),
+        ] {
+            assert!(events.contains(event));
+        }
+    }
+
+    #[test]
+    fn test_prefetch_queue_cancel_with_new_batch() {
+        let blob = Arc::new(RafsBlobEntry::default());
+        let queue = PrefetchQueue::default();
+
+        // A cancelled batch keeps being dropped even if a new batch begins meanwhile.
+        queue.begin();
+        queue.cancel();
+        queue.begin();
+        queue.push(request(&blob, &[0]), PrefetchPriority::Hinted);
+        queue.end();
+        queue.push(request(&blob, &[1]), PrefetchPriority::Hinted);
+        assert!(!queue.is_active());
+        queue.end();
+        assert_eq!(queue.progress().state, PrefetchState::Cancelled);
+        assert_eq!(queue.progress().cancelled_chunks, 2);
+
+        // Batches begun after all cancelled ones end are accepted.
+        queue.begin();
+        queue.push(request(&blob, &[2]), PrefetchPriority::Hinted);
+        assert!(queue.is_active());
+        queue.end();
+        let mr = queue.pop().unwrap();
+        queue.finish(&mr, &Ok(0x1000));
+        assert_eq!(queue.progress().state, PrefetchState::Complete);
+
+        // Cancel while batches are issued by multiple threads, every chunk is either done or
+        // cancelled at last.
+        let queue = Arc::new(PrefetchQueue::default());
+        let barrier = Arc::new(Barrier::new(5));
+        let mut threads = Vec::new();
+        for i in 0..4u32 {
+            let (q, b, blob) = (queue.clone(), barrier.clone(), blob.clone());
+            threads.push(thread::spawn(move || {
+                b.wait();
+                for j in 0..100 {
+                    q.begin();
+                    q.push(request(&blob, &[i * 100 + j]), PrefetchPriority::Hinted);
+                    q.end();
+                }
+            }));
+        }
+        barrier.wait();
+        for _ in 0..100 {
+            queue.cancel();
+            thread::yield_now();
+        }
+        for t in threads {
+            t.join().unwrap();
+        }
+        queue.close();
+        while let Some(mr) = queue.pop() {
+            queue.finish(&mr, &Ok(0x1000));
+        }
+        let progress = queue.progress();
+        assert_ne!(progress.state, PrefetchState::Running);
+        assert_eq!(progress.planned_chunks, 400);
+        assert_eq!(progress.done_chunks + progress.cancelled_chunks, 400);
+    }
+
+    #[test]
+    fn test_prefetch_queue_yield_to_user_io() {
+        let blob = Arc::new(RafsBlobEntry::default());
+        let queue = Arc::new(PrefetchQueue {
+            yield_timeout: Duration::from_secs(3600),
+            ..Default::default()
+        });
+        queue.push(request(&blob, &[0]), PrefetchPriority::Hinted);
+
+        // Prefetch waits for the user read.
+        let guard = queue.user_io();
+        let (tx, rx) = mpsc::channel();
+        let q = queue.clone();
+        let worker = thread::spawn(move || {
+            let mr = q.pop().unwrap();
+            tx.send(mr.chunks[0].index()).unwrap();
+        });
+        assert!(rx.recv_timeout(Duration::from_millis(50)).is_err());
+        drop(guard);
+        assert_eq!(rx.recv().unwrap(), 0);
+        worker.join().unwrap();
+
+        // Prefetch is throttled rather than starved by continuous user reads.
+        let queue = PrefetchQueue {
+            yield_timeout: Duration::from_millis(10),
+            ..Default::default()
+        };
+        queue.push(request(&blob, &[1]), PrefetchPriority::Hinted);
+        let _guard = queue.user_io();
+        assert_eq!(pop_index(&queue), 1);
+    }
+}
@@ -15,13 +15,12 @@ use flate2::Compression;
 mod lz4_standard;
 use self::lz4_standard::*;
 
-const COMPRESSION_MINIMUM_RATIO: usize = 100;
-
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Algorithm {
     None,
     Lz4Block,
     GZip,
+    Zstd,
 }
 
 impl Default for Algorithm {
@@ -44,7 +43,10 @@ impl FromStr for Algorithm {
             "none" => Ok(Self::None),
             "lz4_block" => Ok(Self::Lz4Block),
             "gzip" => Ok(Self::GZip),
-            _ => Err(einval!("compression algorithm should be none or lz4_block")),
+            "zstd" => Ok(Self::Zstd),
+            _ => Err(einval!(
+                "compression algorithm should be none, lz4_block, gzip or zstd"
+            )),
         }
     }
 }
@@ -55,6 +57,43 @@ impl Algorithm {
     }
 }
 
+/// Options to tune compression.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CompressOptions {
+    /// Compression level, `None` for the default level of the algorithm.
+    pub level: Option<i32>,
+    /// Minimum space savings in percentage, data is kept uncompressed if compression
+    /// saves less than it.
+    pub min_savings: u32,
+}
+
+impl CompressOptions {
+    /// Check whether the options are valid for the compression algorithm.
+    pub fn validate(&self, algorithm: Algorithm) -> Result<()> {
+        if self.min_savings >= 100 {
+            return Err(einval!(
+                "minimum compression savings should be less than 100"
+            ));
+        }
+
+        if let Some(level) = self.level {
+            let valid = match algorithm {
+                Algorithm::GZip => (0..=9).contains(&level),
+                Algorithm::Zstd => (1..=22).contains(&level),
+                _ => false,
+            };
+            if !valid {
+                return Err(einval!(format!(
+                    "invalid compression level {} for algorithm {}",
+                    level, algorithm
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
 // Algorithm::LZ4Block:
 // 1. Default ratio
 // 2. No prepend size
@@ -63,6 +102,18 @@ impl Algorithm {
 // with data blocks so that we don't really care about lz4 header magic numbers like
 // as being done with all these rust lz4 implementations
 pub fn compress(src: &[u8], algorithm: Algorithm) -> Result<(Cow<[u8]>, bool)> {
+    compress_with_options(src, algorithm, &CompressOptions::default())
+}
+
+/// Compress data with the compression level and minimum space savings in `options`.
+/// Return whether data is compressed together with the output data.
+///
+/// `options` should have been checked by `CompressOptions::validate()` against the algorithm.
+pub fn compress_with_options<'a>(
+    src: &'a [u8],
+    algorithm: Algorithm,
+    options: &CompressOptions,
+) -> Result<(Cow<'a, [u8]>, bool)> {
     let src_size = src.len();
     if src_size == 0 {
         return Ok((Cow::Borrowed(src), false));
@@ -72,17 +123,23 @@ pub fn compress(src: &[u8], algorithm: Algorithm) -> Result<(Cow<[u8]>, bool)> {
         Algorithm::None => return Ok((Cow::Borrowed(src), false)),
         Algorithm::Lz4Block => lz4_compress(src)?,
         Algorithm::GZip => {
+            let level = options
+                .level
+                .map(|l| Compression::new(l as u32))
+                .unwrap_or_default();
             let dst: Vec<u8> = Vec::new();
-            let mut gz = GzEncoder::new(dst, Compression::default());
+            let mut gz = GzEncoder::new(dst, level);
             gz.write_all(src)?;
             gz.finish()?
         }
+        Algorithm::Zstd => zstd::block::compress(
+            src,
+            options.level.unwrap_or(zstd::DEFAULT_COMPRESSION_LEVEL),
+        )?,
     };
 
-    // Abandon compressed data when compression ratio greater than COMPRESSION_MINIMUM_RATIO
-    if (COMPRESSION_MINIMUM_RATIO == 100 && compressed.len() >= src_size)
-        || ((100 * compressed.len() / src_size) >= COMPRESSION_MINIMUM_RATIO)
-    {
+    // Abandon compressed data when it doesn't save enough space.
+    if 100 * compressed.len() >= (100 - options.min_savings as usize) * src_size {
         return Ok((Cow::Borrowed(src), false));
     }
     Ok((Cow::Owned(compressed), true))
@@ -109,6 +166,15 @@ pub fn decompress(
             };
             Ok(dst.len())
         }
+        Algorithm::Zstd => {
+            if let Some(f) = src_file {
+                let mut zstd = zstd::stream::read::Decoder::new(f)?;
+                zstd.read_exact(dst)?;
+                Ok(dst.len())
+            } else {
+                zstd::block::decompress_to_buffer(src, dst)
+            }
+        }
     }
 }
 
@@ -153,6 +219,90 @@ mod tests {
         assert_eq!(buf, decompressed);
     }
 
+    #[test]
+    fn test_compress_algorithm_zstd() {
+        let buf = vec![0x2u8; 4097];
+        let (compressed, is_compressed) = compress(&buf, Algorithm::Zstd).unwrap();
+        assert!(is_compressed);
+        assert!(compressed.len() < buf.len());
+
+        let mut decompressed = vec![0; buf.len()];
+        let sz = decompress(
+            &compressed,
+            None,
+            decompressed.as_mut_slice(),
+            Algorithm::Zstd,
+        )
+        .unwrap();
+        assert_eq!(sz, 4097);
+        assert_eq!(buf, decompressed);
+
+        let mut tmp_file = TempFile::new().unwrap().into_file();
+        tmp_file.write_all(&compressed).unwrap();
+        tmp_file.seek(SeekFrom::Start(0)).unwrap();
+
+        let mut decompressed = vec![0; buf.len()];
+        let sz = decompress(
+            &compressed,
+            Some(tmp_file),
+            decompressed.as_mut_slice(),
+            Algorithm::Zstd,
+        )
+        .unwrap();
+        assert_eq!(sz, 4097);
+        assert_eq!(buf, decompressed);
+
+        // Decompressed data must fill the destination buffer.
+        let mut decompressed = vec![0; buf.len() - 1];
+        assert!(decompress(
+            &compressed,
+            None,
+            decompressed.as_mut_slice(),
+            Algorithm::Zstd
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_compress_options() {
+        let buf: Vec<u8> = (0..4096u32).map(|i| (i % 13 + i % 7) as u8).collect();
+        let (compressed, _) = compress(&buf, Algorithm::Zstd).unwrap();
+        let savings = 100 - 100 * compressed.len() / buf.len();
+
+        let options = CompressOptions {
+            level: Some(19),
+            min_savings: 0,
+        };
+        let (compressed, is_compressed) =
+            compress_with_options(&buf, Algorithm::Zstd, &options).unwrap();
+        assert!(is_compressed);
+        let mut decompressed = vec![0; buf.len()];
+        decompress(&compressed, None, &mut decompressed, Algorithm::Zstd).unwrap();
+        assert_eq!(buf, decompressed);
+
+        // Keep data uncompressed if it doesn't save enough space.
+        let options = CompressOptions {
+            level: None,
+            min_savings: savings as u32 + 1,
+        };
+        let (data, is_compressed) = compress_with_options(&buf, Algorithm::Zstd, &options).unwrap();
+        assert!(!is_compressed);
+        assert_eq!(data, buf.as_slice());
+
+        let options = CompressOptions {
+            level: Some(10),
+            min_savings: 0,
+        };
+        assert!(options.validate(Algorithm::Zstd).is_ok());
+        assert!(options.validate(Algorithm::GZip).is_err());
+        assert!(options.validate(Algorithm::Lz4Block).is_err());
+        let options = CompressOptions {
+            level: None,
+            min_savings: 100,
+        };
+        assert!(options.validate(Algorithm::Zstd).is_err());
+    }
+
     #[test]
     fn test_compress_algorithm_none() {
         let buf = [
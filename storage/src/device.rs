@@ -13,7 +13,8 @@ use fuse_backend_rs::api::filesystem::{ZeroCopyReader, ZeroCopyWriter};
 use fuse_backend_rs::transport::FileReadWriteVolatile;
 use vm_memory::{Bytes, VolatileSlice};
 
-use crate::cache::RafsCache;
+use crate::cache::prefetch::{PrefetchPriority, PrefetchProgress};
+use crate::cache::{RafsCache, ScrubProgress};
 use crate::{compress, factory, StorageResult};
 
 use nydus_utils::digest::{self, RafsDigest};
@@ -31,6 +32,39 @@ bitflags! {
         /// chunk is compressed
         const COMPRESSED = 0x0000_0001;
         const HOLECHUNK = 0x0000_0002;
+        /// chunk is compressed with lz4_block
+        const COMPRESS_LZ4_BLOCK = 0x0000_0004;
+        /// chunk is compressed with gzip
+        const COMPRESS_GZIP = 0x0000_0008;
+        /// chunk is compressed with zstd
+        const COMPRESS_ZSTD = 0x0000_0010;
+    }
+}
+
+impl RafsChunkFlags {
+    /// Get compression algorithm of the chunk, `None` if not recorded, which is the case
+    /// of chunks from old bootstraps, then the global compressor in superblock applies.
+    pub fn compressor(&self) -> Option<compress::Algorithm> {
+        if self.contains(RafsChunkFlags::COMPRESS_LZ4_BLOCK) {
+            Some(compress::Algorithm::Lz4Block)
+        } else if self.contains(RafsChunkFlags::COMPRESS_GZIP) {
+            Some(compress::Algorithm::GZip)
+        } else if self.contains(RafsChunkFlags::COMPRESS_ZSTD) {
+            Some(compress::Algorithm::Zstd)
+        } else {
+            None
+        }
+    }
+}
+
+impl From<compress::Algorithm> for RafsChunkFlags {
+    fn from(algorithm: compress::Algorithm) -> Self {
+        match algorithm {
+            compress::Algorithm::None => RafsChunkFlags::empty(),
+            compress::Algorithm::Lz4Block => RafsChunkFlags::COMPRESS_LZ4_BLOCK,
+            compress::Algorithm::GZip => RafsChunkFlags::COMPRESS_GZIP,
+            compress::Algorithm::Zstd => RafsChunkFlags::COMPRESS_ZSTD,
+        }
     }
 }
 
@@ -107,6 +141,9 @@ impl RafsDevice {
     }
 
     pub fn close(&self) -> io::Result<()> {
+        // Prefetch workers are kept alive for prefetch requests issued at runtime, stop them
+        // so they don't outlive the device.
+        self.stop_prefetch().unwrap_or_else(|e| error!("{:?}", e));
         self.rw_layer.load().release();
         Ok(())
     }
@@ -126,8 +163,14 @@ impl RafsDevice {
         unimplemented!()
     }
 
-    pub fn prefetch(&self, desc: &mut RafsBioDesc) -> StorageResult<usize> {
-        self.rw_layer.load().prefetch(desc.bi_vec.as_mut_slice())?;
+    pub fn prefetch(
+        &self,
+        desc: &mut RafsBioDesc,
+        priority: PrefetchPriority,
+    ) -> StorageResult<usize> {
+        self.rw_layer
+            .load()
+            .prefetch(desc.bi_vec.as_mut_slice(), priority)?;
 
         Ok(desc.bi_size)
     }
@@ -135,6 +178,31 @@ impl RafsDevice {
     pub fn stop_prefetch(&self) -> StorageResult<()> {
         self.rw_layer.load().stop_prefetch()
     }
+
+    pub fn begin_prefetch(&self) {
+        self.rw_layer.load().begin_prefetch()
+    }
+
+    pub fn end_prefetch(&self) {
+        self.rw_layer.load().end_prefetch()
+    }
+
+    pub fn cancel_prefetch(&self) -> StorageResult<()> {
+        self.rw_layer.load().cancel_prefetch()
+    }
+
+    pub fn prefetch_progress(&self) -> Option<PrefetchProgress> {
+        self.rw_layer.load().prefetch_progress()
+    }
+
+    /// Start verifying cached data of chunks described by `bios` in background.
+    pub fn scrub(&self, bios: Vec<RafsBio>, rate: u32) -> io::Result<()> {
+        self.rw_layer.load().scrub(bios, rate)
+    }
+
+    pub fn scrub_progress(&self) -> Option<ScrubProgress> {
+        self.rw_layer.load().scrub_progress()
+    }
 }
 
 struct RafsBioDevice<'a> {
@@ -226,6 +294,25 @@ impl RafsBlobEntry {
     }
 }
 
+/// Identity of a chunk, the same way as chunks are tracked by chunk maps of the blob cache.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum RafsChunkKey {
+    /// (blob index, chunk index) of blobs with the extended blob table.
+    Index(u32, u32),
+    /// Digest of chunks from old bootstraps, whose chunk index isn't trusted.
+    Digest(RafsDigest),
+}
+
+impl RafsChunkKey {
+    pub fn new(blob: &RafsBlobEntry, chunk: &dyn RafsChunkInfo) -> Self {
+        if blob.with_extended_blob_table() {
+            RafsChunkKey::Index(blob.blob_index, chunk.index())
+        } else {
+            RafsChunkKey::Digest(*chunk.block_id())
+        }
+    }
+}
+
 // Rafs device blob IO descriptor
 #[derive(Default, Debug)]
 pub struct RafsBioDesc {
@@ -61,6 +61,9 @@ pub struct CacheConfig {
     pub cache_type: String,
     #[serde(default, rename = "config")]
     pub cache_config: Value,
+    // Maximum size in bytes of decompressed chunks cached in memory, zero to disable.
+    #[serde(default)]
+    pub mem_cache_size: usize,
     // Whether to validate cache is up to upper layer - Rafs. So don't try to
     // get it from a user configuration file.
     #[serde(skip_serializing, skip_deserializing)]
@@ -69,17 +72,61 @@ pub struct CacheConfig {
     pub prefetch_worker: PrefetchWorker,
 }
 
+// Wrap backends accessing remote storage servers to hedge slow reads if enabled.
+#[cfg(any(
+    feature = "backend-http",
+    feature = "backend-oss",
+    feature = "backend-registry",
+    feature = "backend-s3"
+))]
+fn new_remote_backend(
+    backend: Arc<dyn BlobBackend + Send + Sync>,
+    config: &Value,
+) -> IOResult<Arc<dyn BlobBackend + Send + Sync>> {
+    let common_config: CommonConfig =
+        serde_json::from_value(config.clone()).map_err(|e| einval!(e))?;
+    if common_config.hedge().enabled() {
+        Ok(Arc::new(hedge::HedgedBackend::new(
+            backend,
+            common_config.hedge(),
+        )))
+    } else {
+        Ok(backend)
+    }
+}
+
 pub fn new_backend(
     config: BackendConfig,
     id: &str,
 ) -> IOResult<Arc<dyn BlobBackend + Send + Sync>> {
+    let backend_config = &config.backend_config;
     match config.backend_type.as_str() {
         #[cfg(feature = "backend-oss")]
-        "oss" => Ok(Arc::new(oss::new(config.backend_config, Some(id))?)),
+        "oss" => new_remote_backend(
+            Arc::new(oss::new(backend_config.clone(), Some(id))?),
+            backend_config,
+        ),
         #[cfg(feature = "backend-registry")]
-        "registry" => Ok(Arc::new(registry::new(config.backend_config, Some(id))?)),
+        "registry" => new_remote_backend(
+            Arc::new(registry::new(backend_config.clone(), Some(id))?),
+            backend_config,
+        ),
+        #[cfg(feature = "backend-s3")]
+        "s3" => new_remote_backend(
+            Arc::new(s3::new(backend_config.clone(), Some(id))?),
+            backend_config,
+        ),
+        #[cfg(feature = "backend-http")]
+        "http" => new_remote_backend(
+            Arc::new(http::new(backend_config.clone(), Some(id))?),
+            backend_config,
+        ),
         #[cfg(feature = "backend-localfs")]
         "localfs" => Ok(Arc::new(localfs::new(config.backend_config, Some(id))?)),
+        #[cfg(feature = "backend-oci")]
+        "oci" => Ok(Arc::new(oci::new(config.backend_config, Some(id))?)),
+        #[cfg(feature = "backend-faulty")]
+        "faulty" => Ok(Arc::new(faulty::new(config.backend_config, Some(id))?)),
         _ => Err(einval!(format!(
             "unsupported backend type '{}'",
             config.backend_type
@@ -107,6 +154,7 @@ pub fn new_rw_layer(
             backend,
             compressor,
             digester,
+            id,
         )?)),
     }
 }
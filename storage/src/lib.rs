@@ -10,6 +10,8 @@ extern crate serde;
 extern crate bitflags;
 #[macro_use]
 extern crate nydus_error;
+#[macro_use]
+extern crate lazy_static;
 
 pub mod backend;
 pub mod cache;
@@ -2,10 +2,12 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
+use std::cell::Cell;
 use std::cmp::{self, min, Ordering};
 use std::io::{ErrorKind, Result};
 use std::os::unix::io::RawFd;
 use std::slice::from_raw_parts_mut;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use libc::off64_t;
 use nix::sys::uio::{preadv, IoVec};
@@ -214,6 +216,63 @@ pub fn digest_check(data: &[u8], digest: &RafsDigest, digester: digest::Algorith
     digest == &RafsDigest::from_buf(data, digester)
 }
 
+thread_local! {
+    static RANDOM_STATE: Cell<u64> = Cell::new(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default()
+            | 1,
+    );
+}
+
+/// Cheap pseudo random number in [0, 1) by xorshift64*.
+pub fn random() -> f64 {
+    RANDOM_STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        state.set(x);
+        (x.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11) as f64 / (1u64 << 53) as f64
+    })
+}
+
+/// Apply an open file description lock of `lock_type` (`F_RDLCK`, `F_WRLCK` or `F_UNLCK`) on
+/// `len` bytes of the file starting from `start`, zero `len` means till the end of file.
+/// The lock is shared by all threads using the same file description and is dropped once the
+/// description is closed, so it coordinates among processes and survives no crash.
+/// Return false if `wait` is not set and the lock is held by others.
+pub fn ofd_lock(
+    fd: RawFd,
+    lock_type: libc::c_int,
+    start: u64,
+    len: u64,
+    wait: bool,
+) -> Result<bool> {
+    let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+    lock.l_type = lock_type as libc::c_short;
+    lock.l_whence = libc::SEEK_SET as libc::c_short;
+    lock.l_start = start as libc::off_t;
+    lock.l_len = len as libc::off_t;
+    let cmd = if wait {
+        libc::F_OFD_SETLKW
+    } else {
+        libc::F_OFD_SETLK
+    };
+
+    loop {
+        if unsafe { libc::fcntl(fd, cmd, &lock) } == 0 {
+            return Ok(true);
+        }
+        match std::io::Error::last_os_error().raw_os_error() {
+            Some(libc::EINTR) => continue,
+            Some(libc::EAGAIN) | Some(libc::EACCES) if !wait => return Ok(false),
+            _ => return Err(last_error!("failed to apply file lock")),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use vm_memory::VolatileSlice;
@@ -222,6 +281,8 @@ mod tests {
 
     use super::alloc_buf;
     use super::copyv;
+    use super::ofd_lock;
+    use super::random;
 
     #[test]
     fn test_copyv() {
@@ -295,4 +356,45 @@ mod tests {
             _ => panic!("should overflow"),
         }
     }
+
+    #[test]
+    fn test_random() {
+        for _ in 0..100 {
+            let r = random();
+            assert!((0.0..1.0).contains(&r));
+        }
+    }
+
+    #[test]
+    fn test_ofd_lock() {
+        use std::os::unix::io::AsRawFd;
+        use vmm_sys_util::tempfile::TempFile;
+
+        let tmp_file = TempFile::new().unwrap();
+        // Write locks need files opened for writing.
+        let open = || {
+            std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(tmp_file.as_path())
+                .unwrap()
+        };
+        let (file1, file2) = (open(), open());
+        let (fd1, fd2) = (file1.as_raw_fd(), file2.as_raw_fd());
+
+        // Read locks are compatible, beyond end of file is also lockable.
+        assert!(ofd_lock(fd1, libc::F_RDLCK, 0, 0, false).unwrap());
+        assert!(ofd_lock(fd2, libc::F_RDLCK, 0, 0, false).unwrap());
+        assert!(!ofd_lock(fd1, libc::F_WRLCK, 0, 0, false).unwrap());
+        assert!(ofd_lock(fd2, libc::F_UNLCK, 0, 0, false).unwrap());
+        // Convert to a write lock once others release theirs.
+        assert!(ofd_lock(fd1, libc::F_WRLCK, 0, 0, false).unwrap());
+        assert!(!ofd_lock(fd2, libc::F_RDLCK, 4096, 1, false).unwrap());
+
+        // Locks on different ranges don't conflict.
+        assert!(ofd_lock(fd1, libc::F_UNLCK, 0, 0, false).unwrap());
+        assert!(ofd_lock(fd1, libc::F_WRLCK, 1 << 32, 1, false).unwrap());
+        assert!(ofd_lock(fd2, libc::F_WRLCK, (1 << 32) + 1, 1, false).unwrap());
+        assert!(!ofd_lock(fd2, libc::F_WRLCK, 1 << 32, 1, false).unwrap());
+    }
 }
@@ -209,6 +209,85 @@ impl<'a> Builder<'a> {
         ).unwrap();
     }
 
+    /// Build lower rootfs into `bootstrap` with chunks prepared by `threads` worker threads.
+    pub fn build_lower_with_threads(&mut self, compressor: &str, bootstrap: &str, threads: usize) {
+        let lower_dir = self.work_dir.join("lower");
+
+        self.create_dir(&self.work_dir.join("blobs"));
+
+        exec(
+            format!(
+                "{:?} create --bootstrap {:?} --blob-dir {:?} --log-level info --compressor {} --whiteout-spec {} --threads {} {:?}",
+                self.builder,
+                self.work_dir.join(bootstrap),
+                self.work_dir.join("blobs"),
+                compressor,
+                self.whiteout_spec,
+                threads,
+                lower_dir,
+            )
+            .as_str(),
+            false,
+        ).unwrap();
+    }
+
+    /// Build lower rootfs into RAFS v6 bootstrap `bootstrap-lower-v6`.
+    pub fn build_lower_v6(&mut self, compressor: &str) {
+        let lower_dir = self.work_dir.join("lower");
+
+        self.create_dir(&self.work_dir.join("blobs"));
+
+        exec(
+            format!(
+                "{:?} create --fs-version 6 --bootstrap {:?} --blob-dir {:?} --log-level info --compressor {} --whiteout-spec {} {:?}",
+                self.builder,
+                self.work_dir.join("bootstrap-lower-v6"),
+                self.work_dir.join("blobs"),
+                compressor,
+                self.whiteout_spec,
+                lower_dir,
+            )
+            .as_str(),
+            false,
+        ).unwrap();
+    }
+
+    /// Build entries stored differently by RAFS v6 into bootstrap `bootstrap-v6files`.
+    pub fn build_v6_files(&mut self) {
+        let dir = self.work_dir.join("v6_files");
+        self.create_dir(&dir);
+        self.create_dir(&self.work_dir.join("blobs"));
+
+        self.create_file(&dir.join("file"), b"v6:file");
+        self.set_xattr(&dir.join("file"), "user.key-foo", b"value-foo");
+        self.set_xattr(&dir.join("file"), "user.key-bar", b"value-bar");
+        self.create_hardlink(&dir.join("file"), &dir.join("file-hardlink"));
+        self.create_symlink(
+            &Path::new("file").to_path_buf(),
+            &dir.join("inline-symlink"),
+        );
+        // Symlinks too long to be inlined into the inode are stored in a data block.
+        self.create_symlink(
+            &Path::new(&"long-target/".repeat(340)).to_path_buf(),
+            &dir.join("plain-symlink"),
+        );
+        self.create_dir(&dir.join("empty-dir"));
+
+        exec(
+            format!(
+                "{:?} create --fs-version 6 --bootstrap {:?} --blob-dir {:?} --log-level info --compressor {} --whiteout-spec {} {:?}",
+                self.builder,
+                self.work_dir.join("bootstrap-v6files"),
+                self.work_dir.join("blobs"),
+                "lz4_block",
+                self.whiteout_spec,
+                dir,
+            )
+            .as_str(),
+            false,
+        ).unwrap();
+    }
+
     pub fn build_upper(&mut self, compressor: &str) {
         let upper_dir = self.work_dir.join("upper");
 
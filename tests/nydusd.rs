@@ -4,6 +4,8 @@
 
 use std::fs::{self, File};
 use std::io::{Read, Write};
+#[cfg(feature = "backend-faulty")]
+use std::os::unix::net::UnixStream;
 use std::path::{Path, PathBuf};
 use std::thread::*;
 use std::time;
@@ -24,6 +26,46 @@ pub fn new(
     rafs_mode: RafsMode,
     api_sock: PathBuf,
     digest_validate: bool,
+) -> Nydusd {
+    _new(
+        work_dir,
+        enable_cache,
+        cache_compressed,
+        rafs_mode,
+        api_sock,
+        digest_validate,
+        false,
+    )
+}
+
+/// Create nydusd accessing blobs through the faulty backend, which passes all reads through to
+/// localfs until faults are injected.
+#[cfg(feature = "backend-faulty")]
+pub fn new_faulty(
+    work_dir: &Path,
+    rafs_mode: RafsMode,
+    api_sock: PathBuf,
+    digest_validate: bool,
+) -> Nydusd {
+    _new(
+        work_dir,
+        false,
+        false,
+        rafs_mode,
+        api_sock,
+        digest_validate,
+        true,
+    )
+}
+
+fn _new(
+    work_dir: &Path,
+    enable_cache: bool,
+    cache_compressed: bool,
+    rafs_mode: RafsMode,
+    api_sock: PathBuf,
+    digest_validate: bool,
+    faulty: bool,
 ) -> Nydusd {
     let cache_path = work_dir.join("cache");
     fs::create_dir_all(cache_path).unwrap();
@@ -42,17 +84,37 @@ pub fn new(
         work_dir.join("cache")
     );
 
+    let mut backend = format!(
+        r###"
+        {{
+            "type": "localfs",
+            "config": {{
+                "dir": {:?},
+                "readahead": true
+            }}
+        }}
+    "###,
+        work_dir.join("blobs")
+    );
+    if faulty {
+        backend = format!(
+            r###"
+            {{
+                "type": "faulty",
+                "config": {{
+                    "backend": {}
+                }}
+            }}
+        "###,
+            backend
+        );
+    }
+
     let config = format!(
         r###"
         {{
             "device": {{
-                "backend": {{
-                    "type": "localfs",
-                    "config": {{
-                        "dir": {:?},
-                        "readahead": true
-                    }}
-                }}
+                "backend": {}
                 {}
             }},
             "mode": "{}",
@@ -60,7 +122,7 @@ pub fn new(
             "iostats_files": true
         }}
         "###,
-        work_dir.join("blobs"),
+        backend,
         if enable_cache { cache } else { String::new() },
         rafs_mode,
         digest_validate,
@@ -153,6 +215,24 @@ impl Nydusd {
         assert_eq!(ret.trim(), expected.trim());
     }
 
+    /// Replace faults injected into reads from the backend, `[]` to clear all faults.
+    #[cfg(feature = "backend-faulty")]
+    pub fn inject_faults(&self, faults: &str) {
+        let mut stream = UnixStream::connect(self.work_dir.join(&self.api_sock)).unwrap();
+        write!(
+            stream,
+            "PUT /api/v1/backend/faults HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            faults.len(),
+            faults
+        )
+        .unwrap();
+
+        let mut resp = [0u8; 1024];
+        let len = stream.read(&mut resp).unwrap();
+        let resp = String::from_utf8_lossy(&resp[..len]);
+        assert!(resp.starts_with("HTTP/1.1 204"), "{}", resp);
+    }
+
     pub fn is_mounted(&self, mount_path: &str) -> bool {
         let ret = exec("cat /proc/mounts", true).unwrap();
         for line in ret.split('\n') {
@@ -4,6 +4,8 @@
 #[macro_use]
 extern crate log;
 
+use std::fs;
+use std::os::unix::fs::MetadataExt;
 use std::path::Path;
 
 use nydus_app::setup_logging;
@@ -179,6 +181,16 @@ fn integration_test_directory_9() {
     test("lz4_block", true, false, "direct", "overlayfs")
 }
 
+#[test]
+fn integration_test_directory_10() {
+    test("zstd", true, true, "cached", "oci")
+}
+
+#[test]
+fn integration_test_directory_11() {
+    test("zstd", false, false, "direct", "oci")
+}
+
 #[test]
 fn integration_test_compact() {
     info!("\n\n==================== testing run: compact test");
@@ -225,6 +237,121 @@ fn integration_test_special_files() {
     }
 }
 
+#[test]
+fn integration_test_parallel_build() {
+    info!("\n\n==================== testing run: parallel build test");
+
+    let tmp_dir = TempDir::new().unwrap();
+    let work_dir = tmp_dir.as_path().to_path_buf();
+
+    let mut builder = builder::new(&work_dir, "oci");
+    builder.make_lower();
+    builder.build_lower("lz4_block");
+    builder.build_lower_with_threads("lz4_block", "bootstrap-lower-threads", 4);
+
+    // Image built in parallel must be the same as the one built with a single thread.
+    let bootstrap = fs::read(work_dir.join("bootstrap-lower")).unwrap();
+    let bootstrap_threads = fs::read(work_dir.join("bootstrap-lower-threads")).unwrap();
+    assert_eq!(bootstrap, bootstrap_threads);
+
+    let nydusd = nydusd::new(
+        &work_dir,
+        false,
+        false,
+        "direct".parse().unwrap(),
+        "api.sock".into(),
+        true,
+    );
+    nydusd.start(Some("bootstrap-lower-threads"), "mnt");
+    nydusd.check("directory/lower.result", "mnt");
+    nydusd.umount("mnt");
+}
+
+#[test]
+fn integration_test_rafs_v6() {
+    info!("\n\n==================== testing run: rafs v6 test");
+
+    let tmp_dir = TempDir::new().unwrap();
+    let work_dir = tmp_dir.as_path().to_path_buf();
+
+    let mut builder = builder::new(&work_dir, "oci");
+    builder.make_lower();
+    builder.build_lower_v6("lz4_block");
+    builder.build_v6_files();
+
+    let nydusd = nydusd::new(
+        &work_dir,
+        true,
+        false,
+        "direct".parse().unwrap(),
+        "api.sock".into(),
+        true,
+    );
+    nydusd.start(Some("bootstrap-lower-v6"), "mnt");
+    nydusd.check("directory/lower.result", "mnt");
+    let mnt = work_dir.join("mnt");
+    assert_eq!(
+        xattr::get(mnt.join("sub/sub-1"), "user.key-foo").unwrap(),
+        Some(b"value-foo".to_vec())
+    );
+    nydusd.umount("mnt");
+
+    nydusd.start(Some("bootstrap-v6files"), "mnt");
+    let file = fs::metadata(mnt.join("file")).unwrap();
+    let hardlink = fs::metadata(mnt.join("file-hardlink")).unwrap();
+    assert_eq!(file.ino(), hardlink.ino());
+    assert_eq!(file.nlink(), 2);
+    assert_eq!(fs::read(mnt.join("file-hardlink")).unwrap(), b"v6:file");
+    assert_eq!(
+        xattr::get(mnt.join("file"), "user.key-bar").unwrap(),
+        Some(b"value-bar".to_vec())
+    );
+    assert_eq!(
+        fs::read_link(mnt.join("inline-symlink")).unwrap(),
+        Path::new("file")
+    );
+    assert_eq!(
+        fs::read_link(mnt.join("plain-symlink")).unwrap(),
+        Path::new(&"long-target/".repeat(340))
+    );
+    assert!(fs::metadata(mnt.join("empty-dir")).unwrap().is_dir());
+    assert_eq!(fs::read_dir(mnt.join("empty-dir")).unwrap().count(), 0);
+    nydusd.umount("mnt");
+}
+
+#[test]
+#[cfg(feature = "backend-faulty")]
+fn integration_test_faulty_backend() {
+    info!("\n\n==================== testing run: faulty backend test");
+
+    let tmp_dir = TempDir::new().unwrap();
+    let work_dir = tmp_dir.as_path().to_path_buf();
+
+    let mut builder = builder::new(&work_dir, "oci");
+    builder.make_lower();
+    builder.build_lower("lz4_block");
+
+    let nydusd = nydusd::new_faulty(
+        &work_dir,
+        "direct".parse().unwrap(),
+        "api.sock".into(),
+        true,
+    );
+    nydusd.start(Some("bootstrap-lower"), "mnt");
+
+    // Corrupted data must be rejected by digest validation.
+    nydusd.inject_faults(r#"[{"fault": "corrupt"}]"#);
+    assert!(exec(
+        format!("cat {:?}", work_dir.join("mnt/root-large")).as_str(),
+        true
+    )
+    .is_err());
+
+    nydusd.inject_faults("[]");
+    nydusd.check("directory/lower.result", "mnt");
+    nydusd.umount("mnt");
+}
+
 #[test]
 fn integration_test_stargz() {
     info!("\n\n==================== testing run: stargz test");
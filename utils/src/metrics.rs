@@ -4,7 +4,7 @@
 
 //! Rafs fop stats accounting and exporting.
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::ops::{Deref, Drop};
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
@@ -62,6 +62,9 @@ fn request_size_index(size: usize) -> usize {
 // <=1ms, <=20ms, <=50ms, <=100ms, <=500ms, <=1s, <=2s, >2s
 const READ_LATENCY_RANGE_MAX: usize = 8;
 
+// Number of recent successful reads kept to calculate latency percentiles.
+const RECENT_LATENCY_WINDOW: usize = 256;
+
 fn latency_millis_range_index(elapsed: u64) -> usize {
     match elapsed {
         _ if elapsed <= 1 => 0,
@@ -110,6 +113,12 @@ lazy_static! {
         Arc::new(Mutex::new(ErrorHolder::new(500, 50 * 1024)));
 }
 
+lazy_static! {
+    /// Holds state changes of prefetch only, which are published to `ERROR_HOLDER` as well.
+    pub static ref PREFETCH_EVENT_HOLDER: Arc<Mutex<ErrorHolder>> =
+        Arc::new(Mutex::new(ErrorHolder::new(100, 10 * 1024)));
+}
+
 #[derive(Default, Debug, Serialize)]
 pub struct GlobalIoStats {
     // Whether to enable each file accounting switch.
@@ -181,7 +190,35 @@ pub struct AccessPattern {
     first_access_time_nanos: AtomicU32,
 }
 
+/// Version of the access trace format.
+pub const ACCESS_TRACE_VERSION: u32 = 1;
+
+/// Files of a rafs in order of their first read, exported in a stable format so that
+/// `nydus-image create --prefetch-trace` could build the prefetch table from it.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AccessTrace {
+    pub version: u32,
+    pub files: Vec<AccessTraceEntry>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct AccessTraceEntry {
+    /// File path relative to rafs root.
+    pub path: PathBuf,
+    /// Time of the first read relative to the earliest one of all files, in unit of
+    /// microseconds, so traces of different runs are comparable.
+    pub first_access_usecs: u64,
+    pub nr_read: u64,
+}
+
 impl AccessPattern {
+    fn first_access_time(&self) -> Duration {
+        Duration::new(
+            self.first_access_time_secs.load(Ordering::Relaxed),
+            self.first_access_time_nanos.load(Ordering::Relaxed),
+        )
+    }
+
     fn record_access_time(&self) {
         if self.first_access_time_secs.load(Ordering::Relaxed) == 0 {
             let t = SystemTime::now()
@@ -390,6 +427,36 @@ impl GlobalIoStats {
         .map_err(IoStatsError::Serialize)
     }
 
+    fn access_trace(&self) -> AccessTrace {
+        let records = self.access_patterns.read().expect("Not poisoned lock");
+        let mut files = records
+            .values()
+            .filter(|r| r.nr_read.count() != 0)
+            .map(|r| (r.first_access_time(), r))
+            .collect::<Vec<_>>();
+        files.sort_by(|a, b| {
+            a.0.cmp(&b.0)
+                .then_with(|| a.1.file_path.cmp(&b.1.file_path))
+        });
+
+        let start = files.first().map(|f| f.0).unwrap_or_default();
+        AccessTrace {
+            version: ACCESS_TRACE_VERSION,
+            files: files
+                .into_iter()
+                .map(|(t, r)| AccessTraceEntry {
+                    path: r.file_path.clone(),
+                    first_access_usecs: (t - start).as_micros() as u64,
+                    nr_read: r.nr_read.count(),
+                })
+                .collect(),
+        }
+    }
+
+    fn export_files_access_trace(&self) -> Result<String, IoStatsError> {
+        serde_json::to_string(&self.access_trace()).map_err(IoStatsError::Serialize)
+    }
+
     fn export_global_stats(&self) -> Result<String, IoStatsError> {
         serde_json::to_string(self).map_err(IoStatsError::Serialize)
     }
@@ -484,6 +551,24 @@ pub fn export_files_access_pattern(name: &Option<String>) -> Result<String, IoSt
     }
 }
 
+pub fn export_files_access_trace(name: &Option<String>) -> Result<String, IoStatsError> {
+    let ios_set = IOS_SET.read().unwrap();
+    match name {
+        Some(k) => ios_set
+            .get(k)
+            .ok_or(IoStatsError::NoCounter)
+            .map(|v| v.export_files_access_trace())?,
+        None => {
+            if ios_set.len() == 1 {
+                if let Some(ios) = ios_set.values().next() {
+                    return ios.export_files_access_trace();
+                }
+            }
+            Err(IoStatsError::NoCounter)
+        }
+    }
+}
+
 pub fn export_global_stats(name: &Option<String>) -> Result<String, IoStatsError> {
     // With only one rafs instance, we allow caller to ask for an unknown ios name.
     let ios_set = IOS_SET.read().unwrap();
@@ -546,6 +631,11 @@ pub fn export_events() -> IoStatsResult<String> {
     serde_json::to_string(ERROR_HOLDER.lock().unwrap().deref()).map_err(IoStatsError::Serialize)
 }
 
+pub fn export_prefetch_events() -> IoStatsResult<String> {
+    serde_json::to_string(PREFETCH_EVENT_HOLDER.lock().unwrap().deref())
+        .map_err(IoStatsError::Serialize)
+}
+
 pub trait Metric {
     /// Adds `value` to the current counter.
     fn add(&self, value: u64);
@@ -597,6 +687,98 @@ pub struct BackendMetrics {
     read_count_block_size_dist: [BasicMetric; BLOCK_READ_SIZES_MAX],
     // Categorize metrics as per their latency and request size
     read_latency_hits_dist: [[BasicMetric; READ_LATENCY_RANGE_MAX]; BLOCK_READ_SIZES_MAX],
+    // Cumulative count of retried read requests to backend
+    read_retries: BasicMetric,
+    // Cumulative count of hedged read requests issued to backend
+    hedges_issued: BasicMetric,
+    // Cumulative count of hedged read requests completed before the original ones
+    hedges_won: BasicMetric,
+    // Latency of recent successful reads in unit of microsecond
+    #[serde(skip_serializing)]
+    recent_latency: Mutex<VecDeque<u64>>,
+    // Health status of mirror servers in front of the backend, in priority order.
+    mirrors: RwLock<Vec<Arc<MirrorMetrics>>>,
+    circuit_breaker: CircuitBreakerMetrics,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl Default for CircuitState {
+    fn default() -> Self {
+        CircuitState::Closed
+    }
+}
+
+/// State of the circuit breaker protecting the storage backend.
+#[derive(Default, Serialize, Debug)]
+pub struct CircuitBreakerMetrics {
+    state: RwLock<CircuitState>,
+    // Consecutive failed requests since the last successful one
+    consecutive_failures: AtomicU32,
+    // Cumulative count of circuit transitions to open
+    trips: BasicMetric,
+    // Cumulative count of requests failed fast while circuit is open
+    rejected: BasicMetric,
+}
+
+impl CircuitBreakerMetrics {
+    pub fn state(&self) -> CircuitState {
+        *self.state.read().unwrap()
+    }
+
+    pub fn set_state(&self, state: CircuitState, consecutive_failures: u32) {
+        if state == CircuitState::Open && self.state() != CircuitState::Open {
+            self.trips.inc();
+        }
+        *self.state.write().unwrap() = state;
+        self.consecutive_failures
+            .store(consecutive_failures, Ordering::Relaxed);
+    }
+
+    pub fn reject(&self) {
+        self.rejected.inc()
+    }
+}
+
+/// Health status of a mirror server of the storage backend.
+#[derive(Default, Serialize, Debug)]
+pub struct MirrorMetrics {
+    host: String,
+    healthy: AtomicBool,
+    // Cumulative count of requests failed over to the next mirror or the origin server
+    failovers: BasicMetric,
+}
+
+impl MirrorMetrics {
+    pub fn new(host: &str) -> Arc<Self> {
+        Arc::new(Self {
+            host: host.to_string(),
+            healthy: AtomicBool::new(true),
+            ..Default::default()
+        })
+    }
+
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+    }
+
+    pub fn set_healthy(&self, healthy: bool) {
+        self.healthy.store(healthy, Ordering::Relaxed)
+    }
+
+    pub fn failover(&self) {
+        self.failovers.inc()
+    }
 }
 
 impl Metric for BasicMetric {
@@ -661,6 +843,47 @@ impl BackendMetrics {
             .ok_or(IoStatsError::NoCounter)
     }
 
+    /// Create and register health metrics for a mirror of the backend.
+    pub fn new_mirror(&self, host: &str) -> Arc<MirrorMetrics> {
+        let mirror = MirrorMetrics::new(host);
+        self.mirrors.write().unwrap().push(mirror.clone());
+        mirror
+    }
+
+    pub fn circuit_breaker(&self) -> &CircuitBreakerMetrics {
+        &self.circuit_breaker
+    }
+
+    pub fn retry(&self) {
+        self.read_retries.inc()
+    }
+
+    pub fn hedge_issued(&self) {
+        self.hedges_issued.inc()
+    }
+
+    pub fn hedge_won(&self) {
+        self.hedges_won.inc()
+    }
+
+    /// Get the percentile of recent read latency, or `None` if there are less than
+    /// `min_samples` successful reads.
+    pub fn latency_percentile(&self, percentile: f64, min_samples: usize) -> Option<Duration> {
+        let mut samples = self
+            .recent_latency
+            .lock()
+            .unwrap()
+            .iter()
+            .copied()
+            .collect::<Vec<_>>();
+        if samples.is_empty() || samples.len() < min_samples {
+            return None;
+        }
+        samples.sort_unstable();
+        let index = (samples.len() as f64 * percentile.clamp(0.0, 100.0) / 100.0) as usize;
+        Some(Duration::from_micros(samples[index.min(samples.len() - 1)]))
+    }
+
     pub fn begin(&self) -> SystemTime {
         SystemTime::now()
     }
@@ -672,6 +895,12 @@ impl BackendMetrics {
             self.read_count.inc();
             if error {
                 self.read_errors.inc();
+            } else {
+                let mut recent = self.recent_latency.lock().unwrap();
+                if recent.len() >= RECENT_LATENCY_WINDOW {
+                    recent.pop_front();
+                }
+                recent.push_back(saturating_duration_micros(&d));
             }
 
             self.read_cumulative_latency_millis_total.add(elapsed);
@@ -703,9 +932,17 @@ pub struct BlobcacheMetrics {
     // How many `read` requests are processed by the blobcache instance.
     // This metric will be helpful when comparing with cache hits times.
     pub total: BasicMetric,
-    // Scale of blobcache. Blobcache does not evict entries.
+    // Scale of blobcache.
     // Means the number of chunks in ready status.
     pub entries_count: BasicMetric,
+    // How many times blobs are evicted from blobcache and disk space freed by them
+    // in unit of Bytes, only when blobcache size is limited.
+    pub evictions: BasicMetric,
+    pub evicted_bytes: BasicMetric,
+    // How many chunks are served from the in-memory chunk cache and how many are looked up
+    // but absent, only when the in-memory chunk cache is enabled.
+    pub mem_cache_hits: BasicMetric,
+    pub mem_cache_misses: BasicMetric,
     // Together with below two fields, we can figure out average merging size thus
     // to estimate the possibility to merge backend IOs.
     // In unit of Bytes
@@ -753,6 +990,72 @@ impl BlobcacheMetrics {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_access_trace() {
+        let ios = GlobalIoStats::default();
+        ios.toggle_access_pattern(true);
+        for ino in 1..=3 {
+            ios.new_file_counter(ino, |i| PathBuf::from(format!("/file-{}", i)));
+        }
+        ios.new_file_counter(4, |_| PathBuf::from("/unread"));
+
+        let records = ios.access_patterns.read().unwrap();
+        for (ino, secs) in &[(1, 12), (2, 10), (3, 10)] {
+            let r = &records[ino];
+            r.nr_read.add(*ino);
+            r.first_access_time_secs.store(*secs, Ordering::Relaxed);
+            r.first_access_time_nanos.store(500, Ordering::Relaxed);
+        }
+        drop(records);
+
+        let trace: AccessTrace =
+            serde_json::from_str(&ios.export_files_access_trace().unwrap()).unwrap();
+        assert_eq!(trace.version, ACCESS_TRACE_VERSION);
+        let files = trace
+            .files
+            .iter()
+            .map(|f| (f.path.to_str().unwrap(), f.first_access_usecs, f.nr_read))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            files,
+            vec![
+                ("/file-2", 0, 2),
+                ("/file-3", 0, 3),
+                ("/file-1", 2_000_000, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_latency_percentile() {
+        let metrics = BackendMetrics::default();
+        assert!(metrics.latency_percentile(50.0, 0).is_none());
+        for i in 1..=RECENT_LATENCY_WINDOW as u64 + 100 {
+            metrics.recent_latency.lock().unwrap().push_back(i);
+            if metrics.recent_latency.lock().unwrap().len() > RECENT_LATENCY_WINDOW {
+                metrics.recent_latency.lock().unwrap().pop_front();
+            }
+        }
+        assert!(metrics
+            .latency_percentile(50.0, RECENT_LATENCY_WINDOW + 1)
+            .is_none());
+        assert_eq!(
+            metrics.latency_percentile(50.0, 1),
+            Some(Duration::from_micros(229))
+        );
+        assert_eq!(
+            metrics.latency_percentile(100.0, 1),
+            Some(Duration::from_micros(356))
+        );
+
+        let begin = metrics.begin();
+        metrics.end(&begin, 4096, false);
+        assert_eq!(
+            metrics.recent_latency.lock().unwrap().len(),
+            RECENT_LATENCY_WINDOW
+        );
+    }
+
     #[test]
     fn test_request_size_index() {
         assert_eq!(request_size_index(0x0), 0);